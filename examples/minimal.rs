@@ -0,0 +1,53 @@
+// Headless use of the library: runs a rom for 600 frames, prints its serial output & saves the
+// last frame as a PPM image.
+//
+//   cargo run --example minimal -- path/to/rom.gb [screenshot.ppm]
+
+use rustboy::{
+	HEIGHT, WIDTH,
+	gameboy::{GameBoy, RunOutcome},
+	options::EmulatorOptions,
+};
+use std::{env, fs, process};
+
+const FRAMES: usize = 600;
+
+fn main() {
+	let mut args = env::args().skip(1);
+	let Some(rom_path) = args.next() else {
+		eprintln!("usage: minimal <rom> [screenshot.ppm]");
+		process::exit(1);
+	};
+	let screenshot_path = args.next().unwrap_or("screenshot.ppm".to_string());
+
+	let mut gameboy =
+		GameBoy::from_file(&rom_path, &EmulatorOptions::default()).unwrap_or_else(|e| {
+			eprintln!("unable to read {}: {}", rom_path, e);
+			process::exit(1);
+		});
+	let mut serial = Vec::new();
+	for _ in 0..FRAMES {
+		let outcome = gameboy.run_frame();
+		serial.extend(gameboy.take_serial_output());
+		if let RunOutcome::Stalled(stall) = outcome {
+			eprintln!("stalled: {}", stall);
+			break;
+		}
+	}
+	print!("{}", String::from_utf8_lossy(&serial));
+
+	let mut ppm = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+	gameboy
+		.get_frame_buffer()
+		.iter()
+		.for_each(|pixel| ppm.extend(&pixel.to_be_bytes()[1..]));
+	fs::write(&screenshot_path, ppm).unwrap_or_else(|e| {
+		eprintln!("unable to write {}: {}", screenshot_path, e);
+		process::exit(1);
+	});
+	println!(
+		"{} frames, screenshot saved to {}",
+		gameboy.frame_count(),
+		screenshot_path
+	);
+}
@@ -0,0 +1,111 @@
+// Windowed frontend on the pixels/winit stack instead of minifb: runs a rom at the console's frame
+// rate, presents every frame & maps the keyboard to the joypad through the public Button API.
+//
+//   cargo run --example pixels_frontend --features pixels-frontend -- path/to/rom.gb
+
+use pixels::{Pixels, SurfaceTexture};
+use rustboy::{HEIGHT, WIDTH, gameboy::GameBoy, joypad::Button, options::EmulatorOptions};
+use std::{
+	env, process,
+	time::{Duration, Instant},
+};
+use winit::{
+	dpi::LogicalSize,
+	event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+	event_loop::EventLoop,
+	window::WindowBuilder,
+};
+
+// 70224 cycles at 4194304 Hz
+const FRAME_TIME: Duration = Duration::from_nanos(16_742_706);
+
+// same layout as the minifb frontend
+fn button(key: VirtualKeyCode) -> Option<Button> {
+	match key {
+		VirtualKeyCode::J => Some(Button::A),
+		VirtualKeyCode::K => Some(Button::B),
+		VirtualKeyCode::Back => Some(Button::SELECT),
+		VirtualKeyCode::Return => Some(Button::START),
+		VirtualKeyCode::D => Some(Button::RIGHT),
+		VirtualKeyCode::A => Some(Button::LEFT),
+		VirtualKeyCode::W => Some(Button::UP),
+		VirtualKeyCode::S => Some(Button::DOWN),
+		_ => None,
+	}
+}
+
+fn main() {
+	let Some(rom_path) = env::args().nth(1) else {
+		eprintln!("usage: pixels_frontend <rom>");
+		process::exit(1);
+	};
+	let mut gameboy =
+		GameBoy::from_file(&rom_path, &EmulatorOptions::default()).unwrap_or_else(|e| {
+			eprintln!("unable to read {}: {}", rom_path, e);
+			process::exit(1);
+		});
+
+	let event_loop = EventLoop::new();
+	let window = WindowBuilder::new()
+		.with_title("RustBoy (pixels)")
+		.with_inner_size(LogicalSize::new(4 * WIDTH as u32, 4 * HEIGHT as u32))
+		.build(&event_loop)
+		.expect("unable to create window");
+	let size = window.inner_size();
+	let mut pixels = Pixels::new(
+		WIDTH as u32,
+		HEIGHT as u32,
+		SurfaceTexture::new(size.width, size.height, &window),
+	)
+	.expect("unable to create a surface");
+
+	let mut held: Vec<Button> = Vec::new();
+	let mut next_frame = Instant::now();
+	event_loop.run(move |event, _, control_flow| match event {
+		Event::WindowEvent { event, .. } => match event {
+			WindowEvent::CloseRequested => control_flow.set_exit(),
+			WindowEvent::Resized(size) => {
+				let _ = pixels.resize_surface(size.width, size.height);
+			}
+			WindowEvent::KeyboardInput {
+				input: KeyboardInput {
+					virtual_keycode: Some(key),
+					state,
+					..
+				},
+				..
+			} => {
+				if let Some(button) = button(key) {
+					held.retain(|&b| b != button);
+					if state == ElementState::Pressed {
+						held.push(button);
+					}
+				}
+			}
+			_ => {}
+		},
+		Event::MainEventsCleared => {
+			if Instant::now() >= next_frame {
+				gameboy.set_input(held.clone());
+				gameboy.run_frame();
+				print!("{}", String::from_utf8_lossy(&gameboy.take_serial_output()));
+				// a host that can't keep up runs behind instead of catching up in a burst
+				next_frame = (next_frame + FRAME_TIME).max(Instant::now());
+				window.request_redraw();
+			}
+			control_flow.set_wait_until(next_frame);
+		}
+		Event::RedrawRequested(_) => {
+			let frame = gameboy.get_frame_buffer();
+			for (rgba, pixel) in pixels.frame_mut().chunks_exact_mut(4).zip(frame) {
+				let [_, r, g, b] = pixel.to_be_bytes();
+				rgba.copy_from_slice(&[r, g, b, 0xFF]);
+			}
+			if let Err(e) = pixels.render() {
+				eprintln!("unable to render: {}", e);
+				control_flow.set_exit();
+			}
+		}
+		_ => {}
+	});
+}
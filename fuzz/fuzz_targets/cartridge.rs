@@ -0,0 +1,39 @@
+// Loads an arbitrary rom & pokes the whole address space, any panic is a finding. Exercises the
+// header driven mapper setup & banking much more directly than running a rom.
+//
+//   cargo +nightly fuzz run cartridge
+//
+// Input layout: a u16 (little endian) rom size, clamped to 0x150-0xFFFF, followed by the rom
+// (zero padded to that size) & then memory accesses of 4 bytes each: read (even) or write (odd),
+// u16 address (little endian) & the value written. Crashes found are reduced into
+// tests/fixtures, which tests/cartridge.rs replays.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustboy::gameboy::GameBoy;
+
+const MAX_ACCESSES: usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+	let Some((size, data)) = data.split_first_chunk::<2>() else {
+		return;
+	};
+	let size = (u16::from_le_bytes(*size) as usize).max(0x150);
+	let (rom, accesses) = data.split_at(data.len().min(size));
+	let mut rom = rom.to_vec();
+	rom.resize(size, 0x00);
+
+	let mut gameboy = GameBoy::from_bytes(rom);
+	accesses
+		.chunks_exact(4)
+		.take(MAX_ACCESSES)
+		.for_each(|access| {
+			let address = u16::from_le_bytes([access[1], access[2]]);
+			match access[0] & 0x01 {
+				0 => {
+					gameboy.read_byte(address);
+				}
+				_ => gameboy.write_byte(address, access[3]),
+			}
+		});
+});
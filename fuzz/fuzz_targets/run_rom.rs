@@ -0,0 +1,42 @@
+// Runs arbitrary roms with arbitrary input for a few frames, any panic is a finding.
+//
+//   cargo install cargo-fuzz
+//   cargo +nightly fuzz run run_rom
+//
+// Input layout: the first 16 bytes are the buttons held on each frame (one bit per Button, in
+// Button order), the rest is the rom, zero padded to 32 KiB.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustboy::{
+	gameboy::{GameBoy, RunOutcome},
+	joypad::Button,
+	options::EmulatorOptions,
+};
+
+const FRAMES: usize = 16;
+
+fuzz_target!(|data: &[u8]| {
+	let (inputs, rom) = data.split_at(data.len().min(FRAMES));
+	let mut rom = rom.to_vec();
+	if rom.len() < 0x8000 {
+		rom.resize(0x8000, 0x00);
+	}
+
+	let options = EmulatorOptions {
+		stall_frames: 2,
+		..EmulatorOptions::default()
+	};
+	let mut gameboy = GameBoy::new(rom.into(), None, &options);
+	for held in inputs.iter().chain([0x00].iter().cycle()).take(FRAMES) {
+		let pressed = Button::values()
+			.into_iter()
+			.filter(|&button| button != Button::UNKNOWN && (held >> button as u8) & 0x01 == 0x01)
+			.collect();
+		gameboy.set_input(pressed);
+		// a locked up rom is a valid outcome
+		if let RunOutcome::Stalled(_) = gameboy.run_frame() {
+			break;
+		}
+	}
+});
@@ -1,4 +1,10 @@
-use std::ops::{BitAnd, BitOr, Rem, Shl};
+use crate::{compat, header, save::RtcState, utils::is_bit_set};
+use log::{info, warn};
+use std::{
+	ops::{BitAnd, BitOr},
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH},
+};
 
 const ROM_SIZE_MAP: [(u8, u16); 12] = [
 	(0x00, 2),   //    32 KiB
@@ -23,8 +29,24 @@ const RAM_SIZE_MAP: [(u8, u8); 6] = [
 	(0x05, 32), //  64 KiB
 ];
 
-pub trait Cartridge {
-	fn new(_: Vec<u8>) -> Box<dyn Cartridge>
+// Bank registers of the mapper, fields not used by a mapper stay at their default values
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BankingState {
+	pub rom_bank: u16,
+	pub ram_bank: u8,
+	pub ram_enable: bool,
+	pub banking_mode: bool,
+}
+
+impl Clone for Box<dyn Cartridge> {
+	fn clone(&self) -> Self {
+		self.box_clone()
+	}
+}
+
+pub trait Cartridge: Send {
+	#[allow(clippy::new_ret_no_self)]
+	fn new(_: Arc<[u8]>) -> Box<dyn Cartridge>
 	where
 		Self: Sized;
 
@@ -32,38 +54,98 @@ pub trait Cartridge {
 
 	fn write_byte(&mut self, _: u16, _: u8);
 
+	// complete copy incl. RAM & banking registers, rom data is shared
+	fn box_clone(&self) -> Box<dyn Cartridge>;
+
+	fn banking_state(&self) -> BankingState {
+		BankingState::default()
+	}
+
+	fn set_banking_state(&mut self, _: BankingState) {}
+
+	// true if 0xA000-0xBFFF is backed by enabled RAM, otherwise nothing drives the bus there &
+	// reads return 0xFF
+	fn ram_accessible(&self) -> bool {
+		false
+	}
+
+	// bytes of cartridge RAM, 0 without any
+	fn ram_size(&self) -> usize {
+		self.ram().len()
+	}
+
+	// all of cartridge RAM, every bank
+	fn ram(&self) -> &[u8] {
+		&[]
+	}
+
+	// `data` copied over the start of cartridge RAM, as far as both go
+	fn load_ram(&mut self, _: &[u8]) {}
+
+	// Clock registers of cartridges with a RTC, the timestamp is left to whoever saves them
+	fn rtc(&self) -> Option<RtcState> {
+		None
+	}
+
+	// clock registers from a save, then run for `elapsed_seconds`
+	fn load_rtc(&mut self, _: &RtcState, _: u64) {}
+
+	// true if writing to `address` (0x0000-0x7FFF) reaches a mapper register on hardware
+	fn is_register(&self, _: u16) -> bool {
+		true
+	}
+
 	fn get_title(&self) -> String {
 		(0x0134..0x0144)
 			.map(|a| self.read_byte(a))
 			.take_while(|&u| u != 0 && u.is_ascii())
-			.map(|u| char::from(u))
+			.map(char::from)
 			.collect::<String>()
 	}
 
 	fn get_total_rom_banks(&self) -> u16 {
-		ROM_SIZE_MAP[ROM_SIZE_MAP
-			.binary_search_by_key(&self.read_byte(0x0148), |&(a, _)| a)
-			.expect("game not supported")]
-		.1
+		declared_rom_banks(self.read_byte(0x0148))
 	}
 
+	// 2 KiB banks as declared by 0x0149, 8 KiB if the type byte says there is RAM but 0x0149
+	// declares none (see check_ram_header)
 	fn get_total_ram_banks(&self) -> u8 {
-		RAM_SIZE_MAP[RAM_SIZE_MAP
+		let declared = RAM_SIZE_MAP
 			.binary_search_by_key(&self.read_byte(0x0149), |&(a, _)| a)
-			.expect("game not suppoted")]
-		.1
+			.map_or(0, |i| RAM_SIZE_MAP[i].1);
+		match (
+			RAM_CARTRIDGE_TYPES.contains(&self.read_byte(0x0147)),
+			declared,
+		) {
+			(true, 0) => 4,
+			_ => declared,
+		}
 	}
 }
 
+// 16 KiB banks as declared by the 0x0148 size byte, 32 KiB for unknown size bytes. Mappers wrap
+// bank numbers around the actual rom, so a header claiming more than the file holds can't index
+// past it.
+fn declared_rom_banks(size_byte: u8) -> u16 {
+	ROM_SIZE_MAP
+		.binary_search_by_key(&size_byte, |&(a, _)| a)
+		.map_or(2, |i| ROM_SIZE_MAP[i].1)
+}
+
+#[derive(Clone)]
 struct RomOnly {
-	rom_data: Vec<u8>,
+	rom_data: Arc<[u8]>,
 }
 
 impl Cartridge for RomOnly {
-	fn new(data: Vec<u8>) -> Box<dyn Cartridge> {
+	fn new(data: Arc<[u8]>) -> Box<dyn Cartridge> {
 		Box::new(RomOnly { rom_data: data })
 	}
 
+	fn box_clone(&self) -> Box<dyn Cartridge> {
+		Box::new(self.clone())
+	}
+
 	fn read_byte(&self, address: u16) -> u8 {
 		match address {
 			0x0000..0x8000 => self.rom_data[address as usize],
@@ -73,6 +155,10 @@ impl Cartridge for RomOnly {
 	}
 
 	fn write_byte(&mut self, _: u16, _: u8) {}
+
+	fn is_register(&self, _: u16) -> bool {
+		false
+	}
 }
 
 // MBC1 Registers:
@@ -80,18 +166,35 @@ impl Cartridge for RomOnly {
 // - 2000-3FFF: 5 bits of ROM Bank Number
 // - 4000-5FFF: RAM Bank Number / upper 2 bits of ROM Bank Number
 // - 6000-7FFF: Banking Mode
-#[allow(dead_code)]
+#[derive(Clone)]
 struct MBC1 {
 	banking_mode: bool,
 	ram_enable: bool,
 	ram_bank_register: u8,
 	rom_bank_register: u8,
 	ram_data: Vec<u8>,
-	rom_data: Vec<u8>,
+	rom_data: Arc<[u8]>,
+}
+
+impl MBC1 {
+	// from the rom itself, reading 0x0148 through read_byte would bank (& recurse) in mode 1
+	fn rom_banks(&self) -> u16 {
+		declared_rom_banks(self.rom_data[0x0148])
+	}
+
+	// RAM bank register only selects a bank on 32 KiB carts in mode 1, smaller RAM is mirrored
+	// over 0xA000-0xBFFF (2 KiB carts every 0x800 bytes) & the register is ignored
+	fn ram_offset(&self, address: u16) -> usize {
+		let ram_bank_number = match self.banking_mode {
+			true => self.ram_bank_register.bitand(0x03) as usize,
+			false => 0,
+		};
+		(0x2000 * ram_bank_number + (address as usize - 0xA000)) % self.ram_data.len()
+	}
 }
 
 impl Cartridge for MBC1 {
-	fn new(data: Vec<u8>) -> Box<dyn Cartridge> {
+	fn new(data: Arc<[u8]>) -> Box<dyn Cartridge> {
 		let mut c = Box::new(MBC1 {
 			banking_mode: false,
 			ram_enable: false,
@@ -104,39 +207,37 @@ impl Cartridge for MBC1 {
 		c
 	}
 
+	fn box_clone(&self) -> Box<dyn Cartridge> {
+		Box::new(self.clone())
+	}
+
 	fn read_byte(&self, address: u16) -> u8 {
 		match address {
 			0x0000..0x4000 => match self.banking_mode {
 				false => self.rom_data[address as usize],
 				true => {
-					let rom_bank_number = match self.get_total_rom_banks() {
+					let rom_bank_number = match self.rom_banks() {
 						0..=32 => 0,
 						_ => self
 							.rom_bank_register
 							.bitand(0x0F)
-							.bitor(self.ram_bank_register.bitand(0x03).shl(4) as u8),
+							.bitor((self.ram_bank_register & 0x03) << 4),
 					} as usize;
-					self.rom_data[0x4000 * rom_bank_number + address as usize]
+					self.rom_data[(0x4000 * rom_bank_number + address as usize) % self.rom_data.len()]
 				}
 			},
 			0x4000..0x8000 => {
 				let rom_bank_number = match self.rom_bank_register {
 					0x00 => 0x01,
-					val => val.bitand((self.get_total_rom_banks().min(32) - 1) as u8),
-				} | match self.get_total_rom_banks() {
+					val => val.bitand((self.rom_banks().min(32) - 1) as u8),
+				} | match self.rom_banks() {
 					0..=32 => 0x00,
-					_ => self.ram_bank_register.bitand(0x03).shl(4) as u8,
-				};
-				self.rom_data[0x4000 * rom_bank_number as usize + address as usize - 0x4000]
-			}
-			0xA000..0xC000 if self.ram_enable => {
-				let ram_bank_size = 0x2000.min(0x0800 * self.get_total_ram_banks() as usize);
-				let ram_bank_number = match self.banking_mode {
-					true => self.ram_bank_register.bitand(0x03).shl(2) as usize,
-					false => 0,
+					_ => (self.ram_bank_register & 0x03) << 4,
 				};
-				self.ram_data[0x0800 * ram_bank_number + (address as usize - 0xA000).rem(ram_bank_size)]
+				self.rom_data
+					[(0x4000 * rom_bank_number as usize + address as usize - 0x4000) % self.rom_data.len()]
 			}
+			0xA000..0xC000 if self.ram_accessible() => self.ram_data[self.ram_offset(address)],
 			0xA000..0xC000 => 0xFF,
 			_ => unreachable!(),
 		}
@@ -149,64 +250,235 @@ impl Cartridge for MBC1 {
 			0x4000..0x6000 => self.ram_bank_register = value,
 			0x6000..0x8000 => self.banking_mode = value & 0x01 == 0x01,
 			0xA000..0xC000 => {
-				if !self.ram_enable {
+				if !self.ram_accessible() {
 					return;
 				}
-				let ram_bank_size = 0x2000.min(0x0800 * self.get_total_ram_banks() as usize);
-				let ram_bank_number = match self.banking_mode {
-					true => self.ram_bank_register.bitand(0x03).shl(2) as usize,
-					false => 0,
-				};
-				self.ram_data[0x0800 * ram_bank_number + (address as usize - 0xA000).rem(ram_bank_size)] =
-					value;
+				let offset = self.ram_offset(address);
+				self.ram_data[offset] = value;
 			}
 			_ => unreachable!(),
 		}
 	}
+
+	fn banking_state(&self) -> BankingState {
+		BankingState {
+			rom_bank: self.rom_bank_register as u16,
+			ram_bank: self.ram_bank_register,
+			ram_enable: self.ram_enable,
+			banking_mode: self.banking_mode,
+		}
+	}
+
+	fn set_banking_state(&mut self, state: BankingState) {
+		self.rom_bank_register = state.rom_bank as u8;
+		self.ram_bank_register = state.ram_bank;
+		self.ram_enable = state.ram_enable;
+		self.banking_mode = state.banking_mode;
+	}
+
+	fn ram_accessible(&self) -> bool {
+		self.ram_enable && !self.ram_data.is_empty()
+	}
+
+	fn ram(&self) -> &[u8] {
+		&self.ram_data
+	}
+
+	fn load_ram(&mut self, data: &[u8]) {
+		let size = data.len().min(self.ram_data.len());
+		self.ram_data[..size].copy_from_slice(&data[..size]);
+	}
 }
 
 // MBC3 Registers:
-// - 0000-1FFF: RAM Enable
+// - 0000-1FFF: RAM & RTC Enable
 // - 2000-3FFF: 7 bits of ROM Bank Number
-// - 4000-5FFF: RAM Bank Number
+// - 4000-5FFF: RAM Bank Number (0x00-0x03) or RTC Register (0x08-0x0C) mapped at A000-BFFF
+// - 6000-7FFF: Latch Clock Data, writing 0x00 then 0x01 copies the clock to what reads return
+#[derive(Clone)]
 struct MBC3 {
 	ram_enable: bool,
 	ram_bank_register: u8,
 	rom_bank_register: u8,
 	ram_data: Vec<u8>,
-	rom_data: Vec<u8>,
+	rom_data: Arc<[u8]>,
+	// only on MBC3+TIMER cartridges
+	rtc: Option<Rtc>,
+}
+
+fn unix_millis() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_millis() as u64)
+}
+
+// MBC3 real time clock, registers in order S, M, H, DL, DH where DH holds bit 8 of the day
+// counter (bit 0), halt (bit 6) & day counter carry (bit 7). Like the crystal on the cartridge it
+// runs on wall time, whatever the emulator is doing: registers are brought up to date with the
+// time passed since the last sync whenever they get latched, written or saved.
+#[derive(Clone)]
+struct Rtc {
+	registers: [u8; 5],
+	latched: [u8; 5],
+	// last write to 0x6000-0x7FFF was 0x00, so 0x01 latches
+	latch_armed: bool,
+	// wall time (ms since the epoch) the registers are up to date with
+	synced_at: u64,
+	// ms into the current second
+	millis: u64,
+}
+
+impl Rtc {
+	// bits that exist, per register
+	const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+	fn is_halted(&self) -> bool {
+		is_bit_set(self.registers[4], 6)
+	}
+
+	fn day(&self) -> u16 {
+		(self.registers[4] as u16 & 0x01) << 8 | self.registers[3] as u16
+	}
+
+	fn set_day(&mut self, day: u16) {
+		self.registers[3] = day as u8;
+		self.registers[4] = (self.registers[4] & 0xFE) | (day >> 8) as u8;
+	}
+
+	fn new(now: u64) -> Self {
+		Rtc {
+			registers: [0; 5],
+			latched: [0; 5],
+			latch_armed: false,
+			synced_at: now,
+			millis: 0,
+		}
+	}
+
+	// catches up with the wall time `now`, a halted clock just doesn't count the time passed
+	fn sync(&mut self, now: u64) {
+		let elapsed = now.saturating_sub(self.synced_at);
+		self.synced_at = self.synced_at.max(now);
+		if self.is_halted() {
+			return;
+		}
+		let millis = self.millis + elapsed;
+		self.millis = millis % 1000;
+		self.advance(millis / 1000);
+	}
+
+	// Out of range values (written by the game) count up to what their bits hold & wrap to 0
+	// without carrying into the next register
+	fn tick_second(&mut self) {
+		let step = |value: u8, limit: u8, mask: u8| match value + 1 == limit {
+			true => (0, true),
+			false => ((value + 1) & mask, false),
+		};
+		let [s, m, h, ..] = self.registers;
+		let (s, carry) = step(s, 60, 0x3F);
+		self.registers[0] = s;
+		if !carry {
+			return;
+		}
+		let (m, carry) = step(m, 60, 0x3F);
+		self.registers[1] = m;
+		if !carry {
+			return;
+		}
+		let (h, carry) = step(h, 24, 0x1F);
+		self.registers[2] = h;
+		if !carry {
+			return;
+		}
+		match self.day() {
+			0x1FF => {
+				self.set_day(0);
+				self.registers[4] |= 0x80;
+			}
+			day => self.set_day(day + 1),
+		}
+	}
+
+	// `seconds` at once, e.g. while the emulator wasn't running
+	fn advance(&mut self, mut seconds: u64) {
+		if self.is_halted() {
+			return;
+		}
+		// a second at a time until every register is in range, then all the rest in one go
+		while seconds > 0
+			&& !(self.registers[0] < 60 && self.registers[1] < 60 && self.registers[2] < 24)
+		{
+			self.tick_second();
+			seconds -= 1;
+		}
+		let [s, m, h, ..] = self.registers.map(|r| r as u64);
+		let total = self.day() as u64 * 86400 + h * 3600 + m * 60 + s + seconds;
+		let days = total / 86400;
+		if days > 0x1FF {
+			self.registers[4] |= 0x80;
+		}
+		self.set_day((days % 0x200) as u16);
+		self.registers[2] = (total % 86400 / 3600) as u8;
+		self.registers[1] = (total % 3600 / 60) as u8;
+		self.registers[0] = (total % 60) as u8;
+	}
+
+	fn write(&mut self, register: usize, value: u8, now: u64) {
+		self.sync(now);
+		if register == 0 {
+			self.millis = 0;
+		}
+		self.registers[register] = value & Self::MASKS[register];
+	}
+
+	fn write_latch(&mut self, value: u8, now: u64) {
+		if self.latch_armed && value == 0x01 {
+			self.sync(now);
+			self.latched = self.registers;
+		}
+		self.latch_armed = value == 0x00;
+	}
+}
+
+impl MBC3 {
+	fn ram_offset(&self, address: u16) -> usize {
+		let ram_bank_number = self.ram_bank_register.bitand(0x03) as usize;
+		(0x2000 * ram_bank_number + (address as usize - 0xA000)) % self.ram_data.len()
+	}
 }
 
 impl Cartridge for MBC3 {
-	fn new(data: Vec<u8>) -> Box<dyn Cartridge> {
+	fn new(data: Arc<[u8]>) -> Box<dyn Cartridge> {
 		let mut c = Box::new(MBC3 {
 			ram_enable: false,
 			ram_bank_register: 0x00,
 			rom_bank_register: 0x00,
 			ram_data: vec![0; 0],
+			rtc: matches!(data[0x0147], 0x0F | 0x10).then(|| Rtc::new(unix_millis())),
 			rom_data: data,
 		});
 		c.ram_data = vec![0; 0x0800 * c.get_total_ram_banks() as usize];
 		c
 	}
 
+	fn box_clone(&self) -> Box<dyn Cartridge> {
+		Box::new(self.clone())
+	}
+
 	fn read_byte(&self, address: u16) -> u8 {
 		match address {
 			0x0000..0x4000 => self.rom_data[address as usize],
 			0x4000..0x8000 => {
-				let rom_bank_number = match self.rom_bank_register.bitand(0x07) {
+				let rom_bank_number = match self.rom_bank_register.bitand(0x7F) {
 					0x00 => 0x01,
 					val => val,
 				} as usize;
-				self.rom_data[0x4000 * rom_bank_number + address as usize - 0x4000]
-			}
-			0xA000..0xC000 if self.ram_enable => {
-				let ram_bank_number = match self.ram_bank_register.bitand(0x0F) {
-					val if val < 0x04 => val,
-					_ => unimplemented!("Real Time Clock!"),
-				} as usize;
-				self.ram_data[0x2000 * ram_bank_number + address as usize - 0xA000]
+				self.rom_data[(0x4000 * rom_bank_number + address as usize - 0x4000) % self.rom_data.len()]
 			}
+			0xA000..0xC000 if self.ram_accessible() => match (self.ram_bank_register & 0x0F, &self.rtc) {
+				(register @ 0x08..=0x0C, Some(rtc)) => rtc.latched[register as usize - 0x08],
+				_ => self.ram_data[self.ram_offset(address)],
+			},
 			0xA000..0xC000 => 0xFF,
 			_ => unreachable!(),
 		}
@@ -217,20 +489,194 @@ impl Cartridge for MBC3 {
 			0x0000..0x2000 => self.ram_enable = (value & 0x0F) == 0x0A,
 			0x2000..0x4000 => self.rom_bank_register = value,
 			0x4000..0x6000 => self.ram_bank_register = value,
-			0x6000..0x8000 => (),
+			0x6000..0x8000 => {
+				if let Some(rtc) = &mut self.rtc {
+					rtc.write_latch(value, unix_millis());
+				}
+			}
 			0xA000..0xC000 => {
-				if !self.ram_enable {
+				if !self.ram_accessible() {
 					return;
 				}
-				let ram_bank_number = match self.ram_bank_register.bitand(0x0F) {
-					val if val < 0x04 => val,
-					_ => unimplemented!("Real Time Clock!"),
+				match (self.ram_bank_register & 0x0F, &mut self.rtc) {
+					(register @ 0x08..=0x0C, Some(rtc)) => {
+						rtc.write(register as usize - 0x08, value, unix_millis())
+					}
+					_ => {
+						let offset = self.ram_offset(address);
+						self.ram_data[offset] = value;
+					}
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn banking_state(&self) -> BankingState {
+		BankingState {
+			rom_bank: self.rom_bank_register as u16,
+			ram_bank: self.ram_bank_register,
+			ram_enable: self.ram_enable,
+			banking_mode: false,
+		}
+	}
+
+	fn set_banking_state(&mut self, state: BankingState) {
+		self.rom_bank_register = state.rom_bank as u8;
+		self.ram_bank_register = state.ram_bank;
+		self.ram_enable = state.ram_enable;
+	}
+
+	// banks 0x04-0x07 & beyond 0x0C have nothing behind them
+	fn ram_accessible(&self) -> bool {
+		self.ram_enable
+			&& match self.ram_bank_register & 0x0F {
+				0x00..=0x03 => !self.ram_data.is_empty(),
+				0x08..=0x0C => self.rtc.is_some(),
+				_ => false,
+			}
+	}
+
+	fn ram(&self) -> &[u8] {
+		&self.ram_data
+	}
+
+	fn load_ram(&mut self, data: &[u8]) {
+		let size = data.len().min(self.ram_data.len());
+		self.ram_data[..size].copy_from_slice(&data[..size]);
+	}
+
+	fn rtc(&self) -> Option<RtcState> {
+		self.rtc.as_ref().map(|rtc| {
+			let mut rtc = rtc.clone();
+			rtc.sync(unix_millis());
+			RtcState {
+				current: rtc.registers,
+				latched: rtc.latched,
+				timestamp: 0,
+			}
+		})
+	}
+
+	fn load_rtc(&mut self, state: &RtcState, elapsed_seconds: u64) {
+		if let Some(rtc) = &mut self.rtc {
+			*rtc = Rtc::new(unix_millis());
+			rtc.registers = state.current;
+			rtc.latched = state.latched;
+			rtc
+				.registers
+				.iter_mut()
+				.zip(Rtc::MASKS)
+				.for_each(|(r, mask)| *r &= mask);
+			rtc.advance(elapsed_seconds);
+		}
+	}
+}
+
+// HuC1 Registers:
+// - 0000-1FFF: RAM/IR Select, 0x0E maps the IR port at A000-BFFF instead of RAM
+// - 2000-3FFF: 6 bits of ROM Bank Number
+// - 4000-5FFF: RAM Bank Number
+// RAM needs no enabling. Nothing ever shines on the IR sensor, so the port reads 0xC0 (no light)
+// & the LED writes are dropped.
+#[derive(Clone)]
+struct HuC1 {
+	ir_mode: bool,
+	ram_bank_register: u8,
+	rom_bank_register: u8,
+	ram_data: Vec<u8>,
+	rom_data: Arc<[u8]>,
+}
+
+impl HuC1 {
+	fn ram_offset(&self, address: u16) -> usize {
+		let ram_bank_number = self.ram_bank_register.bitand(0x03) as usize;
+		(0x2000 * ram_bank_number + (address as usize - 0xA000)) % self.ram_data.len()
+	}
+}
+
+impl Cartridge for HuC1 {
+	fn new(data: Arc<[u8]>) -> Box<dyn Cartridge> {
+		let mut c = Box::new(HuC1 {
+			ir_mode: false,
+			ram_bank_register: 0x00,
+			rom_bank_register: 0x00,
+			ram_data: vec![0; 0],
+			rom_data: data,
+		});
+		c.ram_data = vec![0; 0x0800 * c.get_total_ram_banks() as usize];
+		c
+	}
+
+	fn box_clone(&self) -> Box<dyn Cartridge> {
+		Box::new(self.clone())
+	}
+
+	fn read_byte(&self, address: u16) -> u8 {
+		match address {
+			0x0000..0x4000 => self.rom_data[address as usize],
+			0x4000..0x8000 => {
+				let rom_bank_number = match self.rom_bank_register.bitand(0x3F) {
+					0x00 => 0x01,
+					val => val,
 				} as usize;
-				self.ram_data[0x2000 * ram_bank_number + address as usize - 0xA000] = value;
+				self.rom_data[(0x4000 * rom_bank_number + address as usize - 0x4000) % self.rom_data.len()]
 			}
+			0xA000..0xC000 if self.ir_mode => 0xC0,
+			0xA000..0xC000 if self.ram_accessible() => self.ram_data[self.ram_offset(address)],
+			0xA000..0xC000 => 0xFF,
 			_ => unreachable!(),
 		}
 	}
+
+	fn write_byte(&mut self, address: u16, value: u8) {
+		match address {
+			0x0000..0x2000 => self.ir_mode = value == 0x0E,
+			0x2000..0x4000 => self.rom_bank_register = value,
+			0x4000..0x6000 => self.ram_bank_register = value,
+			0x6000..0x8000 => (),
+			0xA000..0xC000 => {
+				if self.ram_accessible() {
+					let offset = self.ram_offset(address);
+					self.ram_data[offset] = value;
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn banking_state(&self) -> BankingState {
+		BankingState {
+			rom_bank: self.rom_bank_register as u16,
+			ram_bank: self.ram_bank_register,
+			ram_enable: !self.ir_mode,
+			banking_mode: false,
+		}
+	}
+
+	fn set_banking_state(&mut self, state: BankingState) {
+		self.rom_bank_register = state.rom_bank as u8;
+		self.ram_bank_register = state.ram_bank;
+		self.ir_mode = !state.ram_enable;
+	}
+
+	fn ram_accessible(&self) -> bool {
+		!self.ir_mode && !self.ram_data.is_empty()
+	}
+
+	fn ram(&self) -> &[u8] {
+		&self.ram_data
+	}
+
+	fn load_ram(&mut self, data: &[u8]) {
+		let size = data.len().min(self.ram_data.len());
+		self.ram_data[..size].copy_from_slice(&data[..size]);
+	}
+
+	// 0x6000-0x7FFF has no register
+	fn is_register(&self, address: u16) -> bool {
+		address < 0x6000
+	}
 }
 
 // MBC5 Registers:
@@ -238,17 +684,26 @@ impl Cartridge for MBC3 {
 // - 2000-2FFF: 8 bits of ROM Bank Number
 // - 3000-3FFF: 9th bit of ROM Bank Number
 // - 4000-5FFF: RAM Bank Number
+#[derive(Clone)]
 struct MBC5 {
 	ram_enable: bool,
 	ram_bank_register: u8,
 	rom_bank_register_lo: u8,
 	rom_bank_register_hi: u8,
 	ram_data: Vec<u8>,
-	rom_data: Vec<u8>,
+	rom_data: Arc<[u8]>,
+}
+
+impl MBC5 {
+	// banks past the allocated RAM wrap around it
+	fn ram_offset(&self, address: u16) -> usize {
+		let ram_bank_number = self.ram_bank_register.bitand(0x0F) as usize;
+		(0x2000 * ram_bank_number + (address as usize - 0xA000)) % self.ram_data.len()
+	}
 }
 
 impl Cartridge for MBC5 {
-	fn new(data: Vec<u8>) -> Box<dyn Cartridge> {
+	fn new(data: Arc<[u8]>) -> Box<dyn Cartridge> {
 		let mut c = Box::new(MBC5 {
 			ram_enable: false,
 			ram_bank_register: 0x00,
@@ -261,6 +716,10 @@ impl Cartridge for MBC5 {
 		c
 	}
 
+	fn box_clone(&self) -> Box<dyn Cartridge> {
+		Box::new(self.clone())
+	}
+
 	fn read_byte(&self, address: u16) -> u8 {
 		match address {
 			0x0000..0x4000 => self.rom_data[address as usize],
@@ -268,12 +727,9 @@ impl Cartridge for MBC5 {
 				let rom_bank_number =
 					u16::from_be_bytes([self.rom_bank_register_hi, self.rom_bank_register_lo]).bitand(0x01FF)
 						as usize;
-				self.rom_data[0x4000 * rom_bank_number + address as usize - 0x4000]
-			}
-			0xA000..0xC000 if self.ram_enable => {
-				let ram_bank_number = self.ram_bank_register.bitand(0x0F) as usize;
-				self.ram_data[0x2000 * ram_bank_number + address as usize - 0xA000]
+				self.rom_data[(0x4000 * rom_bank_number + address as usize - 0x4000) % self.rom_data.len()]
 			}
+			0xA000..0xC000 if self.ram_accessible() => self.ram_data[self.ram_offset(address)],
 			0xA000..0xC000 => 0xFF,
 			_ => unreachable!(),
 		}
@@ -287,29 +743,355 @@ impl Cartridge for MBC5 {
 			0x4000..0x6000 => self.ram_bank_register = value,
 			0x6000..0x8000 => (),
 			0xA000..0xC000 => {
-				if !self.ram_enable {
+				if !self.ram_accessible() {
 					return;
 				}
-				let ram_bank_number = self.ram_bank_register.bitand(0x0F) as usize;
-				self.ram_data[0x2000 * ram_bank_number + address as usize - 0xA000] = value;
+				let offset = self.ram_offset(address);
+				self.ram_data[offset] = value;
 			}
 			_ => unreachable!(),
 		}
 	}
+
+	fn banking_state(&self) -> BankingState {
+		BankingState {
+			rom_bank: u16::from_be_bytes([self.rom_bank_register_hi, self.rom_bank_register_lo]),
+			ram_bank: self.ram_bank_register,
+			ram_enable: self.ram_enable,
+			banking_mode: false,
+		}
+	}
+
+	fn set_banking_state(&mut self, state: BankingState) {
+		[self.rom_bank_register_hi, self.rom_bank_register_lo] = state.rom_bank.to_be_bytes();
+		self.ram_bank_register = state.ram_bank;
+		self.ram_enable = state.ram_enable;
+	}
+
+	fn ram_accessible(&self) -> bool {
+		self.ram_enable && !self.ram_data.is_empty()
+	}
+
+	fn ram(&self) -> &[u8] {
+		&self.ram_data
+	}
+
+	fn load_ram(&mut self, data: &[u8]) {
+		let size = data.len().min(self.ram_data.len());
+		self.ram_data[..size].copy_from_slice(&data[..size]);
+	}
+
+	// 0x6000-0x7FFF has no register
+	fn is_register(&self, address: u16) -> bool {
+		address < 0x6000
+	}
+}
+
+// cartridge types (0x0147) with external RAM
+const RAM_CARTRIDGE_TYPES: [u8; 15] = [
+	0x02, 0x03, 0x08, 0x09, 0x0C, 0x0D, 0x10, 0x12, 0x13, 0x1A, 0x1B, 0x1D, 0x1E, 0x22, 0xFF,
+];
+
+// Mislabelled headers are common in hacks & homebrew. Whichever byte claims RAM wins, so RAM is
+// the declared size or 8 KiB when the type byte says there is RAM but none is declared, and it's
+// persisted only when the type byte says there is a battery (header::has_battery).
+fn check_ram_header(data: &[u8]) {
+	let (cartridge_type, ram_size) = (data[0x0147], data[0x0149]);
+	match (RAM_CARTRIDGE_TYPES.contains(&cartridge_type), ram_size) {
+		(_, 0x06..) => warn!("unknown RAM size byte {:02X}, treating it as 0", ram_size),
+		(true, 0x00) => warn!(
+			"cartridge type {:02X} has RAM but RAM size byte is 0, using 8 KiB",
+			cartridge_type
+		),
+		(false, 0x01..) => warn!(
+			"cartridge type {:02X} has no RAM but RAM size byte is {:02X}, using it anyway",
+			cartridge_type, ram_size
+		),
+		_ => {}
+	};
 }
 
-pub fn create(data: Vec<u8>) -> Box<dyn Cartridge> {
+// Rom data is shared, not copied, so one loaded rom can back several cartridges (e.g. on reset).
+// Unsupported mappers run as ROM ONLY (only the first 32 KiB reachable) unless `strict`, in
+// which case they panic.
+// Roms smaller than 32 KiB (hand-written test roms, truncated dumps) are padded with 0x00, which
+// reads as a ROM ONLY header without RAM where the header is missing & as NOPs elsewhere
+fn pad(data: Arc<[u8]>) -> Arc<[u8]> {
+	match data.len() < 0x8000 {
+		true => {
+			warn!("rom is only {} bytes, padding it to 32 KiB", data.len());
+			let mut padded = data.to_vec();
+			padded.resize(0x8000, 0x00);
+			padded.into()
+		}
+		false => data,
+	}
+}
+
+pub fn create(data: Arc<[u8]>, strict: bool) -> Box<dyn Cartridge> {
+	let data = pad(data);
+	// boot rom refuses to start on a mismatch, carts are still run but it's likely a bad dump
+	let checksum = header::header_checksum(&data);
+	if checksum != data[0x014D] {
+		warn!(
+			"header checksum mismatch: expected {:02X}, computed {:02X}",
+			data[0x014D], checksum
+		);
+	}
+
+	check_ram_header(&data);
+	if ROM_SIZE_MAP
+		.binary_search_by_key(&data[0x0148], |&(a, _)| a)
+		.is_err()
+	{
+		warn!(
+			"unknown rom size byte {:02X}, assuming 32 KiB",
+			data[0x0148]
+		);
+	}
+
+	if let Some(game) = compat::lookup(&data) {
+		info!("known issue with {}: {}", game.title, game.note);
+	}
+
 	let c = match data[0x0147] {
 		0x00 => RomOnly::new(data),
-		0x01 | 0x02 | 0x03 => MBC1::new(data),
-		0x11 | 0x12 | 0x13 => MBC3::new(data),
-		0x19 | 0x1A | 0x1B => MBC5::new(data),
-		_ => todo!(),
+		0x01..=0x03 => MBC1::new(data),
+		0x0F..=0x13 => MBC3::new(data),
+		0x19..=0x1B => MBC5::new(data),
+		0xFF => HuC1::new(data),
+		cartridge_type => {
+			let name = header::cartridge_type_name(cartridge_type);
+			if strict {
+				panic!(
+					"unsupported cartridge type {:02X} ({})",
+					cartridge_type, name
+				);
+			}
+			warn!(
+				"unsupported cartridge type {:02X} ({}), running it as ROM ONLY: no banking, only the first 32 KiB are reachable",
+				cartridge_type, name
+			);
+			RomOnly::new(data)
+		}
 	};
 
-	println!("title: {:?}", c.get_title());
-	println!("rom banks: {}", c.get_total_rom_banks());
-	println!("ram banks: {}\n", c.get_total_ram_banks());
+	info!(
+		"title: {:?}, rom banks: {}, ram banks: {}",
+		c.get_title(),
+		c.get_total_rom_banks(),
+		c.get_total_ram_banks()
+	);
 
 	c
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// 32 KiB rom with the given header type & RAM size bytes
+	fn rom(cartridge_type: u8, ram_size: u8) -> Arc<[u8]> {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0147] = cartridge_type;
+		rom[0x0149] = ram_size;
+		rom.into()
+	}
+
+	#[test]
+	fn mbc1_2kib_ram_mirrors_every_0x800_bytes() {
+		let mut c = create(rom(0x03, 0x01), false);
+		assert_eq!(c.ram_size(), 0x0800);
+		c.write_byte(0x0000, 0x0A);
+		c.write_byte(0xA000, 0x42);
+		c.write_byte(0xA801, 0x24);
+		assert_eq!(c.read_byte(0xA800), 0x42);
+		assert_eq!(c.read_byte(0xB800), 0x42);
+		assert_eq!(c.read_byte(0xA001), 0x24);
+	}
+
+	#[test]
+	fn banking_state_round_trip() {
+		// MBC1, MBC3, MBC5 & HuC1, 1 MiB with the bank number at the start of each bank & 32 KiB
+		// of RAM
+		for cartridge_type in [0x03, 0x13, 0x1B, 0xFF] {
+			let mut rom = vec![0; 0x100000];
+			(0..64).for_each(|bank| rom[bank * 0x4000] = bank as u8);
+			rom[0x0147] = cartridge_type;
+			rom[0x0148] = 0x05;
+			rom[0x0149] = 0x03;
+			let rom: Arc<[u8]> = rom.into();
+			let mut c = create(rom.clone(), false);
+			[
+				(0x0000, 0x0A),
+				(0x2000, 0x05),
+				(0x3000, 0x01),
+				(0x4000, 0x02),
+				(0x6000, 0x01),
+			]
+			.into_iter()
+			.for_each(|(address, value)| c.write_byte(address, value));
+			c.write_byte(0xA000, 0x42);
+			let state = c.banking_state();
+			assert_ne!(state, BankingState::default());
+
+			let mut restored = create(rom, false);
+			restored.load_ram(c.ram());
+			restored.set_banking_state(state);
+			assert_eq!(restored.banking_state(), state);
+			for address in [0x0000, 0x4000, 0x7FFF, 0xA000] {
+				assert_eq!(restored.read_byte(address), c.read_byte(address));
+			}
+		}
+	}
+
+	// (type with RAM & battery, type without RAM) for MBC1, MBC3 & MBC5
+	const MAPPERS: [(u8, u8); 3] = [(0x03, 0x01), (0x10, 0x11), (0x1B, 0x19)];
+
+	// offset into RAM that a write to 0xA000 + `offset` lands at, RAM bank register 2 selected
+	fn mbc1_ram_offset(ram_size: u8, banking_mode: u8, offset: u16) -> usize {
+		let mut c = create(rom(0x03, ram_size), false);
+		c.write_byte(0x0000, 0x0A);
+		c.write_byte(0x4000, 0x02);
+		c.write_byte(0x6000, banking_mode);
+		c.write_byte(0xA000 + offset, 0x42);
+		assert_eq!(c.ram().iter().filter(|&&b| b == 0x42).count(), 1);
+		c.ram().iter().position(|&b| b == 0x42).unwrap()
+	}
+
+	#[test]
+	fn mbc1_ram_banking_by_size_and_mode() {
+		for banking_mode in [0, 1] {
+			// 2 KiB & 8 KiB ignore the bank register & wrap
+			assert_eq!(mbc1_ram_offset(0x01, banking_mode, 0x1FFF), 0x07FF);
+			assert_eq!(mbc1_ram_offset(0x02, banking_mode, 0x1FFF), 0x1FFF);
+		}
+		// 32 KiB is banked in mode 1 only
+		assert_eq!(mbc1_ram_offset(0x03, 0, 0x0123), 0x0123);
+		assert_eq!(mbc1_ram_offset(0x03, 1, 0x0123), 0x4123);
+	}
+
+	#[test]
+	fn disabled_ram_reads_0xff() {
+		for (with_ram, _) in MAPPERS {
+			let mut c = create(rom(with_ram, 0x02), false);
+			c.write_byte(0xA000, 0x42);
+			assert_eq!(c.read_byte(0xA000), 0xFF);
+			c.write_byte(0x0000, 0x0A);
+			c.write_byte(0xA000, 0x42);
+			assert_eq!(c.read_byte(0xA000), 0x42);
+			c.write_byte(0x0000, 0x00);
+			assert_eq!(c.read_byte(0xA000), 0xFF);
+			c.write_byte(0xA000, 0x24);
+			c.write_byte(0x0000, 0x0A);
+			assert_eq!(c.read_byte(0xA000), 0x42);
+		}
+	}
+
+	#[test]
+	fn ram_header_mismatches() {
+		for (with_ram, without_ram) in MAPPERS {
+			// type says RAM, size byte says none: 8 KiB
+			assert_eq!(create(rom(with_ram, 0x00), false).ram_size(), 0x2000);
+			// both agree: the declared size
+			assert_eq!(create(rom(with_ram, 0x03), false).ram_size(), 0x8000);
+			// type says no RAM, size byte says 8 KiB: the declared size
+			assert_eq!(create(rom(without_ram, 0x02), false).ram_size(), 0x2000);
+			// neither: no RAM
+			assert_eq!(create(rom(without_ram, 0x00), false).ram_size(), 0);
+			// persisted whenever the type byte claims a battery
+			assert!(header::has_battery(with_ram));
+			assert!(!header::has_battery(without_ram));
+		}
+	}
+
+	fn latch(rtc: &mut Rtc, now: u64) {
+		rtc.write_latch(0x00, now);
+		rtc.write_latch(0x01, now);
+	}
+
+	#[test]
+	fn rtc_latch() {
+		let mut rtc = Rtc::new(0);
+		rtc.sync(5500);
+		// time passed, but reads still see the power on latch
+		assert_eq!(rtc.latched, [0; 5]);
+		latch(&mut rtc, 5500);
+		assert_eq!(rtc.latched, [5, 0, 0, 0, 0]);
+		// 0x01 alone doesn't latch
+		rtc.write_latch(0x01, 125_000);
+		assert_eq!(rtc.latched, [5, 0, 0, 0, 0]);
+		latch(&mut rtc, 125_000);
+		assert_eq!(rtc.latched, [5, 2, 0, 0, 0]);
+	}
+
+	#[test]
+	fn rtc_day_rollover_sets_carry() {
+		let mut rtc = Rtc::new(0);
+		rtc.registers = [59, 59, 23, 0xFE, 0x01];
+		rtc.sync(1000);
+		assert_eq!(rtc.registers, [0, 0, 0, 0xFF, 0x01]);
+		rtc.sync(86_401_000);
+		assert_eq!(rtc.registers, [0, 0, 0, 0x00, 0x80]);
+		// the carry sticks until written
+		rtc.sync(2 * 86_400_000 + 1000);
+		assert_eq!(rtc.registers, [0, 0, 0, 0x01, 0x80]);
+		rtc.write(4, 0x00, 2 * 86_400_000 + 1000);
+		assert_eq!(rtc.registers[4], 0x00);
+		// catching up on a long time at once also wraps
+		rtc.sync(600 * 86_400_000);
+		assert_eq!(rtc.registers[4] & 0x81, 0x80);
+	}
+
+	#[test]
+	fn rtc_halt_stops_the_clock() {
+		let mut rtc = Rtc::new(0);
+		rtc.write(4, 0x40, 3000);
+		assert_eq!(rtc.registers[0], 3);
+		rtc.sync(3_600_000);
+		assert_eq!(rtc.registers[0..3], [3, 0, 0]);
+		// counting resumes from the moment halt is cleared, at the start of a second
+		rtc.write(0, 10, 3_600_000);
+		rtc.write(4, 0x00, 3_600_000);
+		rtc.sync(3_602_500);
+		assert_eq!(rtc.registers[0..3], [12, 0, 0]);
+	}
+
+	#[test]
+	fn rtc_survives_a_save_round_trip() {
+		let mut c = create(rom(0x10, 0x03), true);
+		c.write_byte(0x0000, 0x0A);
+		[
+			(0x08, 30),
+			(0x09, 15),
+			(0x0A, 6),
+			(0x0B, 0x2C),
+			(0x0C, 0x01),
+		]
+		.iter()
+		.for_each(|&(register, value)| {
+			c.write_byte(0x4000, register);
+			c.write_byte(0xA000, value);
+		});
+		c.write_byte(0x6000, 0x00);
+		c.write_byte(0x6000, 0x01);
+		c.write_byte(0x4000, 0x08);
+		assert_eq!(c.read_byte(0xA000), 30);
+
+		let rtc = RtcState {
+			timestamp: 1_000_000,
+			..c.rtc().unwrap()
+		};
+		let data = crate::save::join(c.ram(), Some(&rtc));
+		let (ram, loaded) = crate::save::split(&data, c.ram_size());
+		assert_eq!(ram, c.ram());
+		assert_eq!(loaded, Some(rtc));
+
+		// saved 1 day, 1 hour & 90 seconds ago
+		let mut reloaded = create(rom(0x10, 0x03), true);
+		reloaded.load_rtc(&loaded.unwrap(), 86400 + 3600 + 90);
+		let current = reloaded.rtc().unwrap().current;
+		assert_eq!(current, [0, 17, 7, 0x2D, 0x01]);
+		assert_eq!(reloaded.rtc().unwrap().latched, rtc.latched);
+	}
+}
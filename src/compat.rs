@@ -0,0 +1,50 @@
+use crate::{header, options::EmulatorOptions};
+
+// Accuracy option a game is known to need, turned on whenever it's loaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quirk {
+	EarlyLyReset,
+	OpenBus,
+	DmaBlocksOam,
+	SgbStub,
+}
+
+// What's known about a game, matched by header::fingerprint() so a fix or hack of it doesn't
+// match by accident. `note` is shown at load: a known glitch, or what the game needs that
+// can't be turned on for it (e.g. "requires boot ROM", "needs MBC1M").
+#[derive(Clone, Copy, Debug)]
+pub struct Game {
+	pub fingerprint: &'static str,
+	pub title: &'static str,
+	pub note: &'static str,
+	pub quirks: &'static [Quirk],
+}
+
+// Add a game with the fingerprint shown by --info, e.g.
+// Game {
+// 	fingerprint: "0123ABCD",
+// 	title: "EXAMPLE",
+// 	note: "relies on STAT blocking, title screen flickers",
+// 	quirks: &[Quirk::EarlyLyReset],
+// },
+pub const GAMES: &[Game] = &[];
+
+pub fn lookup(rom: &[u8]) -> Option<&'static Game> {
+	let fingerprint = header::fingerprint(rom);
+	GAMES.iter().find(|game| game.fingerprint == fingerprint)
+}
+
+// `options` with the quirks of the game (if known) turned on, ones already on stay on
+pub fn with_quirks(rom: &[u8], options: &EmulatorOptions) -> EmulatorOptions {
+	let mut options = options.clone();
+	lookup(rom)
+		.iter()
+		.flat_map(|game| game.quirks)
+		.for_each(|quirk| match quirk {
+			Quirk::EarlyLyReset => options.early_ly_reset = true,
+			Quirk::OpenBus => options.open_bus = true,
+			Quirk::DmaBlocksOam => options.dma_blocks_oam = true,
+			Quirk::SgbStub => options.sgb_stub = true,
+		});
+	options
+}
@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+
+// Something hardware tolerates but a game working as intended rarely does. Tracked with
+// --compat-report to back a "game X glitches" report with what the game actually did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+	// write to an IO address nothing is mapped to on DMG
+	UnknownIoWrite,
+	// read of the unusable region (0xFEA0-0xFEFF) or an unmapped IO address
+	UnmappedRead,
+	// write to 0x0000-0x7FFF that hits no mapper register
+	RomWrite,
+	// opcode the CPU doesn't have, hangs it
+	IllegalOpcode,
+	// object beyond the 10 per line, address is its OAM entry
+	SpriteOverflow,
+	// STAT written while the PPU is drawing (mode 3)
+	StatWriteInMode3,
+	// read or write of 0xA000-0xBFFF with cartridge RAM disabled or absent
+	DisabledRamAccess,
+}
+
+const EVENTS: [(Event, &str); 7] = [
+	(Event::UnknownIoWrite, "unknown_io_write"),
+	(Event::UnmappedRead, "unmapped_read"),
+	(Event::RomWrite, "rom_write"),
+	(Event::IllegalOpcode, "illegal_opcode"),
+	(Event::SpriteOverflow, "sprite_overflow"),
+	(Event::StatWriteInMode3, "stat_write_in_mode_3"),
+	(Event::DisabledRamAccess, "disabled_ram_access"),
+];
+
+// occurrences of an event & where it first happened
+#[derive(Clone, Copy, Debug, Default)]
+struct Tally {
+	count: u64,
+	first_pc: u16,
+	first_address: u16,
+}
+
+// Events of a session, the PC being that of the instruction executing when they happened
+#[derive(Clone, Debug, Default)]
+pub struct CompatReport {
+	pc: u16,
+	tallies: [Tally; EVENTS.len()],
+}
+
+// shared between the MMU & its copies, so counts survive reset, restore & rewind
+pub(crate) type SharedReport = Arc<Mutex<CompatReport>>;
+
+impl CompatReport {
+	pub(crate) fn set_pc(&mut self, pc: u16) {
+		self.pc = pc;
+	}
+
+	pub(crate) fn record(&mut self, event: Event, address: u16) {
+		let tally = &mut self.tallies[event as usize];
+		if tally.count == 0 {
+			tally.first_pc = self.pc;
+			tally.first_address = address;
+		}
+		tally.count += 1;
+	}
+
+	pub fn count(&self, event: Event) -> u64 {
+		self.tallies[event as usize].count
+	}
+
+	// One line object with an entry per event, e.g. "rom_write":{"count":2,"first_pc":"0150",
+	// "first_address":"2000"}, where nothing happened both are null
+	pub fn to_json(&self) -> String {
+		let fields = EVENTS
+			.iter()
+			.map(|&(event, name)| {
+				let tally = self.tallies[event as usize];
+				let hex = |value: u16| match tally.count {
+					0 => "null".to_string(),
+					_ => format!("\"{:04X}\"", value),
+				};
+				format!(
+					"\"{}\":{{\"count\":{},\"first_pc\":{},\"first_address\":{}}}",
+					name,
+					tally.count,
+					hex(tally.first_pc),
+					hex(tally.first_address)
+				)
+			})
+			.collect::<Vec<_>>();
+		format!("{{{}}}", fields.join(","))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{gameboy::GameBoy, options::EmulatorOptions};
+
+	#[test]
+	fn json_of_a_synthetic_session() {
+		let mut report = CompatReport::default();
+		report.set_pc(0x0150);
+		report.record(Event::RomWrite, 0x2000);
+		report.set_pc(0x0200);
+		report.record(Event::RomWrite, 0x3000);
+		report.record(Event::SpriteOverflow, 0xFE28);
+		assert_eq!(report.count(Event::RomWrite), 2);
+		assert_eq!(report.count(Event::UnmappedRead), 0);
+		assert_eq!(
+			report.to_json(),
+			concat!(
+				"{",
+				r#""unknown_io_write":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""unmapped_read":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""rom_write":{"count":2,"first_pc":"0150","first_address":"2000"},"#,
+				r#""illegal_opcode":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""sprite_overflow":{"count":1,"first_pc":"0200","first_address":"FE28"},"#,
+				r#""stat_write_in_mode_3":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""disabled_ram_access":{"count":0,"first_pc":null,"first_address":null}"#,
+				"}"
+			)
+		);
+	}
+
+	#[test]
+	fn session_of_a_game() {
+		#[rustfmt::skip]
+		let program = [
+			0xEA, 0x00, 0x20, // 0100: LD ($2000), A
+			0xFA, 0x00, 0xA0, // 0103: LD A, ($A000)
+			0xEA, 0x7F, 0xFF, // 0106: LD ($FF7F), A
+			0xFA, 0xA0, 0xFE, // 0109: LD A, ($FEA0)
+			0xEA, 0x00, 0x21, // 010C: LD ($2100), A
+			0xD3,             // 010F: illegal
+		];
+		let mut rom = vec![0; 0x8000];
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+		let options = EmulatorOptions {
+			compat_report: Some("report.json".into()),
+			..EmulatorOptions::default()
+		};
+		let mut gameboy = GameBoy::new(rom.into(), None, &options);
+		(0..10).for_each(|_| {
+			gameboy.step();
+		});
+		let report = gameboy.compat_report().unwrap();
+		assert_eq!(
+			report.to_json(),
+			concat!(
+				"{",
+				r#""unknown_io_write":{"count":1,"first_pc":"0106","first_address":"FF7F"},"#,
+				r#""unmapped_read":{"count":1,"first_pc":"0109","first_address":"FEA0"},"#,
+				r#""rom_write":{"count":2,"first_pc":"0100","first_address":"2000"},"#,
+				r#""illegal_opcode":{"count":1,"first_pc":"010F","first_address":"010F"},"#,
+				r#""sprite_overflow":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""stat_write_in_mode_3":{"count":0,"first_pc":null,"first_address":null},"#,
+				r#""disabled_ram_access":{"count":1,"first_pc":"0103","first_address":"A000"}"#,
+				"}"
+			)
+		);
+	}
+}
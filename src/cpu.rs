@@ -1,8 +1,124 @@
 use crate::DEBUG_FLAG;
-use crate::MMU;
+use crate::compatreport::Event;
+use crate::disasm;
+use crate::mmu::MMU;
+use crate::options::TraceFormat;
+use crate::symbols::Symbols;
 use crate::utils::Checks;
-use std::ops::{Shl, Shr};
+use log::{info, warn};
+use std::{
+	collections::HashMap,
+	fmt,
+	ops::{Shl, Shr},
+	sync::{Arc, Mutex},
+};
+
+// memory as seen by the CPU, what a PC hook gets to read & write
+pub trait Bus: Send {
+	fn read_byte(&self, address: u16) -> u8;
+	fn write_byte(&mut self, address: u16, value: u8);
+	// complete copy incl. cartridge RAM & banking, e.g. to be read after the hook returns
+	fn box_clone(&self) -> Box<dyn Bus>;
+
+	// little endian, low byte is read first & address wraps around
+	fn read_word(&self, address: u16) -> u16 {
+		u16::from_le_bytes([
+			self.read_byte(address),
+			self.read_byte(address.wrapping_add(1)),
+		])
+	}
+
+	// little endian, low byte is written first & address wraps around
+	fn write_word(&mut self, address: u16, value: u16) {
+		let [l, h] = value.to_le_bytes();
+		self.write_byte(address, l);
+		self.write_byte(address.wrapping_add(1), h);
+	}
+}
+
+// What the CPU executes against: the MMU, or in tests a bus recording every access. Beyond
+// memory it keeps what the CPU leaves on the bus & the compat report up to date.
+pub trait CpuBus: Bus {
+	// last byte fetched from the instruction stream
+	fn latch_bus(&self, _: u8) {}
+	// rom bank mapped at 0x4000-0x7FFF, for symbols
+	fn rom_bank(&self) -> u16 {
+		1
+	}
+	// the instruction about to be executed, for events recorded meanwhile
+	fn report_pc(&self, _: u16) {}
+	fn record(&self, _: Event, _: u16) {}
+}
+
+impl Bus for MMU {
+	fn read_byte(&self, address: u16) -> u8 {
+		MMU::read_byte(self, address)
+	}
+
+	fn write_byte(&mut self, address: u16, value: u8) {
+		MMU::write_byte(self, address, value)
+	}
+
+	fn box_clone(&self) -> Box<dyn Bus> {
+		Box::new(self.clone())
+	}
+}
+
+impl CpuBus for MMU {
+	fn latch_bus(&self, value: u8) {
+		MMU::latch_bus(self, value);
+	}
+
+	fn rom_bank(&self) -> u16 {
+		MMU::rom_bank(self)
+	}
+
+	fn report_pc(&self, pc: u16) {
+		if let Some(report) = self.report() {
+			report.lock().unwrap().set_pc(pc);
+		}
+	}
+
+	fn record(&self, event: Event, address: u16) {
+		MMU::record(self, event, address);
+	}
+}
+
+// interrupts in IE/IF bit order
+const INTERRUPT_NAMES: [&str; 5] = ["VBlank", "STAT", "Timer", "Serial", "Joypad"];
+
+// interrupts dispatched so far, in IE/IF bit order
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InterruptCounts(pub [u64; 5]);
+
+impl fmt::Display for InterruptCounts {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let counts = INTERRUPT_NAMES
+			.iter()
+			.zip(self.0)
+			.map(|(name, count)| format!("{}: {}", name, count))
+			.collect::<Vec<_>>();
+		write!(f, "{}", counts.join(", "))
+	}
+}
 
+// what to do with the instruction at the hooked PC once the hook has run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookAction {
+	// execute it as usual (with whatever the hook changed)
+	Continue,
+	// step over it without executing it
+	SkipInstruction,
+	// return from the routine (as RET would) with A set to the value, meant for hooks at the
+	// start of a CALLed routine replacing it altogether
+	ReturnFromCall(u8),
+}
+
+// Called before the instruction at its address is fetched, whatever ROM bank is mapped. Hooks may
+// read & write memory through the bus & change registers, but can't add or remove hooks.
+pub type PcHook = Box<dyn FnMut(&mut CPU, &mut dyn Bus) -> HookAction + Send>;
+
+#[derive(Clone)]
 pub struct CPU {
 	a: u8,
 	f: u8,
@@ -17,10 +133,62 @@ pub struct CPU {
 	ime: bool,
 	ime_scheduled: bool,
 	low_power_mode: bool,
+	// hung by an illegal opcode, until the next reset (power cycle)
+	locked: bool,
+	// last call to execute_next dispatched an interrupt instead of executing an instruction
+	interrupt_dispatched: bool,
+	interrupt_counts: InterruptCounts,
+	// log every dispatch with the interrupted PC & IE/IF
+	log_interrupts: bool,
+	trace_format: TraceFormat,
+	// cycles since power on as of this instruction, kept up to date by GameBoy only while tracing
+	trace_cycles: u64,
+	// shared, so cloning the CPU for a snapshot stays cheap
+	symbols: Arc<Symbols>,
+	// PC hooks by address, None until one is added so executing stays cheap without any. Shared
+	// as well, hooks keep working after restoring a snapshot.
+	hooks: Option<Arc<Mutex<HashMap<u16, PcHook>>>>,
+}
+
+impl fmt::Display for CPU {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} IME:{}",
+			self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc, self.ime
+		)
+	}
 }
 
 impl CPU {
-	pub fn new() -> Self {
+	pub fn new(boot_rom: bool) -> Self {
+		if boot_rom {
+			// boot rom starts from 0x0000 & sets up the registers by itself
+			return CPU {
+				a: 0x00,
+				f: 0x00,
+				b: 0x00,
+				c: 0x00,
+				d: 0x00,
+				e: 0x00,
+				h: 0x00,
+				l: 0x00,
+				sp: 0x0000,
+				pc: 0x0000,
+				ime: false,
+				ime_scheduled: false,
+				low_power_mode: false,
+				locked: false,
+				interrupt_dispatched: false,
+				interrupt_counts: InterruptCounts::default(),
+				log_interrupts: false,
+				trace_format: TraceFormat::Plain,
+				trace_cycles: 0,
+				symbols: Arc::default(),
+				hooks: None,
+			};
+		}
+
 		CPU {
 			a: 0x01,
 			f: 0xB0,
@@ -35,41 +203,181 @@ impl CPU {
 			ime: false,
 			ime_scheduled: false,
 			low_power_mode: false,
+			locked: false,
+			interrupt_dispatched: false,
+			interrupt_counts: InterruptCounts::default(),
+			log_interrupts: false,
+			trace_format: TraceFormat::Plain,
+			trace_cycles: 0,
+			symbols: Arc::default(),
+			hooks: None,
+		}
+	}
+
+	pub fn pc(&self) -> u16 {
+		self.pc
+	}
+
+	pub fn ime(&self) -> bool {
+		self.ime
+	}
+
+	pub fn set_ime(&mut self, value: bool) {
+		self.ime = value;
+		self.ime_scheduled = false;
+	}
+
+	pub fn interrupt_dispatched(&self) -> bool {
+		self.interrupt_dispatched
+	}
+
+	pub fn interrupt_counts(&self) -> InterruptCounts {
+		self.interrupt_counts
+	}
+
+	pub fn set_log_interrupts(&mut self, log_interrupts: bool) {
+		self.log_interrupts = log_interrupts;
+	}
+
+	pub fn set_trace_format(&mut self, trace_format: TraceFormat) {
+		self.trace_format = trace_format;
+	}
+
+	pub fn set_trace_cycles(&mut self, cycles: u64) {
+		self.trace_cycles = cycles;
+	}
+
+	// symbols used to annotate the trace
+	pub fn set_symbols(&mut self, symbols: Symbols) {
+		self.symbols = Arc::new(symbols);
+	}
+
+	pub fn share_symbols(&mut self, other: &CPU) {
+		self.symbols = other.symbols.clone();
+	}
+
+	// address of the label in the symbols, if there's one by that name
+	pub fn symbol_address(&self, label: &str) -> Option<u16> {
+		self.symbols.find(label)
+	}
+
+	// replaces any hook already at the address, panics when called from within a hook
+	pub fn add_pc_hook(&mut self, address: u16, hook: PcHook) {
+		self
+			.hooks
+			.get_or_insert_default()
+			.try_lock()
+			.expect("PC hooks can't be added from within a hook")
+			.insert(address, hook);
+	}
+
+	// panics when called from within a hook
+	pub fn remove_pc_hook(&mut self, address: u16) {
+		if let Some(hooks) = &self.hooks {
+			hooks
+				.try_lock()
+				.expect("PC hooks can't be removed from within a hook")
+				.remove(&address);
+		}
+	}
+
+	pub fn share_hooks(&mut self, other: &CPU) {
+		self.hooks = other.hooks.clone();
+	}
+
+	// no hook runs until share_hooks brings them back, e.g. by restoring a snapshot
+	pub fn detach_hooks(&mut self) {
+		self.hooks = None;
+	}
+
+	// runs the hook at PC, if any, & carries out its action. Returns the cycles taken when the
+	// instruction at PC isn't to be executed.
+	fn run_pc_hook(&mut self, mmu: &mut impl CpuBus) -> Option<u16> {
+		let hooks = self.hooks.clone()?;
+		let mut hooks = hooks.lock().unwrap();
+		let hook = hooks.get_mut(&self.pc)?;
+		match hook(self, mmu) {
+			HookAction::Continue => None,
+			HookAction::SkipInstruction => {
+				let length = disasm::decode(self.pc, |a| mmu.read_byte(a)).length;
+				self.pc = self.pc.wrapping_add(length);
+				Some(4)
+			}
+			HookAction::ReturnFromCall(value) => {
+				self.a = value;
+				self.pc = self.pop_stack(mmu);
+				Some(16)
+			}
+		}
+	}
+
+	pub fn a(&self) -> u8 {
+		self.a
+	}
+
+	pub fn set_a(&mut self, value: u8) {
+		self.a = value;
+	}
+
+	pub fn sp(&self) -> u16 {
+		self.sp
+	}
+
+	pub fn set_sp(&mut self, value: u16) {
+		self.sp = value;
+	}
+
+	pub fn set_pc(&mut self, value: u16) {
+		self.pc = value;
+	}
+
+	pub fn check_invariants(&self, mmu: &MMU) -> Vec<String> {
+		let mut violations = Vec::new();
+		// stack is expected to live in external/work ram or hram
+		if !matches!(self.sp, 0xA000..=0xE000 | 0xFF80..=0xFFFF) && !mmu.is_boot_rom_mapped() {
+			violations.push(format!("SP outside of RAM: {:04X}", self.sp));
+		}
+		if mmu.read_byte(0xFF0F) & 0xE0 != 0xE0 {
+			violations.push(format!(
+				"IF upper bits not set: {:02X}",
+				mmu.read_byte(0xFF0F)
+			));
 		}
+		violations
 	}
 
-	fn af(&self) -> u16 {
+	pub fn af(&self) -> u16 {
 		self.f as u16 | (self.a as u16) << 8
 	}
 
-	fn bc(&self) -> u16 {
+	pub fn bc(&self) -> u16 {
 		self.c as u16 | (self.b as u16) << 8
 	}
 
-	fn de(&self) -> u16 {
+	pub fn de(&self) -> u16 {
 		self.e as u16 | (self.d as u16) << 8
 	}
 
-	fn hl(&self) -> u16 {
+	pub fn hl(&self) -> u16 {
 		self.l as u16 | (self.h as u16) << 8
 	}
 
-	fn set_af(&mut self, val: u16) {
+	pub fn set_af(&mut self, val: u16) {
 		self.f = (val & 0xFFF0) as u8;
 		self.a = (val >> 8) as u8;
 	}
 
-	fn set_bc(&mut self, val: u16) {
+	pub fn set_bc(&mut self, val: u16) {
 		self.c = val as u8;
 		self.b = (val >> 8) as u8;
 	}
 
-	fn set_de(&mut self, val: u16) {
+	pub fn set_de(&mut self, val: u16) {
 		self.e = val as u8;
 		self.d = (val >> 8) as u8;
 	}
 
-	fn set_hl(&mut self, val: u16) {
+	pub fn set_hl(&mut self, val: u16) {
 		self.l = val as u8;
 		self.h = (val >> 8) as u8;
 	}
@@ -117,13 +425,20 @@ impl CPU {
 		self.set_flag(4, flag);
 	}
 
-	fn get_byte(&mut self, mmu: &MMU) -> u8 {
+	fn get_byte(&mut self, mmu: &impl CpuBus) -> u8 {
 		let byte = mmu.read_byte(self.pc);
+		mmu.latch_bus(byte);
 		self.pc = self.pc.wrapping_add(1);
 		byte
 	}
 
-	fn push_stack(&mut self, mmu: &mut MMU, val: u16) {
+	// immediate word, low byte first, each byte left on the bus as it's fetched
+	fn get_word(&mut self, mmu: &impl CpuBus) -> u16 {
+		u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)])
+	}
+
+	// high byte is pushed first
+	fn push_stack(&mut self, mmu: &mut impl CpuBus, val: u16) {
 		self.sp = self.sp.wrapping_sub(1);
 		mmu.write_byte(self.sp, (val >> 8) as u8);
 
@@ -131,56 +446,65 @@ impl CPU {
 		mmu.write_byte(self.sp, val as u8);
 	}
 
-	fn pop_stack(&mut self, mmu: &MMU) -> u16 {
-		let l = mmu.read_byte(self.sp);
-		self.sp = self.sp.wrapping_add(1);
-
-		let h = mmu.read_byte(self.sp);
-		self.sp = self.sp.wrapping_add(1);
-
-		u16::from_le_bytes([l, h])
+	fn pop_stack(&mut self, mmu: &impl CpuBus) -> u16 {
+		let word = mmu.read_word(self.sp);
+		self.sp = self.sp.wrapping_add(2);
+		word
 	}
 
-	fn execute_interrupts(&mut self, mmu: &mut MMU) -> u16 {
+	// Dispatch takes 5 M-cycles: 2 idle, PC high byte pushed, PC low byte pushed & PC set to the
+	// vector. Pending interrupts are sampled again right after the high byte is pushed, that push
+	// may land on IE (SP = 0x0000) and change which interrupt wins or cancel the dispatch, in which
+	// case PC = 0x0000. Rest of the hardware advances only after the whole dispatch, so only the
+	// writes made by the dispatch itself are visible to that second sampling.
+	fn execute_interrupts(&mut self, mmu: &mut impl CpuBus) -> u16 {
 		let ie_reg = mmu.read_byte(0xFFFF);
 		let if_reg = mmu.read_byte(0xFF0F);
 
 		if 0x1F & ie_reg & if_reg > 0 {
+			// leaving HALT: with IME off execution simply resumes after HALT & IF is left as is,
+			// with IME on the wake up costs an extra M-cycle before the dispatch
+			let wake_cycles = if self.low_power_mode { 4 } else { 0 };
 			self.low_power_mode = false;
 			if self.ime {
 				self.ime = false;
-				self.push_stack(mmu, self.pc);
-				match ie_reg & if_reg {
-					x if (x >> 0) & 0x01 == 0x01 => {
-						self.pc = 0x0040;
-						mmu.write_byte(0xFF0F, if_reg & 0xFE);
-					}
-					x if (x >> 1) & 0x01 == 0x01 => {
-						self.pc = 0x0048;
-						mmu.write_byte(0xFF0F, if_reg & 0xFD);
-					}
-					x if (x >> 2) & 0x01 == 0x01 => {
-						self.pc = 0x0050;
-						mmu.write_byte(0xFF0F, if_reg & 0xFB);
-					}
-					x if (x >> 3) & 0x01 == 0x01 => {
-						self.pc = 0x0058;
-						mmu.write_byte(0xFF0F, if_reg & 0xF7);
-					}
-					x if (x >> 4) & 0x01 == 0x01 => {
-						self.pc = 0x0060;
-						mmu.write_byte(0xFF0F, if_reg & 0xEF);
+				self.sp = self.sp.wrapping_sub(1);
+				mmu.write_byte(self.sp, (self.pc >> 8) as u8);
+
+				let ie_reg = mmu.read_byte(0xFFFF);
+				let if_reg = mmu.read_byte(0xFF0F);
+				let pending = 0x1F & ie_reg & if_reg;
+
+				self.sp = self.sp.wrapping_sub(1);
+				mmu.write_byte(self.sp, self.pc as u8);
+				let interrupted_pc = self.pc;
+				self.pc = match pending {
+					0 => 0x0000,
+					_ => {
+						let bit = pending.trailing_zeros() as u8;
+						mmu.write_byte(0xFF0F, if_reg & !(1 << bit));
+						self.interrupt_counts.0[bit as usize] += 1;
+						0x0040 + 8 * bit as u16
 					}
-					_ => unreachable!(),
 				};
-				return 20;
+				if self.log_interrupts {
+					let name = match pending {
+						0 => "none (cancelled)",
+						_ => INTERRUPT_NAMES[pending.trailing_zeros() as usize],
+					};
+					info!(
+						"interrupt {} -> {:04X} at PC {:04X}, IE: {:02X}, IF: {:02X}",
+						name, self.pc, interrupted_pc, ie_reg, if_reg
+					);
+				}
+				return 20 + wake_cycles;
 			}
 		}
 
 		0
 	}
 
-	fn execute_prefixed(&mut self, mmu: &mut MMU) -> u16 {
+	fn execute_prefixed(&mut self, mmu: &mut impl CpuBus) -> u16 {
 		let opcode = self.get_byte(mmu);
 
 		match opcode {
@@ -593,8 +917,12 @@ impl CPU {
 		}
 	}
 
-	pub fn execute_next(&mut self, mmu: &mut MMU) -> u16 {
+	pub fn execute_next(&mut self, mmu: &mut impl CpuBus) -> u16 {
+		if self.locked {
+			return 4;
+		}
 		let cycles = self.execute_interrupts(mmu);
+		self.interrupt_dispatched = cycles > 0;
 
 		if cycles > 0 {
 			return cycles;
@@ -603,6 +931,25 @@ impl CPU {
 		}
 
 		if DEBUG_FLAG {
+			if !self.symbols.is_empty() {
+				let rom_bank = mmu.rom_bank();
+				if let Some(label) = self.symbols.lookup(self.pc, rom_bank) {
+					println!("{}:", label);
+				}
+				let instruction = disasm::decode(self.pc, |a| mmu.read_byte(a));
+				println!(
+					"; {}",
+					disasm::symbolize(&instruction, &self.symbols, rom_bank)
+				);
+			}
+			if self.trace_format == TraceFormat::Timestamped {
+				print!(
+					"CY:{:010} LY:{:03} M:{} ",
+					self.trace_cycles,
+					mmu.read_byte(0xFF44),
+					mmu.read_byte(0xFF41) & 0x03
+				);
+			}
 			println!(
 				"A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}\n",
 				self.a,
@@ -622,13 +969,20 @@ impl CPU {
 			);
 		}
 
+		if let Some(cycles) = self.run_pc_hook(mmu) {
+			return cycles;
+		}
+
+		#[cfg(debug_assertions)]
+		crate::recorder::record(self.pc, mmu.read_byte(self.pc));
+		mmu.report_pc(self.pc);
 		let opcode = self.get_byte(mmu);
 
 		let cycles = match opcode {
 			0x00 => 4,
 
 			0x01 => {
-				let x = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let x = self.get_word(mmu);
 				self.set_bc(x);
 				12
 			}
@@ -699,9 +1053,8 @@ impl CPU {
 			}
 
 			0x08 => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
-				mmu.write_byte(address, self.sp as u8);
-				mmu.write_byte(address + 1, (self.sp >> 8) as u8);
+				let address = self.get_word(mmu);
+				mmu.write_word(address, self.sp);
 				20
 			}
 
@@ -792,7 +1145,7 @@ impl CPU {
 			}
 
 			0x11 => {
-				let x = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let x = self.get_word(mmu);
 				self.set_de(x);
 				12
 			}
@@ -964,7 +1317,7 @@ impl CPU {
 			}
 
 			0x21 => {
-				let x = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let x = self.get_word(mmu);
 				self.set_hl(x);
 				12
 			}
@@ -1146,7 +1499,7 @@ impl CPU {
 			}
 
 			0x31 => {
-				self.sp = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				self.sp = self.get_word(mmu);
 				12
 			}
 
@@ -1297,10 +1650,7 @@ impl CPU {
 				4
 			}
 
-			0x40 => {
-				self.b = self.b;
-				4
-			}
+			0x40 => 4,
 
 			0x41 => {
 				self.b = self.c;
@@ -1342,10 +1692,7 @@ impl CPU {
 				4
 			}
 
-			0x49 => {
-				self.c = self.c;
-				4
-			}
+			0x49 => 4,
 
 			0x4A => {
 				self.c = self.d;
@@ -1387,10 +1734,7 @@ impl CPU {
 				4
 			}
 
-			0x52 => {
-				self.d = self.d;
-				4
-			}
+			0x52 => 4,
 
 			0x53 => {
 				self.d = self.e;
@@ -1432,10 +1776,7 @@ impl CPU {
 				4
 			}
 
-			0x5B => {
-				self.e = self.e;
-				4
-			}
+			0x5B => 4,
 
 			0x5C => {
 				self.e = self.h;
@@ -1477,10 +1818,7 @@ impl CPU {
 				4
 			}
 
-			0x64 => {
-				self.h = self.h;
-				4
-			}
+			0x64 => 4,
 
 			0x65 => {
 				self.h = self.l;
@@ -1522,10 +1860,7 @@ impl CPU {
 				4
 			}
 
-			0x6D => {
-				self.l = self.l;
-				4
-			}
+			0x6D => 4,
 
 			0x6E => {
 				self.l = mmu.read_byte(self.hl());
@@ -1612,10 +1947,7 @@ impl CPU {
 				8
 			}
 
-			0x7F => {
-				self.a = self.a;
-				4
-			}
+			0x7F => 4,
 
 			0x80 => {
 				if {
@@ -2940,7 +3272,7 @@ impl CPU {
 			}
 
 			0xC2 => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if !self.get_z_flag() {
 					self.pc = address;
 					16
@@ -2950,12 +3282,12 @@ impl CPU {
 			}
 
 			0xC3 => {
-				self.pc = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				self.pc = self.get_word(mmu);
 				16
 			}
 
 			0xC4 => {
-				let x = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let x = self.get_word(mmu);
 				if !self.get_z_flag() {
 					self.push_stack(mmu, self.pc);
 					self.pc = x;
@@ -3019,7 +3351,7 @@ impl CPU {
 			}
 
 			0xCA => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if self.get_z_flag() {
 					self.pc = address;
 					16
@@ -3031,7 +3363,7 @@ impl CPU {
 			0xCB => self.execute_prefixed(mmu),
 
 			0xCC => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if self.get_z_flag() {
 					self.push_stack(mmu, self.pc);
 					self.pc = address;
@@ -3042,7 +3374,7 @@ impl CPU {
 			}
 
 			0xCD => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				self.push_stack(mmu, self.pc);
 				self.pc = address;
 				24
@@ -3093,7 +3425,7 @@ impl CPU {
 			}
 
 			0xD2 => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if !self.get_c_flag() {
 					self.pc = address;
 					16
@@ -3103,7 +3435,7 @@ impl CPU {
 			}
 
 			0xD4 => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if !self.get_c_flag() {
 					self.push_stack(mmu, self.pc);
 					self.pc = address;
@@ -3168,7 +3500,7 @@ impl CPU {
 			}
 
 			0xDA => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if self.get_c_flag() {
 					self.pc = address;
 					16
@@ -3178,7 +3510,7 @@ impl CPU {
 			}
 
 			0xDC => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				if self.get_c_flag() {
 					self.push_stack(mmu, self.pc);
 					self.pc = address;
@@ -3257,6 +3589,9 @@ impl CPU {
 				16
 			}
 
+			// ADD SP,e8, 4 M-cycles: opcode, operand read, then 2 internal ones adding the low byte
+			// (setting H & C from it, unsigned) & the high byte with the sign extension. Nothing
+			// outside the CPU sees SP or F in between, so applying it all at once is exact.
 			0xE8 => {
 				let x = self.get_byte(mmu);
 				self.set_z_flag(false);
@@ -3287,7 +3622,7 @@ impl CPU {
 			}
 
 			0xEA => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				mmu.write_byte(address, self.a);
 				16
 			}
@@ -3357,6 +3692,8 @@ impl CPU {
 				16
 			}
 
+			// LD HL,SP+e8, flags as ADD SP,e8 but 3 M-cycles: the high byte add overlaps the next
+			// opcode fetch, so only a single internal cycle
 			0xF8 => {
 				let x = self.get_byte(mmu);
 				self.set_z_flag(false);
@@ -3387,7 +3724,7 @@ impl CPU {
 			}
 
 			0xFA => {
-				let address = u16::from_le_bytes([self.get_byte(mmu), self.get_byte(mmu)]);
+				let address = self.get_word(mmu);
 				self.a = mmu.read_byte(address);
 				16
 			}
@@ -3430,19 +3767,395 @@ impl CPU {
 				16
 			}
 
-			_ => panic!("opcode: {:02X?}, not implemented", opcode),
+			// illegal opcodes (D3, DB, DD, E3, E4, EB, EC, ED, F4, FC & FD) hang the CPU on hardware
+			// until it's powered off, only the other components keep running
+			_ => {
+				warn!(
+					"illegal opcode {:02X} at {:04X}, CPU locked up",
+					opcode,
+					self.pc.wrapping_sub(1)
+				);
+				mmu.record(Event::IllegalOpcode, self.pc.wrapping_sub(1));
+				self.locked = true;
+				4
+			}
 		};
 
+		// EI takes effect after the instruction following it, interrupts are then checked against
+		// IE & IF as they are at the start of the next execute_next. So with `EI; LD (FF0F),A`
+		// setting an enabled bit, the interrupt is serviced right after the LD (returning to the
+		// instruction after it), & `EI; DI` never services anything.
 		if self.ime_scheduled && opcode != 0xFB {
 			self.ime = true;
 			self.ime_scheduled = false;
 		}
 
-		if mmu.read_byte(0xFF02) == 0x81 {
-			print!("{}", char::from_u32(mmu.read_byte(0xFF01) as u32).unwrap());
-			mmu.write_byte(0xFF02, 0x00);
+		cycles
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::options::EmulatorOptions;
+
+	// CPU in the post boot state with `program` at the 0x0100 entry point of a MBC1+RAM cart
+	fn setup_with(program: &[u8], options: &EmulatorOptions) -> (CPU, MMU) {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0147] = 0x02;
+		rom[0x0149] = 0x02;
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+		(CPU::new(false), MMU::new(rom.into(), None, options))
+	}
+
+	fn setup(program: &[u8]) -> (CPU, MMU) {
+		setup_with(program, &EmulatorOptions::default())
+	}
+
+	#[derive(Clone, Copy, Debug, PartialEq)]
+	enum Access {
+		Read(u16),
+		Write(u16, u8),
+	}
+	use Access::{Read, Write};
+
+	// 64 KiB of plain RAM, no IO behind any address, recording every access in order
+	#[derive(Clone)]
+	struct TestBus {
+		memory: Vec<u8>,
+		accesses: std::cell::RefCell<Vec<Access>>,
+	}
+
+	impl TestBus {
+		// `program` at 0x0100, IE & IF clear
+		fn new(program: &[u8]) -> Self {
+			let mut memory = vec![0; 0x10000];
+			memory[0x0100..0x0100 + program.len()].copy_from_slice(program);
+			TestBus {
+				memory,
+				accesses: Default::default(),
+			}
+		}
+
+		// Accesses made by executing the instruction at PC, checking they're preceded by the
+		// interrupt check (IE & IF) & in debug builds by the recorder's look at the opcode
+		fn execute(&mut self, cpu: &mut CPU) -> Vec<Access> {
+			let pc = cpu.pc();
+			self.accesses.borrow_mut().clear();
+			cpu.execute_next(self);
+			let mut accesses = self.accesses.take();
+			assert_eq!(
+				accesses.drain(..2).collect::<Vec<_>>(),
+				[Read(0xFFFF), Read(0xFF0F)]
+			);
+			if cfg!(debug_assertions) {
+				assert_eq!(accesses.remove(0), Read(pc));
+			}
+			accesses
 		}
+	}
 
-		cycles
+	impl Bus for TestBus {
+		fn read_byte(&self, address: u16) -> u8 {
+			self.accesses.borrow_mut().push(Read(address));
+			self.memory[address as usize]
+		}
+
+		fn write_byte(&mut self, address: u16, value: u8) {
+			self.accesses.borrow_mut().push(Write(address, value));
+			self.memory[address as usize] = value;
+		}
+
+		fn box_clone(&self) -> Box<dyn Bus> {
+			Box::new(self.clone())
+		}
+	}
+
+	impl CpuBus for TestBus {}
+
+	#[test]
+	fn multi_byte_instructions_access_the_bus_in_order() {
+		let mut cpu = CPU::new(false);
+		cpu.set_sp(0xD000);
+		cpu.set_bc(0x1234);
+		let mut bus = TestBus::new(&[
+			0x08, 0x00, 0xC0, // LD ($C000), SP
+			0xCD, 0x00, 0x02, // CALL $0200
+		]);
+		bus.memory[0x0200..0x0206].copy_from_slice(&[
+			0xC5, // PUSH BC
+			0xFA, 0x34, 0x12, // LD A, ($1234)
+			0xE1, // POP HL
+			0xC9, // RET
+		]);
+		bus.memory[0x1234] = 0x42;
+
+		// immediate low then high byte, SP written low byte first
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[
+				Read(0x0100),
+				Read(0x0101),
+				Read(0x0102),
+				Write(0xC000, 0x00),
+				Write(0xC001, 0xD0)
+			]
+		);
+		// return address pushed high byte first
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[
+				Read(0x0103),
+				Read(0x0104),
+				Read(0x0105),
+				Write(0xCFFF, 0x01),
+				Write(0xCFFE, 0x06)
+			]
+		);
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[Read(0x0200), Write(0xCFFD, 0x12), Write(0xCFFC, 0x34)]
+		);
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[Read(0x0201), Read(0x0202), Read(0x0203), Read(0x1234)]
+		);
+		assert_eq!(cpu.a(), 0x42);
+		// popped low byte first
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[Read(0x0204), Read(0xCFFC), Read(0xCFFD)]
+		);
+		assert_eq!(cpu.hl(), 0x1234);
+		assert_eq!(
+			bus.execute(&mut cpu),
+			[Read(0x0205), Read(0xCFFE), Read(0xCFFF)]
+		);
+		assert_eq!((cpu.pc(), cpu.sp()), (0x0106, 0xD000));
+	}
+
+	#[test]
+	fn return_from_call_hook_returns_to_the_caller() {
+		let mut cpu = CPU::new(false);
+		cpu.set_sp(0xD000);
+		// CALL $0200; INC A, with a routine at 0x0200 that would loop forever
+		let mut bus = TestBus::new(&[0xCD, 0x00, 0x02, 0x3C]);
+		bus.memory[0x0200..0x0202].copy_from_slice(&[0x18, 0xFE]);
+		cpu.add_pc_hook(
+			0x0200,
+			Box::new(|cpu, bus| {
+				// sees the return address on the stack
+				assert_eq!(bus.read_word(cpu.sp()), 0x0103);
+				HookAction::ReturnFromCall(0x41)
+			}),
+		);
+
+		assert_eq!(cpu.execute_next(&mut bus), 24);
+		assert_eq!((cpu.pc(), cpu.sp()), (0x0200, 0xCFFE));
+		// as long as RET, none of the routine gets fetched
+		bus.accesses.take();
+		assert_eq!(cpu.execute_next(&mut bus), 16);
+		let accesses = bus.accesses.take();
+		assert!(!accesses.contains(&Read(0x0200)), "{:?}", accesses);
+		assert_eq!((cpu.pc(), cpu.sp(), cpu.a()), (0x0103, 0xD000, 0x41));
+		// & on with the caller
+		assert_eq!(cpu.execute_next(&mut bus), 4);
+		assert_eq!((cpu.pc(), cpu.a()), (0x0104, 0x42));
+	}
+
+	// dispatch with SP = 0x0000, so the PC high byte (0x01, VBlank) is pushed onto IE
+	fn dispatch_pushing_onto_ie(ie: u8, if_reg: u8) -> (CPU, MMU) {
+		let (mut cpu, mut mmu) = setup(&[0x00]);
+		cpu.set_sp(0x0000);
+		cpu.ime = true;
+		mmu.write_byte(0xFFFF, ie);
+		mmu.write_byte(0xFF0F, if_reg);
+		assert_eq!(cpu.execute_next(&mut mmu), 20);
+		(cpu, mmu)
+	}
+
+	#[test]
+	fn push_onto_ie_cancels_the_dispatch() {
+		// timer pending, but only VBlank is enabled once the high byte landed on IE
+		let (cpu, mmu) = dispatch_pushing_onto_ie(0x04, 0x04);
+		assert_eq!(cpu.pc(), 0x0000);
+		assert_eq!(mmu.read_byte(0xFFFF), 0x01);
+		// nothing got acknowledged or counted
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x1F, 0x04);
+		assert_eq!(cpu.interrupt_counts(), InterruptCounts::default());
+		assert!(!cpu.ime());
+		// the low byte went below, to 0xFFFE
+		assert_eq!((cpu.sp(), mmu.read_byte(0xFFFE)), (0xFFFE, 0x00));
+	}
+
+	#[test]
+	fn push_onto_ie_changes_the_interrupt_dispatched() {
+		// timer wins as sampled before the push, VBlank as sampled after it
+		let (cpu, mmu) = dispatch_pushing_onto_ie(0x04, 0x05);
+		assert_eq!(cpu.pc(), 0x0040);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x1F, 0x04);
+		assert_eq!(cpu.interrupt_counts().0, [1, 0, 0, 0, 0]);
+	}
+
+	#[test]
+	fn interrupt_dispatch_accesses_the_bus_in_order() {
+		let mut cpu = CPU::new(false);
+		cpu.set_sp(0xD000);
+		cpu.ime = true;
+		let mut bus = TestBus::new(&[0x00]);
+		// timer pending & enabled
+		bus.memory[0xFFFF] = 0x04;
+		bus.memory[0xFF0F] = 0x04;
+		assert_eq!(cpu.execute_next(&mut bus), 20);
+		// checked, PC high byte pushed, sampled again, low byte pushed & the request cleared
+		assert_eq!(
+			bus.accesses.take(),
+			[
+				Read(0xFFFF),
+				Read(0xFF0F),
+				Write(0xCFFF, 0x01),
+				Read(0xFFFF),
+				Read(0xFF0F),
+				Write(0xCFFE, 0x00),
+				Write(0xFF0F, 0x00),
+			]
+		);
+		assert_eq!(cpu.pc(), 0x0050);
+	}
+
+	#[test]
+	fn illegal_opcode_locks_up() {
+		let (mut cpu, mut mmu) = setup(&[0xD3, 0x3C]);
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		// not even an interrupt gets it going again
+		mmu.write_byte(0xFFFF, 0x1F);
+		mmu.write_byte(0xFF0F, 0x1F);
+		cpu.ime = true;
+		for _ in 0..100 {
+			assert_eq!(cpu.execute_next(&mut mmu), 4);
+		}
+		assert_eq!(cpu.pc(), 0x0101);
+		assert_eq!(cpu.a(), 0x01);
+	}
+
+	#[test]
+	fn open_bus_reads_last_immediate_byte() {
+		let options = EmulatorOptions {
+			open_bus: true,
+			..EmulatorOptions::default()
+		};
+		// LD A,(A123); LD HL,BFFF; LD A,(HL); LD A,(A000) with RAM enabled
+		let program = [0xFA, 0x23, 0xA1, 0x21, 0xFF, 0xBF, 0x7E, 0xFA, 0x00, 0xA0];
+		let (mut cpu, mut mmu) = setup_with(&program, &options);
+		cpu.execute_next(&mut mmu);
+		// the high address byte, fetched last
+		assert_eq!(cpu.a(), 0xA1);
+		cpu.execute_next(&mut mmu);
+		cpu.execute_next(&mut mmu);
+		// the opcode
+		assert_eq!(cpu.a(), 0x7E);
+		mmu.write_byte(0xA000, 0x42);
+		mmu.write_byte(0x0000, 0x0A);
+		mmu.write_byte(0xA000, 0x42);
+		cpu.execute_next(&mut mmu);
+		assert_eq!(cpu.a(), 0x42);
+	}
+
+	#[test]
+	fn ei_then_if_write_is_serviced_after_the_write() {
+		// EI; LDH (0F),A; NOP
+		let (mut cpu, mut mmu) = setup(&[0xFB, 0xE0, 0x0F, 0x00]);
+		mmu.write_byte(0xFFFF, 0x04);
+		cpu.a = 0x04;
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		assert_eq!(cpu.execute_next(&mut mmu), 12);
+		assert_eq!(cpu.execute_next(&mut mmu), 20);
+		assert_eq!(cpu.pc(), 0x0050);
+		// returning to the NOP
+		assert_eq!(mmu.read_word(cpu.sp()), 0x0103);
+	}
+
+	#[test]
+	fn ei_then_if_clear_cancels_the_pending_interrupt() {
+		// EI; LDH (0F),A; NOP with A = 0
+		let (mut cpu, mut mmu) = setup(&[0xFB, 0xE0, 0x0F, 0x00]);
+		mmu.write_byte(0xFFFF, 0x04);
+		mmu.write_byte(0xFF0F, 0x04);
+		cpu.a = 0x00;
+		cpu.execute_next(&mut mmu);
+		cpu.execute_next(&mut mmu);
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		assert_eq!(cpu.pc(), 0x0104);
+	}
+
+	#[test]
+	fn ei_di_never_services() {
+		// EI; DI; LDH (0F),A; NOP
+		let (mut cpu, mut mmu) = setup(&[0xFB, 0xF3, 0xE0, 0x0F, 0x00]);
+		mmu.write_byte(0xFFFF, 0x04);
+		mmu.write_byte(0xFF0F, 0x04);
+		cpu.a = 0x04;
+		(0..4).for_each(|_| {
+			cpu.execute_next(&mut mmu);
+		});
+		assert_eq!(cpu.pc(), 0x0105);
+		assert!(!cpu.ime());
+	}
+
+	#[test]
+	fn sp_plus_e8() {
+		// SP, e8, result, flags: H & C come from the unsigned low byte addition, Z & N are cleared
+		for (sp, e8, result, f) in [
+			(0xFFF8, 0x08, 0x0000, 0x30),
+			(0x0001, 0xFF, 0x0000, 0x30),
+			(0x1000, 0xFF, 0x0FFF, 0x00),
+			(0x000F, 0x01, 0x0010, 0x20),
+			(0x00F0, 0x10, 0x0100, 0x10),
+		] {
+			// ADD SP,e8
+			let (mut cpu, mut mmu) = setup(&[0xE8, e8]);
+			(cpu.sp, cpu.f) = (sp, 0xC0);
+			let hl = cpu.hl();
+			assert_eq!(cpu.execute_next(&mut mmu), 16);
+			assert_eq!((cpu.sp, cpu.f, cpu.hl()), (result, f, hl));
+
+			// LD HL,SP+e8
+			let (mut cpu, mut mmu) = setup(&[0xF8, e8]);
+			(cpu.sp, cpu.f) = (sp, 0xC0);
+			assert_eq!(cpu.execute_next(&mut mmu), 12);
+			assert_eq!((cpu.hl(), cpu.f, cpu.sp), (result, f, sp));
+		}
+	}
+
+	#[test]
+	fn halt_with_ime_on_wakes_into_the_handler() {
+		// HALT; INC A
+		let (mut cpu, mut mmu) = setup(&[0x76, 0x3C]);
+		mmu.write_byte(0xFFFF, 0x04);
+		cpu.ime = true;
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		mmu.write_byte(0xFF0F, 0x04);
+		// dispatch plus the wake up M-cycle
+		assert_eq!(cpu.execute_next(&mut mmu), 24);
+		assert_eq!(cpu.pc(), 0x0050);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0);
+		assert!(!cpu.ime());
+	}
+
+	#[test]
+	fn halt_with_ime_off_resumes_after_halt() {
+		// HALT; INC A
+		let (mut cpu, mut mmu) = setup(&[0x76, 0x3C]);
+		mmu.write_byte(0xFFFF, 0x04);
+		cpu.ime = false;
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		mmu.write_byte(0xFF0F, 0x04);
+		// no dispatch, INC A runs right away & IF is left for the program to clear
+		assert_eq!(cpu.execute_next(&mut mmu), 4);
+		assert_eq!(cpu.pc(), 0x0102);
+		assert_eq!(cpu.a(), 0x02);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0x04);
 	}
 }
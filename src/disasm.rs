@@ -0,0 +1,318 @@
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const ALU: [&str; 8] = [
+	"ADD A,", "ADC A,", "SUB", "SBC A,", "AND", "XOR", "OR", "CP",
+];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+// Operand placeholders:
+// - n8  : immediate byte              - n16 : immediate word
+// - a8  : 0xFF00 + immediate byte     - a16 : immediate address (jump/call target)
+// - e8  : signed offset from the next instruction (jump target)
+// - s8  : signed immediate byte
+#[rustfmt::skip]
+const OPCODES: [&str; 0x40] = [
+	"NOP",        "LD BC,n16", "LD (BC),A",  "INC BC", "INC B", "DEC B", "LD B,n8",    "RLCA",
+	"LD (a16),SP","ADD HL,BC", "LD A,(BC)",  "DEC BC", "INC C", "DEC C", "LD C,n8",    "RRCA",
+	"STOP",       "LD DE,n16", "LD (DE),A",  "INC DE", "INC D", "DEC D", "LD D,n8",    "RLA",
+	"JR e8",      "ADD HL,DE", "LD A,(DE)",  "DEC DE", "INC E", "DEC E", "LD E,n8",    "RRA",
+	"JR NZ,e8",   "LD HL,n16", "LD (HL+),A", "INC HL", "INC H", "DEC H", "LD H,n8",    "DAA",
+	"JR Z,e8",    "ADD HL,HL", "LD A,(HL+)", "DEC HL", "INC L", "DEC L", "LD L,n8",    "CPL",
+	"JR NC,e8",   "LD SP,n16", "LD (HL-),A", "INC SP", "INC (HL)", "DEC (HL)", "LD (HL),n8", "SCF",
+	"JR C,e8",    "ADD HL,SP", "LD A,(HL-)", "DEC SP", "INC A", "DEC A", "LD A,n8",    "CCF",
+];
+
+#[rustfmt::skip]
+const OPCODES_HIGH: [&str; 0x40] = [
+	"RET NZ",      "POP BC", "JP NZ,a16", "JP a16", "CALL NZ,a16", "PUSH BC", "ADD A,n8", "RST $00",
+	"RET Z",       "RET",    "JP Z,a16",  "PREFIX", "CALL Z,a16",  "CALL a16","ADC A,n8", "RST $08",
+	"RET NC",      "POP DE", "JP NC,a16", "-",      "CALL NC,a16", "PUSH DE", "SUB n8",   "RST $10",
+	"RET C",       "RETI",   "JP C,a16",  "-",      "CALL C,a16",  "-",       "SBC A,n8", "RST $18",
+	"LDH (a8),A",  "POP HL", "LD (C),A",  "-",      "-",           "PUSH HL", "AND n8",   "RST $20",
+	"ADD SP,s8",   "JP HL",  "LD (a16),A","-",      "-",           "-",       "XOR n8",   "RST $28",
+	"LDH A,(a8)",  "POP AF", "LD A,(C)",  "DI",     "-",           "PUSH AF", "OR n8",    "RST $30",
+	"LD HL,SP+s8", "LD SP,HL","LD A,(a16)","EI",    "-",           "-",       "CP n8",    "RST $38",
+];
+
+const HEADER_FIELDS: [(u16, u16, &str); 13] = [
+	(0x0104, 0x0134, "nintendo logo"),
+	(0x0134, 0x0143, "title"),
+	(0x0143, 0x0144, "cgb flag"),
+	(0x0144, 0x0146, "new licensee code"),
+	(0x0146, 0x0147, "sgb flag"),
+	(0x0147, 0x0148, "cartridge type"),
+	(0x0148, 0x0149, "rom size"),
+	(0x0149, 0x014A, "ram size"),
+	(0x014A, 0x014B, "destination code"),
+	(0x014B, 0x014C, "old licensee code"),
+	(0x014C, 0x014D, "mask rom version"),
+	(0x014D, 0x014E, "header checksum"),
+	(0x014E, 0x0150, "global checksum"),
+];
+
+pub struct Instruction {
+	pub length: u16,
+	pub mnemonic: String,
+	// jump/call target, if any
+	pub target: Option<u16>,
+	// control never falls through to the next instruction (JP, JR, RET, RETI, JP HL)
+	pub ends_flow: bool,
+}
+
+// `read` returns the byte at given address, bytes past the end of the region read as 0x00
+pub fn decode(address: u16, read: impl Fn(u16) -> u8) -> Instruction {
+	let opcode = read(address);
+	let template = match opcode {
+		0x76 => "HALT".to_string(),
+		0x40..=0x7F => format!(
+			"LD {},{}",
+			R8[(opcode as usize >> 3) & 7],
+			R8[opcode as usize & 7]
+		),
+		0x80..=0xBF => format!(
+			"{} {}",
+			ALU[(opcode as usize >> 3) & 7],
+			R8[opcode as usize & 7]
+		),
+		0xCB => {
+			let cb = read(address.wrapping_add(1));
+			let r = R8[cb as usize & 7];
+			let mnemonic = match cb >> 6 {
+				0 => format!("{} {}", ROT[(cb as usize >> 3) & 7], r),
+				1 => format!("BIT {},{}", (cb >> 3) & 7, r),
+				2 => format!("RES {},{}", (cb >> 3) & 7, r),
+				_ => format!("SET {},{}", (cb >> 3) & 7, r),
+			};
+			return Instruction {
+				length: 2,
+				mnemonic,
+				target: None,
+				ends_flow: false,
+			};
+		}
+		0x00..=0x3F => OPCODES[opcode as usize].to_string(),
+		_ => OPCODES_HIGH[opcode as usize - 0xC0].to_string(),
+	};
+
+	let n8 = read(address.wrapping_add(1));
+	let n16 = u16::from_le_bytes([n8, read(address.wrapping_add(2))]);
+	let (length, target, mnemonic) = if template.contains("n16") {
+		(3, None, template.replace("n16", &format!("${:04X}", n16)))
+	} else if template.contains("a16") {
+		let target = template.starts_with("JP") || template.starts_with("CALL");
+		(
+			3,
+			target.then_some(n16),
+			template.replace("a16", &format!("${:04X}", n16)),
+		)
+	} else if template.contains("e8") {
+		let target = address.wrapping_add(2).wrapping_add_signed(n8 as i8 as i16);
+		(
+			2,
+			Some(target),
+			template.replace("e8", &format!("${:04X}", target)),
+		)
+	} else if template.contains("a8") {
+		(2, None, template.replace("a8", &format!("$FF{:02X}", n8)))
+	} else if template.contains("s8") {
+		(2, None, template.replace("s8", &format!("{}", n8 as i8)))
+	} else if template.contains("n8") {
+		(2, None, template.replace("n8", &format!("${:02X}", n8)))
+	} else {
+		(1, None, template)
+	};
+
+	let target = target.or(match opcode {
+		0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Some((opcode & 0x38) as u16),
+		_ => None,
+	});
+	let ends_flow = matches!(opcode, 0x18 | 0xC3 | 0xC9 | 0xD9 | 0xE9);
+
+	Instruction {
+		length,
+		mnemonic,
+		target,
+		ends_flow,
+	}
+}
+
+// Replaces the jump/call target of the instruction with its symbol, if there is one
+pub fn symbolize(instruction: &Instruction, symbols: &Symbols, rom_bank: u16) -> String {
+	match instruction
+		.target
+		.and_then(|t| Some((t, symbols.lookup(t, rom_bank)?)))
+	{
+		Some((target, name)) => match instruction.mnemonic.starts_with("RST") {
+			true => format!("{} ; {}", instruction.mnemonic, name),
+			false => instruction
+				.mnemonic
+				.replace(&format!("${:04X}", target), name),
+		},
+		None => instruction.mnemonic.clone(),
+	}
+}
+
+// Offset of given address in the rom, with `bank` mapped in 0x4000-0x7FFF
+fn rom_offset(address: u16, bank: usize) -> usize {
+	match address {
+		0x0000..0x4000 => address as usize,
+		_ => 0x4000 * bank + (address as usize - 0x4000),
+	}
+}
+
+// Listing of rom region [start, end) with `bank` mapped in 0x4000-0x7FFF. Jump & call targets get
+// labels (taken from `symbols` when known), header fields are annotated & bytes following an
+// unconditional jump/return are rendered as data until the next label.
+pub fn disassemble(rom: &[u8], bank: usize, start: u16, end: u16, symbols: &Symbols) -> String {
+	let read = |a: u16| match a < end {
+		true => rom.get(rom_offset(a, bank)).copied().unwrap_or(0x00),
+		false => 0x00,
+	};
+	let in_header = |a: u16| HEADER_FIELDS.iter().find(|(s, e, _)| (*s..*e).contains(&a));
+
+	// first pass collects the jump/call targets inside the region
+	let mut labels = BTreeSet::new();
+	let mut address = start;
+	while address < end {
+		if let Some(&(_, e, _)) = in_header(address) {
+			address = e;
+			continue;
+		}
+		let instruction = decode(address, read);
+		if let Some(target) = instruction.target.filter(|t| (start..end).contains(t)) {
+			labels.insert(target);
+		}
+		address = address.saturating_add(instruction.length);
+	}
+
+	let label = |a: u16| match symbols.lookup(a, bank as u16) {
+		Some(name) => name.to_string(),
+		None => format!("L_{:04X}", a),
+	};
+	let mut listing = String::new();
+	let mut data = false;
+	let mut address = start;
+	while address < end {
+		if labels.contains(&address) {
+			data = false;
+			listing += &format!("{}:\n", label(address));
+		}
+
+		if let Some(&(s, e, name)) = in_header(address) {
+			let bytes = (address..e.min(end))
+				.map(|a| format!("${:02X}", read(a)))
+				.collect::<Vec<_>>()
+				.join(",");
+			listing += &format!("  {:04X}: DB {:<23} ; header: {}", address, bytes, name);
+			if name == "title" {
+				let title = (s..e).map(read).take_while(|&b| b != 0 && b.is_ascii());
+				listing += &format!(" {:?}", title.map(char::from).collect::<String>());
+			}
+			listing += "\n";
+			address = e;
+			continue;
+		}
+
+		if data {
+			listing += &format!("  {:04X}: DB ${:02X}\n", address, read(address));
+			address = address.saturating_add(1);
+			continue;
+		}
+
+		let instruction = decode(address, read);
+		let mut mnemonic = symbolize(&instruction, symbols, bank as u16);
+		if let Some(target) = instruction.target.filter(|t| labels.contains(t)) {
+			mnemonic = match mnemonic.starts_with("RST") {
+				true => format!("{} ; {}", instruction.mnemonic, label(target)),
+				false => instruction
+					.mnemonic
+					.replace(&format!("${:04X}", target), &label(target)),
+			};
+		}
+		let bytes = (address..address.saturating_add(instruction.length))
+			.map(|a| format!("{:02X}", read(a)))
+			.collect::<Vec<_>>()
+			.join(" ");
+		listing += &format!("  {:04X}: {:<8} {}\n", address, bytes, mnemonic);
+		data = instruction.ends_flow;
+		address = address.saturating_add(instruction.length);
+	}
+
+	listing
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// 4 banks, a small program at the entry point jumping over the header, a routine in bank 2
+	fn rom() -> Vec<u8> {
+		let mut rom = vec![0; 0x10000];
+		#[rustfmt::skip]
+		let program = [
+			(0x0100, &[0x00][..]),             // NOP
+			(0x0101, &[0xC3, 0x50, 0x01]),     // JP $0150
+			(0x0134, b"GOLDEN"),
+			(0x0147, &[0x01, 0x01]),           // MBC1, 64 KiB
+			(0x014D, &[0x9C]),
+			(0x0150, &[0x3E, 0x01]),           // LD A,$01
+			(0x0152, &[0xCD, 0x58, 0x01]),     // CALL copy
+			(0x0155, &[0x18, 0xF9]),           // JR $0150
+			(0x0157, &[0xFF]),                 // data
+			(0x0158, &[0x22, 0xC9]),           // copy: LD (HL+),A & RET
+			(0x015A, &[0x12, 0x34]),           // data
+			(0x4000, &[0xC3, 0x00, 0x40]),     // bank 1: JP $4000
+			(0x8000, &[0xCD, 0x05, 0x40]),     // bank 2: CALL $4005
+			(0x8003, &[0x18, 0xFE]),           // JR $4003
+			(0x8005, &[0xC9]),                 // RET
+		];
+		program
+			.iter()
+			.for_each(|(offset, bytes)| rom[*offset..*offset + bytes.len()].copy_from_slice(bytes));
+		rom
+	}
+
+	#[test]
+	fn golden_listing() {
+		let symbols = Symbols::parse("00:0158 copy\n");
+		let listing = r#"  0100: 00       NOP
+  0101: C3 50 01 JP L_0150
+  0104: DB LOGO ; header: nintendo logo
+  0134: DB $47,$4F,$4C,$44,$45,$4E,$00,$00,$00,$00,$00,$00,$00,$00,$00 ; header: title "GOLDEN"
+  0143: DB $00                     ; header: cgb flag
+  0144: DB $00,$00                 ; header: new licensee code
+  0146: DB $00                     ; header: sgb flag
+  0147: DB $01                     ; header: cartridge type
+  0148: DB $01                     ; header: rom size
+  0149: DB $00                     ; header: ram size
+  014A: DB $00                     ; header: destination code
+  014B: DB $00                     ; header: old licensee code
+  014C: DB $00                     ; header: mask rom version
+  014D: DB $9C                     ; header: header checksum
+  014E: DB $00,$00                 ; header: global checksum
+L_0150:
+  0150: 3E 01    LD A,$01
+  0152: CD 58 01 CALL copy
+  0155: 18 F9    JR L_0150
+  0157: DB $FF
+copy:
+  0158: 22       LD (HL+),A
+  0159: C9       RET
+  015A: DB $12
+  015B: DB $34
+"#
+		.replace("LOGO", &["$00"; 48].join(","));
+		assert_eq!(disassemble(&rom(), 1, 0x0100, 0x015C, &symbols), listing);
+
+		// bank 2 mapped, not 1
+		let listing = "  4000: CD 05 40 CALL L_4005
+L_4003:
+  4003: 18 FE    JR L_4003
+L_4005:
+  4005: C9       RET
+";
+		assert_eq!(disassemble(&rom(), 2, 0x4000, 0x4006, &symbols), listing);
+	}
+}
@@ -0,0 +1,187 @@
+use crate::{HEIGHT, WIDTH};
+
+// Receives the final frame just before presentation
+pub type PresentHook = Box<dyn FnMut(&mut [u32])>;
+
+// Post-processing applied on the final frame just before it gets presented
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+	Scanlines,
+	Grayscale,
+}
+
+impl Filter {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"scanlines" => Some(Filter::Scanlines),
+			"grayscale" => Some(Filter::Grayscale),
+			_ => None,
+		}
+	}
+
+	fn scale(pixel: u32, numerator: u32, denominator: u32) -> u32 {
+		let [_, r, g, b] = pixel.to_be_bytes();
+		let f = |c: u8| (c as u32 * numerator / denominator).min(0xFF) as u8;
+		u32::from_be_bytes([0, f(r), f(g), f(b)])
+	}
+
+	pub fn into_hook(self) -> PresentHook {
+		Box::new(move |frame| self.apply(frame))
+	}
+
+	pub fn apply(self, frame: &mut [u32]) {
+		match self {
+			// darken every other line by 25%
+			Filter::Scanlines => frame
+				.chunks_mut(WIDTH)
+				.skip(1)
+				.step_by(2)
+				.flatten()
+				.for_each(|p| *p = Self::scale(*p, 3, 4)),
+			// ITU-R BT.601 luma
+			Filter::Grayscale => frame.iter_mut().for_each(|p| {
+				let [_, r, g, b] = p.to_be_bytes();
+				let y = ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8;
+				*p = u32::from_be_bytes([0, y, y, y]);
+			}),
+		}
+	}
+}
+
+// Upscales the frame by an integer factor for presenting, centered on a target at least that
+// large with the rest of it in the border color. At scales of 2 & more the LCD grid darkens the
+// rightmost column and bottom row of every pixel block, mimicking the visible gaps between pixels
+// of the DMG LCD, & CRT rounding cuts off the corners of the screen. Unlike filters it changes
+// the size of the frame, so it's applied last.
+pub struct Upscaler {
+	// darkening of the grid lines in %
+	grid: Option<u32>,
+	// corner radius in (unscaled) pixels
+	rounding: usize,
+	border: u32,
+}
+
+impl Upscaler {
+	pub fn new(grid: Option<u8>, rounding: Option<u8>, border: u32) -> Self {
+		Upscaler {
+			grid: grid.map(|intensity| intensity.min(100) as u32),
+			rounding: rounding.map_or(0, |radius| (radius as usize).min(HEIGHT / 2)),
+			border,
+		}
+	}
+
+	// pixels cut off either end of the row `y` rows away from the top or bottom edge
+	fn inset(y: usize, radius: usize) -> usize {
+		match y < radius {
+			// doubled distances from the corner's center to the pixel's center
+			true => {
+				let dy = 2 * (radius - y) - 1;
+				(0..radius)
+					.find(|&x| {
+						let dx = 2 * (radius - x) - 1;
+						dx * dx + dy * dy <= 4 * radius * radius
+					})
+					.unwrap_or(radius)
+			}
+			false => 0,
+		}
+	}
+
+	// `target` is `width` pixels wide
+	pub fn apply(&self, frame: &[u32], scale: usize, target: &mut [u32], width: usize) {
+		let (screen_width, screen_height) = (WIDTH * scale, HEIGHT * scale);
+		let left = (width - screen_width) / 2;
+		let top = (target.len() / width - screen_height) / 2;
+		let radius = self.rounding * scale;
+		target.fill(self.border);
+		(0..screen_height).for_each(|y| {
+			let inset = Self::inset(y.min(screen_height - 1 - y), radius);
+			let source = &frame[y / scale * WIDTH..][..WIDTH];
+			let row = &mut target[(top + y) * width + left..][..screen_width];
+			(inset..screen_width - inset).for_each(|x| {
+				let pixel = source[x / scale];
+				let edge = x % scale == scale - 1 || y % scale == scale - 1;
+				row[x] = match self.grid {
+					Some(intensity) if scale >= 2 && edge => Filter::scale(pixel, 100 - intensity, 100),
+					_ => pixel,
+				};
+			});
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PIXEL: u32 = 0x00C8C8C8;
+	const BORDER: u32 = 0x00123456;
+
+	fn upscale(upscaler: &Upscaler, scale: usize, width: usize, height: usize) -> Vec<u32> {
+		let mut target = vec![0; width * height];
+		upscaler.apply(&[PIXEL; WIDTH * HEIGHT], scale, &mut target, width);
+		target
+	}
+
+	#[test]
+	fn grid_at_any_integer_scale() {
+		let upscaler = Upscaler::new(Some(50), None, BORDER);
+		// nowhere to draw it without covering the pixels
+		assert!(
+			upscale(&upscaler, 1, WIDTH, HEIGHT)
+				.iter()
+				.all(|&p| p == PIXEL)
+		);
+		(2..=5).for_each(|scale| {
+			let target = upscale(&upscaler, scale, WIDTH * scale, HEIGHT * scale);
+			target.iter().enumerate().for_each(|(i, &p)| {
+				let (x, y) = (i % (WIDTH * scale), i / (WIDTH * scale));
+				let edge = x % scale == scale - 1 || y % scale == scale - 1;
+				assert_eq!(p, if edge { 0x00646464 } else { PIXEL });
+			});
+		});
+	}
+
+	#[test]
+	fn screen_centered_in_the_border_color() {
+		let upscaler = Upscaler::new(None, None, BORDER);
+		let (width, height) = (2 * WIDTH + 11, 2 * HEIGHT + 7);
+		let target = upscale(&upscaler, 2, width, height);
+		let inside =
+			|x: usize, y: usize| (5..5 + 2 * WIDTH).contains(&x) && (3..3 + 2 * HEIGHT).contains(&y);
+		target.iter().enumerate().for_each(|(i, &p)| {
+			let color = if inside(i % width, i / width) {
+				PIXEL
+			} else {
+				BORDER
+			};
+			assert_eq!(p, color);
+		});
+	}
+
+	#[test]
+	fn crt_rounding_cuts_off_the_corners() {
+		let upscaler = Upscaler::new(None, Some(4), BORDER);
+		let (width, height) = (WIDTH * 2, HEIGHT * 2);
+		let target = upscale(&upscaler, 2, width, height);
+		let at = |x: usize, y: usize| target[y * width + x];
+		// every corner the same, cut off along a circle of radius 8
+		[
+			(0, 0),
+			(width - 1, 0),
+			(0, height - 1),
+			(width - 1, height - 1),
+		]
+		.iter()
+		.for_each(|&(x, y)| {
+			assert_eq!(at(x, y), BORDER);
+			assert_eq!(at(x.abs_diff(2), y.abs_diff(2)), PIXEL);
+			assert_eq!(at(x.abs_diff(4), y), BORDER);
+			assert_eq!(at(x.abs_diff(5), y), PIXEL);
+			assert_eq!(at(x, y.abs_diff(4)), BORDER);
+			assert_eq!(at(x, y.abs_diff(5)), PIXEL);
+		});
+		assert_eq!(at(width / 2, 0), PIXEL);
+		assert_eq!(at(0, height / 2), PIXEL);
+	}
+}
@@ -0,0 +1,42 @@
+use crate::gameboy::{GameBoy, RunOutcome};
+use std::collections::BTreeMap;
+
+// Cycles from one VBlank to the next on a single speed DMG, 154 lines of 456 cycles
+pub const CYCLES_PER_FRAME: u64 = 70224;
+// ~59.73 at 4 MiHz
+pub const FRAMES_PER_SECOND: f64 = 4_194_304.0 / CYCLES_PER_FRAME as f64;
+
+// Runs `frames` frames and returns how many of them took each number of cycles. Frames are
+// measured between consecutive VBlank entries, so the partial frame before the first VBlank
+// isn't part of the distribution. Stops early if emulation stalls.
+pub fn measure(gameboy: &mut GameBoy, frames: usize) -> BTreeMap<u64, usize> {
+	let mut distribution = BTreeMap::new();
+	for _ in 0..frames {
+		if let RunOutcome::Stalled(_) = gameboy.run_frame() {
+			break;
+		}
+		if let Some(cycles) = gameboy.frame_cycles() {
+			*distribution.entry(cycles).or_insert(0) += 1;
+		}
+	}
+	distribution
+}
+
+// Prints the distribution, returns true if every frame took exactly CYCLES_PER_FRAME cycles
+pub fn report(distribution: &BTreeMap<u64, usize>) -> bool {
+	distribution.iter().for_each(|(cycles, count)| {
+		println!(
+			"{:>8} cycles: {:>6} frame(s){}",
+			cycles,
+			count,
+			if *cycles == CYCLES_PER_FRAME {
+				""
+			} else {
+				" <- unexpected"
+			}
+		)
+	});
+	distribution
+		.keys()
+		.all(|&cycles| cycles == CYCLES_PER_FRAME)
+}
@@ -0,0 +1,1246 @@
+use crate::{
+	DEBUG_FLAG, compat,
+	compatreport::CompatReport,
+	frametiming::CYCLES_PER_FRAME,
+	header,
+	joypad::Button,
+	mmu::MMU,
+	options::{EmulatorOptions, InputLatch},
+	ppu::PPU,
+	save::RtcState,
+	statefile::StateFile,
+	symbols::Symbols,
+	utils,
+};
+use log::warn;
+use std::{
+	fmt, fs, io, mem,
+	path::Path,
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+// timer internals, see timer_state()
+pub use crate::mmu::TimerState;
+// mapper registers, see banking_state()
+pub use crate::cartridge::BankingState;
+// PC hooks, see add_pc_hook()
+pub use crate::cpu::{Bus, CPU, HookAction, PcHook};
+// dispatches by interrupt type, see interrupt_counts()
+pub use crate::cpu::InterruptCounts;
+// DMG shades, see set_palette()
+pub use crate::ppu::{PALETTES, Palette};
+// see set_layers()
+pub use crate::ppu::Layers;
+
+fn unix_time() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs())
+}
+
+// Emulation can't make progress: interrupts keep getting dispatched without the interrupted code
+// executing a single instruction in between, or no frame got completed for a long time. Mostly a bug (ours or the
+// game's) that would otherwise show up as a frozen screen at full CPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stall {
+	pub reason: &'static str,
+	pub pc: u16,
+	pub ie: u8,
+	pub if_reg: u8,
+	pub ime: bool,
+}
+
+impl fmt::Display for Stall {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} (PC:{:04X} IE:{:02X} IF:{:02X} IME:{})",
+			self.reason, self.pc, self.ie, self.if_reg, self.ime
+		)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunOutcome {
+	FrameCompleted,
+	Stalled(Stall),
+}
+
+// Result of run_cycles. Instructions aren't split, so the last one usually runs past the budget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CyclesRun {
+	pub cycles: u64,
+	// cycles run beyond the budget
+	pub overshoot: u64,
+	pub frame_completed: bool,
+}
+
+// Complete emulation state at some point, restoring it resumes emulation deterministically
+// from there. Options, observers & pending input aren't part of it.
+#[derive(Clone)]
+pub struct Snapshot {
+	cpu: CPU,
+	mmu: MMU,
+	ppu: PPU,
+	instructions: u64,
+	frame_count: u64,
+	cycles: u64,
+	frame_boundary_cycle: Option<u64>,
+	frame_cycles: Option<u64>,
+}
+
+impl Snapshot {
+	// memory held by the snapshot, shared data (rom & symbols) not included
+	pub fn size(&self) -> usize {
+		size_of::<Snapshot>() + self.mmu.heap_size() + self.ppu.heap_size()
+	}
+}
+
+// Stop condition for fast_forward_until, checked after every instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+	// about to execute the instruction at this address
+	Pc(u16),
+	// byte at the address (as seen by the CPU) equals the value
+	MemoryEquals(u16, u8),
+	// this many frames completed since fast forwarding started
+	Frames(u64),
+}
+
+// Receives the frame number (starting at 1) & the completed frame
+pub type FrameObserver = Box<dyn FnMut(u64, &[u32]) + Send>;
+
+// A frame ends on the PPU entering VBLANK at LY 144, the one boundary everything frame based keys
+// off: frame_count, the return value of step/run_frame, the VBlank input latch & frame observers.
+pub struct GameBoy {
+	cpu: CPU,
+	mmu: MMU,
+	ppu: PPU,
+	instructions: u64,
+	input_latch: InputLatch,
+	pending_input: Option<Vec<Button>>,
+	frame_count: u64,
+	cycles: u64,
+	// cycle count at the last frame boundary & the cycles between last two boundaries
+	frame_boundary_cycle: Option<u64>,
+	frame_cycles: Option<u64>,
+	frame_observers: Vec<FrameObserver>,
+	// stall detection, cycles allowed without a frame completing
+	stall_cycles: u64,
+	// dispatches since the last instruction retired outside of interrupt handlers, SP the outermost
+	// handler returns to & cycles spent in handlers since the last dispatch
+	dispatches_in_a_row: u32,
+	isr_stack: Option<u16>,
+	isr_cycles: u64,
+	cycles_since_frame: u64,
+	// the ongoing stall, None once emulation makes progress again
+	stall: Option<Stall>,
+	// what the GameBoy got built from, to power it on again on reset
+	rom: Arc<[u8]>,
+	boot_rom: Option<Vec<u8>>,
+	options: EmulatorOptions,
+}
+
+impl GameBoy {
+	// Options of known games (see compat) get their quirks turned on
+	pub fn new(cartridge: Arc<[u8]>, boot_rom: Option<Vec<u8>>, options: &EmulatorOptions) -> Self {
+		let options = &compat::with_quirks(&cartridge, options);
+		let (cpu, mmu, ppu) = Self::power_on(cartridge.clone(), boot_rom.clone(), options);
+		let mut gameboy = GameBoy {
+			cpu,
+			mmu,
+			ppu,
+			instructions: 0,
+			input_latch: options.input_latch,
+			pending_input: None,
+			frame_count: 0,
+			cycles: 0,
+			frame_boundary_cycle: None,
+			frame_cycles: None,
+			frame_observers: Vec::new(),
+			stall_cycles: options.stall_frames * CYCLES_PER_FRAME,
+			dispatches_in_a_row: 0,
+			isr_stack: None,
+			isr_cycles: 0,
+			cycles_since_frame: 0,
+			stall: None,
+			rom: cartridge,
+			boot_rom,
+			options: options.clone(),
+		};
+		gameboy.hold_boot_buttons();
+		if options.skip_logo {
+			gameboy.skip_boot_rom();
+		}
+		gameboy
+	}
+
+	fn power_on(
+		cartridge: Arc<[u8]>,
+		boot_rom: Option<Vec<u8>>,
+		options: &EmulatorOptions,
+	) -> (CPU, MMU, PPU) {
+		let mut cpu = CPU::new(boot_rom.is_some());
+		cpu.set_log_interrupts(options.log_interrupts);
+		cpu.set_trace_format(options.trace_format);
+		let mut mmu = MMU::new(cartridge, boot_rom, options);
+		let mut ppu = PPU::from_mmu(&mmu, options.double_buffer);
+		if let Some(seed) = options.initial_phase {
+			let (div_counter, ly, cycles) = Self::power_up_phase(seed);
+			mmu.set_div_counter(div_counter);
+			ppu.set_phase(&mut mmu, ly, cycles);
+		}
+		ppu.set_early_ly_reset(options.early_ly_reset);
+		ppu.set_dma_blocks_oam(options.dma_blocks_oam);
+		ppu.set_palette(options.palette);
+		ppu.set_layers(options.layers);
+		ppu.set_fifo_trace_line(options.trace_fifo);
+		(cpu, mmu, ppu)
+	}
+
+	// (DIV counter, LY, cycles into the line) to power up with for --initial-phase `seed`: three
+	// values of SplitMix64 seeded with it, DIV counter & cycles kept M-cycle aligned. The PPU
+	// starts somewhere in VBLANK (LY 144-153) so its state is one it could be in. Without a seed
+	// it's DIV counter 0xABCC, LY 0 & cycle 0.
+	pub fn power_up_phase(seed: u64) -> (u16, u8, u16) {
+		let mut state = seed;
+		let div_counter = utils::splitmix64(&mut state) as u16 & !3;
+		let ly = 144 + (utils::splitmix64(&mut state) % 10) as u8;
+		let cycles = (utils::splitmix64(&mut state) % 456) as u16 & !3;
+		(div_counter, ly, cycles)
+	}
+
+	// Hard reset is a power cycle, everything starts over incl. cartridge RAM unless it's battery
+	// backed. Soft reset is the console's reset: CPU, PPU, timers & work RAM start over while the
+	// cartridge (RAM & banking registers) is kept as is. Symbols, frame observers, the palette &
+	// layers survive either.
+	pub fn reset(&mut self, hard: bool) {
+		let (mut cpu, mut mmu, mut ppu) =
+			Self::power_on(self.rom.clone(), self.boot_rom.clone(), &self.options);
+		cpu.share_symbols(&self.cpu);
+		cpu.share_hooks(&self.cpu);
+		mmu.share_report(&self.mmu);
+		match hard {
+			true if self.has_battery() => {
+				mmu.load_cartridge_ram(self.mmu.cartridge_ram());
+				if let Some(rtc) = self.mmu.rtc() {
+					mmu.load_rtc(&rtc, 0);
+				}
+			}
+			true => {}
+			false => mmu.swap_cartridge(&mut self.mmu),
+		};
+		ppu.set_palette(self.ppu.palette());
+		ppu.set_layers(self.ppu.layers());
+		self.cpu = cpu;
+		self.mmu = mmu;
+		self.ppu = ppu;
+		self.instructions = 0;
+		self.pending_input = None;
+		self.frame_count = 0;
+		self.cycles = 0;
+		self.frame_boundary_cycle = None;
+		self.frame_cycles = None;
+		self.dispatches_in_a_row = 0;
+		self.isr_stack = None;
+		self.isr_cycles = 0;
+		self.cycles_since_frame = 0;
+		self.stall = None;
+		self.hold_boot_buttons();
+		if self.options.skip_logo {
+			self.skip_boot_rom();
+		}
+	}
+
+	// rom from anywhere (download, embedded asset, test fixture), default options & no boot rom
+	pub fn from_bytes(rom: Vec<u8>) -> Self {
+		Self::new(rom.into(), None, &EmulatorOptions::default())
+	}
+
+	pub fn from_file(path: impl AsRef<Path>, options: &EmulatorOptions) -> io::Result<Self> {
+		Ok(Self::new(fs::read(path)?.into(), None, options))
+	}
+
+	// Runs the boot rom headless until it hands over control to the game (unmaps itself), intro
+	// frames aren't counted. A boot rom failing its checks locks up, so give up after a while.
+	fn skip_boot_rom(&mut self) {
+		const MAX_FRAMES: u64 = 600;
+		while self.mmu.is_boot_rom_mapped() && self.frame_count < MAX_FRAMES {
+			self.step();
+		}
+		if self.mmu.is_boot_rom_mapped() {
+			warn!("boot rom still running after {} frames", MAX_FRAMES);
+		}
+		self.frame_count = 0;
+	}
+
+	// executes a single instruction (or interrupt dispatch) and advances rest of the hardware
+	// by the same number of cycles, returns true if a frame got completed meanwhile.
+	// Interrupts are checked at the top of the next execute_next, i.e. once the timer & PPU have
+	// caught up with every M-cycle of this instruction & before the next opcode fetch: IF set in
+	// any of them (the last one included) is dispatched right after this instruction, as on
+	// hardware where the check overlaps the fetch.
+	pub fn step(&mut self) -> bool {
+		if DEBUG_FLAG {
+			self.cpu.set_trace_cycles(self.cycles);
+		}
+		let cycles = self.cpu.execute_next(&mut self.mmu);
+		self.instructions += 1;
+		let mut frame_ready = false;
+		(0..cycles).for_each(|_| {
+			self.mmu.update_timers(1);
+			self.ppu.tick(&mut self.mmu);
+			self.cycles += 1;
+			if self.ppu.is_frame_ready() {
+				frame_ready = true;
+				self.frame_cycles = self.frame_boundary_cycle.map(|c| self.cycles - c);
+				self.frame_boundary_cycle = Some(self.cycles);
+			}
+			if self.ppu.is_frame_start()
+				&& let Some(pressed) = self.pending_input.take()
+			{
+				self.apply_input(&pressed);
+			}
+		});
+		self.detect_stall(cycles as u64, frame_ready);
+		if frame_ready {
+			self.frame_count += 1;
+			if self.frame_count == self.options.hold_frames {
+				self
+					.options
+					.hold
+					.iter()
+					.for_each(|&button| self.mmu.release_key(button));
+			}
+			let frame = self.ppu.get_frame_buffer();
+			self
+				.frame_observers
+				.iter_mut()
+				.for_each(|observer| observer(self.frame_count, frame));
+		}
+		frame_ready
+	}
+
+	// Instructions of interrupt handlers (EI, RETI...) don't count as progress, only the ones of the
+	// interrupted code do: the handler is left once SP is back where it was before the outermost
+	// dispatch. A handler that never returns becomes the interrupted code after a frame's worth of
+	// cycles without a dispatch. A stall lasts until emulation makes progress again & is logged
+	// once, emulation itself carries on as usual.
+	fn detect_stall(&mut self, cycles: u64, frame_ready: bool) {
+		const MAX_DISPATCHES_IN_A_ROW: u32 = 1000;
+		match (self.cpu.interrupt_dispatched(), self.isr_stack) {
+			(true, isr_stack) => {
+				self.dispatches_in_a_row += 1;
+				// the return address got pushed
+				self.isr_stack = isr_stack.or(Some(self.cpu.sp().wrapping_add(2)));
+				self.isr_cycles = 0;
+			}
+			(false, Some(isr_stack)) => {
+				self.isr_cycles += cycles;
+				if self.cpu.sp() >= isr_stack || self.isr_cycles > CYCLES_PER_FRAME {
+					self.isr_stack = None;
+				}
+			}
+			(false, None) => self.dispatches_in_a_row = 0,
+		};
+		self.cycles_since_frame = match frame_ready {
+			true => 0,
+			false => self.cycles_since_frame + cycles,
+		};
+
+		let reason = if self.dispatches_in_a_row > MAX_DISPATCHES_IN_A_ROW {
+			"interrupts dispatched without the interrupted code executing any instruction"
+		} else if self.stall_cycles > 0 && self.cycles_since_frame > self.stall_cycles {
+			"no frame completed"
+		} else {
+			self.stall = None;
+			return;
+		};
+		if self.stall.is_none() {
+			let stall = Stall {
+				reason,
+				pc: self.cpu.pc(),
+				ie: self.mmu.read_byte(0xFFFF),
+				if_reg: self.mmu.read_byte(0xFF0F),
+				ime: self.cpu.ime(),
+			};
+			warn!("emulation stalled: {}", stall);
+			self.stall = Some(stall);
+		}
+	}
+
+	pub fn snapshot(&self) -> Snapshot {
+		Snapshot {
+			cpu: self.cpu.clone(),
+			mmu: self.mmu.clone(),
+			ppu: self.ppu.clone(),
+			instructions: self.instructions,
+			frame_count: self.frame_count,
+			cycles: self.cycles,
+			frame_boundary_cycle: self.frame_boundary_cycle,
+			frame_cycles: self.frame_cycles,
+		}
+	}
+
+	pub fn restore(&mut self, snapshot: &Snapshot) {
+		self.cpu = snapshot.cpu.clone();
+		self.mmu = snapshot.mmu.clone();
+		let (palette, layers) = (self.ppu.palette(), self.ppu.layers());
+		self.ppu = snapshot.ppu.clone();
+		self.ppu.set_palette(palette);
+		self.ppu.set_layers(layers);
+		self.instructions = snapshot.instructions;
+		self.frame_count = snapshot.frame_count;
+		self.cycles = snapshot.cycles;
+		self.frame_boundary_cycle = snapshot.frame_boundary_cycle;
+		self.frame_cycles = snapshot.frame_cycles;
+		self.pending_input = None;
+		self.dispatches_in_a_row = 0;
+		self.isr_stack = None;
+		self.isr_cycles = 0;
+		self.cycles_since_frame = 0;
+	}
+
+	// meant to be taken right after a frame got completed, see StateFile
+	pub fn state_file(&self) -> StateFile {
+		StateFile {
+			registers: [self.cpu.af(), self.cpu.bc(), self.cpu.de(), self.cpu.hl()],
+			sp: self.cpu.sp(),
+			pc: self.cpu.pc(),
+			ime: self.cpu.ime(),
+			div_counter: self.mmu.timer_state().div_counter,
+			banking: self.mmu.banking_state(),
+			memory: self.mmu.memory_image().to_vec(),
+			cartridge_ram: self.mmu.cartridge_ram().to_vec(),
+		}
+	}
+
+	// Picks up from a state file taken with this rom, the frame shows up once the PPU completed
+	// the next one. Counters keep running from where they are.
+	pub fn load_state_file(&mut self, state: &StateFile) {
+		let [af, bc, de, hl] = state.registers;
+		self.cpu.set_af(af);
+		self.cpu.set_bc(bc);
+		self.cpu.set_de(de);
+		self.cpu.set_hl(hl);
+		self.cpu.set_sp(state.sp);
+		self.cpu.set_pc(state.pc);
+		self.cpu.set_ime(state.ime);
+		self.mmu.load_memory_image(&state.memory);
+		self.mmu.set_div_counter(state.div_counter);
+		self.mmu.set_banking_state(state.banking);
+		self.mmu.load_cartridge_ram(&state.cartridge_ram);
+		let (palette, layers) = (self.ppu.palette(), self.ppu.layers());
+		self.ppu = PPU::from_mmu(&self.mmu, self.options.double_buffer);
+		self.ppu.set_palette(palette);
+		self.ppu.set_layers(layers);
+		self.pending_input = None;
+		self.dispatches_in_a_row = 0;
+		self.isr_stack = None;
+		self.isr_cycles = 0;
+		self.cycles_since_frame = 0;
+	}
+
+	pub fn stall(&self) -> Option<&Stall> {
+		self.stall.as_ref()
+	}
+
+	// Runs as fast as possible until `condition` holds, giving up after `max_frames` frames.
+	// Returns true if the condition got met. Stalls don't stop it, see stall().
+	pub fn fast_forward_until(&mut self, condition: Condition, max_frames: u64) -> bool {
+		let start_frame = self.frame_count;
+		let met = |gameboy: &GameBoy| match condition {
+			Condition::Pc(pc) => gameboy.cpu.pc() == pc,
+			Condition::MemoryEquals(address, value) => gameboy.mmu.read_byte(address) == value,
+			Condition::Frames(frames) => gameboy.frame_count - start_frame >= frames,
+		};
+		while !met(self) {
+			if self.frame_count - start_frame >= max_frames {
+				return false;
+			}
+			self.step();
+		}
+		true
+	}
+
+	// Stops at the step a stall starts in (see stall()), so a stall is reported once
+	pub fn run_frame(&mut self) -> RunOutcome {
+		loop {
+			let stalled = self.stall.is_some();
+			let frame_ready = self.step();
+			if !stalled && let Some(stall) = &self.stall {
+				return RunOutcome::Stalled(stall.clone());
+			}
+			if frame_ready {
+				return RunOutcome::FrameCompleted;
+			}
+		}
+	}
+
+	// Runs instructions until at least `budget` cycles have passed, stepping exactly as run_frame
+	// does so any split of a run into slices ends in the same state. The overshoot isn't carried
+	// over, pass a budget reduced by it to stay on a fixed schedule.
+	pub fn run_cycles(&mut self, budget: u32) -> CyclesRun {
+		let start = self.cycles;
+		let mut frame_completed = false;
+		while self.cycles - start < budget as u64 {
+			frame_completed |= self.step();
+		}
+		let cycles = self.cycles - start;
+		CyclesRun {
+			cycles,
+			overshoot: cycles - budget as u64,
+			frame_completed,
+		}
+	}
+
+	// Run-ahead: emulates the next frame with the input as currently set & returns it, leaving the
+	// GameBoy as it was. Neither frame observers nor PC hooks (scripts included) run for that
+	// frame, their side effects happen once, in the frame that counts. A hook changing what the
+	// game does (skipping or replacing code) makes the returned frame differ from the next one.
+	pub fn run_ahead(&mut self) -> Vec<u32> {
+		let snapshot = self.snapshot();
+		// restoring the snapshot brings them back
+		self.cpu.detach_hooks();
+		let pending_input = self.pending_input.clone();
+		let (dispatches_in_a_row, isr_stack, isr_cycles, cycles_since_frame) = (
+			self.dispatches_in_a_row,
+			self.isr_stack,
+			self.isr_cycles,
+			self.cycles_since_frame,
+		);
+		let stall = self.stall.clone();
+		let frame_observers = mem::take(&mut self.frame_observers);
+
+		self.run_frame();
+		let frame = self.get_frame_buffer().to_vec();
+
+		self.restore(&snapshot);
+		self.pending_input = pending_input;
+		self.dispatches_in_a_row = dispatches_in_a_row;
+		self.isr_stack = isr_stack;
+		self.isr_cycles = isr_cycles;
+		self.cycles_since_frame = cycles_since_frame;
+		self.stall = stall;
+		self.frame_observers = frame_observers;
+		frame
+	}
+
+	// returns description of every violated invariant, meant to be called right after a frame
+	pub fn check_invariants(&self) -> Vec<String> {
+		let mut violations = self.cpu.check_invariants(&self.mmu);
+		violations.extend(self.ppu.check_invariants(&self.mmu));
+		violations
+	}
+
+	pub fn state(&self) -> String {
+		format!(
+			"instructions: {}\ncpu: {}\nppu: {}\ntimer: {}\nIE: {:02X}, IF: {:02X}",
+			self.instructions,
+			self.cpu,
+			self.ppu.state(),
+			self.mmu.timer_state(),
+			self.mmu.read_byte(0xFFFF),
+			self.mmu.read_byte(0xFF0F)
+		)
+	}
+
+	// Hex dump of [start, end) as seen by the CPU, i.e. with the currently banked ROM & cartridge
+	// RAM mapped in, every region is preceded by a line naming what is shown there
+	pub fn dump_memory(&self, start: u16, end: u16) -> String {
+		let banking = self.mmu.banking_state();
+		let region = |address: u16| match address {
+			0x4000..0x8000 => format!("ROM bank {}", self.mmu.rom_bank()),
+			0xA000..0xC000 => format!(
+				"cartridge RAM bank {}{}",
+				banking.ram_bank,
+				if banking.ram_enable {
+					""
+				} else {
+					" (disabled)"
+				}
+			),
+			_ => String::new(),
+		};
+
+		let mut dump = String::new();
+		let mut previous_region = None;
+		(start..end).step_by(16).for_each(|line| {
+			let name = region(line);
+			if previous_region.as_ref() != Some(&name) && !name.is_empty() {
+				dump += &format!("; {}\n", name);
+			}
+			previous_region = Some(name);
+			let bytes = (line..end.min(line.saturating_add(16)))
+				.map(|a| format!("{:02X}", self.mmu.read_byte(a)))
+				.collect::<Vec<_>>()
+				.join(" ");
+			dump += &format!("{:04X}: {}\n", line, bytes);
+		});
+		dump
+	}
+
+	pub fn timer_state(&self) -> TimerState {
+		self.mmu.timer_state()
+	}
+
+	// bank registers of the mapper, e.g. for a debugger to show which banks are mapped in
+	pub fn banking_state(&self) -> BankingState {
+		self.mmu.banking_state()
+	}
+
+	// switches banks as the game's writes to the mapper would, fields the mapper lacks are ignored
+	pub fn set_banking_state(&mut self, state: BankingState) {
+		self.mmu.set_banking_state(state);
+	}
+
+	// true if cartridge RAM is battery backed, as the cartridge type says regardless of RAM size
+	pub fn has_battery(&self) -> bool {
+		header::has_battery(self.rom[0x0147])
+	}
+
+	// all of cartridge RAM (every bank), what a battery save holds
+	pub fn cartridge_ram(&self) -> &[u8] {
+		self.mmu.cartridge_ram()
+	}
+
+	// e.g. from a battery save, a shorter save fills only the start & a longer one is cut off
+	pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+		self.mmu.load_cartridge_ram(data);
+	}
+
+	// MBC3 clock as of now (the timestamp), None on cartridges without one
+	pub fn rtc(&self) -> Option<RtcState> {
+		self.mmu.rtc().map(|rtc| RtcState {
+			timestamp: unix_time(),
+			..rtc
+		})
+	}
+
+	// MBC3 clock from a battery save, caught up with the wall time passed since it got saved
+	pub fn load_rtc(&mut self, rtc: &RtcState) {
+		self
+			.mmu
+			.load_rtc(rtc, unix_time().saturating_sub(rtc.timestamp));
+	}
+
+	pub fn vram(&self) -> &[u8] {
+		self.mmu.vram()
+	}
+
+	// memory as seen by the CPU, without any side effect
+	pub fn read_byte(&self, address: u16) -> u8 {
+		self.mmu.read_byte(address)
+	}
+
+	// write as done by the CPU, with all its side effects (e.g. bank switching)
+	pub fn write_byte(&mut self, address: u16, value: u8) {
+		self.mmu.write_byte(address, value);
+	}
+
+	pub fn get_frame_buffer(&self) -> &[u32] {
+		self.ppu.get_frame_buffer()
+	}
+
+	pub fn frame_count(&self) -> u64 {
+		self.frame_count
+	}
+
+	// cycles between the last two frame boundaries, None until two frames are completed
+	pub fn frame_cycles(&self) -> Option<u64> {
+		self.frame_cycles
+	}
+
+	pub fn add_frame_observer(&mut self, observer: FrameObserver) {
+		self.frame_observers.push(observer);
+	}
+
+	pub fn is_frame_changed(&self) -> bool {
+		self.ppu.is_frame_changed()
+	}
+
+	pub fn get_dropped_sprites(&self) -> &[(u8, u8)] {
+		self.ppu.get_dropped_sprites()
+	}
+
+	pub fn palette(&self) -> Palette {
+		self.ppu.palette()
+	}
+
+	// Shades the 4 DMG colors are drawn with, kept across reset() & restore() as it's a display
+	// setting rather than emulated state
+	pub fn set_palette(&mut self, palette: Palette) {
+		self.ppu.set_palette(palette);
+	}
+
+	pub fn layers(&self) -> Layers {
+		self.ppu.layers()
+	}
+
+	// Layers drawn, kept across reset() & restore() like the palette
+	pub fn set_layers(&mut self, layers: Layers) {
+		self.ppu.set_layers(layers);
+	}
+
+	// events tracked so far, None without --compat-report
+	pub fn compat_report(&self) -> Option<CompatReport> {
+		self
+			.mmu
+			.report()
+			.map(|report| report.lock().unwrap().clone())
+	}
+
+	pub fn interrupt_counts(&self) -> InterruptCounts {
+		self.cpu.interrupt_counts()
+	}
+
+	pub fn cpu(&self) -> &CPU {
+		&self.cpu
+	}
+
+	pub fn cpu_mut(&mut self) -> &mut CPU {
+		&mut self.cpu
+	}
+
+	pub fn set_symbols(&mut self, symbols: Symbols) {
+		self.cpu.set_symbols(symbols);
+	}
+
+	// `hook` runs whenever the CPU is about to execute the instruction at `address` (see PcHook),
+	// a hook already there is replaced. Hooks survive resets & restoring snapshots.
+	pub fn add_pc_hook(&mut self, address: u16, hook: PcHook) {
+		self.cpu.add_pc_hook(address, hook);
+	}
+
+	pub fn remove_pc_hook(&mut self, address: u16) {
+		self.cpu.remove_pc_hook(address);
+	}
+
+	// hooks a routine by its label in the symbols, false if there's no such label
+	pub fn add_label_hook(&mut self, label: &str, hook: PcHook) -> bool {
+		match self.cpu.symbol_address(label) {
+			Some(address) => {
+				self.add_pc_hook(address, hook);
+				true
+			}
+			None => false,
+		}
+	}
+
+	pub fn take_serial_output(&mut self) -> Vec<u8> {
+		self.mmu.take_serial_output()
+	}
+
+	fn apply_input(&mut self, pressed: &[Button]) {
+		Button::values()
+			.iter()
+			.for_each(|&button| match pressed.contains(&button) {
+				true => self.mmu.press_key(button),
+				false => self.mmu.release_key(button),
+			});
+	}
+
+	// --hold from power on & reset, the buttons get released once hold_frames frames completed
+	fn hold_boot_buttons(&mut self) {
+		if self.options.hold_frames > 0 {
+			self
+				.options
+				.hold
+				.iter()
+				.for_each(|&button| self.mmu.press_key(button));
+		}
+	}
+
+	// sets the buttons currently held (plus the --hold ones early on), applied according to the
+	// input latch option
+	pub fn set_input(&mut self, mut pressed: Vec<Button>) {
+		if self.frame_count < self.options.hold_frames {
+			pressed.extend(&self.options.hold);
+		}
+		match self.input_latch {
+			InputLatch::VBlank => self.apply_input(&pressed),
+			InputLatch::FrameStart => self.pending_input = Some(pressed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::hash::{Hash, Hasher};
+
+	// ROM ONLY cart with `program` at the 0x0100 entry point & `handler` at the VBlank vector,
+	// started in the post boot state
+	fn gameboy(program: &[u8], handler: &[u8]) -> GameBoy {
+		gameboy_with(program, handler, &EmulatorOptions::default())
+	}
+
+	fn gameboy_with(program: &[u8], handler: &[u8], options: &EmulatorOptions) -> GameBoy {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0040..0x0040 + handler.len()].copy_from_slice(handler);
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+		GameBoy::new(rom.into(), None, options)
+	}
+
+	// IE = IF = VBlank, EI & loop forever
+	const ENABLE_VBLANK: [u8; 9] = [0x3E, 0x01, 0xE0, 0xFF, 0xE0, 0x0F, 0xFB, 0x18, 0xFE];
+
+	#[test]
+	fn lock_vram_renders_the_same_frame() {
+		let render = |lock_vram: bool| {
+			let options = EmulatorOptions {
+				lock_vram,
+				..EmulatorOptions::default()
+			};
+			// loop forever over a checkerboard of tile 1 with an object on top
+			let mut rom = vec![0; 0x8000];
+			rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+			let mut gb = GameBoy::new(rom.into(), None, &options);
+			(0..16).for_each(|i| {
+				gb.mmu
+					.write_register(0x8010 + i, [0xAA, 0x55][i as usize % 2])
+			});
+			(0..0x400).for_each(|i| gb.mmu.write_register(0x9800 + i, (i + i / 32) as u8 & 1));
+			[40, 40, 0x01, 0x00]
+				.iter()
+				.enumerate()
+				.for_each(|(i, &b)| gb.mmu.write_register(0xFE00 + i as u16, b));
+			gb.mmu.write_register(0xFF40, 0x93);
+			(0..2).for_each(|_| {
+				gb.run_frame();
+			});
+			gb.get_frame_buffer().to_vec()
+		};
+		let frame = render(true);
+		assert!(frame.iter().any(|&pixel| pixel != frame[0]));
+		assert_eq!(frame, render(false));
+	}
+
+	#[test]
+	fn hidden_layers_only_change_the_pixels() {
+		// background of tile 1 (color 3) with the window (tile 0, color 0) over its lower half & an
+		// object of tile 2 (color 1) on top, LDH A,(0x41) & loop to sample STAT along the way
+		let render = |layers: Layers| {
+			let mut gb = gameboy(&[0xF0, 0x41, 0x18, 0xFC], &[]);
+			gb.set_layers(layers);
+			(0..16).for_each(|i| gb.mmu.write_register(0x8010 + i, 0xFF));
+			(0..16).for_each(|i| {
+				gb.mmu
+					.write_register(0x8020 + i, [0xFF, 0x00][i as usize % 2])
+			});
+			(0..0x400).for_each(|i| gb.mmu.write_register(0x9800 + i, 1));
+			[40, 40, 0x02, 0x00]
+				.iter()
+				.enumerate()
+				.for_each(|(i, &b)| gb.mmu.write_register(0xFE00 + i as u16, b));
+			gb.mmu.write_register(0xFF48, 0xE4);
+			gb.mmu.write_register(0xFF4A, 72);
+			gb.mmu.write_register(0xFF4B, 7);
+			gb.mmu.write_register(0xFF40, 0xF3);
+			let stat: Vec<u8> = (0..2 * 70224 / 12)
+				.map(|_| {
+					gb.step();
+					gb.cpu.a()
+				})
+				.collect();
+			(gb.get_frame_buffer().to_vec(), stat, gb.cycles)
+		};
+		let shades = Palette::default().shades;
+		let (frame, stat, cycles) = render(Layers::default());
+		let pixel = |frame: &[u32], x: usize, y: usize| frame[y * crate::WIDTH + x];
+		// object at (32, 24), window from line 72
+		assert_eq!(pixel(&frame, 32, 24), shades[1]);
+		assert_eq!(pixel(&frame, 0, 0), shades[3]);
+		assert_eq!(pixel(&frame, 0, 100), shades[0]);
+		[
+			("background", (0, 0), shades[0]),
+			("window", (0, 100), shades[0]),
+			("objects", (32, 24), shades[3]),
+		]
+		.iter()
+		.for_each(|&(hidden, (x, y), shade)| {
+			let (hidden_frame, hidden_stat, hidden_cycles) = render(Layers::hiding(hidden).unwrap());
+			assert_eq!(pixel(&hidden_frame, x, y), shade, "{} hidden", hidden);
+			assert_eq!((hidden_stat, hidden_cycles), (stat.clone(), cycles));
+		});
+	}
+
+	#[test]
+	fn ly_polling_sees_every_line_for_456_cycles() {
+		// LDH A,(0x44); JR -4, a read every 24 cycles
+		let mut gb = gameboy(&[0xF0, 0x44, 0x18, 0xFC], &[]);
+		let mut reads = Vec::new();
+		while reads.len() < 3 * 154 * 19 {
+			gb.step();
+			if gb.cpu.pc() == 0x0102 {
+				reads.push(gb.cpu.a());
+			}
+		}
+		// runs of the same value, leaving out the partial first & last ones
+		let mut runs: Vec<(u8, usize)> = Vec::new();
+		reads.iter().for_each(|&ly| match runs.last_mut() {
+			Some((value, count)) if *value == ly => *count += 1,
+			_ => runs.push((ly, 1)),
+		});
+		let runs = &runs[1..runs.len() - 1];
+		assert!(runs.len() > 154);
+		runs
+			.windows(2)
+			.for_each(|w| assert_eq!(w[1].0, ((w[0].0 as u16 + 1) % 154) as u8));
+		// 456 / 24
+		assert!(runs.iter().all(|&(_, count)| count == 19));
+	}
+
+	// Return address pushed by the timer interrupt, when TIMA overflows `t` cycles into `program`
+	// (started with EI) & the handler loops forever
+	fn timer_interrupt_return_address(program: &[u8], t: u16) -> u16 {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0050..0x0052].copy_from_slice(&[0x18, 0xFE]);
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+		let mut gb = GameBoy::from_bytes(rom);
+		gb.mmu.write_byte(0xFFFF, 0x04);
+		gb.mmu.write_byte(0xFF0F, 0x00);
+		gb.mmu.write_byte(0xFF05, 0xFF);
+		// TIMA counts on the falling edge of DIV bit 5, `t` cycles away
+		gb.mmu.set_div_counter(64 - t);
+		gb.mmu.write_byte(0xFF07, 0x06);
+		while !gb.cpu.interrupt_dispatched() {
+			gb.step();
+		}
+		assert_eq!(gb.cpu.pc(), 0x0050);
+		gb.mmu.read_word(gb.cpu.sp())
+	}
+
+	#[test]
+	fn interrupt_is_dispatched_after_the_instruction_raising_it() {
+		// EI & NOPs: whichever NOP's M-cycle sets IF is the last one before the dispatch, the one
+		// right after EI included as it turns IME on
+		let mut program = [0x00; 16];
+		program[0] = 0xFB;
+		for t in 5..=40 {
+			assert_eq!(
+				timer_interrupt_return_address(&program, t),
+				0x0100 + (t - 1) / 4 + 1,
+				"IF set {} cycles in",
+				t
+			);
+		}
+	}
+
+	#[test]
+	fn if_set_in_the_last_m_cycle_is_not_delayed() {
+		// EI; NOP; LD A,(C000) over cycles 8-24; NOP; NOP
+		let program = [0xFB, 0x00, 0xFA, 0x00, 0xC0, 0x00, 0x00];
+		for t in 9..=24 {
+			assert_eq!(timer_interrupt_return_address(&program, t), 0x0105);
+		}
+		for t in 25..=28 {
+			assert_eq!(timer_interrupt_return_address(&program, t), 0x0106);
+		}
+	}
+
+	#[test]
+	fn reader_never_sees_a_half_updated_frame() {
+		// LDH A,(0x47); XOR 0x03; LDH (0x47),A; RETI: BGP flips at every VBlank, so each frame is
+		// a single shade & its first & last pixels make for sentinels
+		const FLIP_BGP: [u8; 7] = [0xF0, 0x47, 0xEE, 0x03, 0xE0, 0x47, 0xD9];
+		let half_updated = |gb: &GameBoy| {
+			let frame = gb.get_frame_buffer();
+			frame[0] != frame[crate::WIDTH * crate::HEIGHT - 1]
+		};
+		// drawing straight into the frame buffer does get caught
+		let mut gb = gameboy(&ENABLE_VBLANK, &FLIP_BGP);
+		assert!((0..3 * 70224 / 4).any(|_| {
+			gb.step();
+			half_updated(&gb)
+		}));
+
+		// emulation on its own thread, the reader in between any two instructions
+		let options = EmulatorOptions {
+			double_buffer: true,
+			..EmulatorOptions::default()
+		};
+		let gb = Arc::new(std::sync::Mutex::new(gameboy_with(
+			&ENABLE_VBLANK,
+			&FLIP_BGP,
+			&options,
+		)));
+		let emulator = {
+			let gb = gb.clone();
+			std::thread::spawn(move || {
+				while gb.lock().unwrap().frame_count < 10 {
+					gb.lock().unwrap().step();
+				}
+			})
+		};
+		while !emulator.is_finished() {
+			assert!(!half_updated(&gb.lock().unwrap()));
+		}
+		emulator.join().unwrap();
+		let gb = gb.lock().unwrap();
+		assert!(!half_updated(&gb));
+		assert_eq!(gb.frame_count, 10);
+	}
+
+	#[test]
+	fn frame_observers_fire_once_per_frame() {
+		let mut gb = gameboy(&ENABLE_VBLANK, &[0xD9]);
+		let seen: Vec<Arc<std::sync::Mutex<Vec<u64>>>> = (0..3).map(|_| Arc::default()).collect();
+		seen.iter().for_each(|frames| {
+			let frames = frames.clone();
+			gb.add_frame_observer(Box::new(move |frame_count, frame| {
+				assert_eq!(frame.len(), crate::WIDTH * crate::HEIGHT);
+				frames.lock().unwrap().push(frame_count);
+			}));
+		});
+		for _ in 0..5 {
+			gb.run_cycles(3 * 70224 + 1000);
+			// run-ahead frames aren't observed
+			gb.run_ahead();
+		}
+		let expected = (1..=gb.frame_count()).collect::<Vec<_>>();
+		assert!(expected.len() >= 15);
+		seen
+			.iter()
+			.for_each(|frames| assert_eq!(*frames.lock().unwrap(), expected));
+	}
+
+	#[test]
+	fn dump_memory_shows_the_banks_mapped_in() {
+		// MBC1+RAM, 128 KiB with the bank number at the start of each bank & 8 KiB of RAM
+		let mut rom = vec![0; 0x20000];
+		(0..8).for_each(|bank| rom[bank * 0x4000] = bank as u8);
+		rom[0x0147] = 0x02;
+		rom[0x0148] = 0x02;
+		rom[0x0149] = 0x02;
+		let mut gb = GameBoy::from_bytes(rom);
+		gb.set_banking_state(BankingState {
+			rom_bank: 5,
+			ram_enable: true,
+			..gb.banking_state()
+		});
+		gb.mmu.write_byte(0xA000, 0x42);
+		assert_eq!(
+			gb.dump_memory(0x4000, 0x4004),
+			"; ROM bank 5\n4000: 05 00 00 00\n"
+		);
+		assert_eq!(
+			gb.dump_memory(0xA000, 0xA002),
+			"; cartridge RAM bank 0\nA000: 42 00\n"
+		);
+		gb.set_banking_state(BankingState {
+			ram_enable: false,
+			..gb.banking_state()
+		});
+		assert_eq!(
+			gb.dump_memory(0xA000, 0xA002),
+			"; cartridge RAM bank 0 (disabled)\nA000: FF FF\n"
+		);
+	}
+
+	#[test]
+	fn tiny_rom_is_padded() {
+		let mut gameboy = GameBoy::from_bytes(vec![0xAA; 0x100]);
+		assert_eq!(gameboy.read_byte(0x00FF), 0xAA);
+		assert_eq!(gameboy.read_byte(0x0100), 0x00);
+		assert_eq!(gameboy.read_byte(0x7FFF), 0x00);
+		// runs the padding as NOPs
+		gameboy.step();
+		assert_eq!(gameboy.cpu().pc(), 0x0101);
+		assert_eq!(gameboy.run_frame(), RunOutcome::FrameCompleted);
+	}
+
+	#[test]
+	fn handler_raising_its_own_interrupt_stalls() {
+		// LD A,1; LDH (FF0F),A; RETI
+		let mut gameboy = gameboy(&ENABLE_VBLANK, &[0x3E, 0x01, 0xE0, 0x0F, 0xD9]);
+		match gameboy.run_frame() {
+			RunOutcome::Stalled(stall) => {
+				assert!(stall.reason.starts_with("interrupts dispatched"));
+				assert_eq!((stall.ie & 0x01, stall.ime), (0x01, false));
+			}
+			outcome => panic!("{:?}", outcome),
+		}
+		// reported once, the stall goes on but frames still complete
+		assert_eq!(gameboy.run_frame(), RunOutcome::FrameCompleted);
+		assert!(gameboy.stall().is_some());
+	}
+
+	#[test]
+	fn stall_ends_with_progress() {
+		// the handler re-raises VBlank until BC counts down to 0:
+		// LD A,B; OR C; JR Z,+6; DEC BC; LD A,1; LDH (FF0F),A; RETI; RETI
+		let handler = [
+			0x78, 0xB1, 0x28, 0x06, 0x0B, 0x3E, 0x01, 0xE0, 0x0F, 0xD9, 0xD9,
+		];
+		// LD BC,3000 then ENABLE_VBLANK, ~80 cycles a dispatch
+		let mut program = vec![0x01, 0xB8, 0x0B];
+		program.extend(ENABLE_VBLANK);
+		let mut gameboy = gameboy(&program, &handler);
+		gameboy.run_cycles(2 * CYCLES_PER_FRAME as u32);
+		assert!(gameboy.stall().is_some());
+		gameboy.run_cycles(2 * CYCLES_PER_FRAME as u32);
+		assert_eq!(gameboy.stall(), None);
+		// only regular VBlanks from now on
+		(0..10).for_each(|_| assert_eq!(gameboy.run_frame(), RunOutcome::FrameCompleted));
+	}
+
+	// everything that tells two runs apart: registers, PPU & timer state, memory, cartridge RAM,
+	// the frame & the counters
+	fn state_hash(gameboy: &GameBoy) -> u64 {
+		let mut hasher = std::hash::DefaultHasher::new();
+		gameboy.state().hash(&mut hasher);
+		gameboy.state_file().encode().hash(&mut hasher);
+		gameboy.get_frame_buffer().hash(&mut hasher);
+		(gameboy.cycles, gameboy.frame_count, gameboy.frame_cycles).hash(&mut hasher);
+		hasher.finish()
+	}
+
+	// (LY, DIV) as the instruction at 0x0100 is about to execute
+	fn phase_at_first_instruction(initial_phase: Option<u64>) -> (u8, u8) {
+		let options = EmulatorOptions {
+			initial_phase,
+			..EmulatorOptions::default()
+		};
+		let mut gameboy = gameboy_with(&[0x18, 0xFE], &[], &options);
+		let seen = Arc::new(std::sync::Mutex::new(None));
+		let phase = seen.clone();
+		gameboy.add_pc_hook(
+			0x0100,
+			Box::new(move |_, bus| {
+				phase
+					.lock()
+					.unwrap()
+					.get_or_insert((bus.read_byte(0xFF44), bus.read_byte(0xFF04)));
+				HookAction::Continue
+			}),
+		);
+		gameboy.step();
+		seen.lock().unwrap().unwrap()
+	}
+
+	#[test]
+	fn initial_phase_seeds_power_up_differently() {
+		// the fixed phase without a seed
+		assert_eq!(phase_at_first_instruction(None), (0x00, 0xAB));
+		let phase = |seed| {
+			let (div_counter, ly, _) = GameBoy::power_up_phase(seed);
+			(ly, (div_counter >> 8) as u8)
+		};
+		let (first, second) = (
+			phase_at_first_instruction(Some(1)),
+			phase_at_first_instruction(Some(2)),
+		);
+		assert_eq!(first, phase(1));
+		assert_eq!(second, phase(2));
+		assert_ne!(first.0, second.0);
+		assert_ne!(first.1, second.1);
+		assert!((144..154).contains(&first.0) && (144..154).contains(&second.0));
+		// & the same seed powers up the same every time
+		assert_eq!(phase_at_first_instruction(Some(1)), first);
+	}
+
+	#[test]
+	fn instances_sharing_a_rom_run_independently() {
+		// MBC1+RAM, 4 banks with the bank number at the start of each & 8 KiB of RAM. The program
+		// enables RAM, switches to bank 3 & counts $A000 up in a loop.
+		let mut rom = vec![0; 0x10000];
+		(0..4).for_each(|bank| rom[bank * 0x4000] = bank as u8);
+		rom[0x0147] = 0x02;
+		rom[0x0148] = 0x01;
+		rom[0x0149] = 0x02;
+		#[rustfmt::skip]
+		let program = [
+			0x3E, 0x0A, 0xEA, 0x00, 0x00, // LD A, $0A; LD ($0000), A
+			0x3E, 0x03, 0xEA, 0x00, 0x20, // LD A, $03; LD ($2000), A
+			0x21, 0x00, 0xA0,             // LD HL, $A000
+			0x34,                         // INC (HL)   ; loop
+			0x18, 0xFD,                   // JR loop
+		];
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+		let rom: Arc<[u8]> = rom.into();
+
+		let options = EmulatorOptions::default();
+		let mut running = GameBoy::new(rom.clone(), None, &options);
+		let idle = GameBoy::new(rom.clone(), None, &options);
+		assert!(Arc::ptr_eq(&running.rom, &idle.rom));
+
+		let running = std::thread::spawn(move || {
+			(0..3).for_each(|_| {
+				running.run_frame();
+			});
+			running
+		});
+		let mut other = GameBoy::new(rom.clone(), None, &options);
+		(0..2).for_each(|_| {
+			other.run_frame();
+		});
+		let running = running.join().unwrap();
+
+		assert_eq!(running.read_byte(0x4000), 3);
+		assert_ne!(running.cartridge_ram()[0], 0);
+		// each counted on its own RAM, a frame apart
+		assert_ne!(running.cartridge_ram()[0], other.cartridge_ram()[0]);
+		// untouched by either
+		assert_eq!(idle.read_byte(0x4000), 1);
+		assert!(idle.cartridge_ram().iter().all(|&b| b == 0));
+		assert_eq!(idle.frame_count(), 0);
+		assert_eq!(rom[0x4000 * 3], 3);
+	}
+
+	#[test]
+	fn run_cycles_slices_end_as_run_frame() {
+		let mut framed = GameBoy::from_bytes(crate::selftest::rom());
+		(0..5).for_each(|_| {
+			framed.run_frame();
+		});
+		// uneven slices, some shorter than an instruction & some spanning frames, then single steps
+		// up to where run_frame stopped
+		let mut sliced = GameBoy::from_bytes(crate::selftest::rom());
+		let mut slices = [1, 3, 4, 80, 456, 1000, 70224, 100_000, 7].iter().cycle();
+		while sliced.cycles + 100_000 < framed.cycles {
+			sliced.run_cycles(*slices.next().unwrap());
+		}
+		while sliced.cycles < framed.cycles {
+			sliced.step();
+		}
+		assert_eq!(sliced.cycles, framed.cycles);
+		assert_eq!(state_hash(&sliced), state_hash(&framed));
+
+		// & on from there, slices of a frame each stay in step with run_frame
+		(0..3).for_each(|_| {
+			framed.run_frame();
+			let run = sliced.run_cycles((framed.cycles - sliced.cycles) as u32);
+			assert!(run.frame_completed);
+			assert_eq!(run.overshoot, 0);
+		});
+		assert_eq!(state_hash(&sliced), state_hash(&framed));
+	}
+
+	#[test]
+	fn state_file_picks_up_where_it_was_taken() {
+		let mut gameboy = GameBoy::from_bytes(crate::selftest::rom());
+		(0..30).for_each(|_| {
+			gameboy.run_frame();
+		});
+		let state = StateFile::decode(&gameboy.state_file().encode()).unwrap();
+		let mut loaded = GameBoy::from_bytes(crate::selftest::rom());
+		loaded.load_state_file(&state);
+		assert_eq!(loaded.state_file(), state);
+		assert_eq!(loaded.vram(), gameboy.vram());
+		// the selftest is done & spinning, so the next frames look the same
+		(0..2).for_each(|_| {
+			gameboy.run_frame();
+			loaded.run_frame();
+		});
+		assert!(loaded.get_frame_buffer() == gameboy.get_frame_buffer());
+	}
+
+	#[test]
+	fn regular_interrupts_do_not_stall() {
+		let mut gameboy = gameboy(&ENABLE_VBLANK, &[0xD9]);
+		(0..100).for_each(|_| assert_eq!(gameboy.run_frame(), RunOutcome::FrameCompleted));
+		assert_eq!(gameboy.stall(), None);
+	}
+}
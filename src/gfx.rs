@@ -0,0 +1,240 @@
+use crate::{
+	gameboy::GameBoy,
+	png,
+	ppu::{PPU, Palette},
+	statefile::StateFile,
+	utils::is_bit_set,
+};
+use std::{fs, io, path::Path};
+
+// tile data (0x8000-0x97FF) holds 384 tiles of 16 bytes, 2bpp planar as in VRAM
+pub const TILE_COUNT: usize = 384;
+const TILE_DATA_SIZE: usize = TILE_COUNT * 16;
+const SHEET_COLUMNS: usize = 16;
+
+// tile data exactly as stored in VRAM, the .2bpp/.chr layout most tile editors expect
+pub fn tile_data(vram: &[u8]) -> &[u8] {
+	&vram[..TILE_DATA_SIZE]
+}
+
+// Tiles [first, first + count) in VRAM order, 16 per row, colored through `palette` (a BGP or
+// OBP value). Returns width, height & the pixels.
+pub fn tile_sheet(
+	vram: &[u8],
+	first: usize,
+	count: usize,
+	palette: u8,
+) -> (usize, usize, Vec<u32>) {
+	let (width, height) = (SHEET_COLUMNS * 8, count.div_ceil(SHEET_COLUMNS) * 8);
+	let mut pixels = vec![0; width * height];
+	let shades = Palette::default().shades;
+	tile_data(vram)[first * 16..(first + count) * 16]
+		.chunks(2)
+		.enumerate()
+		.for_each(|(row_index, row)| {
+			let (tile, row_in_tile) = (row_index / 8, row_index % 8);
+			let x = tile % SHEET_COLUMNS * 8;
+			let y = tile / SHEET_COLUMNS * 8 + row_in_tile;
+			PPU::get_tile_row(row[0], row[1])
+				.iter()
+				.enumerate()
+				.for_each(|(i, &color_id)| {
+					pixels[y * width + x + i] = shades[PPU::palette_to_shade(palette, color_id)];
+				});
+		});
+	(width, height, pixels)
+}
+
+// The 32x32 tile map at `base` rendered as the PPU would (256x256), tile data addressed as
+// selected by LCDC.4
+pub fn map_image(vram: &[u8], base: u16, lcdc: u8, palette: u8) -> (usize, usize, Vec<u32>) {
+	let offset = (base - 0x8000) as usize;
+	let mut pixels = vec![0; 256 * 256];
+	let shades = Palette::default().shades;
+	vram[offset..offset + 0x400]
+		.iter()
+		.enumerate()
+		.for_each(|(i, &index)| {
+			let tile = match is_bit_set(lcdc, 4) {
+				true => index as usize,
+				false => (256 + index as i8 as isize) as usize,
+			};
+			(0..8).for_each(|row| {
+				let data = &vram[tile * 16 + row * 2..];
+				PPU::get_tile_row(data[0], data[1])
+					.iter()
+					.enumerate()
+					.for_each(|(x, &color_id)| {
+						let (px, py) = (i % 32 * 8 + x, i / 32 * 8 + row);
+						pixels[py * 256 + px] = shades[PPU::palette_to_shade(palette, color_id)];
+					});
+			});
+		});
+	(256, 256, pixels)
+}
+
+// every pixel becomes a `scale` x `scale` block
+pub fn upscale(
+	(width, height, pixels): (usize, usize, Vec<u32>),
+	scale: usize,
+) -> (usize, usize, Vec<u32>) {
+	let scaled = (0..height * scale)
+		.flat_map(|y| (0..width * scale).map(move |x| (x / scale, y / scale)))
+		.map(|(x, y)| pixels[y * width + x])
+		.collect();
+	(width * scale, height * scale, scaled)
+}
+
+// The 32x32 tile map at `base` (0x9800 or 0x9C00) as CSV, a row per line. DMG has no VRAM bank 1
+// so there are no attribute bytes, only tile indices.
+pub fn map_csv(vram: &[u8], base: u16) -> String {
+	let offset = (base - 0x8000) as usize;
+	vram[offset..offset + 0x400]
+		.chunks(32)
+		.map(|row| {
+			row
+				.iter()
+				.map(|index| index.to_string())
+				.collect::<Vec<_>>()
+				.join(",")
+		})
+		.map(|line| line + "\n")
+		.collect()
+}
+
+// Writes into `dir` (created if needed):
+// - tiles.2bpp: raw tile data
+// - tiles.png: all tiles with BGP, sprites.png: object tiles (0x8000-0x8FFF) with OBP0
+// - bg_map.csv & window_map.csv: maps as currently selected by LCDC
+// - map_9800.png & map_9C00.png: both maps rendered with BGP
+// Images are upscaled by `scale`.
+pub fn export(dir: &Path, gameboy: &GameBoy, scale: usize) -> io::Result<()> {
+	export_vram(
+		dir,
+		&Registers::from(|address| gameboy.read_byte(address)),
+		gameboy.vram(),
+		scale,
+	)
+}
+
+// as export, from a save state without emulating anything
+pub fn export_state(dir: &Path, state: &StateFile, scale: usize) -> io::Result<()> {
+	export_vram(
+		dir,
+		&Registers::from(|address| state.byte(address)),
+		state.vram(),
+		scale,
+	)
+}
+
+// the registers the exported images depend on
+#[derive(Clone, Copy)]
+struct Registers {
+	lcdc: u8,
+	bgp: u8,
+	obp0: u8,
+}
+
+impl Registers {
+	fn from(read_byte: impl Fn(u16) -> u8) -> Self {
+		Registers {
+			lcdc: read_byte(0xFF40),
+			bgp: read_byte(0xFF47),
+			obp0: read_byte(0xFF48),
+		}
+	}
+}
+
+fn export_vram(dir: &Path, registers: &Registers, vram: &[u8], scale: usize) -> io::Result<()> {
+	let Registers { lcdc, bgp, obp0 } = *registers;
+	let map_base = |bit: u8| match is_bit_set(lcdc, bit) {
+		true => 0x9C00,
+		false => 0x9800,
+	};
+	let write_png = |name: &str, image| {
+		let (width, height, pixels) = upscale(image, scale);
+		fs::write(dir.join(name), png::encode(width, height, &pixels))
+	};
+
+	fs::create_dir_all(dir)?;
+	fs::write(dir.join("tiles.2bpp"), tile_data(vram))?;
+	write_png("tiles.png", tile_sheet(vram, 0, TILE_COUNT, bgp))?;
+	write_png("sprites.png", tile_sheet(vram, 0, 256, obp0))?;
+	write_png("map_9800.png", map_image(vram, 0x9800, lcdc, bgp))?;
+	write_png("map_9C00.png", map_image(vram, 0x9C00, lcdc, bgp))?;
+	fs::write(dir.join("bg_map.csv"), map_csv(vram, map_base(3)))?;
+	fs::write(dir.join("window_map.csv"), map_csv(vram, map_base(6)))?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::gameboy::BankingState;
+	use std::{env, process};
+
+	// tile 1 striped, the 0x9800 map counting up & the 0x9C00 map all tile 1, LCDC selecting
+	// 0x9800 for the background & 0x9C00 for the window
+	fn state() -> StateFile {
+		let mut memory = vec![0; 0x8000];
+		memory[0x10..0x20].copy_from_slice(&[0xFF, 0x00].repeat(8));
+		(0..0x400).for_each(|i| memory[0x1800 + i] = i as u8);
+		memory[0x1C00..0x2000].fill(1);
+		memory[0x7F40] = 0xD1;
+		memory[0x7F47] = 0xE4;
+		memory[0x7F48] = 0xE4;
+		StateFile {
+			registers: [0; 4],
+			sp: 0xFFFE,
+			pc: 0x0100,
+			ime: false,
+			div_counter: 0,
+			banking: BankingState::default(),
+			memory,
+			cartridge_ram: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn exports_vram_from_a_state_file() {
+		let dir = env::temp_dir().join(format!("rustboy-gfx-{}", process::id()));
+		let state = state();
+		export_state(&dir, &state, 2).unwrap();
+
+		let tiles = fs::read(dir.join("tiles.2bpp")).unwrap();
+		assert_eq!(tiles.len(), TILE_COUNT * 16);
+		assert_eq!(tiles, &state.memory[..0x1800]);
+		assert_eq!(&tiles[0x10..0x14], [0xFF, 0x00, 0xFF, 0x00]);
+
+		let bg_map = fs::read_to_string(dir.join("bg_map.csv")).unwrap();
+		let rows = bg_map.lines().collect::<Vec<_>>();
+		assert_eq!(rows.len(), 32);
+		assert_eq!(
+			rows[0],
+			(0..32).map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+		);
+		assert_eq!(rows[8].split(',').next(), Some("0"));
+		let window_map = fs::read_to_string(dir.join("window_map.csv")).unwrap();
+		assert_eq!(window_map, format!("{}\n", ["1"; 32].join(",")).repeat(32));
+
+		["tiles.png", "sprites.png", "map_9800.png", "map_9C00.png"]
+			.iter()
+			.for_each(|name| assert!(dir.join(name).exists(), "{} missing", name));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn tiles_use_the_palette() {
+		let state = state();
+		let shades = Palette::default().shades;
+		// color 1 in every row of tile 1, shade 1 with BGP identity & shade 3 with everything dark
+		let (width, height, pixels) = tile_sheet(state.vram(), 1, 1, 0xE4);
+		assert_eq!((width, height), (128, 8));
+		assert_eq!(pixels[0], shades[1]);
+		let (_, _, pixels) = tile_sheet(state.vram(), 1, 1, 0xFF);
+		assert_eq!(pixels[0], shades[3]);
+		// map_image of the window map shows tile 1 everywhere
+		let (_, _, pixels) = map_image(state.vram(), 0x9C00, 0xD1, 0xE4);
+		assert!(pixels.iter().all(|&p| p == shades[1]));
+	}
+}
@@ -0,0 +1,123 @@
+use std::{
+	sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+	time::Duration,
+};
+
+// A completed frame & the number of frames completed so far, counting it
+pub type Frame = (u64, Vec<u32>);
+
+// Emulation side of the frame hand-off of --threaded-present, fed by a frame observer. Never
+// blocks: a frame completed while the previous one is still waiting to be presented is dropped.
+pub struct FrameSender {
+	sender: SyncSender<Frame>,
+	// with --skip-unchanged-frames, the last frame completed
+	previous: Option<Vec<u32>>,
+}
+
+// Presenting (UI) side of the frame hand-off, gets the frames in the order they were completed
+pub struct FrameReceiver {
+	receiver: Receiver<Frame>,
+}
+
+// Room for a single frame, the one waiting to be presented
+pub fn frame_handoff(skip_unchanged_frames: bool) -> (FrameSender, FrameReceiver) {
+	let (sender, receiver) = mpsc::sync_channel(1);
+	let previous = skip_unchanged_frames.then(Vec::new);
+	(FrameSender { sender, previous }, FrameReceiver { receiver })
+}
+
+impl FrameSender {
+	pub fn send(&mut self, frames: u64, frame: &[u32]) {
+		if self.previous.as_deref() == Some(frame) {
+			return;
+		}
+		// a full channel drops the frame, a closed one is noticed through the input channel
+		let _ = self.sender.try_send((frames, frame.to_vec()));
+		if let Some(previous) = &mut self.previous {
+			previous.clear();
+			previous.extend_from_slice(frame);
+		}
+	}
+}
+
+impl FrameReceiver {
+	pub fn recv_timeout(&self, timeout: Duration) -> Result<Frame, RecvTimeoutError> {
+		self.receiver.recv_timeout(timeout)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{
+		thread,
+		time::{Duration, Instant},
+	};
+
+	// frame number `n`, every pixel set to it
+	fn frame(n: u64) -> Vec<u32> {
+		vec![n as u32; 16]
+	}
+
+	#[test]
+	fn slow_presenter_does_not_slow_emulation_down() {
+		const FRAMES: u64 = 120;
+		const FRAME_TIME: Duration = Duration::from_millis(4);
+		let (mut sender, receiver) = frame_handoff(false);
+		// paced at FRAME_TIME per frame, as long as handing frames over never blocks
+		let emulation = thread::spawn(move || {
+			let start = Instant::now();
+			(1..=FRAMES).for_each(|n| {
+				sender.send(n, &frame(n));
+				let due = start + FRAME_TIME * n as u32;
+				while Instant::now() < due {
+					thread::yield_now();
+				}
+			});
+			start.elapsed()
+		});
+
+		// takes 5 frame times to present one
+		let mut presented = Vec::new();
+		loop {
+			match receiver.recv_timeout(Duration::from_millis(100)) {
+				Ok((frames, pixels)) => {
+					assert_eq!(pixels, frame(frames), "frame {} torn", frames);
+					presented.push(frames);
+					thread::sleep(FRAME_TIME * 5);
+				}
+				Err(RecvTimeoutError::Timeout) => panic!("no frame for 100ms"),
+				Err(RecvTimeoutError::Disconnected) => break,
+			}
+		}
+
+		let elapsed = emulation.join().unwrap();
+		assert!(
+			elapsed < FRAME_TIME * FRAMES as u32 * 3 / 2,
+			"{} frames took {:?}",
+			FRAMES,
+			elapsed
+		);
+		// frames got dropped rather than queued up, those presented in order
+		assert!(presented.len() < FRAMES as usize / 2, "{:?}", presented);
+		assert!(presented.windows(2).all(|w| w[0] < w[1]), "{:?}", presented);
+		assert_eq!(presented[0], 1);
+	}
+
+	#[test]
+	fn unchanged_frames_are_skipped_on_request() {
+		let sent = |skip_unchanged_frames: bool| {
+			let (mut sender, receiver) = frame_handoff(skip_unchanged_frames);
+			let mut sent = Vec::new();
+			[1, 1, 2, 2, 2, 1].iter().enumerate().for_each(|(i, &n)| {
+				sender.send(i as u64 + 1, &frame(n));
+				if let Ok((frames, _)) = receiver.recv_timeout(Duration::ZERO) {
+					sent.push(frames);
+				}
+			});
+			sent
+		};
+		assert_eq!(sent(false), [1, 2, 3, 4, 5, 6]);
+		assert_eq!(sent(true), [1, 3, 6]);
+	}
+}
@@ -0,0 +1,306 @@
+use crate::utils::crc32;
+use std::fmt;
+
+const CARTRIDGE_TYPES: [(u8, &str); 28] = [
+	(0x00, "ROM ONLY"),
+	(0x01, "MBC1"),
+	(0x02, "MBC1+RAM"),
+	(0x03, "MBC1+RAM+BATTERY"),
+	(0x05, "MBC2"),
+	(0x06, "MBC2+BATTERY"),
+	(0x08, "ROM+RAM"),
+	(0x09, "ROM+RAM+BATTERY"),
+	(0x0B, "MMM01"),
+	(0x0C, "MMM01+RAM"),
+	(0x0D, "MMM01+RAM+BATTERY"),
+	(0x0F, "MBC3+TIMER+BATTERY"),
+	(0x10, "MBC3+TIMER+RAM+BATTERY"),
+	(0x11, "MBC3"),
+	(0x12, "MBC3+RAM"),
+	(0x13, "MBC3+RAM+BATTERY"),
+	(0x19, "MBC5"),
+	(0x1A, "MBC5+RAM"),
+	(0x1B, "MBC5+RAM+BATTERY"),
+	(0x1C, "MBC5+RUMBLE"),
+	(0x1D, "MBC5+RUMBLE+RAM"),
+	(0x1E, "MBC5+RUMBLE+RAM+BATTERY"),
+	(0x20, "MBC6"),
+	(0x22, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+	(0xFC, "POCKET CAMERA"),
+	(0xFD, "BANDAI TAMA5"),
+	(0xFE, "HuC3"),
+	(0xFF, "HuC1+RAM+BATTERY"),
+];
+
+// the most common ones, others are printed as their code only
+const NEW_LICENSEES: [(&str, &str); 16] = [
+	("00", "None"),
+	("01", "Nintendo R&D1"),
+	("08", "Capcom"),
+	("13", "Electronic Arts"),
+	("18", "Hudson Soft"),
+	("20", "KSS"),
+	("28", "Kemco"),
+	("31", "Nintendo"),
+	("33", "Ocean Software/Acclaim Entertainment"),
+	("34", "Konami"),
+	("41", "Ubi Soft"),
+	("51", "Acclaim Entertainment"),
+	("52", "Activision"),
+	("69", "Electronic Arts"),
+	("A4", "Konami (Yu-Gi-Oh!)"),
+	("B2", "Bandai"),
+];
+
+fn lookup<K: PartialEq, V: Copy>(table: &[(K, V)], key: &K) -> Option<V> {
+	table.iter().find(|(k, _)| k == key).map(|&(_, v)| v)
+}
+
+// name of a cartridge type byte (0x0147)
+pub fn cartridge_type_name(cartridge_type: u8) -> &'static str {
+	lookup(&CARTRIDGE_TYPES, &cartridge_type).unwrap_or("unknown")
+}
+
+// true if the cartridge type byte (0x0147) says RAM is battery backed, i.e. worth persisting
+pub fn has_battery(cartridge_type: u8) -> bool {
+	matches!(
+		cartridge_type,
+		0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+	)
+}
+
+// Value the boot rom expects at 0x014D, computed over 0x0134-0x014C
+pub fn header_checksum(rom: &[u8]) -> u8 {
+	rom[0x0134..=0x014C]
+		.iter()
+		.fold(0_u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+
+// sum of every byte but the checksum itself, never verified by hardware
+pub fn global_checksum(rom: &[u8]) -> u16 {
+	rom
+		.iter()
+		.enumerate()
+		.filter(|&(i, _)| i != 0x014E && i != 0x014F)
+		.fold(0_u16, |sum, (_, &b)| sum.wrapping_add(b as u16))
+}
+
+// Identifies a game across runs: CRC32 of the whole rom as 8 hex digits, so revisions & hacks
+// sharing a header still tell apart
+pub fn fingerprint(rom: &[u8]) -> String {
+	format!("{:08X}", crc32(rom))
+}
+
+// Decoded cartridge header (0x0100-0x014F)
+#[derive(Clone, Debug)]
+pub struct Header {
+	pub title: String,
+	pub manufacturer_code: String,
+	pub cgb_flag: u8,
+	pub new_licensee_code: String,
+	pub sgb_flag: u8,
+	pub cartridge_type: u8,
+	pub rom_size: u8,
+	pub ram_size: u8,
+	pub destination_code: u8,
+	pub old_licensee_code: u8,
+	pub mask_rom_version: u8,
+	pub header_checksum: u8,
+	pub header_checksum_valid: bool,
+	pub global_checksum: u16,
+	pub global_checksum_valid: bool,
+	pub fingerprint: String,
+}
+
+impl Header {
+	// None if the rom is too small to hold a header
+	pub fn parse(rom: &[u8]) -> Option<Self> {
+		if rom.len() < 0x0150 {
+			return None;
+		}
+		let text = |range: std::ops::Range<usize>| {
+			rom[range]
+				.iter()
+				.take_while(|&&u| u != 0 && u.is_ascii())
+				.map(|&u| char::from(u))
+				.collect::<String>()
+		};
+		// on CGB carts the last title bytes hold the manufacturer code & CGB flag
+		let cgb_flag = rom[0x0143];
+		let (title, manufacturer_code) = match cgb_flag & 0x80 == 0x80 {
+			true => (text(0x0134..0x013F), text(0x013F..0x0143)),
+			false => (text(0x0134..0x0144), String::new()),
+		};
+		let checksum = u16::from_be_bytes([rom[0x014E], rom[0x014F]]);
+		Some(Header {
+			title,
+			manufacturer_code,
+			cgb_flag,
+			new_licensee_code: text(0x0144..0x0146),
+			sgb_flag: rom[0x0146],
+			cartridge_type: rom[0x0147],
+			rom_size: rom[0x0148],
+			ram_size: rom[0x0149],
+			destination_code: rom[0x014A],
+			old_licensee_code: rom[0x014B],
+			mask_rom_version: rom[0x014C],
+			header_checksum: rom[0x014D],
+			header_checksum_valid: header_checksum(rom) == rom[0x014D],
+			global_checksum: checksum,
+			global_checksum_valid: global_checksum(rom) == checksum,
+			fingerprint: fingerprint(rom),
+		})
+	}
+
+	pub fn cartridge_type_name(&self) -> &'static str {
+		cartridge_type_name(self.cartridge_type)
+	}
+
+	pub fn licensee(&self) -> String {
+		match self.old_licensee_code {
+			0x33 => match lookup(&NEW_LICENSEES, &self.new_licensee_code.as_str()) {
+				Some(name) => format!("{} ({})", name, self.new_licensee_code),
+				None => format!("new licensee {:?}", self.new_licensee_code),
+			},
+			code => format!("old licensee {:02X}", code),
+		}
+	}
+
+	// in KiB, None for a code not in use
+	pub fn rom_size_kib(&self) -> Option<u32> {
+		match self.rom_size {
+			0x00..=0x08 => Some(32 << self.rom_size),
+			0x52 => Some(1152),
+			0x53 => Some(1280),
+			0x54 => Some(1536),
+			_ => None,
+		}
+	}
+
+	pub fn ram_size_kib(&self) -> Option<u32> {
+		match self.ram_size {
+			0x00 => Some(0),
+			0x01 => Some(2),
+			0x02 => Some(8),
+			0x03 => Some(32),
+			0x04 => Some(128),
+			0x05 => Some(64),
+			_ => None,
+		}
+	}
+
+	fn cgb_support(&self) -> &'static str {
+		match self.cgb_flag {
+			0xC0 => "CGB only",
+			0x80 => "CGB enhanced",
+			_ => "DMG",
+		}
+	}
+
+	// one line object, strings are plain ascii (see parse) so only quotes & backslashes need escaping
+	pub fn to_json(&self) -> String {
+		let string = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+		let number = |n: Option<u32>| n.map_or("null".to_string(), |n| n.to_string());
+		let fields = [
+			("title", string(&self.title)),
+			("manufacturer_code", string(&self.manufacturer_code)),
+			("cgb_flag", self.cgb_flag.to_string()),
+			("licensee", string(&self.licensee())),
+			("old_licensee_code", self.old_licensee_code.to_string()),
+			("new_licensee_code", string(&self.new_licensee_code)),
+			("sgb_flag", self.sgb_flag.to_string()),
+			("cartridge_type", self.cartridge_type.to_string()),
+			("cartridge_type_name", string(self.cartridge_type_name())),
+			("rom_size_kib", number(self.rom_size_kib())),
+			("ram_size_kib", number(self.ram_size_kib())),
+			("destination_code", self.destination_code.to_string()),
+			("mask_rom_version", self.mask_rom_version.to_string()),
+			("header_checksum", self.header_checksum.to_string()),
+			(
+				"header_checksum_valid",
+				self.header_checksum_valid.to_string(),
+			),
+			("global_checksum", self.global_checksum.to_string()),
+			(
+				"global_checksum_valid",
+				self.global_checksum_valid.to_string(),
+			),
+			("fingerprint", string(&self.fingerprint)),
+		];
+		let fields = fields
+			.iter()
+			.map(|(key, value)| format!("\"{}\":{}", key, value))
+			.collect::<Vec<_>>();
+		format!("{{{}}}", fields.join(","))
+	}
+}
+
+impl fmt::Display for Header {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let size = |kib: Option<u32>, code: u8| match kib {
+			Some(kib) => format!("{} KiB", kib),
+			None => format!("unknown ({:02X})", code),
+		};
+		let validity = |valid: bool| match valid {
+			true => "valid",
+			false => "invalid",
+		};
+		writeln!(f, "title:             {}", self.title)?;
+		writeln!(f, "manufacturer code: {}", self.manufacturer_code)?;
+		writeln!(
+			f,
+			"CGB flag:          {:02X} ({})",
+			self.cgb_flag,
+			self.cgb_support()
+		)?;
+		writeln!(f, "licensee:          {}", self.licensee())?;
+		writeln!(
+			f,
+			"SGB flag:          {:02X} ({})",
+			self.sgb_flag,
+			match self.sgb_flag {
+				0x03 => "SGB functions",
+				_ => "no SGB functions",
+			}
+		)?;
+		writeln!(
+			f,
+			"cartridge type:    {:02X} ({})",
+			self.cartridge_type,
+			self.cartridge_type_name()
+		)?;
+		writeln!(
+			f,
+			"ROM size:          {}",
+			size(self.rom_size_kib(), self.rom_size)
+		)?;
+		writeln!(
+			f,
+			"RAM size:          {}",
+			size(self.ram_size_kib(), self.ram_size)
+		)?;
+		writeln!(
+			f,
+			"destination:       {:02X} ({})",
+			self.destination_code,
+			match self.destination_code {
+				0x00 => "Japan",
+				_ => "overseas",
+			}
+		)?;
+		writeln!(f, "mask ROM version:  {:02X}", self.mask_rom_version)?;
+		writeln!(
+			f,
+			"header checksum:   {:02X} ({})",
+			self.header_checksum,
+			validity(self.header_checksum_valid)
+		)?;
+		writeln!(
+			f,
+			"global checksum:   {:04X} ({})",
+			self.global_checksum,
+			validity(self.global_checksum_valid)
+		)?;
+		write!(f, "fingerprint:       {}", self.fingerprint)
+	}
+}
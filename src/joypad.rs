@@ -1,6 +1,6 @@
 use crate::utils::is_bit_set;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Button {
 	A = 0,
 	B = 1,
@@ -27,33 +27,143 @@ impl Button {
 			Button::UNKNOWN,
 		]
 	}
+
+	// by name (a, b, select, start, right, left, up, down), case insensitive
+	pub fn find(name: &str) -> Option<Button> {
+		Some(match name.to_ascii_lowercase().as_str() {
+			"a" => Button::A,
+			"b" => Button::B,
+			"select" => Button::SELECT,
+			"start" => Button::START,
+			"right" => Button::RIGHT,
+			"left" => Button::LEFT,
+			"up" => Button::UP,
+			"down" => Button::DOWN,
+			_ => return None,
+		})
+	}
+}
+
+// Minimal Super Game Boy side of the joypad port, it decodes the packets sent by games & only
+// answers MLT_REQ so that SGB multiplayer menus work. Packet transfer:
+// - P14 & P15 low           => reset pulse, start of packet
+// - P14 low (P15 high)      => bit 0
+// - P15 low (P14 high)      => bit 1
+// - P14 & P15 high          => separator between pulses
+// 16 bytes (LSB first) are followed by a 0 stop bit.
+#[derive(Clone)]
+struct Sgb {
+	players: u8,
+	current_player: u8,
+	receiving: bool,
+	bits_received: u8,
+	packet: [u8; 16],
+	prev_select: u8,
+}
+
+impl Sgb {
+	const MLT_REQ: u8 = 0x11;
+
+	fn new() -> Self {
+		Sgb {
+			players: 1,
+			current_player: 0,
+			receiving: false,
+			bits_received: 0,
+			packet: [0; 16],
+			prev_select: 0x30,
+		}
+	}
+
+	fn write(&mut self, select: u8) {
+		let prev_select = self.prev_select;
+		self.prev_select = select;
+
+		match (prev_select, select) {
+			(_, 0x00) => {
+				self.receiving = true;
+				self.bits_received = 0;
+				self.packet = [0; 16];
+			}
+			(0x30, 0x10) | (0x30, 0x20) if self.receiving => {
+				let bit = (select == 0x10) as u8;
+				if self.bits_received == 128 {
+					// stop bit, packet complete
+					self.receiving = false;
+					self.process_packet();
+					return;
+				}
+				self.packet[self.bits_received as usize / 8] |= bit << (self.bits_received % 8);
+				self.bits_received += 1;
+			}
+			// next joypad gets selected on rising edge of P15
+			(p, s) if !self.receiving && !is_bit_set(p, 5) && is_bit_set(s, 5) => {
+				self.current_player = (self.current_player + 1) % self.players;
+			}
+			_ => {}
+		}
+	}
+
+	fn process_packet(&mut self) {
+		if self.packet[0] >> 3 == Self::MLT_REQ {
+			self.players = match self.packet[1] & 0x03 {
+				0x01 => 2,
+				0x03 => 4,
+				_ => 1,
+			};
+			self.current_player = 0;
+		}
+	}
 }
 
-pub struct Joypad(u8);
+#[derive(Clone)]
+pub struct Joypad {
+	state: u8,
+	sgb: Option<Sgb>,
+}
 
 impl Joypad {
-	pub fn new() -> Joypad {
-		Joypad(0xFF)
+	pub fn new(sgb_stub: bool) -> Joypad {
+		Joypad {
+			state: 0xFF,
+			sgb: if sgb_stub { Some(Sgb::new()) } else { None },
+		}
+	}
+
+	pub fn write(&mut self, r_joypad: u8) {
+		if let Some(sgb) = self.sgb.as_mut() {
+			sgb.write(r_joypad & 0x30);
+		}
 	}
 
 	pub fn read(&self, r_joypad: u8) -> u8 {
-		// println!("joypad register: {:08b}, state: {:08b}", r_joypad, self.0);
-		(r_joypad & 0xF0)
+		let (player, players) = match &self.sgb {
+			Some(sgb) => (sgb.current_player, sgb.players),
+			None => (0, 1),
+		};
+		// other players (sgb multiplayer) never have any button pressed
+		let state = if player == 0 { self.state } else { 0xFF };
+
+		// bits 6 & 7 are unused & always read as 1, whatever got stored in the register
+		0xC0
+			| (r_joypad & 0x30)
 			| match (is_bit_set(r_joypad, 4), is_bit_set(r_joypad, 5)) {
-				(false, false) => 0x0F & (self.0 | (self.0 >> 4)), // both action & direction buttons
-				(false, true) => 0x0F & (self.0 >> 4),             // only direction buttons
-				(true, false) => 0x0F & self.0,                    // only action buttons (SsBA)
-				(true, true) => 0x0F,                              // none
+				(false, false) => 0x0F & (state | (state >> 4)), // both action & direction buttons
+				(false, true) => 0x0F & (state >> 4),            // only direction buttons
+				(true, false) => 0x0F & state,                   // only action buttons (SsBA)
+				// none, on DMG (or SGB without MLT_REQ) always 0x0F so games don't detect a SGB,
+				// in SGB multiplayer mode it's the joypad ID: 0x0F - player
+				(true, true) if players > 1 => 0x0F - player,
+				(true, true) => 0x0F,
 			}
 	}
 
 	pub fn pressed(&mut self, button: Button) -> bool {
 		match button {
 			Button::UNKNOWN => false,
-			b if !is_bit_set(self.0, b as u8) => false,
+			b if !is_bit_set(self.state, b as u8) => false,
 			b => {
-				self.0 &= !(1 << b as u8);
-				// println!("button pressed: {:?}, joypad: {:08b}", button, self.0);
+				self.state &= !(1 << b as u8);
 				true
 			}
 		}
@@ -63,9 +173,109 @@ impl Joypad {
 		match button {
 			Button::UNKNOWN => (),
 			_ => {
-				self.0 |= 1 << button as u8;
-				// println!("button released: {:?}, joypad: {:08b}", button, self.0);
+				self.state |= 1 << button as u8;
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// P1 writes sending `packet` the way games do: reset pulse, 128 bits LSB first, each followed
+	// by both lines high, then the 0 stop bit
+	fn packet_writes(packet: [u8; 16]) -> Vec<u8> {
+		let bits = (0..128).map(|i| (packet[i / 8] >> (i % 8)) & 1 == 1);
+		[0x00, 0x30]
+			.into_iter()
+			.chain(bits.chain([false]).flat_map(|bit| match bit {
+				true => [0x10, 0x30],
+				false => [0x20, 0x30],
+			}))
+			.collect()
+	}
+
+	// SGB detection as done by SGB enhanced games: MLT_REQ for 2 players, then reading the joypad
+	// ID with both lines deselected around P15 pulses (which switch to the next joypad), then
+	// MLT_REQ back to 1 player. Low nibbles of the ID reads.
+	fn probe(joypad: &mut Joypad) -> Vec<u8> {
+		let mut ids = Vec::new();
+		let mut mlt_req = [0; 16];
+		mlt_req[0] = (Sgb::MLT_REQ << 3) | 1;
+		mlt_req[1] = 0x01;
+		packet_writes(mlt_req)
+			.into_iter()
+			.for_each(|select| joypad.write(select));
+		(0..4).for_each(|_| {
+			joypad.write(0x30);
+			ids.push(joypad.read(0x30) & 0x0F);
+			// P15 low & back high
+			joypad.write(0x10);
+			joypad.read(0x10);
+			joypad.write(0x30);
+			ids.push(joypad.read(0x30) & 0x0F);
+		});
+		mlt_req[1] = 0x00;
+		packet_writes(mlt_req)
+			.into_iter()
+			.for_each(|select| joypad.write(select));
+		(0..2).for_each(|_| {
+			joypad.write(0x10);
+			joypad.write(0x30);
+			ids.push(joypad.read(0x30) & 0x0F);
+		});
+		ids
+	}
+
+	#[test]
+	fn sgb_probe_reads_as_a_dmg() {
+		let mut joypad = Joypad::new(false);
+		joypad.pressed(Button::START);
+		assert!(probe(&mut joypad).iter().all(|&id| id == 0x0F));
+		// the buttons still read through the probe
+		assert_eq!(joypad.read(0x10) & 0x0F, 0x07);
+	}
+
+	#[test]
+	fn sgb_stub_answers_mlt_req() {
+		let mut joypad = Joypad::new(true);
+		joypad.pressed(Button::START);
+		assert_eq!(
+			probe(&mut joypad),
+			[0x0F, 0x0E, 0x0E, 0x0F, 0x0F, 0x0E, 0x0E, 0x0F, 0x0F, 0x0F]
+		);
+		// player 1 selected again, with START held
+		assert_eq!(joypad.read(0x10) & 0x0F, 0x07);
+	}
+
+	#[test]
+	fn top_bits_read_as_1_whatever_is_stored() {
+		// SGB with 4 joypads
+		let mut multiplayer = Joypad::new(true);
+		let mut mlt_req = [0; 16];
+		mlt_req[0] = (Sgb::MLT_REQ << 3) | 1;
+		mlt_req[1] = 0x03;
+		packet_writes(mlt_req)
+			.into_iter()
+			.for_each(|select| multiplayer.write(select));
+
+		let mut pressed = Joypad::new(false);
+		Button::values().into_iter().for_each(|b| {
+			pressed.pressed(b);
+		});
+		for joypad in [Joypad::new(false), Joypad::new(true), pressed, multiplayer] {
+			(0..=0xFF).for_each(|stored: u8| {
+				let value = joypad.read(stored);
+				assert_eq!(value & 0xC0, 0xC0, "{:02X} read {:02X}", stored, value);
+				assert_eq!(
+					value & 0x30,
+					stored & 0x30,
+					"{:02X} read {:02X}",
+					stored,
+					value
+				);
+			});
+		}
+	}
+}
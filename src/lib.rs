@@ -0,0 +1,37 @@
+#![allow(clippy::upper_case_acronyms, clippy::blocks_in_conditions)]
+
+mod cartridge;
+pub mod compat;
+pub mod compatreport;
+mod cpu;
+pub mod disasm;
+pub mod filters;
+pub mod frametiming;
+pub mod gameboy;
+pub mod gfx;
+pub mod header;
+pub mod joypad;
+mod mmu;
+pub mod options;
+pub mod patch;
+mod png;
+mod ppu;
+pub mod recorder;
+pub mod rewind;
+pub mod save;
+pub mod saves;
+pub mod scoreboard;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod selftest;
+pub mod serial;
+pub mod speed;
+pub mod statefile;
+pub mod symbols;
+pub mod testroms;
+pub mod text;
+mod utils;
+
+const DEBUG_FLAG: bool = false;
+pub const WIDTH: usize = 160;
+pub const HEIGHT: usize = 144;
@@ -0,0 +1,116 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::env;
+
+// Minimal logger writing `LEVEL target: message` lines to stderr, keeps stdout for serial output
+struct StderrLogger;
+
+impl Log for StderrLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= log::max_level()
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			eprintln!(
+				"{:<5} {}: {}",
+				record.level(),
+				record.target(),
+				record.args()
+			);
+		}
+	}
+
+	fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// Only the first call installs the logger, so embedders with their own logger can skip it. Without
+// a level, RUST_LOG is used if it holds a plain level (error, warn, info, debug, trace or off).
+pub fn init(level: Option<LevelFilter>) {
+	let level = level
+		.or_else(|| env::var("RUST_LOG").ok()?.parse().ok())
+		.unwrap_or(LevelFilter::Info);
+	if log::set_logger(&LOGGER).is_ok() {
+		log::set_max_level(level);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use log::Level;
+	use rustboy::gameboy::GameBoy;
+	use std::{
+		sync::Mutex,
+		thread::{self, ThreadId},
+	};
+
+	// thread, level, target & message
+	type Captured = (ThreadId, Level, String, String);
+
+	// Keeps what gets logged, by thread as tests run in parallel. The logger is process wide, so it
+	// stands in for StderrLogger in every test of the binary.
+	struct CapturingLogger(Mutex<Vec<Captured>>);
+
+	impl Log for CapturingLogger {
+		fn enabled(&self, _: &Metadata) -> bool {
+			true
+		}
+
+		fn log(&self, record: &Record) {
+			self.0.lock().unwrap().push((
+				thread::current().id(),
+				record.level(),
+				record.target().to_string(),
+				record.args().to_string(),
+			));
+		}
+
+		fn flush(&self) {}
+	}
+
+	static CAPTURED: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+	// (level, target, message) of the records logged by `f`
+	fn capture(f: impl FnOnce()) -> Vec<(Level, String, String)> {
+		if log::set_logger(&CAPTURED).is_ok() {
+			log::set_max_level(LevelFilter::Trace);
+		}
+		let thread = thread::current().id();
+		let records = |captured: &mut Vec<Captured>| {
+			captured
+				.extract_if(.., |(t, ..)| *t == thread)
+				.map(|(_, level, target, message)| (level, target, message))
+				.collect::<Vec<_>>()
+		};
+		records(&mut CAPTURED.0.lock().unwrap());
+		f();
+		records(&mut CAPTURED.0.lock().unwrap())
+	}
+
+	#[test]
+	fn bad_header_checksum_warns_once() {
+		let warnings = |rom: Vec<u8>| {
+			capture(|| {
+				GameBoy::from_bytes(rom);
+			})
+			.into_iter()
+			.filter(|(level, ..)| *level == Level::Warn)
+			.collect::<Vec<_>>()
+		};
+
+		// the checksum of an all 0x00 header is 0xE7
+		let mut rom = vec![0; 0x8000];
+		assert_eq!(
+			warnings(rom.clone()),
+			[(
+				Level::Warn,
+				"rustboy::cartridge".to_string(),
+				"header checksum mismatch: expected 00, computed E7".to_string()
+			)]
+		);
+		rom[0x014D] = 0xE7;
+		assert_eq!(warnings(rom), []);
+	}
+}
@@ -1,96 +1,838 @@
-mod cartridge;
-mod cpu;
-mod joypad;
-mod mmu;
-mod ppu;
-mod utils;
-
-use cpu::CPU;
-use joypad::Button;
-use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
-use mmu::MMU;
-use ppu::PPU;
+#![allow(clippy::upper_case_acronyms, clippy::blocks_in_conditions)]
+
+mod handoff;
+mod logger;
+mod menu;
+mod palettes;
+mod romlist;
+mod scripting;
+mod state;
+
+use log::{error, info, warn};
+use menu::{MenuAction, Overlay, PauseMenu};
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+use palettes::GamePalettes;
+use romlist::{Pick, RomMenu};
+use rustboy::{
+	HEIGHT, WIDTH, disasm,
+	filters::{PresentHook, Upscaler},
+	frametiming::{self, CYCLES_PER_FRAME},
+	gameboy::{GameBoy, InterruptCounts, Palette, Snapshot},
+	gfx,
+	header::{self, Header},
+	joypad::Button,
+	options::{self, EmulatorOptions},
+	patch, recorder,
+	rewind::Rewind,
+	save, saves, scoreboard, selftest,
+	speed::SpeedMeter,
+	statefile::StateFile,
+	symbols::Symbols,
+	testroms,
+};
+use state::State;
 use std::{
-	env, fs, thread,
-	time::{Duration, SystemTime},
+	env, fs, io,
+	path::{Path, PathBuf},
+	process,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+		mpsc::{self, RecvTimeoutError, TryRecvError},
+	},
+	thread,
+	time::Duration,
 };
 
-const DEBUG_FLAG: bool = false;
-const WIDTH: usize = 160;
-const HEIGHT: usize = 144;
-
-impl From<Button> for Key {
-	fn from(button: Button) -> Self {
-		match button {
-			Button::A => Key::J,
-			Button::B => Key::K,
-			Button::SELECT => Key::Backspace,
-			Button::START => Key::Enter,
-			Button::RIGHT => Key::D,
-			Button::LEFT => Key::A,
-			Button::UP => Key::W,
-			Button::DOWN => Key::S,
-			Button::UNKNOWN => Key::Unknown,
+fn key(button: Button) -> Key {
+	match button {
+		Button::A => Key::J,
+		Button::B => Key::K,
+		Button::SELECT => Key::Backspace,
+		Button::START => Key::Enter,
+		Button::RIGHT => Key::D,
+		Button::LEFT => Key::A,
+		Button::UP => Key::W,
+		Button::DOWN => Key::S,
+		Button::UNKNOWN => Key::Unknown,
+	}
+}
+
+fn pressed_buttons(window: &Window) -> Vec<Button> {
+	Button::values()
+		.into_iter()
+		.filter(|&button| window.is_key_down(key(button)))
+		.collect()
+}
+
+fn report_violations(gameboy: &GameBoy) {
+	let violations = gameboy.check_invariants();
+	if !violations.is_empty() {
+		error!("self check failed:");
+		violations.iter().for_each(|v| error!("  {}", v));
+		error!("{}", gameboy.state());
+	}
+}
+
+fn print_serial_output(gameboy: &mut GameBoy) {
+	let serial_output = gameboy.take_serial_output();
+	if !serial_output.is_empty() {
+		print!("{}", String::from_utf8_lossy(&serial_output));
+	}
+}
+
+// "RustBoy - " & the cartridge title, just "RustBoy" when the rom has none
+fn window_title(rom: &[u8]) -> String {
+	match Header::parse(rom).map(|header| header.title) {
+		Some(title) if !title.is_empty() => format!("RustBoy - {}", title),
+		_ => "RustBoy".to_string(),
+	}
+}
+
+fn set_fps_title(window: &mut Window, title: &str, meter: &SpeedMeter) {
+	window.set_title(
+		format!(
+			"{} - FPS: {:.0} ({:.0}%)",
+			title,
+			meter.fps(),
+			meter.speed_percent()
+		)
+		.as_str(),
+	);
+}
+
+// Everything between a completed frame & the window: post-processing hooks, then upscaling
+struct Screen {
+	hooks: Vec<PresentHook>,
+	buffer: Vec<u32>,
+	upscaler: Upscaler,
+	scale: usize,
+	exact_aspect: bool,
+	// bars around the screen when the window isn't 10:9
+	border_color: u32,
+	// what's handed to the window, upscaled
+	output: Vec<u32>,
+}
+
+impl Screen {
+	fn new(options: &EmulatorOptions) -> Self {
+		Screen {
+			hooks: options
+				.filters
+				.iter()
+				.map(|filter| filter.into_hook())
+				.collect(),
+			buffer: vec![0_u32; WIDTH * HEIGHT],
+			upscaler: Upscaler::new(options.lcd_grid, options.crt_rounding, options.border_color),
+			scale: options.scale,
+			exact_aspect: options.exact_aspect,
+			border_color: options.border_color,
+			output: Vec::new(),
 		}
 	}
+
+	// initial window size, frames are upscaled here rather than by the window
+	fn window_size(&self) -> (usize, usize) {
+		(WIDTH * self.scale, HEIGHT * self.scale)
+	}
+
+	fn scale_mode(&self) -> ScaleMode {
+		match self.exact_aspect {
+			// frames are as large as the window already
+			true => ScaleMode::Center,
+			false => ScaleMode::AspectRatioStretch,
+		}
+	}
+
+	// `overlay` is drawn over the processed frame
+	fn present(&mut self, window: &mut Window, frame: &[u32], overlay: Option<&dyn Overlay>) {
+		self.buffer.copy_from_slice(frame);
+		self
+			.hooks
+			.iter_mut()
+			.for_each(|hook| hook(&mut self.buffer));
+		if let Some(overlay) = overlay {
+			overlay.draw(&mut self.buffer);
+		}
+		let (width, height, scale) = match self.exact_aspect {
+			// the largest integer scale fitting the window, the rest of it is letterboxed
+			true => {
+				let (width, height) = window.get_size();
+				let scale = (width / WIDTH).min(height / HEIGHT).max(1);
+				(width.max(WIDTH * scale), height.max(HEIGHT * scale), scale)
+			}
+			false => (WIDTH * self.scale, HEIGHT * self.scale, self.scale),
+		};
+		self.output.resize(width * height, 0);
+		// the window fills what the frame doesn't cover when stretching, the upscaler otherwise
+		let [_, r, g, b] = self.border_color.to_be_bytes();
+		window.set_background_color(r, g, b);
+		self
+			.upscaler
+			.apply(&self.buffer, scale, &mut self.output, width);
+		let _ = window.update_with_buffer(&self.output, width, height);
+	}
+}
+
+// Emulation runs on its own thread & hands every completed frame over to this (UI) thread through a
+// frame observer, the UI thread presents it and sends the held buttons back, so a slow present
+// never stalls emulation. A frame completed while the previous one is still being presented is
+// dropped, but still counted.
+// The pause menu, frame advance, reset, palette & rom list hotkeys, --export-gfx, --watch &
+// --runahead aren't available in this mode.
+#[allow(clippy::too_many_arguments)]
+fn run_threaded(
+	window: &mut Window,
+	options: &EmulatorOptions,
+	rom_path: &Path,
+	cartridge: Arc<[u8]>,
+	boot_rom: Option<Vec<u8>>,
+	symbols: Symbols,
+	mut screen: Screen,
+	meter: &mut SpeedMeter,
+) -> InterruptCounts {
+	let (mut frame_sender, frame_receiver) = handoff::frame_handoff(options.skip_unchanged_frames);
+	let (input_sender, input_receiver) = mpsc::channel::<Vec<Button>>();
+	let options = options.clone();
+	let rom_path = rom_path.to_path_buf();
+	let title = window_title(&cartridge);
+	let emulation = thread::spawn(move || {
+		let mut gameboy = GameBoy::new(cartridge, boot_rom, &options);
+		gameboy.set_symbols(symbols);
+		load_battery_save(&mut gameboy, &rom_path);
+		gameboy.add_frame_observer(Box::new(move |frames, frame| {
+			frame_sender.send(frames, frame)
+		}));
+		loop {
+			if gameboy.step() {
+				if options.self_check {
+					report_violations(&gameboy);
+				}
+				thread::sleep(Duration::from_millis(12));
+				let mut pressed = None;
+				loop {
+					match input_receiver.try_recv() {
+						Ok(buttons) => pressed = Some(buttons),
+						Err(TryRecvError::Empty) => break,
+						Err(TryRecvError::Disconnected) => {
+							write_compat_report(&gameboy, &options);
+							write_battery_save(&gameboy, &rom_path);
+							return (gameboy.frame_count(), gameboy.interrupt_counts());
+						}
+					}
+				}
+				if let Some(pressed) = pressed {
+					gameboy.set_input(pressed);
+				}
+			}
+			print_serial_output(&mut gameboy);
+		}
+	});
+
+	let mut record = frame_recorder();
+	while window.is_open() && !window.is_key_down(Key::Escape) {
+		match frame_receiver.recv_timeout(Duration::from_millis(16)) {
+			Ok((frames, frame)) => {
+				record(meter, frames);
+				set_fps_title(window, &title, meter);
+				screen.present(window, &frame, None);
+			}
+			Err(RecvTimeoutError::Timeout) => window.update(),
+			Err(RecvTimeoutError::Disconnected) => break,
+		}
+		let _ = input_sender.send(pressed_buttons(window));
+	}
+
+	drop(frame_receiver);
+	drop(input_sender);
+	let (frames, interrupt_counts) = emulation.join().expect("emulation thread panicked");
+	record(meter, frames);
+	interrupt_counts
+}
+
+// Records the frames completed since it was last called, given the frames completed so far, into
+// the meter
+fn frame_recorder() -> impl FnMut(&mut SpeedMeter, u64) {
+	let mut frames_seen = 0;
+	move |meter: &mut SpeedMeter, frames: u64| {
+		let new_frames = frames.saturating_sub(frames_seen);
+		meter.record(new_frames, new_frames * CYCLES_PER_FRAME);
+		frames_seen = frames;
+	}
+}
+
+// Frames completed by `gameboy` get counted into `frames` & reach the script, both through frame
+// observers, needed for every GameBoy the frontend creates
+fn observe_frames(gameboy: &mut GameBoy, frames: &Arc<AtomicU64>, scripting: &scripting::Runner) {
+	let frames = frames.clone();
+	gameboy.add_frame_observer(Box::new(move |_, _| {
+		frames.fetch_add(1, Ordering::Relaxed);
+	}));
+	scripting.observe(gameboy);
+}
+
+// switches to the palette after the current one, remembered for the game
+fn next_palette(
+	gameboy: &mut GameBoy,
+	game_palettes: &mut GamePalettes,
+	cartridge: &[u8],
+) -> Palette {
+	let palette = gameboy.palette().next();
+	gameboy.set_palette(palette);
+	info!("palette: {}", palette.name);
+	if let Err(e) = game_palettes.set(&header::fingerprint(cartridge), palette) {
+		warn!("unable to save palette: {}", e);
+	}
+	palette
 }
 
-fn update_joypad_key(window: &Window, mmu: &mut MMU, button: Button) {
-	match window.is_key_down(Key::from(button)) {
-		true => mmu.press_key(button),
-		false => mmu.release_key(button),
+// Battery backed cartridge RAM is kept next to the rom as .sav, see save
+fn load_battery_save(gameboy: &mut GameBoy, rom_path: &Path) {
+	if !gameboy.has_battery() {
+		return;
+	}
+	let path = rom_path.with_extension("sav");
+	match save::read(&path) {
+		Ok(data) => {
+			let (ram, rtc) = save::split(&data, gameboy.cartridge_ram().len());
+			gameboy.load_cartridge_ram(ram);
+			if let Some(rtc) = rtc {
+				gameboy.load_rtc(&rtc);
+			}
+			info!("loaded {}", path.display());
+		}
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+		Err(e) => warn!("unable to read {}: {}", path.display(), e),
 	};
 }
 
+fn write_battery_save(gameboy: &GameBoy, rom_path: &Path) {
+	let rtc = gameboy.rtc();
+	if !gameboy.has_battery() || (gameboy.cartridge_ram().is_empty() && rtc.is_none()) {
+		return;
+	}
+	let path = rom_path.with_extension("sav");
+	if let Err(e) = save::write(&path, &save::join(gameboy.cartridge_ram(), rtc.as_ref())) {
+		warn!("unable to write {}: {}", path.display(), e);
+	}
+}
+
+// --inspect-save, the save is taken as it is even when it fails its integrity check as it may
+// have been edited by hand
+fn inspect_save(rom_path: &Path, fix_checksum: bool) {
+	let exit = |message: String| -> ! {
+		eprintln!("{}", message);
+		process::exit(1);
+	};
+	let rom = fs::read(rom_path)
+		.unwrap_or_else(|e| exit(format!("unable to read {}: {}", rom_path.display(), e)));
+	let header = Header::parse(&rom)
+		.unwrap_or_else(|| exit(format!("{} is too small to be a rom", rom_path.display())));
+	let codec = saves::codec_for(&header.title)
+		.unwrap_or_else(|| exit(format!("no save codec for {:?}", header.title)));
+	let path = rom_path.with_extension("sav");
+	let data =
+		fs::read(&path).unwrap_or_else(|e| exit(format!("unable to read {}: {}", path.display(), e)));
+	let data = save::strip(&data);
+	let ram_size = header
+		.ram_size_kib()
+		.map_or(data.len(), |kib| kib as usize * 1024);
+	let (ram, rtc) = save::split(data, ram_size);
+	let mut ram = ram.to_vec();
+	println!("{}", codec.decode(&ram).unwrap_or_else(|e| exit(e)));
+
+	if !fix_checksum {
+		return;
+	}
+	match codec.fix_checksums(&mut ram)[..] {
+		[] => eprintln!("checksums are valid, {} left as it is", path.display()),
+		ref fixed => {
+			if let Err(e) = save::write(&path, &save::join(&ram, rtc.as_ref())) {
+				exit(format!("unable to write {}: {}", path.display(), e));
+			}
+			eprintln!(
+				"fixed {} checksum(s) of {}, the previous save is kept as .bak1",
+				fixed.join(", "),
+				path.display()
+			);
+		}
+	}
+}
+
+// with --compat-report, overwrites the report with the events tracked so far
+fn write_compat_report(gameboy: &GameBoy, options: &EmulatorOptions) {
+	let (Some(path), Some(report)) = (&options.compat_report, gameboy.compat_report()) else {
+		return;
+	};
+	match fs::write(path, report.to_json() + "\n") {
+		Ok(()) => info!("compat report written to {}", path.display()),
+		Err(e) => warn!("unable to write {}: {}", path.display(), e),
+	};
+}
+
+// Shows the roms in `dir` until one is picked, None if cancelled or there are none
+fn pick_rom(window: &mut Window, screen: &mut Screen, dir: &Path) -> Option<PathBuf> {
+	let roms = romlist::list_roms(dir).unwrap_or_else(|e| {
+		warn!("unable to list roms in {}: {}", dir.display(), e);
+		Vec::new()
+	});
+	if roms.is_empty() {
+		warn!("no roms found in {}", dir.display());
+		return None;
+	}
+	let mut menu = RomMenu::new(roms, pressed_buttons(window));
+	let blank = vec![0_u32; WIDTH * HEIGHT];
+	window.set_title("RustBoy");
+	while window.is_open() && !window.is_key_down(Key::Escape) {
+		match menu.update(pressed_buttons(window)) {
+			Some(Pick::Rom(path)) => return Some(path),
+			Some(Pick::Cancel) => return None,
+			None => screen.present(window, &blank, Some(&menu)),
+		};
+		thread::sleep(Duration::from_millis(16));
+	}
+	None
+}
+
 fn main() {
+	// values remembered from the last run replace the defaults, the command line overrides them
+	let state_path = state::default_path();
+	let remembered = state_path.as_deref().and_then(State::load);
+	let defaults = match &remembered {
+		Some(state) => state.restore(EmulatorOptions::default()),
+		None => EmulatorOptions::default(),
+	};
+	let options = EmulatorOptions::parse_onto(defaults, env::args().skip(1)).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		process::exit(1);
+	});
+	if options.help {
+		print!("{}", options::USAGE);
+		return;
+	}
+	logger::init(options.log_level);
+	recorder::install_panic_hook();
+	if options.selftest {
+		process::exit(if selftest::run() { 0 } else { 1 });
+	}
+	if options.accuracy {
+		// test rom suites aren't bundled, only the built-in test rom runs without --test-roms
+		let mut outcomes = vec![selftest::outcome()];
+		if let Some(dir) = &options.test_roms {
+			outcomes.extend(testroms::run_dir(dir));
+		}
+		match options.json {
+			true => println!("{}", scoreboard::json(&outcomes)),
+			false => print!("{}", scoreboard::markdown(&outcomes)),
+		};
+		process::exit(if outcomes.iter().all(|o| o.passed) {
+			0
+		} else {
+			1
+		});
+	}
+	let symbols = match &options.symbols {
+		Some(path) => Symbols::parse(&fs::read_to_string(path).unwrap_or_else(|e| {
+			eprintln!("unable to read {}: {}", path.display(), e);
+			process::exit(1);
+		})),
+		None => Symbols::default(),
+	};
+	if let Some(path) = &options.info {
+		let rom = fs::read(path).unwrap_or_else(|e| {
+			eprintln!("unable to read {}: {}", path.display(), e);
+			process::exit(1);
+		});
+		let Some(header) = Header::parse(&rom) else {
+			eprintln!("{} is too small to be a rom", path.display());
+			process::exit(1);
+		};
+		match options.json {
+			true => println!("{}", header.to_json()),
+			false => println!("{}", header),
+		};
+		return;
+	}
+	if let Some(path) = &options.inspect_save {
+		inspect_save(path, options.fix_checksum);
+		return;
+	}
+	if let (Some(dir), Some(path)) = (&options.export_gfx, &options.from_state) {
+		let state = StateFile::read(path).unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			process::exit(1);
+		});
+		if let Err(e) = gfx::export_state(dir, &state, options.export_scale) {
+			eprintln!("unable to export graphics: {}", e);
+			process::exit(1);
+		}
+		return;
+	}
+	if let Some(path) = &options.disasm {
+		let rom = fs::read(path).unwrap_or_else(|e| {
+			eprintln!("unable to read {}: {}", path.display(), e);
+			process::exit(1);
+		});
+		let (start, end) = options.disasm_range;
+		print!(
+			"{}",
+			disasm::disassemble(&rom, options.disasm_bank, start, end, &symbols)
+		);
+		return;
+	}
+
 	let cwd = env::current_dir().expect("unable to get current working directory");
-	let cartridge = fs::read(cwd.join("rom.gb")).expect("unable to load cartridge");
+	let mut rom_path = options.rom.clone().unwrap_or_else(|| cwd.join("rom.gb"));
+	// the rom with --patch applied, for every load incl. --watch reloads & rom list switches
+	let read_cartridge = |path: &Path| -> Result<Arc<[u8]>, String> {
+		let rom = fs::read(path).map_err(|e| {
+			match options.rom.is_none() && e.kind() == io::ErrorKind::NotFound {
+				true => "no rom given & no rom.gb in the current directory, see --help".to_string(),
+				false => format!("unable to read {}: {}", path.display(), e),
+			}
+		})?;
+		match &options.patch {
+			Some(patch) => fs::read(patch)
+				.map_err(|e| e.to_string())
+				.and_then(|patch| patch::apply(&rom, &patch))
+				.map(|rom| rom.into())
+				.map_err(|e| format!("unable to apply {}: {}", patch.display(), e)),
+			None => Ok(rom.into()),
+		}
+	};
+	let load_cartridge = |path: &Path| -> Arc<[u8]> {
+		read_cartridge(path).unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			process::exit(1);
+		})
+	};
+	let boot_rom = fs::read(cwd.join("dmg_boot.bin")).ok();
+	if let Some(frames) = options.frame_timing {
+		let mut gameboy = GameBoy::new(load_cartridge(&rom_path), boot_rom, &options);
+		let passed = frametiming::report(&frametiming::measure(&mut gameboy, frames));
+		process::exit(if passed { 0 } else { 1 });
+	}
+	if let (Some(dir), Some(frames)) = (&options.export_gfx, options.export_frames) {
+		let mut gameboy = GameBoy::new(load_cartridge(&rom_path), boot_rom, &options);
+		(0..frames).for_each(|_| {
+			gameboy.run_frame();
+		});
+		if let Err(e) = gfx::export(dir, &gameboy, options.export_scale) {
+			eprintln!("unable to export graphics: {}", e);
+			process::exit(1);
+		}
+		return;
+	}
+	let mut scripting = scripting::Runner::load(&options);
+	if options.headless {
+		let mut gameboy = GameBoy::new(load_cartridge(&rom_path), boot_rom, &options);
+		gameboy.set_symbols(symbols);
+		scripting.run_headless(gameboy);
+	}
+	// a rom that can't be loaded is reported before any window opens
+	let preloaded = options.rom_dir.is_none().then(|| load_cartridge(&rom_path));
+	let mut screen = Screen::new(&options);
+	// window size of the last run wins over the default one, unless the scale changed
+	let (width, height) = remembered
+		.and_then(|state| state.window_size(options.scale))
+		.unwrap_or_else(|| screen.window_size());
 	let mut window = Window::new(
 		"RustBoy",
-		WIDTH,
-		HEIGHT,
+		width,
+		height,
 		WindowOptions {
 			resize: true,
-			scale: Scale::X4,
-			scale_mode: ScaleMode::AspectRatioStretch,
+			scale: Scale::X1,
+			scale_mode: screen.scale_mode(),
 			..WindowOptions::default()
 		},
 	)
 	.expect("unable to create window");
-	let mut mmu = MMU::new(cartridge);
-	let mut cpu = CPU::new();
-	let mut ppu = PPU::new(&mmu);
-	let mut frames = 0;
-	let start = SystemTime::now();
+	if let Some(dir) = &options.rom_dir {
+		match pick_rom(&mut window, &mut screen, dir) {
+			Some(path) => rom_path = path,
+			None => return,
+		};
+	}
+	let mut cartridge = preloaded.unwrap_or_else(|| load_cartridge(&rom_path));
+	let mut title = window_title(&cartridge);
+	let mut meter = SpeedMeter::new();
+	let mut game_palettes =
+		GamePalettes::load(state::config_dir().map(|dir| dir.join("palettes.toml")));
 
-	while window.is_open() && !window.is_key_down(Key::Escape) {
-		let cycles = cpu.execute_next(&mut mmu);
-		(0..cycles).for_each(|_| {
-			mmu.update_timers(1);
-			ppu.tick(&mut mmu);
-
-			if ppu.is_frame_ready() {
-				window.set_title(
-					format!(
-						"RustBoy - FPS: {}",
-						1_000_000 * frames / start.elapsed().unwrap().as_micros()
-					)
-					.as_str(),
-				);
-				let _ = window.update_with_buffer(ppu.get_frame_buffer(), WIDTH, HEIGHT);
-				frames += 1;
-				thread::sleep(Duration::from_millis(12));
-				Button::values()
-					.iter()
-					.for_each(|button| update_joypad_key(&window, &mut mmu, *button));
-			}
-		});
+	if options.threaded_present && options.script.is_some() {
+		warn!("--script isn't run with --threaded-present");
+	}
+	if options.threaded_present && options.runahead > 0 {
+		warn!("--runahead isn't supported with --threaded-present");
 	}
+	let mut state = State::from_options(&options);
+	let interrupt_counts = match options.threaded_present {
+		true => run_threaded(
+			&mut window,
+			&EmulatorOptions {
+				palette: game_palettes.get(&header::fingerprint(&cartridge), options.palette),
+				..options.clone()
+			},
+			&rom_path,
+			cartridge,
+			boot_rom,
+			symbols,
+			screen,
+			&mut meter,
+		),
+		false => {
+			let frames = Arc::new(AtomicU64::new(0));
+			let mut record = frame_recorder();
+			let mut gameboy = GameBoy::new(cartridge.clone(), boot_rom.clone(), &options);
+			observe_frames(&mut gameboy, &frames, &scripting);
+			gameboy.set_symbols(symbols.clone());
+			gameboy.set_palette(game_palettes.get(&header::fingerprint(&cartridge), options.palette));
+			load_battery_save(&mut gameboy, &rom_path);
+			gameboy = scripting.start(gameboy);
+			// buttons held on the keyboard & by the script
+			let pressed = |window: &Window, scripting: &scripting::Runner| {
+				let mut pressed = pressed_buttons(window);
+				pressed.extend(scripting.held());
+				pressed
+			};
+			let mut menu: Option<PauseMenu> = None;
+			// the pause menu's save state slot, for the game running
+			let mut saved_state: Option<Snapshot> = None;
+			// frame advance: Space pauses/resumes, `.` steps a frame forward & `,` one back
+			let mut frame_paused = false;
+			let mut rewind = Rewind::new(options.rewind_memory);
+			let modified = |path: &PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+			let mut rom_modified = modified(&rom_path);
+			// every frame gets emulated twice with run-ahead, turned off for good once that's more
+			// than the host keeps up with
+			let mut runahead = options.runahead > 0;
+
+			while window.is_open() && !window.is_key_down(Key::Escape) {
+				if window.is_key_pressed(Key::P, KeyRepeat::No) {
+					menu = match menu {
+						Some(_) => None,
+						None => Some(PauseMenu::new(pressed_buttons(&window))),
+					};
+				}
+				// O goes back to the rom list to switch games
+				if let Some(dir) = &options.rom_dir
+					&& window.is_key_pressed(Key::O, KeyRepeat::No)
+				{
+					if let Some(path) = pick_rom(&mut window, &mut screen, dir) {
+						match read_cartridge(&path) {
+							Ok(rom) => {
+								write_battery_save(&gameboy, &rom_path);
+								rom_path = path;
+								rom_modified = modified(&rom_path);
+								cartridge = rom;
+								title = window_title(&cartridge);
+								gameboy = GameBoy::new(cartridge.clone(), boot_rom.clone(), &options);
+								observe_frames(&mut gameboy, &frames, &scripting);
+								gameboy.set_symbols(symbols.clone());
+								gameboy.set_layers(state.layers);
+								saved_state = None;
+								load_battery_save(&mut gameboy, &rom_path);
+								gameboy.set_palette(
+									game_palettes.get(&header::fingerprint(&cartridge), options.palette),
+								);
+								rewind = Rewind::new(options.rewind_memory);
+							}
+							Err(e) => warn!("{}", e),
+						};
+					}
+					continue;
+				}
+				// R resets like the console's reset (cartridge RAM kept), Shift+R power cycles
+				if window.is_key_pressed(Key::R, KeyRepeat::No) {
+					let hard = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+					gameboy.reset(hard);
+					rewind = Rewind::new(options.rewind_memory);
+				}
+				if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+					write_compat_report(&gameboy, &options);
+				}
+				// C switches to the next palette, remembered for this game
+				if window.is_key_pressed(Key::C, KeyRepeat::No) {
+					state.palette = next_palette(&mut gameboy, &mut game_palettes, &cartridge);
+				}
+				// 1, 2 & 3 toggle the background, window & objects
+				let toggled =
+					[Key::Key1, Key::Key2, Key::Key3].map(|key| window.is_key_pressed(key, KeyRepeat::No));
+				if toggled.contains(&true) {
+					let mut layers = gameboy.layers();
+					layers.background ^= toggled[0];
+					layers.window ^= toggled[1];
+					layers.objects ^= toggled[2];
+					gameboy.set_layers(layers);
+					info!("hidden layers: {}", layers.hidden());
+					state.layers = layers;
+				}
+				if let Some(dir) = &options.export_gfx
+					&& window.is_key_pressed(Key::G, KeyRepeat::No)
+				{
+					match gfx::export(dir, &gameboy, options.export_scale) {
+						Ok(()) => info!("graphics exported to {}", dir.display()),
+						Err(e) => warn!("unable to export graphics: {}", e),
+					};
+				}
+				if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+					frame_paused = !frame_paused;
+				}
+				if frame_paused && menu.is_none() {
+					if window.is_key_pressed(Key::Period, KeyRepeat::Yes) {
+						gameboy.set_input(pressed(&window, &scripting));
+						gameboy.run_frame();
+						rewind.push(gameboy.snapshot());
+						print_serial_output(&mut gameboy);
+					} else if window.is_key_pressed(Key::Comma, KeyRepeat::Yes)
+						&& let Some(snapshot) = rewind.step_back()
+					{
+						gameboy.restore(snapshot);
+					}
+					window.set_title(&format!(
+						"{} - paused at frame {} (rewind: {:.1} MiB, {:.1}s)",
+						title,
+						gameboy.frame_count(),
+						rewind.memory_usage() as f64 / (1 << 20) as f64,
+						rewind.window_seconds()
+					));
+					screen.present(&mut window, gameboy.get_frame_buffer(), None);
+					thread::sleep(Duration::from_millis(16));
+					continue;
+				}
+				if let Some(pause_menu) = menu.as_mut() {
+					match pause_menu.update(pressed_buttons(&window)) {
+						Some(MenuAction::Resume) => menu = None,
+						Some(MenuAction::Reset) => {
+							gameboy.reset(true);
+							rewind = Rewind::new(options.rewind_memory);
+							menu = None;
+						}
+						// exact in memory, the file is for later runs & tools (see StateFile)
+						Some(MenuAction::SaveState) => {
+							saved_state = Some(gameboy.snapshot());
+							let path = rom_path.with_extension("ss1");
+							match gameboy.state_file().write(&path) {
+								Ok(()) => info!("state saved to {}", path.display()),
+								Err(e) => warn!("unable to write {}: {}", path.display(), e),
+							};
+							menu = None;
+						}
+						Some(MenuAction::LoadState) => {
+							// the file left by an earlier run unless a state got saved in this one
+							let loaded = match &saved_state {
+								Some(snapshot) => {
+									gameboy.restore(snapshot);
+									Ok(())
+								}
+								None => StateFile::read(&rom_path.with_extension("ss1"))
+									.map(|state| gameboy.load_state_file(&state)),
+							};
+							match loaded {
+								Ok(()) => {
+									rewind = Rewind::new(options.rewind_memory);
+									menu = None;
+								}
+								Err(e) => warn!("{}", e),
+							};
+						}
+						// the menu stays open to see the palette
+						Some(MenuAction::ChangePalette) => {
+							state.palette = next_palette(&mut gameboy, &mut game_palettes, &cartridge);
+						}
+						Some(MenuAction::Quit) => break,
+						None => {
+							screen.present(&mut window, gameboy.get_frame_buffer(), Some(&*pause_menu));
+							thread::sleep(Duration::from_millis(16));
+						}
+					};
+					continue;
+				}
 
-	println!(
-		"frames: {}, time elapsed: {:?}, fps: {}",
-		frames,
-		start.elapsed(),
-		1_000_000.0 * (frames as f32) / (start.elapsed().unwrap().as_micros() as f32)
+				if gameboy.step() {
+					record(&mut meter, frames.load(Ordering::Relaxed));
+					gameboy = scripting.on_frame(gameboy);
+					rewind.push(gameboy.snapshot());
+					if options.self_check {
+						report_violations(&gameboy);
+					}
+					match gameboy.stall() {
+						Some(stall) => window.set_title(&format!("{} - stalled: {}", title, stall)),
+						None => set_fps_title(&mut window, &title, &meter),
+					};
+					if runahead && meter.speed_percent() > 0.0 && meter.speed_percent() < 95.0 {
+						warn!(
+							"run-ahead turned off, emulation runs at {:.0}% with it",
+							meter.speed_percent()
+						);
+						runahead = false;
+					}
+					match options.skip_unchanged_frames && !gameboy.is_frame_changed() && !runahead {
+						true => window.update(),
+						false => {
+							let osd = scripting.osd();
+							let overlay = osd.as_ref().map(|osd| osd as &dyn Overlay);
+							// the frame after this one, as it'll be with the buttons held right now
+							let ahead = runahead.then(|| {
+								gameboy.set_input(pressed(&window, &scripting));
+								gameboy.run_ahead()
+							});
+							let frame = ahead.as_deref().unwrap_or(gameboy.get_frame_buffer());
+							screen.present(&mut window, frame, overlay)
+						}
+					};
+					thread::sleep(Duration::from_millis(12));
+					gameboy.set_input(pressed(&window, &scripting));
+
+					// a rom still being written fails to load or has a newer mtime on the next poll
+					if options.watch && modified(&rom_path) != rom_modified {
+						rom_modified = modified(&rom_path);
+						match read_cartridge(&rom_path) {
+							Ok(rom) => {
+								info!("rom changed, resetting");
+								// a rebuilt rom has a new fingerprint but is still the same game, its
+								// save carries over through the .sav unless told otherwise
+								if !options.watch_reset_ram {
+									write_battery_save(&gameboy, &rom_path);
+								}
+								let palette = gameboy.palette();
+								cartridge = rom;
+								title = window_title(&cartridge);
+								gameboy = GameBoy::new(cartridge.clone(), boot_rom.clone(), &options);
+								observe_frames(&mut gameboy, &frames, &scripting);
+								gameboy.set_symbols(symbols.clone());
+								gameboy.set_palette(palette);
+								gameboy.set_layers(state.layers);
+								saved_state = None;
+								if !options.watch_reset_ram {
+									load_battery_save(&mut gameboy, &rom_path);
+								}
+								rewind = Rewind::new(options.rewind_memory);
+							}
+							Err(e) => warn!("rom not reloaded: {}", e),
+						};
+					}
+				}
+				print_serial_output(&mut gameboy);
+			}
+			write_compat_report(&gameboy, &options);
+			write_battery_save(&gameboy, &rom_path);
+			gameboy.interrupt_counts()
+		}
+	};
+
+	if let Some(path) = &state_path {
+		state.window = Some(window.get_size());
+		if let Err(e) = state.save(path) {
+			warn!("unable to save {}: {}", path.display(), e);
+		}
+	}
+	info!(
+		"frames: {}, time elapsed: {:?}, fps: {:.2}",
+		meter.frames(),
+		meter.elapsed(),
+		meter.lifetime_fps()
 	);
+	info!("interrupts: {}", interrupt_counts);
 }
@@ -0,0 +1,89 @@
+use rustboy::{
+	HEIGHT, WIDTH,
+	joypad::Button,
+	text::{draw_text, text_width},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+	Resume,
+	Reset,
+	SaveState,
+	LoadState,
+	ChangePalette,
+	Quit,
+}
+
+// Drawn over the frame before it gets presented
+pub trait Overlay {
+	fn draw(&self, buffer: &mut [u32]);
+}
+
+const ITEMS: [(&str, MenuAction); 6] = [
+	("RESUME", MenuAction::Resume),
+	("RESET", MenuAction::Reset),
+	("SAVE STATE", MenuAction::SaveState),
+	("LOAD STATE", MenuAction::LoadState),
+	("CHANGE PALETTE", MenuAction::ChangePalette),
+	("QUIT", MenuAction::Quit),
+];
+
+// Pause menu drawn over the dimmed frame, a vertical list navigated with UP/DOWN & picked with
+// A (or START), B resumes
+pub struct PauseMenu {
+	selected: usize,
+	held: Vec<Button>,
+}
+
+impl PauseMenu {
+	pub fn new(held: Vec<Button>) -> Self {
+		// buttons already held while opening the menu shouldn't trigger anything
+		PauseMenu { selected: 0, held }
+	}
+
+	// takes the buttons currently held & returns the picked action, if any
+	pub fn update(&mut self, pressed: Vec<Button>) -> Option<MenuAction> {
+		let just_pressed = |button: Button| pressed.contains(&button) && !self.held.contains(&button);
+		let action = if just_pressed(Button::UP) {
+			self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+			None
+		} else if just_pressed(Button::DOWN) {
+			self.selected = (self.selected + 1) % ITEMS.len();
+			None
+		} else if just_pressed(Button::A) || just_pressed(Button::START) {
+			Some(ITEMS[self.selected].1)
+		} else if just_pressed(Button::B) {
+			Some(MenuAction::Resume)
+		} else {
+			None
+		};
+		self.held = pressed;
+		action
+	}
+}
+
+impl Overlay for PauseMenu {
+	fn draw(&self, buffer: &mut [u32]) {
+		buffer.iter_mut().for_each(|p| *p = (*p >> 2) & 0x003F3F3F);
+
+		let top = (HEIGHT - 8 * (ITEMS.len() + 2)) / 2;
+		draw_text(
+			buffer,
+			(WIDTH - text_width("PAUSED")) / 2,
+			top,
+			"PAUSED",
+			0x00FFFFFF,
+		);
+		ITEMS.iter().enumerate().for_each(|(i, (name, _))| {
+			let y = top + 8 * (i + 2);
+			let x = (WIDTH - text_width(name)) / 2;
+			match i == self.selected {
+				true => {
+					draw_text(buffer, x - 8, y, ">", 0x00FFFFFF);
+					draw_text(buffer, x, y, name, 0x00FFFFFF);
+				}
+				false => draw_text(buffer, x, y, name, 0x00A0A0A0),
+			}
+		});
+	}
+}
@@ -1,87 +1,423 @@
 use crate::{
-	cartridge::{self, Cartridge},
+	cartridge::{self, BankingState, Cartridge},
+	compatreport::{CompatReport, Event, SharedReport},
 	joypad::{Button, Joypad},
+	options::{EmulatorOptions, Model, RamInit},
+	save::RtcState,
 	utils::is_bit_set,
 };
+use std::{
+	cell::Cell,
+	fmt,
+	sync::{Arc, Mutex},
+};
+
+// CPU access on the address bus, as far as the OAM corruption bug cares
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OamAccess {
+	Read,
+	Write,
+}
+
+// Timer internals, DIV being the upper byte of div_counter & TIMA incrementing on a falling edge
+// of `div_counter bit selected_bit AND timer enabled`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimerState {
+	pub div_counter: u16,
+	pub selected_bit: u8,
+	pub and_result: bool,
+	pub tima: u8,
+	pub tma: u8,
+	pub tac: u8,
+}
 
+impl fmt::Display for TimerState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"DIV:{:02X} (counter {:04X}, bit {}) AND:{} TIMA:{:02X} TMA:{:02X} TAC:{:02X}",
+			self.div_counter >> 8,
+			self.div_counter,
+			self.selected_bit,
+			self.and_result as u8,
+			self.tima,
+			self.tma,
+			self.tac
+		)
+	}
+}
+
+#[derive(Clone)]
 pub struct MMU {
+	boot_rom: Option<Vec<u8>>,
 	cartridge: Box<dyn Cartridge>,
 	memory: [u8; 0x10000],
 	div_counter: u16,
 	prev_and_result: bool,
 	dma_cycles_counter: u16,
+	// cycles until the serial transfer in progress completes, 0 if none is
+	serial_cycles_counter: u16,
 	joypad: Joypad,
+	serial_output: Vec<u8>,
+	// reads of 0xA000-0xBFFF without enabled cartridge RAM return `bus` instead of 0xFF
+	open_bus: bool,
+	// while the LCD is on, the CPU can't reach VRAM in mode 3 nor OAM (& the unusable region after
+	// it) in modes 2 & 3
+	lock_vram: bool,
+	// with oam_bug, the last unusable region access made during OAM scan, for the PPU to corrupt
+	// OAM with (see take_oam_bug_access)
+	oam_bug: bool,
+	oam_bug_access: Cell<Option<OamAccess>>,
+	model: Model,
+	// last byte the CPU fetched from the instruction stream, what's left on the bus when nothing
+	// drives it (e.g. the high address byte of LD A,(a16) or the opcode of LD A,(HL))
+	bus: Cell<u8>,
+	// only with --compat-report, otherwise nothing gets tracked
+	report: Option<SharedReport>,
 }
 
 impl MMU {
-	pub fn new(cartridge: Vec<u8>) -> Self {
+	// OAM DMA takes 160 M-cycles (640 T-cycles). CGB double speed (KEY1) isn't emulated, so this &
+	// SERIAL_CYCLES are the only timings.
+	const DMA_CYCLES: u16 = 0x0280;
+	// 8 bits shifted out at 8192 Hz with the internal clock
+	const SERIAL_CYCLES: u16 = 0x1000;
+
+	pub fn new(cartridge: Arc<[u8]>, boot_rom: Option<Vec<u8>>, options: &EmulatorOptions) -> Self {
 		let mut memory = [0_u8; 0x10000];
+		[0x8000..0xA000, 0xC000..0xE000, 0xFE00..0xFEA0]
+			.into_iter()
+			.for_each(|region| Self::fill_ram(options.ram_init, &mut memory[region]));
 		memory[0xFF00] = 0xCF;
-		memory[0xFF02] = 0x7E;
-		memory[0xFF04] = 0xAB;
-		memory[0xFF07] = 0xF8;
-		memory[0xFF0F] = 0xE1;
-		memory[0xFF10] = 0x80;
-		memory[0xFF11] = 0xBF;
-		memory[0xFF12] = 0xF3;
-		memory[0xFF13] = 0xFF;
-		memory[0xFF14] = 0xBF;
-		memory[0xFF16] = 0x3F;
-		memory[0xFF18] = 0xFF;
-		memory[0xFF19] = 0xBF;
-		memory[0xFF1A] = 0x7F;
-		memory[0xFF1B] = 0xFF;
-		memory[0xFF1C] = 0x9F;
-		memory[0xFF1D] = 0xFF;
-		memory[0xFF1E] = 0xBF;
-		memory[0xFF20] = 0xFF;
-		memory[0xFF23] = 0xBF;
-		memory[0xFF24] = 0x77;
-		memory[0xFF25] = 0xF3;
-		memory[0xFF26] = 0xF1;
-		memory[0xFF40] = 0x91;
-		memory[0xFF41] = 0x85;
-		memory[0xFF46] = 0xFF;
-		memory[0xFF47] = 0xFC;
+		// registers as the DMG boot rom leaves them, a boot rom itself starts from the power-on state
+		// where they're mostly 0 (LCD off, DIV counter 0)
+		let div_counter = match boot_rom.is_some() {
+			true => 0x0000,
+			false => 0xABCC,
+		};
+		if boot_rom.is_none() {
+			memory[0xFF02] = 0x7E;
+			memory[0xFF04] = 0xAB;
+			memory[0xFF07] = 0xF8;
+			memory[0xFF0F] = 0xE1;
+			memory[0xFF10] = 0x80;
+			memory[0xFF11] = 0xBF;
+			memory[0xFF12] = 0xF3;
+			memory[0xFF13] = 0xFF;
+			memory[0xFF14] = 0xBF;
+			memory[0xFF16] = 0x3F;
+			memory[0xFF18] = 0xFF;
+			memory[0xFF19] = 0xBF;
+			memory[0xFF1A] = 0x7F;
+			memory[0xFF1B] = 0xFF;
+			memory[0xFF1C] = 0x9F;
+			memory[0xFF1D] = 0xFF;
+			memory[0xFF1E] = 0xBF;
+			memory[0xFF20] = 0xFF;
+			memory[0xFF23] = 0xBF;
+			memory[0xFF24] = 0x77;
+			memory[0xFF25] = 0xF3;
+			memory[0xFF26] = 0xF1;
+			memory[0xFF40] = 0x91;
+			memory[0xFF41] = 0x85;
+			memory[0xFF46] = 0xFF;
+			memory[0xFF47] = 0xFC;
+		}
 
 		MMU {
-			cartridge: cartridge::create(cartridge),
-			memory: memory,
-			div_counter: 0xABCC,
+			boot_rom,
+			cartridge: cartridge::create(cartridge, options.strict_mapper),
+			memory,
+			div_counter,
 			prev_and_result: false,
 			dma_cycles_counter: 0,
-			joypad: Joypad::new(),
+			serial_cycles_counter: 0,
+			joypad: Joypad::new(options.sgb_stub),
+			serial_output: Vec::new(),
+			open_bus: options.open_bus,
+			lock_vram: options.lock_vram,
+			oam_bug: options.oam_bug,
+			oam_bug_access: Cell::new(None),
+			model: options.model,
+			bus: Cell::new(0xFF),
+			report: options
+				.compat_report
+				.is_some()
+				.then(|| Arc::new(Mutex::new(CompatReport::default()))),
+		}
+	}
+
+	fn fill_ram(pattern: RamInit, region: &mut [u8]) {
+		let mut seed = 0x2545F491_u32;
+		region.iter_mut().enumerate().for_each(|(i, b)| {
+			*b = match pattern {
+				RamInit::Zeros => 0x00,
+				RamInit::Ones => 0xFF,
+				RamInit::Alternating => [0x00, 0xFF][i % 2],
+				RamInit::Random => {
+					// xorshift32
+					seed ^= seed << 13;
+					seed ^= seed >> 17;
+					seed ^= seed << 5;
+					seed as u8
+				}
+			}
+		});
+	}
+
+	// exchanges cartridges (incl. RAM & banking registers) with `other`
+	pub fn swap_cartridge(&mut self, other: &mut MMU) {
+		std::mem::swap(&mut self.cartridge, &mut other.cartridge);
+	}
+
+	// keeps on tallying into the report of `other`
+	pub fn share_report(&mut self, other: &MMU) {
+		self.report = other.report.clone();
+	}
+
+	pub fn report(&self) -> Option<&SharedReport> {
+		self.report.as_ref()
+	}
+
+	pub fn record(&self, event: Event, address: u16) {
+		if let Some(report) = &self.report {
+			report.lock().unwrap().record(event, address);
 		}
 	}
 
+	// IO addresses nothing answers on DMG, CGB only registers included
+	fn is_unmapped_io(address: u16) -> bool {
+		matches!(
+			address,
+			0xFF03 | 0xFF08..=0xFF0E | 0xFF27..=0xFF2F | 0xFF4C..=0xFF4F | 0xFF51..=0xFF55 | 0xFF57..=0xFF7F
+		)
+	}
+
+	// power-up DIV counter, see GameBoy::power_up_phase
+	pub fn set_div_counter(&mut self, value: u16) {
+		self.div_counter = value;
+	}
+
+	// The PPU updating STAT & LY, a plain store that unlike CPU writes isn't tracked by the report
+	pub fn write_register(&mut self, address: u16, value: u8) {
+		self.memory[address as usize] = value;
+	}
+
+	fn track_read(&self, address: u16) {
+		match address {
+			0xA000..0xC000 if !self.cartridge.ram_accessible() => {
+				self.record(Event::DisabledRamAccess, address)
+			}
+			0xFEA0..0xFF00 => self.record(Event::UnmappedRead, address),
+			a if Self::is_unmapped_io(a) => self.record(Event::UnmappedRead, address),
+			_ => {}
+		}
+	}
+
+	fn track_write(&self, address: u16) {
+		match address {
+			0x0000..0x8000 if !self.cartridge.is_register(address) => {
+				self.record(Event::RomWrite, address)
+			}
+			0xA000..0xC000 if !self.cartridge.ram_accessible() => {
+				self.record(Event::DisabledRamAccess, address)
+			}
+			0xFF41 if self.memory[0xFF41] & 0x03 == 3 => self.record(Event::StatWriteInMode3, address),
+			a if Self::is_unmapped_io(a) => self.record(Event::UnknownIoWrite, address),
+			_ => {}
+		}
+	}
+
+	// rom bank currently mapped at 0x4000-0x7FFF
+	pub fn rom_bank(&self) -> u16 {
+		self.cartridge.banking_state().rom_bank.max(1)
+	}
+
+	pub fn cartridge_ram(&self) -> &[u8] {
+		self.cartridge.ram()
+	}
+
+	pub fn load_cartridge_ram(&mut self, data: &[u8]) {
+		self.cartridge.load_ram(data);
+	}
+
+	pub fn rtc(&self) -> Option<RtcState> {
+		self.cartridge.rtc()
+	}
+
+	pub fn load_rtc(&mut self, state: &RtcState, elapsed_seconds: u64) {
+		self.cartridge.load_rtc(state, elapsed_seconds);
+	}
+
+	pub fn banking_state(&self) -> BankingState {
+		self.cartridge.banking_state()
+	}
+
+	pub fn set_banking_state(&mut self, state: BankingState) {
+		self.cartridge.set_banking_state(state);
+	}
+
+	pub fn is_dma_active(&self) -> bool {
+		self.dma_cycles_counter > 0
+	}
+
+	pub fn is_boot_rom_mapped(&self) -> bool {
+		self.boot_rom.is_some()
+	}
+
+	// bytes held outside of the struct itself
+	pub fn heap_size(&self) -> usize {
+		self.boot_rom.as_ref().map_or(0, |b| b.len())
+			+ self.cartridge.ram_size()
+			+ self.serial_output.capacity()
+	}
+
+	// 0x8000-0x9FFF as is, regardless of the PPU mode
+	pub fn vram(&self) -> &[u8] {
+		&self.memory[0x8000..0xA000]
+	}
+
+	// 0x8000-0xFFFF as stored, for a state file
+	pub fn memory_image(&self) -> &[u8] {
+		&self.memory[0x8000..]
+	}
+
+	pub fn load_memory_image(&mut self, image: &[u8]) {
+		self.memory[0x8000..].copy_from_slice(image);
+	}
+
 	pub fn read_byte(&self, address: u16) -> u8 {
+		if self.report.is_some() {
+			self.track_read(address);
+		}
+		self.note_oam_bug_access(address, OamAccess::Read);
 		match address {
+			// DMG boot rom covers 0x0000-0x00FF, CGB boot rom additionally covers 0x0200-0x08FF
+			0x0000..0x0100 | 0x0200..0x0900
+				if self
+					.boot_rom
+					.as_ref()
+					.is_some_and(|b| (address as usize) < b.len()) =>
+			{
+				self.boot_rom.as_ref().unwrap()[address as usize]
+			}
+			a if self.is_locked(a) => 0xFF,
+			0xA000..0xC000 if self.open_bus && !self.cartridge.ram_accessible() => self.bus.get(),
 			0x0000..0x8000 | 0xA000..0xC000 => self.cartridge.read_byte(address),
 			0xE000..0xFE00 => self.memory[address as usize - 0x2000],
-			0xFEA0..0xFF00 => 0x00, // reads not allowed on unusable region
+			// Unusable region, DMG reads 0 (0xFF while locked, see is_locked), CGB echoes the row: the
+			// high nibble of the low address byte, twice (0xFEAx reads 0xAA, 0xFEBx 0xBB & so on)
+			0xFEA0..0xFF00 => match self.model {
+				Model::DMG => 0x00,
+				Model::CGB => (address as u8 & 0xF0) | (address as u8 >> 4),
+			},
 			0xFF00 => self.joypad.read(self.memory[0xFF00]),
+			0xFF02 => self.memory[0xFF02] | 0x7E, // bits 1-6 are unused on DMG & always read as 1
 			0xFF04 => (self.div_counter >> 8) as u8,
+			0xFF0F => self.memory[0xFF0F] | 0xE0, // upper 3 bits are unused & always read as 1
+			0xFF41 => self.memory[0xFF41] | 0x80, // bit 7 is unused & always reads as 1
+			// CGB infrared port (RP) stub: bit 1 low means a signal is being received, which never
+			// happens here, so games looking for IR peripherals give up instead of waiting forever.
+			// Bits 2-5 are unused, LED (0) & read enable (6-7) are kept as written.
+			0xFF56 => (self.memory[0xFF56] & 0xC1) | 0x3E,
 			a => self.memory[a as usize],
 		}
 	}
 
+	// The PPU's own reads of VRAM, OAM & the palettes, straight from memory. Rules for what the CPU
+	// may access (report tracking, & any mode based locking) must never apply here or the PPU
+	// would be blinded to its own memory, so ppu.rs reads those ranges only through this.
+	pub fn ppu_read(&self, address: u16) -> u8 {
+		debug_assert!(
+			matches!(address, 0x8000..0xA000 | 0xFE00..0xFEA0 | 0xFF47..=0xFF49),
+			"ppu_read outside of VRAM, OAM & palettes: {:04X}",
+			address
+		);
+		self.memory[address as usize]
+	}
+
 	pub fn write_byte(&mut self, address: u16, value: u8) {
+		if self.report.is_some() {
+			self.track_write(address);
+		}
 		if address == 0xFF46 {
-			self.dma_cycles_counter = 0x0280;
+			self.dma_cycles_counter = Self::DMA_CYCLES;
 		}
+		self.note_oam_bug_access(address, OamAccess::Write);
 
 		match address {
+			a if self.is_locked(a) => {}
 			0x0000..0x8000 | 0xA000..0xC000 => self.cartridge.write_byte(address, value),
 			0xE000..0xFE00 => self.memory[address as usize - 0x2000] = value,
-			0xFEA0..0xFF00 => {} // writes not allowed on unusable region
+			0xFEA0..0xFF00 => {} // writes are dropped on both DMG & CGB
 			0xFF00 => {
-				self.memory[address as usize] = (self.memory[address as usize] & 0xCF) | (value & 0x30)
+				self.memory[address as usize] = (self.memory[address as usize] & 0xCF) | (value & 0x30);
+				self.joypad.write(value);
+			}
+			0xFF02 => {
+				// only transfers on the internal clock ever complete, no link cable is attached
+				self.memory[address as usize] = value;
+				self.serial_cycles_counter = match value & 0x81 {
+					0x81 => Self::SERIAL_CYCLES,
+					_ => 0,
+				};
 			}
 			0xFF04 => self.div_counter = 0,
+			// mode & coincidence (bits 0-2) are the PPU's, only the interrupt sources are writable
+			0xFF41 => {
+				self.memory[address as usize] = (self.memory[address as usize] & 0x07) | (value & 0x78)
+			}
+			0xFF07 => {
+				// disabling timer or switching to a bit that is currently low, can increment TIMA
+				self.memory[address as usize] = value;
+				self.detect_timer_falling_edge();
+			}
+			0xFF50 => {
+				// once unmapped, boot rom can't be mapped again
+				if value & 0x01 == 0x01 {
+					self.boot_rom = None;
+				}
+				self.memory[address as usize] |= value & 0x01;
+			}
 			_ => self.memory[address as usize] = value,
 		};
 	}
 
+	// CPU access to `address` blocked by the PPU using it, with lock_vram on
+	fn is_locked(&self, address: u16) -> bool {
+		self.lock_vram
+			&& is_bit_set(self.memory[0xFF40], 7)
+			&& matches!(
+				(address, self.memory[0xFF41] & 0x03),
+				(0x8000..0xA000, 3) | (0xFE00..0xFF00, 2 | 3)
+			)
+	}
+
+	// DMG only, the unusable region sits on the same bus as OAM
+	fn note_oam_bug_access(&self, address: u16, access: OamAccess) {
+		if self.oam_bug
+			&& self.model == Model::DMG
+			&& (0xFEA0..0xFF00).contains(&address)
+			&& is_bit_set(self.memory[0xFF40], 7)
+			&& self.memory[0xFF41] & 0x03 == 2
+		{
+			self.oam_bug_access.set(Some(access));
+		}
+	}
+
+	// for the PPU, which knows the OAM row it's scanning
+	pub fn take_oam_bug_access(&self) -> Option<OamAccess> {
+		self.oam_bug_access.take()
+	}
+
+	pub fn latch_bus(&self, value: u8) {
+		self.bus.set(value);
+	}
+
+	pub fn take_serial_output(&mut self) -> Vec<u8> {
+		std::mem::take(&mut self.serial_output)
+	}
+
 	pub fn press_key(&mut self, button: Button) {
 		if self.joypad.pressed(button) && (self.memory[0xFF00] >> 4) & 0x03 < 0x03 {
 			self.request_interrupt(4);
@@ -109,18 +445,48 @@ impl MMU {
 			}
 		}
 
+		if self.serial_cycles_counter > 0 {
+			self.serial_cycles_counter = self.serial_cycles_counter.saturating_sub(cycles);
+			if self.serial_cycles_counter == 0 {
+				// nothing on the other end, 1s got shifted in
+				self.serial_output.push(self.memory[0xFF01]);
+				self.memory[0xFF01] = 0xFF;
+				self.memory[0xFF02] &= 0x7F;
+				self.request_interrupt(3);
+			}
+		}
+
 		self.div_counter = self.div_counter.wrapping_add(cycles);
+		self.detect_timer_falling_edge();
+	}
 
-		let tac = self.read_byte(0xFF07);
-		let timer_enabled = is_bit_set(tac, 2);
+	// bit of div_counter feeding the timer, as selected by TAC
+	fn timer_bit(tac: u8) -> u8 {
 		// Explanation: https://github.com/Hacktix/GBEDG/blob/master/timers/index.md
-		let div_counter_bit = match tac & 0x03 {
+		match tac & 0x03 {
 			0x00 => 9, // 1024 cycle @ 4 MHz ~ 1 cycle @   4 KHz
 			0x01 => 3, //   16 cycle @ 4 MHz ~ 1 cycle @ 256 KHz
 			0x02 => 5, //   64 cycle @ 4 MHz ~ 1 cycle @  64 KHz
 			0x03 => 7, //  256 cycle @ 4 MHz ~ 1 cycle @  16 KHz
 			_ => unreachable!(),
-		};
+		}
+	}
+
+	pub fn timer_state(&self) -> TimerState {
+		TimerState {
+			div_counter: self.div_counter,
+			selected_bit: Self::timer_bit(self.memory[0xFF07]),
+			and_result: self.prev_and_result,
+			tima: self.memory[0xFF05],
+			tma: self.memory[0xFF06],
+			tac: self.read_byte(0xFF07),
+		}
+	}
+
+	fn detect_timer_falling_edge(&mut self) {
+		let tac = self.read_byte(0xFF07);
+		let timer_enabled = is_bit_set(tac, 2);
+		let div_counter_bit = Self::timer_bit(tac);
 
 		let curr_div_bit_value = (self.div_counter >> div_counter_bit) & 0x01 == 0x01;
 		let curr_and_result = curr_div_bit_value & timer_enabled;
@@ -137,3 +503,325 @@ impl MMU {
 		self.prev_and_result = curr_and_result;
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn mmu() -> MMU {
+		mmu_with(vec![0; 0x8000])
+	}
+
+	fn mmu_with(rom: Vec<u8>) -> MMU {
+		MMU::new(rom.into(), None, &EmulatorOptions::default())
+	}
+
+	// MBC1+RAM+BATTERY image of `banks` 16 KiB banks with 8 KiB of RAM, every byte of a bank set
+	// to its number
+	fn mbc1(banks: usize) -> Vec<u8> {
+		let mut rom = (0..banks)
+			.flat_map(|bank| [bank as u8; 0x4000])
+			.collect::<Vec<_>>();
+		rom[0x0147] = 0x03;
+		rom[0x0148] = (banks / 2).trailing_zeros() as u8;
+		rom[0x0149] = 0x02;
+		rom
+	}
+
+	// TIMA after writing `new_tac` with the timer running on `tac` & only DIV counter bit 3 set
+	fn tima_after_tac_write(tac: u8, new_tac: u8) -> u8 {
+		let mut mmu = mmu();
+		mmu.set_div_counter(0x0008);
+		mmu.write_byte(0xFF05, 0x10);
+		mmu.write_byte(0xFF07, tac);
+		mmu.write_byte(0xFF07, new_tac);
+		mmu.read_byte(0xFF05)
+	}
+
+	#[test]
+	fn tac_write_falling_edge_increments_tima() {
+		// disabling the timer while its bit is high
+		assert_eq!(tima_after_tac_write(0x05, 0x01), 0x11);
+		// switching from a high bit (3) to a low one (9)
+		assert_eq!(tima_after_tac_write(0x05, 0x04), 0x11);
+		// no falling edge: the new bit is high too, or the old one was low
+		assert_eq!(tima_after_tac_write(0x05, 0x05), 0x10);
+		assert_eq!(tima_after_tac_write(0x04, 0x05), 0x10);
+		assert_eq!(tima_after_tac_write(0x04, 0x00), 0x10);
+	}
+
+	#[test]
+	fn tima_overflow_reloads_tma_and_requests_interrupt() {
+		let mut mmu = mmu();
+		mmu.write_byte(0xFF0F, 0x00);
+		mmu.write_byte(0xFF05, 0xFF);
+		mmu.write_byte(0xFF06, 0x42);
+		mmu.set_div_counter(0x0000);
+		mmu.write_byte(0xFF07, 0x05);
+		// one increment every 16 cycles
+		mmu.update_timers(15);
+		assert_eq!(mmu.read_byte(0xFF05), 0xFF);
+		mmu.update_timers(1);
+		assert_eq!(mmu.read_byte(0xFF05), 0x42);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0x04);
+	}
+
+	#[test]
+	fn boot_rom_is_mapped_until_ff50_unmaps_it() {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0000] = 0xAA;
+		rom[0x0100] = 0xC3;
+		let mut mmu = MMU::new(
+			rom.into(),
+			Some(vec![0x31; 0x100]),
+			&EmulatorOptions::default(),
+		);
+		assert_eq!(mmu.read_byte(0x0000), 0x31);
+		assert_eq!(mmu.read_byte(0x00FF), 0x31);
+		// the cartridge entry point is never covered
+		assert_eq!(mmu.read_byte(0x0100), 0xC3);
+		// bit 0 clear doesn't unmap it
+		mmu.write_byte(0xFF50, 0x00);
+		assert_eq!(mmu.read_byte(0x0000), 0x31);
+		mmu.write_byte(0xFF50, 0x01);
+		assert_eq!(mmu.read_byte(0x0000), 0xAA);
+		// & once unmapped it stays unmapped
+		mmu.write_byte(0xFF50, 0x00);
+		assert_eq!(mmu.read_byte(0x0000), 0xAA);
+	}
+
+	#[test]
+	fn boot_rom_starts_from_power_on_registers() {
+		let boot = MMU::new(
+			vec![0; 0x8000].into(),
+			Some(vec![0; 0x100]),
+			&EmulatorOptions::default(),
+		);
+		assert_eq!(boot.read_byte(0xFF40), 0x00);
+		assert_eq!(boot.read_byte(0xFF04), 0x00);
+		assert_eq!(boot.read_byte(0xFF47), 0x00);
+		assert_eq!(boot.timer_state().div_counter, 0x0000);
+		let post_boot = mmu();
+		assert_eq!(post_boot.read_byte(0xFF40), 0x91);
+		assert_eq!(post_boot.read_byte(0xFF04), 0xAB);
+		assert_eq!(post_boot.read_byte(0xFF47), 0xFC);
+		assert_eq!(post_boot.timer_state().div_counter, 0xABCC);
+	}
+
+	#[test]
+	fn rom_and_ram_go_through_the_cartridge() {
+		let mut mmu = mmu_with(mbc1(4));
+		assert_eq!(mmu.read_byte(0x4000), 1);
+		mmu.write_byte(0x2000, 0x03);
+		assert_eq!(mmu.read_byte(0x4000), 3);
+		// rom isn't writable
+		mmu.write_byte(0x4000, 0x42);
+		assert_eq!(mmu.read_byte(0x4000), 3);
+		// RAM is the cartridge's, disabled it reads 0xFF
+		assert_eq!(mmu.read_byte(0xA000), 0xFF);
+		mmu.write_byte(0x0000, 0x0A);
+		mmu.write_byte(0xA123, 0x42);
+		assert_eq!(mmu.read_byte(0xA123), 0x42);
+		assert_eq!(mmu.cartridge.ram()[0x0123], 0x42);
+	}
+
+	#[test]
+	fn mbc1_128kib_bank_switching() {
+		let mut mmu = mmu_with(mbc1(8));
+		for bank in 1..8 {
+			mmu.write_byte(0x2000 + bank as u16, bank);
+			assert_eq!(mmu.read_byte(0x4000), bank);
+			assert_eq!(mmu.read_byte(0x7FFF), bank);
+		}
+		// bank 0 selects bank 1, bank numbers wrap around the rom
+		mmu.write_byte(0x3FFF, 0x00);
+		assert_eq!(mmu.read_byte(0x4000), 1);
+		mmu.write_byte(0x2000, 0x09);
+		assert_eq!(mmu.read_byte(0x4000), 1);
+		// 0x0000-0x3FFF stays bank 0
+		assert_eq!(mmu.read_byte(0x3FFF), 0);
+	}
+
+	#[test]
+	fn ram_enable_write_reaches_the_cartridge() {
+		let mut mmu = mmu_with(mbc1(2));
+		mmu.write_byte(0xA000, 0x42);
+		mmu.write_byte(0x0000, 0x0A);
+		assert_eq!(mmu.read_byte(0xA000), 0x00);
+		mmu.write_byte(0xA000, 0x42);
+		// kept while RAM is disabled
+		mmu.write_byte(0x1FFF, 0x00);
+		assert_eq!(mmu.read_byte(0xA000), 0xFF);
+		mmu.write_byte(0x1FFF, 0x0A);
+		assert_eq!(mmu.read_byte(0xA000), 0x42);
+	}
+
+	#[test]
+	fn stat_writes_keep_mode_coincidence_and_bit_7() {
+		let mut mmu = mmu();
+		mmu.write_register(0xFF41, 0x86);
+		mmu.write_byte(0xFF41, 0x00);
+		assert_eq!(mmu.read_byte(0xFF41), 0x86);
+		mmu.write_byte(0xFF41, 0xFF);
+		assert_eq!(mmu.read_byte(0xFF41), 0xFE);
+	}
+
+	#[test]
+	fn lock_vram_blocks_the_cpu_but_not_the_ppu() {
+		let options = EmulatorOptions {
+			lock_vram: true,
+			..EmulatorOptions::default()
+		};
+		let mut mmu = MMU::new(vec![0; 0x8000].into(), None, &options);
+		// LCDC, mode, then what the CPU & the PPU see of VRAM & OAM after the CPU writes 0x33 & 0x44
+		// over 0x11 & 0x22
+		for (lcdc, mode, vram, oam) in [
+			(0x91, 0, (0x33, 0x33), (0x44, 0x44)),
+			(0x91, 1, (0x33, 0x33), (0x44, 0x44)),
+			(0x91, 2, (0x33, 0x33), (0xFF, 0x22)),
+			(0x91, 3, (0xFF, 0x11), (0xFF, 0x22)),
+			(0x11, 3, (0x33, 0x33), (0x44, 0x44)),
+		] {
+			mmu.write_register(0x8000, 0x11);
+			mmu.write_register(0xFE00, 0x22);
+			mmu.write_register(0xFF40, lcdc);
+			mmu.write_register(0xFF41, 0x80 | mode);
+			mmu.write_byte(0x8000, 0x33);
+			mmu.write_byte(0xFE00, 0x44);
+			assert_eq!((mmu.read_byte(0x8000), mmu.ppu_read(0x8000)), vram);
+			assert_eq!((mmu.read_byte(0xFE00), mmu.ppu_read(0xFE00)), oam);
+		}
+	}
+
+	#[test]
+	fn unusable_region_by_model() {
+		for model in [Model::DMG, Model::CGB] {
+			let options = EmulatorOptions {
+				model,
+				lock_vram: true,
+				..EmulatorOptions::default()
+			};
+			let mut mmu = MMU::new(vec![0; 0x8000].into(), None, &options);
+			mmu.write_register(0xFF41, 0x80);
+			for address in 0xFEA0..0xFF00 {
+				mmu.write_byte(address, 0x12);
+				let row = (address as u8 >> 4) * 0x11;
+				let expected = match model {
+					Model::DMG => 0x00,
+					Model::CGB => row,
+				};
+				assert_eq!(
+					mmu.read_byte(address),
+					expected,
+					"{:?} {:04X}",
+					model,
+					address
+				);
+				assert_eq!(mmu.ppu_read(0xFE9F), 0x00);
+			}
+			// blocked along with OAM
+			mmu.write_register(0xFF41, 0x82);
+			assert_eq!(mmu.read_byte(0xFEA0), 0xFF);
+		}
+	}
+
+	#[test]
+	fn infrared_port_never_receives_a_signal() {
+		let mut mmu = mmu();
+		assert_eq!(mmu.read_byte(0xFF56) & 0x02, 0x02);
+		// read enabled & LED on, still nothing coming in
+		mmu.write_byte(0xFF56, 0xC1);
+		assert_eq!(mmu.read_byte(0xFF56), 0xFF);
+		mmu.write_byte(0xFF56, 0x00);
+		assert_eq!(mmu.read_byte(0xFF56), 0x3E);
+	}
+
+	#[test]
+	fn dma_completes_after_640_cycles() {
+		let mut mmu = mmu();
+		(0..0xA0).for_each(|i| mmu.write_byte(0xC000 + i, i as u8 + 1));
+		mmu.write_byte(0xFF46, 0xC0);
+		mmu.update_timers(MMU::DMA_CYCLES - 1);
+		assert!(mmu.is_dma_active());
+		mmu.update_timers(1);
+		assert!(!mmu.is_dma_active());
+		(0..0xA0).for_each(|i| assert_eq!(mmu.ppu_read(0xFE00 + i), i as u8 + 1));
+	}
+
+	#[test]
+	fn serial_transfer_completes_after_8_bits() {
+		let mut mmu = mmu();
+		mmu.write_byte(0xFF0F, 0x00);
+		mmu.write_byte(0xFF01, b'A');
+		mmu.write_byte(0xFF02, 0x81);
+		mmu.update_timers(MMU::SERIAL_CYCLES - 1);
+		assert_eq!(mmu.read_byte(0xFF02), 0xFF);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0x00);
+		assert!(mmu.take_serial_output().is_empty());
+		mmu.update_timers(1);
+		assert_eq!(mmu.read_byte(0xFF01), 0xFF);
+		assert_eq!(mmu.read_byte(0xFF02), 0x7F);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0x08);
+		assert_eq!(mmu.take_serial_output(), b"A");
+	}
+
+	#[test]
+	fn serial_transfer_on_external_clock_never_completes() {
+		let mut mmu = mmu();
+		mmu.write_byte(0xFF0F, 0x00);
+		mmu.write_byte(0xFF01, b'A');
+		mmu.write_byte(0xFF02, 0x80);
+		(0..10).for_each(|_| mmu.update_timers(MMU::SERIAL_CYCLES));
+		assert_eq!(mmu.read_byte(0xFF01), b'A');
+		assert_eq!(mmu.read_byte(0xFF02), 0xFE);
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x08, 0x00);
+	}
+
+	#[test]
+	fn timer_state_follows_a_scripted_sequence() {
+		let mut mmu = mmu();
+		// a M-cycle at a time, as the CPU does
+		let run = |mmu: &mut MMU, cycles: u16| (0..cycles / 4).for_each(|_| mmu.update_timers(4));
+		let state = |div_counter, selected_bit, and_result, tima, tac| TimerState {
+			div_counter,
+			selected_bit,
+			and_result,
+			tima,
+			tma: 0x80,
+			tac,
+		};
+
+		mmu.write_byte(0xFF07, 0x00);
+		mmu.write_byte(0xFF04, 0x12);
+		mmu.write_byte(0xFF06, 0x80);
+		mmu.write_byte(0xFF05, 0xFE);
+		mmu.write_byte(0xFF0F, 0x00);
+		// every 16 cycles, on bit 3 falling
+		mmu.write_byte(0xFF07, 0x05);
+		assert_eq!(mmu.timer_state(), state(0x0000, 3, false, 0xFE, 0x05));
+		run(&mut mmu, 8);
+		assert_eq!(mmu.timer_state(), state(0x0008, 3, true, 0xFE, 0x05));
+		run(&mut mmu, 8);
+		assert_eq!(mmu.timer_state(), state(0x0010, 3, false, 0xFF, 0x05));
+		// overflow, reloaded from TMA & the interrupt requested
+		run(&mut mmu, 16);
+		assert_eq!(mmu.timer_state(), state(0x0020, 3, false, 0x80, 0x05));
+		assert_eq!(mmu.read_byte(0xFF0F) & 0x04, 0x04);
+
+		// bit 5 is high: no edge
+		mmu.write_byte(0xFF07, 0x06);
+		assert_eq!(mmu.timer_state(), state(0x0020, 5, true, 0x80, 0x06));
+		// disabling the timer while it's high is a falling edge
+		mmu.write_byte(0xFF07, 0x02);
+		assert_eq!(mmu.timer_state(), state(0x0020, 5, false, 0x81, 0x02));
+		// stopped, DIV keeps counting
+		run(&mut mmu, 0x100);
+		let timer_state = mmu.timer_state();
+		assert_eq!(timer_state, state(0x0120, 5, false, 0x81, 0x02));
+		assert_eq!(mmu.read_byte(0xFF04), 0x01);
+		assert_eq!(
+			timer_state.to_string(),
+			"DIV:01 (counter 0120, bit 5) AND:0 TIMA:81 TMA:80 TAC:02"
+		);
+	}
+}
@@ -0,0 +1,1024 @@
+use crate::{
+	filters::Filter,
+	joypad::Button,
+	ppu::{Layers, PALETTES, Palette},
+};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+// Point at which the buttons sampled by the frontend are applied to the joypad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputLatch {
+	// as soon as they are sampled, right after a frame is presented (VBLANK of LY 144)
+	VBlank,
+	// at the start of the next frame (OAMSCAN of LY 0), exactly once per frame so the same
+	// inputs always land on the same cycle regardless of host timing
+	FrameStart,
+}
+
+// Power-on content of WRAM, VRAM & OAM. Real hardware starts with garbage, so a game relying on
+// zeroed RAM works here but breaks on a console.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RamInit {
+	Zeros,
+	Ones,
+	// 0x00 & 0xFF on alternate bytes
+	Alternating,
+	// pseudo random bytes from a fixed seed, so runs stay reproducible
+	Random,
+}
+
+// Console being emulated. Only the unusable region (0xFEA0-0xFEFF) tells them apart so far,
+// everything else runs as on a DMG.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+	DMG,
+	// revision E onwards
+	CGB,
+}
+
+// Lines of the CPU trace (see DEBUG_FLAG)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+	// registers & the bytes at PC only, what reference logs (gameboy-doctor) compare against
+	Plain,
+	// prefixed with the cycle count since power on, LY & the PPU mode as of the instruction start
+	Timestamped,
+}
+
+#[derive(Clone)]
+pub struct EmulatorOptions {
+	// rom to run, ./rom.gb when not given
+	pub rom: Option<PathBuf>,
+	// print usage & exit
+	pub help: bool,
+	// color of the bars around the screen when window isn't 10:9 (0x00RRGGBB), black by default
+	pub border_color: u32,
+	// palette for games without one remembered for them
+	pub palette: Palette,
+	// layers drawn, 1, 2 & 3 toggle background, window & objects while running
+	pub layers: Layers,
+	// post-processing filters applied in order before presenting a frame
+	pub filters: Vec<Filter>,
+	// window scale, frames get upscaled by this integer factor
+	pub scale: usize,
+	// present at exactly 10:9 with the largest integer scale fitting the window, instead of
+	// stretching frames to it
+	pub exact_aspect: bool,
+	// darken the edges of every upscaled pixel by given % to mimic the LCD grid, at scales of 2 &
+	// more
+	pub lcd_grid: Option<u8>,
+	// round the corners of the screen with given radius in pixels, as on a CRT
+	pub crt_rounding: Option<u8>,
+	// frame buffer only ever exposes complete frames, drawn in a back buffer swapped at VBlank
+	pub double_buffer: bool,
+	// present frames from the main thread while emulation runs on its own thread
+	pub threaded_present: bool,
+	// don't upload frames identical to the previous one, some compositors dislike stale swaps
+	pub skip_unchanged_frames: bool,
+	// when sampled buttons reach the joypad, see InputLatch
+	pub input_latch: InputLatch,
+	// Buttons held from power on & every reset until `hold_frames` frames got completed, on top of
+	// what's pressed. Reaches what games check while booting: debug menus & sound tests (often
+	// Select+B or A+B+Start), region or language switches, or a save getting cleared.
+	pub hold: Vec<Button>,
+	pub hold_frames: u64,
+	// IPS or BPS patch applied to the rom when it gets loaded
+	pub patch: Option<PathBuf>,
+	// pick the rom from the .gb/.gbc files in this directory instead of running `rom`, the list
+	// can be brought back to switch games
+	pub rom_dir: Option<PathBuf>,
+	// reset with the new rom whenever the rom file changes (polled once per frame), battery
+	// backed cartridge RAM carries over through the .sav
+	pub watch: bool,
+	// start the reloaded rom with blank cartridge RAM instead of carrying the save over
+	pub watch_reset_ram: bool,
+	// memory the rewind history (a snapshot per frame) may take, the oldest frames go beyond it
+	pub rewind_memory: usize,
+	// frames presented ahead of emulation (0 or 1) to hide that much of a game's input lag
+	pub runahead: u8,
+	// run the boot rom (if any) at max speed without presenting, straight to the game
+	pub skip_logo: bool,
+	pub ram_init: RamInit,
+	// power-up phase (DIV counter, PPU line & cycle) derived from this seed instead of the fixed
+	// one, to reproduce timing that differs between units (see GameBoy::power_up_phase)
+	pub initial_phase: Option<u64>,
+	// panic on an unsupported cartridge type instead of running it as ROM ONLY
+	pub strict_mapper: bool,
+	// LY reads 0 (& matches LYC = 0) from a few cycles into line 153 as on hardware, instead of
+	// only from line 0 on
+	pub early_ly_reset: bool,
+	// cartridge RAM reads without enabled RAM return the last value on the bus instead of 0xFF
+	pub open_bus: bool,
+	// CPU reads of VRAM in mode 3 & of OAM in modes 2 & 3 return 0xFF & writes are dropped
+	pub lock_vram: bool,
+	// DMG reads & writes of the unusable region during OAM scan corrupt the OAM row being scanned
+	pub oam_bug: bool,
+	pub model: Model,
+	// objects vanish from lines scanned while OAM DMA runs, as the PPU can't read OAM meanwhile
+	pub dma_blocks_oam: bool,
+	// answer SGB MLT_REQ packets (2/4 joypads) instead of behaving like a plain DMG
+	pub sgb_stub: bool,
+	// Rhai script driving the emulator (needs the scripting feature), see script.rs
+	pub script: Option<PathBuf>,
+	// run the script without a window until it calls exit()
+	pub headless: bool,
+	// most verbose diagnostics printed to stderr, when not given RUST_LOG (e.g. `debug`) is
+	// consulted & info is the default
+	pub log_level: Option<LevelFilter>,
+	// report a stall when no frame completes within this many frames worth of cycles, 0 disables
+	pub stall_frames: u64,
+	// log every interrupt dispatch (vector, interrupted PC, IE & IF)
+	pub log_interrupts: bool,
+	pub trace_format: TraceFormat,
+	// log the pixel pipeline cycle by cycle through RENDER of this LY (every frame)
+	pub trace_fifo: Option<u8>,
+	// track suspicious events (see compatreport) & write them here as JSON on exit & with F9
+	pub compat_report: Option<PathBuf>,
+	// verify internal invariants after every frame, not free so off by default
+	pub self_check: bool,
+	// run the built-in test rom headless & exit
+	pub selftest: bool,
+	// run every available test suite & print a scoreboard (markdown, or JSON with --json)
+	pub accuracy: bool,
+	// test roms for --accuracy, in blargg/ & mooneye/ subdirectories
+	pub test_roms: Option<PathBuf>,
+	// run the rom headless for given frames & print the distribution of cycles per frame
+	pub frame_timing: Option<usize>,
+	// directory tile data & tile maps get exported to on pressing G
+	pub export_gfx: Option<PathBuf>,
+	// exported images get upscaled by this factor
+	pub export_scale: usize,
+	// instead of waiting for G, run the rom headless for given frames, export & exit
+	pub export_frames: Option<usize>,
+	// instead of running the rom, export from this save state file (see StateFile) & exit
+	pub from_state: Option<PathBuf>,
+	// symbol file (`BB:AAAA label` per line) used to annotate disassembly & trace
+	pub symbols: Option<PathBuf>,
+	// print the decoded header of the given rom & exit
+	pub info: Option<PathBuf>,
+	// --info & --accuracy output as JSON
+	pub json: bool,
+	// print the battery save (.sav next to it) of the given rom decoded as JSON & exit, for games
+	// with a codec (see saves)
+	pub inspect_save: Option<PathBuf>,
+	// with --inspect-save, write the save back with its checksums recomputed
+	pub fix_checksum: bool,
+	// print disassembly of the given rom & exit
+	pub disasm: Option<PathBuf>,
+	// rom bank mapped at 0x4000-0x7FFF for disassembly
+	pub disasm_bank: usize,
+	// address range [start, end) to disassemble
+	pub disasm_range: (u16, u16),
+}
+
+impl Default for EmulatorOptions {
+	fn default() -> Self {
+		EmulatorOptions {
+			rom: None,
+			help: false,
+			border_color: 0x00000000,
+			palette: Palette::default(),
+			layers: Layers::default(),
+			filters: Vec::new(),
+			scale: 4,
+			exact_aspect: false,
+			lcd_grid: None,
+			crt_rounding: None,
+			double_buffer: false,
+			threaded_present: false,
+			skip_unchanged_frames: false,
+			input_latch: InputLatch::VBlank,
+			hold: Vec::new(),
+			hold_frames: 60,
+			patch: None,
+			rom_dir: None,
+			watch: false,
+			watch_reset_ram: false,
+			rewind_memory: 64 << 20,
+			runahead: 0,
+			skip_logo: false,
+			ram_init: RamInit::Zeros,
+			initial_phase: None,
+			strict_mapper: false,
+			early_ly_reset: false,
+			open_bus: false,
+			lock_vram: false,
+			oam_bug: false,
+			model: Model::DMG,
+			dma_blocks_oam: false,
+			sgb_stub: false,
+			script: None,
+			headless: false,
+			log_level: None,
+			stall_frames: 10,
+			log_interrupts: false,
+			trace_format: TraceFormat::Plain,
+			trace_fifo: None,
+			compat_report: None,
+			self_check: false,
+			selftest: false,
+			accuracy: false,
+			test_roms: None,
+			frame_timing: None,
+			export_gfx: None,
+			export_scale: 1,
+			export_frames: None,
+			from_state: None,
+			symbols: None,
+			info: None,
+			json: false,
+			inspect_save: None,
+			fix_checksum: false,
+			disasm: None,
+			disasm_bank: 1,
+			disasm_range: (0x0000, 0x8000),
+		}
+	}
+}
+
+// bytes, optionally suffixed with KB, MB or GB (powers of 1024), e.g. 256MB
+fn parse_size(value: &str) -> Option<usize> {
+	let upper = value.trim().to_ascii_uppercase();
+	let (number, shift) = [("KB", 10), ("MB", 20), ("GB", 30), ("B", 0)]
+		.iter()
+		.find_map(|&(suffix, shift)| Some((upper.strip_suffix(suffix)?, shift)))
+		.unwrap_or((&upper, 0));
+	number.trim().parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+	let value = value.trim_start_matches("0x").trim_start_matches('$');
+	u32::from_str_radix(value, 16).ok()
+}
+
+// printed by --help
+pub const USAGE: &str = "\
+usage: rustboy [options] [rom]
+
+Runs rom (./rom.gb when not given), dmg_boot.bin in the current directory is used as boot rom.
+
+display:
+  --palette <name>              calc, dmg-nso, 2b-gray or hollow (C cycles while running)
+  --border-color <RRGGBB>       color of the bars around the screen
+  --filter <name>               scanlines or grayscale, repeatable
+  --scale <1-8>                 window scale, 4 by default
+  --exact-aspect                integer scales only, letterboxed to fit the window
+  --lcd-grid <percent>          darken pixel edges to mimic the LCD grid
+  --crt-rounding <radius>       round the screen's corners, radius in pixels
+  --hide <layers>               leave out e.g. window,objects (1, 2 & 3 toggle them)
+  --double-buffer               expose only complete frames
+  --threaded-present            emulate on a thread of its own
+  --skip-unchanged-frames       don't present frames identical to the previous one
+  --runahead <0|1>              present frames ahead to hide input lag
+
+running:
+  --input-latch <vblank|frame-start>
+  --hold <buttons>              hold e.g. select,b from boot (debug menus & such)
+  --hold-frames <frames>        frames --hold lasts, 60 by default
+  --patch <file>                IPS or BPS patch applied to the rom
+  --dir <directory>             pick the rom from a list (O brings it back)
+  --watch                       reset whenever the rom file changes
+  --watch-reset-ram             don't carry the save over to the reloaded rom
+  --rewind-memory <size>        memory for rewinding, e.g. 64MB
+  --skip-logo                   run the boot rom without presenting it
+  --ram-init <zeros|ones|alternating|random>
+  --initial-phase <seed>        power up with DIV & PPU phase derived from seed
+  --script <file>               Rhai script driving the emulator
+  --headless                    run the script without a window
+
+accuracy:
+  --strict-mapper               panic on unsupported cartridge types
+  --early-ly-reset              LY reads 0 early in line 153
+  --open-bus                    disabled cartridge RAM reads the bus
+  --lock-vram                   no CPU access to VRAM/OAM while the PPU uses them
+  --oam-bug                     unusable region accesses in OAM scan corrupt OAM
+  --model <dmg|cgb>             unusable region behaves as on this console
+  --dma-blocks-oam              objects vanish while OAM DMA runs
+  --sgb-stub                    answer SGB multiplayer requests
+
+diagnostics:
+  --log-level <level>           error, warn, info, debug or trace
+  --stall-frames <frames>       frames without progress before reporting a stall
+  --log-interrupts              log every interrupt dispatch
+  --trace-format <plain|timestamped>
+  --trace-fifo <ly>             log the pixel FIFOs through mode 3 of line ly
+  --compat-report <file>        write suspicious events as JSON on exit & with F9
+  --self-check                  verify invariants after every frame
+  --sym <file>                  symbols for disassembly & trace
+
+tools (exit when done):
+  --selftest                    run the built-in test rom
+  --accuracy                    print the test scoreboard
+  --test-roms <directory>       --accuracy with blargg/ & mooneye/ in it
+  --frame-timing <frames>       print cycles per frame
+  --export-gfx <directory>      export tiles & maps (G while running)
+  --export-scale <factor>
+  --export-frames <frames>      export after running headless
+  --from-state <file>           export from a save state instead
+  --info <rom>                  print the rom header
+  --json                        --info & --accuracy as JSON
+  --inspect-save <rom>          print the rom's save decoded as JSON
+  --fix-checksum                --inspect-save fixing the save's checksums
+  --disasm <rom>                disassemble, with --bank <n> & --range <start:end>
+  --help                        print this
+";
+
+impl EmulatorOptions {
+	pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+		Self::parse_onto(EmulatorOptions::default(), args)
+	}
+
+	// `args` override whatever `options` has, e.g. values remembered from the last run
+	pub fn parse_onto(
+		mut options: Self,
+		mut args: impl Iterator<Item = String>,
+	) -> Result<Self, String> {
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--palette" => {
+					let value = args.next().ok_or("--palette expects a name")?;
+					options.palette = Palette::find(&value).ok_or(format!(
+						"unknown palette: {} (one of {})",
+						value,
+						PALETTES.map(|palette| palette.name).join(", ")
+					))?;
+				}
+				"--hide" => {
+					let value = args.next().ok_or("--hide expects layers")?;
+					options.layers = Layers::hiding(&value).ok_or(format!(
+						"unknown layer in {} (background, window or objects)",
+						value
+					))?;
+				}
+				"--border-color" => {
+					let value = args.next().ok_or("--border-color expects a value")?;
+					options.border_color = parse_hex(value.trim_start_matches('#'))
+						.filter(|&c| c <= 0x00FFFFFF)
+						.ok_or(format!("invalid border color: {}", value))?;
+				}
+				"--filter" => {
+					let value = args.next().ok_or("--filter expects a value")?;
+					options
+						.filters
+						.push(Filter::from_name(&value).ok_or(format!("unknown filter: {}", value))?);
+				}
+				"--scale" => {
+					let value = args.next().ok_or("--scale expects a factor")?;
+					options.scale = value
+						.parse()
+						.ok()
+						.filter(|s| (1..=8).contains(s))
+						.ok_or(format!("invalid scale (1-8): {}", value))?;
+				}
+				"--exact-aspect" => options.exact_aspect = true,
+				"--crt-rounding" => {
+					let value = args.next().ok_or("--crt-rounding expects a radius")?;
+					options.crt_rounding = Some(
+						value
+							.parse()
+							.ok()
+							.filter(|r| (1..=72).contains(r))
+							.ok_or(format!("invalid crt rounding radius (1-72): {}", value))?,
+					);
+				}
+				"--lcd-grid" => {
+					let value = args.next().ok_or("--lcd-grid expects an intensity")?;
+					options.lcd_grid = Some(
+						value
+							.parse()
+							.ok()
+							.filter(|&i| i <= 100)
+							.ok_or(format!("invalid lcd grid intensity (0-100): {}", value))?,
+					);
+				}
+				"--double-buffer" => options.double_buffer = true,
+				"--threaded-present" => options.threaded_present = true,
+				"--skip-unchanged-frames" => options.skip_unchanged_frames = true,
+				"--input-latch" => {
+					options.input_latch = match args.next().as_deref() {
+						Some("vblank") => InputLatch::VBlank,
+						Some("frame-start") => InputLatch::FrameStart,
+						_ => return Err("--input-latch expects vblank or frame-start".to_string()),
+					};
+				}
+				"--hold" => {
+					let value = args.next().ok_or("--hold expects buttons, e.g. select,b")?;
+					options.hold = value
+						.split(',')
+						.map(|name| {
+							Button::find(name.trim()).ok_or(format!(
+								"unknown button: {} (a, b, select, start, right, left, up or down)",
+								name
+							))
+						})
+						.collect::<Result<_, _>>()?;
+				}
+				"--hold-frames" => {
+					let value = args.next().ok_or("--hold-frames expects a frame count")?;
+					options.hold_frames = value
+						.parse()
+						.map_err(|_| format!("invalid frame count: {}", value))?;
+				}
+				"--trace-format" => {
+					options.trace_format = match args.next().as_deref() {
+						Some("plain") => TraceFormat::Plain,
+						Some("timestamped") => TraceFormat::Timestamped,
+						_ => return Err("--trace-format expects plain or timestamped".to_string()),
+					};
+				}
+				"--patch" => {
+					options.patch = Some(args.next().ok_or("--patch expects a path")?.into());
+				}
+				"--dir" => {
+					options.rom_dir = Some(args.next().ok_or("--dir expects a directory")?.into());
+				}
+				"--watch" => options.watch = true,
+				"--watch-reset-ram" => options.watch_reset_ram = true,
+				"--rewind-memory" => {
+					let value = args.next().ok_or("--rewind-memory expects a size")?;
+					options.rewind_memory = parse_size(&value).ok_or(format!("invalid size: {}", value))?;
+				}
+				"--runahead" => {
+					options.runahead = match args.next().as_deref() {
+						Some("0") => 0,
+						Some("1") => 1,
+						_ => return Err("--runahead expects 0 or 1 (frames)".to_string()),
+					};
+				}
+				"--skip-logo" => options.skip_logo = true,
+				"--ram-init" => {
+					options.ram_init = match args.next().as_deref() {
+						Some("zeros") => RamInit::Zeros,
+						Some("ones") => RamInit::Ones,
+						Some("alternating") => RamInit::Alternating,
+						Some("random") => RamInit::Random,
+						_ => {
+							return Err("--ram-init expects zeros, ones, alternating or random".to_string());
+						}
+					};
+				}
+				"--initial-phase" => {
+					let value = args.next().ok_or("--initial-phase expects a seed")?;
+					options.initial_phase = Some(
+						value
+							.parse()
+							.map_err(|_| format!("invalid seed: {}", value))?,
+					);
+				}
+				"--strict-mapper" => options.strict_mapper = true,
+				"--early-ly-reset" => options.early_ly_reset = true,
+				"--open-bus" => options.open_bus = true,
+				"--lock-vram" => options.lock_vram = true,
+				"--oam-bug" => options.oam_bug = true,
+				"--model" => {
+					options.model = match args.next().as_deref() {
+						Some("dmg") => Model::DMG,
+						Some("cgb") => Model::CGB,
+						_ => return Err("--model expects dmg or cgb".to_string()),
+					};
+				}
+				"--dma-blocks-oam" => options.dma_blocks_oam = true,
+				"--sgb-stub" => options.sgb_stub = true,
+				"--script" => {
+					options.script = Some(args.next().ok_or("--script expects a path")?.into());
+				}
+				"--headless" => options.headless = true,
+				"--compat-report" => {
+					let path = args.next().ok_or("--compat-report expects a path")?;
+					options.compat_report = Some(path.into());
+				}
+				"--log-level" => {
+					let value = args.next().ok_or("--log-level expects a value")?;
+					options.log_level = Some(
+						value
+							.parse()
+							.map_err(|_| format!("invalid log level: {}", value))?,
+					);
+				}
+				"--stall-frames" => {
+					let value = args.next().ok_or("--stall-frames expects a frame count")?;
+					options.stall_frames = value
+						.parse()
+						.map_err(|_| format!("invalid frame count: {}", value))?;
+				}
+				"--trace-fifo" => {
+					let value = args.next().ok_or("--trace-fifo expects a line (0-143)")?;
+					options.trace_fifo = Some(
+						value
+							.parse()
+							.ok()
+							.filter(|&ly| ly < 144)
+							.ok_or_else(|| format!("invalid line: {}", value))?,
+					);
+				}
+				"--log-interrupts" => options.log_interrupts = true,
+				"--self-check" => options.self_check = true,
+				"--selftest" => options.selftest = true,
+				"--accuracy" => options.accuracy = true,
+				"--test-roms" => {
+					options.test_roms = Some(args.next().ok_or("--test-roms expects a directory")?.into());
+				}
+				"--frame-timing" => {
+					let value = args.next().ok_or("--frame-timing expects a frame count")?;
+					options.frame_timing = Some(
+						value
+							.parse()
+							.map_err(|_| format!("invalid frame count: {}", value))?,
+					);
+				}
+				"--export-gfx" => {
+					options.export_gfx = Some(
+						args
+							.next()
+							.ok_or("--export-gfx expects a directory")?
+							.into(),
+					);
+				}
+				"--export-scale" => {
+					let value = args.next().ok_or("--export-scale expects a factor")?;
+					options.export_scale = value
+						.parse()
+						.ok()
+						.filter(|&s| s > 0)
+						.ok_or(format!("invalid scale: {}", value))?;
+				}
+				"--export-frames" => {
+					let value = args.next().ok_or("--export-frames expects a frame count")?;
+					options.export_frames = Some(
+						value
+							.parse()
+							.map_err(|_| format!("invalid frame count: {}", value))?,
+					);
+				}
+				"--from-state" => {
+					options.from_state = Some(
+						args
+							.next()
+							.ok_or("--from-state expects a save state file")?
+							.into(),
+					);
+				}
+				"--sym" => {
+					options.symbols = Some(args.next().ok_or("--sym expects a path")?.into());
+				}
+				"--info" => {
+					options.info = Some(args.next().ok_or("--info expects a rom path")?.into());
+				}
+				"--json" => options.json = true,
+				"--inspect-save" => {
+					options.inspect_save = Some(
+						args
+							.next()
+							.ok_or("--inspect-save expects a rom path")?
+							.into(),
+					);
+				}
+				"--fix-checksum" => options.fix_checksum = true,
+				"--disasm" => {
+					options.disasm = Some(args.next().ok_or("--disasm expects a rom path")?.into());
+				}
+				"--bank" => {
+					let value = args.next().ok_or("--bank expects a value")?;
+					options.disasm_bank = value
+						.parse()
+						.map_err(|_| format!("invalid bank: {}", value))?;
+				}
+				"--range" => {
+					let value = args.next().ok_or("--range expects start:end")?;
+					options.disasm_range = value
+						.split_once(':')
+						.and_then(|(s, e)| Some((parse_hex(s)?, parse_hex(e)?)))
+						.filter(|&(s, e)| s < e && e <= 0x8000)
+						.map(|(s, e)| (s as u16, e as u16))
+						.ok_or(format!("invalid range: {}", value))?;
+				}
+				"--help" | "-h" => options.help = true,
+				a if a.starts_with('-') => return Err(format!("unknown argument: {} (see --help)", a)),
+				a => match options.rom {
+					None => options.rom = Some(a.into()),
+					Some(_) => {
+						return Err(format!(
+							"unexpected argument: {}, only one rom can be run",
+							a
+						));
+					}
+				},
+			}
+		}
+
+		options.check_combinations()?;
+		Ok(options)
+	}
+
+	// options only meaningful along with another one, & the ones that exclude each other
+	fn check_combinations(&self) -> Result<(), String> {
+		let needs = [
+			(
+				self.watch_reset_ram,
+				"--watch-reset-ram",
+				self.watch,
+				"--watch",
+			),
+			(
+				self.headless,
+				"--headless",
+				self.script.is_some(),
+				"--script",
+			),
+			(
+				self.test_roms.is_some(),
+				"--test-roms",
+				self.accuracy,
+				"--accuracy",
+			),
+			(
+				self.export_frames.is_some(),
+				"--export-frames",
+				self.export_gfx.is_some(),
+				"--export-gfx",
+			),
+			(
+				self.from_state.is_some(),
+				"--from-state",
+				self.export_gfx.is_some(),
+				"--export-gfx",
+			),
+			(
+				self.fix_checksum,
+				"--fix-checksum",
+				self.inspect_save.is_some(),
+				"--inspect-save",
+			),
+		];
+		if let Some((_, option, _, needed)) = needs.iter().find(|(given, _, met, _)| *given && !met) {
+			return Err(format!("{} needs {}", option, needed));
+		}
+		let excluding = [
+			(self.rom_dir.is_some(), "--dir", self.rom.is_some(), "a rom"),
+			(
+				self.from_state.is_some(),
+				"--from-state",
+				self.export_frames.is_some(),
+				"--export-frames",
+			),
+		];
+		match excluding.iter().find(|(a, _, b, _)| *a && *b) {
+			Some((_, a, _, b)) => Err(format!("{} & {} can't be combined", a, b)),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(args: &[&str]) -> Result<EmulatorOptions, String> {
+		EmulatorOptions::parse(args.iter().map(|arg| arg.to_string()))
+	}
+
+	// whether an option got set as expected
+	type Check = fn(&EmulatorOptions) -> bool;
+
+	// every option taking a value, with a valid value & what it must have set
+	fn with_values() -> Vec<(&'static str, &'static str, Check)> {
+		vec![
+			("--palette", "hollow", |o| {
+				o.palette == Palette::find("hollow").unwrap()
+			}),
+			("--border-color", "#FF8000", |o| o.border_color == 0xFF8000),
+			("--filter", "scanlines", |o| {
+				matches!(o.filters[..], [Filter::Scanlines])
+			}),
+			("--scale", "2", |o| o.scale == 2),
+			("--lcd-grid", "30", |o| o.lcd_grid == Some(30)),
+			("--crt-rounding", "8", |o| o.crt_rounding == Some(8)),
+			("--hide", "window,objects", |o| {
+				o.layers == Layers::hiding("objects,window").unwrap()
+			}),
+			("--runahead", "1", |o| o.runahead == 1),
+			("--input-latch", "frame-start", |o| {
+				o.input_latch == InputLatch::FrameStart
+			}),
+			("--hold", "select,b", |o| {
+				o.hold == [Button::SELECT, Button::B]
+			}),
+			("--hold-frames", "120", |o| o.hold_frames == 120),
+			("--patch", "fix.ips", |o| o.patch == Some("fix.ips".into())),
+			("--dir", "roms", |o| o.rom_dir == Some("roms".into())),
+			("--rewind-memory", "256MB", |o| o.rewind_memory == 256 << 20),
+			("--ram-init", "random", |o| o.ram_init == RamInit::Random),
+			("--initial-phase", "42", |o| o.initial_phase == Some(42)),
+			("--script", "run.rhai", |o| {
+				o.script == Some("run.rhai".into())
+			}),
+			("--model", "cgb", |o| o.model == Model::CGB),
+			("--log-level", "debug", |o| {
+				o.log_level == Some(LevelFilter::Debug)
+			}),
+			("--stall-frames", "0", |o| o.stall_frames == 0),
+			("--trace-format", "timestamped", |o| {
+				o.trace_format == TraceFormat::Timestamped
+			}),
+			("--trace-fifo", "143", |o| o.trace_fifo == Some(143)),
+			("--compat-report", "report.json", |o| {
+				o.compat_report == Some("report.json".into())
+			}),
+			("--sym", "game.sym", |o| {
+				o.symbols == Some("game.sym".into())
+			}),
+			("--frame-timing", "60", |o| o.frame_timing == Some(60)),
+			("--export-gfx", "out", |o| {
+				o.export_gfx == Some("out".into())
+			}),
+			("--export-scale", "3", |o| o.export_scale == 3),
+			("--info", "game.gb", |o| o.info == Some("game.gb".into())),
+			("--inspect-save", "game.gb", |o| {
+				o.inspect_save == Some("game.gb".into())
+			}),
+			("--disasm", "game.gb", |o| {
+				o.disasm == Some("game.gb".into())
+			}),
+			("--bank", "5", |o| o.disasm_bank == 5),
+			("--range", "0x150:$200", |o| {
+				o.disasm_range == (0x0150, 0x0200)
+			}),
+		]
+	}
+
+	// options only valid along with another one, with that one & what they must have set
+	fn with_others() -> Vec<(&'static [&'static str], Check)> {
+		vec![
+			(&["--watch-reset-ram", "--watch"], |o| {
+				o.watch_reset_ram && o.watch
+			}),
+			(&["--headless", "--script", "a.rhai"], |o| o.headless),
+			(&["--test-roms", "roms", "--accuracy"], |o| {
+				o.test_roms == Some("roms".into()) && o.accuracy
+			}),
+			(&["--export-frames", "10", "--export-gfx", "out"], |o| {
+				o.export_frames == Some(10)
+			}),
+			(&["--from-state", "a.ss1", "--export-gfx", "out"], |o| {
+				o.from_state == Some("a.ss1".into())
+			}),
+			(&["--fix-checksum", "--inspect-save", "a.gb"], |o| {
+				o.fix_checksum
+			}),
+		]
+	}
+
+	// switches & what they must have set
+	fn switches() -> Vec<(&'static str, Check)> {
+		vec![
+			("--exact-aspect", |o| o.exact_aspect),
+			("--double-buffer", |o| o.double_buffer),
+			("--threaded-present", |o| o.threaded_present),
+			("--skip-unchanged-frames", |o| o.skip_unchanged_frames),
+			("--watch", |o| o.watch),
+			("--skip-logo", |o| o.skip_logo),
+			("--strict-mapper", |o| o.strict_mapper),
+			("--early-ly-reset", |o| o.early_ly_reset),
+			("--open-bus", |o| o.open_bus),
+			("--lock-vram", |o| o.lock_vram),
+			("--oam-bug", |o| o.oam_bug),
+			("--dma-blocks-oam", |o| o.dma_blocks_oam),
+			("--sgb-stub", |o| o.sgb_stub),
+			("--log-interrupts", |o| o.log_interrupts),
+			("--self-check", |o| o.self_check),
+			("--selftest", |o| o.selftest),
+			("--accuracy", |o| o.accuracy),
+			("--json", |o| o.json),
+			("--help", |o| o.help),
+			("-h", |o| o.help),
+		]
+	}
+
+	#[test]
+	fn every_option_in_the_usage_is_covered() {
+		let mut covered = with_values()
+			.iter()
+			.map(|(option, ..)| *option)
+			.collect::<Vec<_>>();
+		covered.extend(switches().iter().map(|(option, _)| *option));
+		covered.extend(with_others().iter().map(|(args, _)| args[0]));
+		USAGE
+			.split_whitespace()
+			.filter(|word| word.starts_with("--"))
+			.filter(|word| {
+				word
+					.chars()
+					.skip(2)
+					.all(|c| c.is_ascii_lowercase() || c == '-')
+			})
+			.for_each(|option| assert!(covered.contains(&option), "{} not covered", option));
+	}
+
+	#[test]
+	fn defaults_without_arguments() {
+		let options = parse(&[]).unwrap();
+		let defaults = EmulatorOptions::default();
+		assert_eq!(options.rom, None);
+		assert_eq!(options.scale, defaults.scale);
+		assert_eq!(options.palette, defaults.palette);
+		assert!(options.filters.is_empty());
+		assert!(!options.help);
+		// nothing a switch turns on is on
+		switches()
+			.iter()
+			.for_each(|(option, set)| assert!(!set(&options), "{}", option));
+	}
+
+	#[test]
+	fn every_switch() {
+		switches().iter().for_each(|(option, set)| {
+			assert!(set(&parse(&[option]).unwrap()), "{}", option);
+		});
+	}
+
+	#[test]
+	fn every_option_with_a_value() {
+		with_values().iter().for_each(|(option, value, set)| {
+			let options = parse(&[option, value]).unwrap();
+			assert!(set(&options), "{} {}", option, value);
+		});
+		with_others().iter().for_each(|(args, set)| {
+			assert!(set(&parse(args).unwrap()), "{:?}", args);
+		});
+		// all at once, along with a rom
+		let mut args = vec!["game.gb"];
+		with_values()
+			.iter()
+			.filter(|(option, ..)| *option != "--dir")
+			.for_each(|(option, value, _)| args.extend([option, value]));
+		let options = parse(&args).unwrap();
+		assert_eq!(options.rom, Some("game.gb".into()));
+		with_values()
+			.iter()
+			.filter(|(option, ..)| *option != "--dir")
+			.for_each(|(option, _, set)| assert!(set(&options), "{}", option));
+	}
+
+	#[test]
+	fn repeated_options() {
+		let options = parse(&["--filter", "scanlines", "--filter", "grayscale"]).unwrap();
+		assert!(matches!(
+			options.filters[..],
+			[Filter::Scanlines, Filter::Grayscale]
+		));
+		// the last one wins
+		let options = parse(&["--scale", "2", "--scale", "6"]).unwrap();
+		assert_eq!(options.scale, 6);
+	}
+
+	#[test]
+	fn missing_values() {
+		with_values().iter().for_each(|(option, ..)| {
+			let error = parse(&[option])
+				.err()
+				.unwrap_or_else(|| panic!("{} parsed", option));
+			assert!(error.starts_with(option), "{}: {}", option, error);
+		});
+		["--test-roms", "--export-frames", "--from-state"]
+			.iter()
+			.for_each(|option| {
+				let error = parse(&[option]).err().unwrap();
+				assert!(
+					error.starts_with(&format!("{} expects", option)),
+					"{}",
+					error
+				);
+			});
+	}
+
+	#[test]
+	fn invalid_values() {
+		[
+			("--palette", "neon", "unknown palette: neon"),
+			("--border-color", "1000000", "invalid border color: 1000000"),
+			("--filter", "sepia", "unknown filter: sepia"),
+			("--scale", "9", "invalid scale (1-8): 9"),
+			("--scale", "0", "invalid scale (1-8): 0"),
+			(
+				"--lcd-grid",
+				"101",
+				"invalid lcd grid intensity (0-100): 101",
+			),
+			(
+				"--crt-rounding",
+				"73",
+				"invalid crt rounding radius (1-72): 73",
+			),
+			("--hide", "sky", "unknown layer in sky"),
+			("--runahead", "2", "--runahead expects 0 or 1 (frames)"),
+			(
+				"--input-latch",
+				"never",
+				"--input-latch expects vblank or frame-start",
+			),
+			("--hold", "select,z", "unknown button: z"),
+			("--hold-frames", "-1", "invalid frame count: -1"),
+			("--rewind-memory", "lots", "invalid size: lots"),
+			(
+				"--ram-init",
+				"garbage",
+				"--ram-init expects zeros, ones, alternating or random",
+			),
+			("--initial-phase", "x", "invalid seed: x"),
+			("--model", "gba", "--model expects dmg or cgb"),
+			("--log-level", "loud", "invalid log level: loud"),
+			(
+				"--trace-format",
+				"json",
+				"--trace-format expects plain or timestamped",
+			),
+			("--trace-fifo", "144", "invalid line: 144"),
+			("--export-scale", "0", "invalid scale: 0"),
+			("--bank", "x", "invalid bank: x"),
+			("--range", "200:150", "invalid range: 200:150"),
+			("--range", "0:8001", "invalid range: 0:8001"),
+		]
+		.iter()
+		.for_each(|(option, value, expected)| {
+			let error = parse(&[option, value]).err().unwrap();
+			assert!(
+				error.starts_with(expected),
+				"{} {}: {}",
+				option,
+				value,
+				error
+			);
+		});
+	}
+
+	#[test]
+	fn unknown_arguments() {
+		assert_eq!(
+			parse(&["--fullscreen"]).err(),
+			Some("unknown argument: --fullscreen (see --help)".to_string())
+		);
+		assert_eq!(
+			parse(&["-x"]).err(),
+			Some("unknown argument: -x (see --help)".to_string())
+		);
+		assert_eq!(
+			parse(&["a.gb", "b.gb"]).err(),
+			Some("unexpected argument: b.gb, only one rom can be run".to_string())
+		);
+	}
+
+	#[test]
+	fn conflicting_combinations() {
+		[
+			(
+				&["--watch-reset-ram"][..],
+				"--watch-reset-ram needs --watch",
+			),
+			(&["--headless"], "--headless needs --script"),
+			(&["--test-roms", "roms"], "--test-roms needs --accuracy"),
+			(
+				&["--export-frames", "10"],
+				"--export-frames needs --export-gfx",
+			),
+			(
+				&["--from-state", "a.ss1"],
+				"--from-state needs --export-gfx",
+			),
+			(&["--fix-checksum"], "--fix-checksum needs --inspect-save"),
+			(
+				&["--dir", "roms", "a.gb"],
+				"--dir & a rom can't be combined",
+			),
+			(
+				&[
+					"--export-gfx",
+					"out",
+					"--from-state",
+					"a.ss1",
+					"--export-frames",
+					"1",
+				],
+				"--from-state & --export-frames can't be combined",
+			),
+		]
+		.iter()
+		.for_each(|(args, expected)| {
+			assert_eq!(parse(args).err().as_deref(), Some(*expected), "{:?}", args);
+		});
+	}
+
+	#[test]
+	fn parse_onto_keeps_what_is_not_given() {
+		let defaults = EmulatorOptions {
+			scale: 3,
+			palette: PALETTES[1],
+			..EmulatorOptions::default()
+		};
+		let args = ["--scale", "5", "--watch"].map(String::from);
+		let options = EmulatorOptions::parse_onto(defaults, args.into_iter()).unwrap();
+		assert_eq!(options.scale, 5);
+		assert_eq!(options.palette, PALETTES[1]);
+		assert!(options.watch);
+	}
+}
@@ -0,0 +1,62 @@
+use rustboy::gameboy::Palette;
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+// Palette last picked for each game, keyed by header::fingerprint(), as `fingerprint = name`
+// lines. Like State it's rewritten as a whole, but on every pick rather than on exit.
+pub struct GamePalettes {
+	path: Option<PathBuf>,
+	palettes: BTreeMap<String, String>,
+}
+
+impl GamePalettes {
+	// Empty if there is no file yet, lines that can't be made sense of are dropped. Without a path
+	// nothing gets remembered.
+	pub fn load(path: Option<PathBuf>) -> Self {
+		let text = path
+			.as_ref()
+			.and_then(|path| fs::read_to_string(path).ok())
+			.unwrap_or_default();
+		let palettes = text
+			.lines()
+			.filter(|line| !line.starts_with('#'))
+			.filter_map(|line| {
+				let (k, v) = line.split_once('=')?;
+				Some((k.trim().to_string(), v.trim().to_string()))
+			})
+			.collect();
+		GamePalettes { path, palettes }
+	}
+
+	// `default` if none was picked for the game or it's no longer known
+	pub fn get(&self, fingerprint: &str, default: Palette) -> Palette {
+		self
+			.palettes
+			.get(fingerprint)
+			.and_then(|name| Palette::find(name))
+			.unwrap_or(default)
+	}
+
+	pub fn set(&mut self, fingerprint: &str, palette: Palette) -> io::Result<()> {
+		self
+			.palettes
+			.insert(fingerprint.to_string(), palette.name.to_string());
+		let Some(path) = &self.path else {
+			return Ok(());
+		};
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let lines: String = self
+			.palettes
+			.iter()
+			.map(|(fingerprint, name)| format!("{} = {}\n", fingerprint, name))
+			.collect();
+		fs::write(
+			path,
+			format!(
+				"# palette per game, written by rustboy when one is picked\n{}",
+				lines
+			),
+		)
+	}
+}
@@ -0,0 +1,156 @@
+use crate::utils::crc32;
+
+// Applies an IPS or BPS patch (told apart by their magic) to `rom`, BPS checksums are verified
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+	match patch {
+		[b'P', b'A', b'T', b'C', b'H', ..] => apply_ips(rom, &patch[5..]),
+		[b'B', b'P', b'S', b'1', ..] => apply_bps(rom, patch),
+		_ => Err("unknown patch format (expected IPS or BPS)".to_string()),
+	}
+}
+
+// IPS: records of a 3 byte offset, 2 byte size & that many bytes (size 0: 2 byte count & a byte
+// repeated count times), all big endian, up to "EOF" optionally followed by a 3 byte size the
+// rom gets truncated to
+fn apply_ips(rom: &[u8], mut records: &[u8]) -> Result<Vec<u8>, String> {
+	let truncated = || "truncated IPS patch".to_string();
+	let take = |n: usize, records: &mut &[u8]| -> Result<usize, String> {
+		let bytes = records.get(..n).ok_or_else(truncated)?;
+		*records = &records[n..];
+		Ok(bytes.iter().fold(0, |value, &b| value << 8 | b as usize))
+	};
+
+	let mut rom = rom.to_vec();
+	loop {
+		if records.starts_with(b"EOF") {
+			records = &records[3..];
+			if records.len() >= 3 {
+				let size = take(3, &mut records)?;
+				rom.truncate(size);
+			}
+			return Ok(rom);
+		}
+		let offset = take(3, &mut records)?;
+		let (size, data) = match take(2, &mut records)? {
+			0 => {
+				let count = take(2, &mut records)?;
+				(count, vec![take(1, &mut records)? as u8; count])
+			}
+			size => {
+				let data = records.get(..size).ok_or_else(truncated)?.to_vec();
+				records = &records[size..];
+				(size, data)
+			}
+		};
+		if rom.len() < offset + size {
+			rom.resize(offset + size, 0);
+		}
+		rom[offset..offset + size].copy_from_slice(&data);
+	}
+}
+
+// reads the actions part of a BPS patch
+struct BpsReader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> BpsReader<'a> {
+	fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+		let bytes = self.bytes.get(self.position..self.position + n)?;
+		self.position += n;
+		Some(bytes)
+	}
+
+	// 7 bits per byte, least significant first, the last byte has bit 7 set
+	fn varint(&mut self) -> Option<usize> {
+		let (mut value, mut shift) = (0_usize, 1_usize);
+		loop {
+			let byte = self.take(1)?[0];
+			value += (byte & 0x7F) as usize * shift;
+			if byte & 0x80 != 0 {
+				return Some(value);
+			}
+			shift <<= 7;
+			value += shift;
+		}
+	}
+
+	// offsets are stored as magnitude << 1 | sign
+	fn signed_varint(&mut self) -> Option<isize> {
+		let value = self.varint()?;
+		Some(match value & 1 {
+			1 => -((value >> 1) as isize),
+			_ => (value >> 1) as isize,
+		})
+	}
+
+	fn is_done(&self) -> bool {
+		self.position >= self.bytes.len()
+	}
+}
+
+// BPS: "BPS1", source size, target size & metadata (varints), then actions up to a footer of
+// source, target & patch CRC32s. An action is a varint holding length - 1 & one of
+// - SourceRead: bytes from the source at the same offset as the output
+// - TargetRead: bytes that follow in the patch
+// - SourceCopy / TargetCopy: bytes from the source / output at a cursor moved by a signed varint
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+	if patch.len() < 16 {
+		return Err("truncated BPS patch".to_string());
+	}
+	let (actions, footer) = patch.split_at(patch.len() - 12);
+	let checksum = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap());
+	if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+		return Err("BPS patch is corrupted (checksum mismatch)".to_string());
+	}
+	if crc32(rom) != checksum(0) {
+		return Err("BPS patch doesn't apply to this rom (source checksum mismatch)".to_string());
+	}
+	let target = decode_bps(rom, actions).ok_or("invalid BPS patch")?;
+	if crc32(&target) != checksum(4) {
+		return Err("patched rom doesn't match the BPS target checksum".to_string());
+	}
+	Ok(target)
+}
+
+fn decode_bps(rom: &[u8], actions: &[u8]) -> Option<Vec<u8>> {
+	let mut reader = BpsReader {
+		bytes: actions,
+		position: 4,
+	};
+	let _source_size = reader.varint()?;
+	let target_size = reader.varint()?;
+	let metadata_size = reader.varint()?;
+	reader.take(metadata_size)?;
+
+	let mut target = Vec::with_capacity(target_size);
+	let (mut source_cursor, mut target_cursor) = (0_isize, 0_isize);
+	while !reader.is_done() {
+		let action = reader.varint()?;
+		let length = (action >> 2) + 1;
+		match action & 3 {
+			0 => {
+				let start = target.len();
+				target.extend_from_slice(rom.get(start..start + length)?);
+			}
+			1 => target.extend_from_slice(reader.take(length)?),
+			2 => {
+				source_cursor += reader.signed_varint()?;
+				let start = usize::try_from(source_cursor).ok()?;
+				target.extend_from_slice(rom.get(start..start + length)?);
+				source_cursor += length as isize;
+			}
+			_ => {
+				target_cursor += reader.signed_varint()?;
+				let start = usize::try_from(target_cursor).ok()?;
+				// may overlap with what it writes, so byte by byte
+				for i in start..start + length {
+					target.push(*target.get(i)?);
+				}
+				target_cursor += length as isize;
+			}
+		}
+	}
+	(target.len() == target_size).then_some(target)
+}
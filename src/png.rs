@@ -0,0 +1,57 @@
+use crate::utils::crc32;
+
+// Bare bones PNG encoder (8 bit RGB, no filtering, stored deflate blocks), images produced here
+// are tiny so compression isn't worth a dependency
+
+fn adler32(bytes: &[u8]) -> u32 {
+	let (a, b) = bytes.iter().fold((1_u32, 0_u32), |(a, b), &byte| {
+		let a = (a + byte as u32) % 65521;
+		(a, (b + a) % 65521)
+	});
+	(b << 16) | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+	png.extend((data.len() as u32).to_be_bytes());
+	let start = png.len();
+	png.extend(kind);
+	png.extend(data);
+	let crc = crc32(&png[start..]);
+	png.extend(crc.to_be_bytes());
+}
+
+// pixels are 0x00RRGGBB, row by row
+pub fn encode(width: usize, height: usize, pixels: &[u32]) -> Vec<u8> {
+	let raw: Vec<u8> = pixels
+		.chunks(width)
+		.flat_map(|row| {
+			// filter type 0 (none) in front of every scanline
+			std::iter::once(0).chain(row.iter().flat_map(|p| {
+				let [_, r, g, b] = p.to_be_bytes();
+				[r, g, b]
+			}))
+		})
+		.collect();
+
+	let mut zlib = vec![0x78, 0x01];
+	let blocks = raw.chunks(0xFFFF);
+	let count = blocks.len();
+	blocks.enumerate().for_each(|(i, block)| {
+		zlib.push((i + 1 == count) as u8);
+		zlib.extend((block.len() as u16).to_le_bytes());
+		zlib.extend((!(block.len() as u16)).to_le_bytes());
+		zlib.extend(block);
+	});
+	zlib.extend(adler32(&raw).to_be_bytes());
+
+	let mut header = Vec::new();
+	header.extend((width as u32).to_be_bytes());
+	header.extend((height as u32).to_be_bytes());
+	header.extend([8, 2, 0, 0, 0]); // 8 bit depth, truecolor, no interlace
+
+	let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+	chunk(&mut png, b"IHDR", &header);
+	chunk(&mut png, b"IDAT", &zlib);
+	chunk(&mut png, b"IEND", &[]);
+	png
+}
@@ -1,4 +1,10 @@
-use crate::{HEIGHT, WIDTH, mmu::MMU, utils::is_bit_set};
+use crate::{
+	HEIGHT, WIDTH,
+	compatreport::Event,
+	mmu::{MMU, OamAccess},
+	utils::is_bit_set,
+};
+use log::{info, warn};
 use std::{
 	collections::VecDeque,
 	ops::{BitAnd, Shl, Shr},
@@ -17,11 +23,103 @@ pub enum Modes {
 // Color 1 (Light Gray)	: #AAAAAA | #6c9421 | #b6b6b6 | #c6b7be
 // Color 2 (Dark Gray)	: #555555 | #426b29 | #676767 | #565a75
 // Color 3 (Black):			: #000000 | #214231 | #000000 | #0f0f1b
-enum Color {
-	White = 0x00fafbf6,
-	LightGray = 0x00c6b7be,
-	DarkGray = 0x00565a75,
-	Black = 0x000f0f1b,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+	pub name: &'static str,
+	// white, light gray, dark gray & black as 0x00RRGGBB
+	pub shades: [u32; 4],
+}
+
+pub const PALETTES: [Palette; 4] = [
+	Palette {
+		name: "calc",
+		shades: [0x00FFFFFF, 0x00AAAAAA, 0x00555555, 0x00000000],
+	},
+	Palette {
+		name: "dmg-nso",
+		shades: [0x008CAD28, 0x006C9421, 0x00426B29, 0x00214231],
+	},
+	Palette {
+		name: "2b-gray",
+		shades: [0x00FFFFFF, 0x00B6B6B6, 0x00676767, 0x00000000],
+	},
+	Palette {
+		name: "hollow",
+		shades: [0x00FAFBF6, 0x00C6B7BE, 0x00565A75, 0x000F0F1B],
+	},
+];
+
+impl Palette {
+	pub fn find(name: &str) -> Option<Palette> {
+		PALETTES
+			.into_iter()
+			.find(|palette| palette.name.eq_ignore_ascii_case(name))
+	}
+
+	// the one after this in PALETTES, wrapping around
+	pub fn next(&self) -> Palette {
+		let index = PALETTES.iter().position(|palette| palette == self);
+		PALETTES[index.map_or(0, |i| (i + 1) % PALETTES.len())]
+	}
+}
+
+impl Default for Palette {
+	fn default() -> Self {
+		PALETTES[3]
+	}
+}
+
+// Layers drawn, the others are left out of frames as a debugging aid: fetches & timing carry on
+// as ever, only the pixels pushed to the LCD change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layers {
+	pub background: bool,
+	pub window: bool,
+	pub objects: bool,
+}
+
+impl Layers {
+	const NAMES: [&str; 3] = ["background", "window", "objects"];
+
+	fn flags(&mut self) -> [&mut bool; 3] {
+		[&mut self.background, &mut self.window, &mut self.objects]
+	}
+
+	// all but the comma separated `names`, None for an unknown name
+	pub fn hiding(names: &str) -> Option<Self> {
+		let mut layers = Layers::default();
+		for name in names
+			.split(',')
+			.map(str::trim)
+			.filter(|name| !name.is_empty())
+		{
+			let index = Self::NAMES.iter().position(|&n| n == name)?;
+			*layers.flags()[index] = false;
+		}
+		Some(layers)
+	}
+
+	// comma separated names of the layers left out, as taken by hiding()
+	pub fn hidden(&self) -> String {
+		let shown = [self.background, self.window, self.objects];
+		Self::NAMES
+			.iter()
+			.zip(shown)
+			.filter(|(_, shown)| !shown)
+			.map(|(name, _)| *name)
+			.collect::<Vec<_>>()
+			.join(",")
+	}
+}
+
+impl Default for Layers {
+	fn default() -> Self {
+		Layers {
+			background: true,
+			window: true,
+			objects: true,
+		}
+	}
 }
 
 impl From<u8> for Modes {
@@ -36,29 +134,52 @@ impl From<u8> for Modes {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct SpriteFifoData {
 	color: u8,
 	palette_address: u16,
 	bg_obj_priority_flag: bool,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PPU {
-	frame_buffer: [u32; WIDTH * HEIGHT],
+	frame_buffer: Box<[u32; WIDTH * HEIGHT]>,
+	// when present, rendering happens here & the two get swapped once a frame is complete, so
+	// frame_buffer only ever holds complete frames
+	back_buffer: Option<Box<[u32; WIDTH * HEIGHT]>>,
 	frame_ready: bool,
+	frame_start: bool,
+	frame_changed: bool,
+	pixels_written: u32,
 	background_fifo: VecDeque<u8>,
 	sprite_fifo: VecDeque<SpriteFifoData>,
 	sprite_buffer: VecDeque<u16>,
-	interrupt_triggered: bool,
+	dropped_sprites: Vec<(u8, u8)>,
+	// STAT interrupt line, the enabled sources (LY == LYC & the modes) ORed together. The interrupt
+	// is requested on its rising edge only, so a source going active while another one keeps the
+	// line high requests nothing ("STAT blocking").
+	stat_line: bool,
 	cycles_waste: u16,
 	cycles_spent: u16,
 	mode: Modes,
 	ly: u8,
 	lx: u8,
+	// low 3 bits of SCX latched at the start of RENDER, used to trim the first tile of the line
+	scx_fine: u8,
 	w_present: bool,
+	// LY matched WY at the start of a line's OAMSCAN, the window may only show from then on & keeps
+	// doing so for the rest of the frame whatever WY gets changed to
+	wy_triggered: bool,
 	w_ly: u8,
 	w_lx: u8,
+	// LY reads 0 from a few cycles into line 153 (the line itself carries on as 153)
+	early_ly_reset: bool,
+	// OAM reads as 0xFF while OAM DMA is running, so lines scanned meanwhile have no objects
+	dma_blocks_oam: bool,
+	palette: Palette,
+	layers: Layers,
+	// LY whose RENDER gets logged cycle by cycle, see fifo_trace()
+	fifo_trace_line: Option<u8>,
 }
 
 impl PPU {
@@ -79,8 +200,10 @@ impl PPU {
 	const WX: u16 = 0xFF4B;
 
 	const MAX_CYCLES_PER_SCANLINE: u16 = 456;
+	// cycle of line 153 at which LY already reads 0, when early_ly_reset is on
+	const LINE_153_LY_RESET: u16 = 4;
 
-	fn get_tile_row(a: u8, b: u8) -> [u8; 8] {
+	pub(crate) fn get_tile_row(a: u8, b: u8) -> [u8; 8] {
 		let mut res = [0_u8; 8];
 		(0..res.len()).for_each(|bit| {
 			res[res.len() - 1 - bit] = match (is_bit_set(b, bit as u8), is_bit_set(a, bit as u8)) {
@@ -93,41 +216,237 @@ impl PPU {
 		res
 	}
 
-	fn palette_to_color(palette: u8, color_id: u8) -> Color {
-		match (palette >> (2 * color_id)) & 3 {
-			0 => Color::White,
-			1 => Color::LightGray,
-			2 => Color::DarkGray,
-			3 => Color::Black,
-			_ => unreachable!(),
-		}
+	// shade (0 white to 3 black) a BGP/OBP value maps `color_id` to
+	pub(crate) fn palette_to_shade(palette: u8, color_id: u8) -> usize {
+		((palette >> (2 * color_id)) & 3) as usize
 	}
 
-	pub fn new(mmu: &MMU) -> Self {
+	// PPU picking up at given mode & LY, e.g. the post boot rom state
+	pub fn new(mode: Modes, ly: u8, double_buffered: bool) -> Self {
 		Self {
-			frame_buffer: [0; WIDTH * HEIGHT],
+			frame_buffer: Box::new([0; WIDTH * HEIGHT]),
+			back_buffer: match double_buffered {
+				true => Some(Box::new([0; WIDTH * HEIGHT])),
+				false => None,
+			},
 			frame_ready: false,
+			frame_start: false,
+			frame_changed: true,
+			pixels_written: 0,
 			background_fifo: VecDeque::with_capacity(8),
 			sprite_fifo: VecDeque::with_capacity(8),
 			sprite_buffer: VecDeque::with_capacity(10),
-			interrupt_triggered: false,
+			dropped_sprites: Vec::new(),
+			stat_line: false,
 			cycles_waste: 0,
 			cycles_spent: 0,
-			mode: Modes::from(mmu.read_byte(Self::STAT) & 0x03),
-			ly: mmu.read_byte(Self::LY),
+			mode,
+			ly,
 			lx: 0,
+			scx_fine: 0,
 			w_present: false,
+			wy_triggered: false,
 			w_ly: 0,
 			w_lx: 0,
+			early_ly_reset: false,
+			dma_blocks_oam: false,
+			palette: Palette::default(),
+			layers: Layers::default(),
+			fifo_trace_line: None,
 		}
 	}
 
+	// Power-up position within VBLANK: line `ly` (144-153), `cycles` into it
+	pub fn set_phase(&mut self, mmu: &mut MMU, ly: u8, cycles: u16) {
+		self.ly = ly;
+		self.cycles_spent = cycles % Self::MAX_CYCLES_PER_SCANLINE;
+		mmu.write_register(Self::LY, ly);
+	}
+
+	pub fn set_fifo_trace_line(&mut self, ly: Option<u8>) {
+		self.fifo_trace_line = ly;
+	}
+
+	pub fn set_early_ly_reset(&mut self, enabled: bool) {
+		self.early_ly_reset = enabled;
+	}
+
+	pub fn set_dma_blocks_oam(&mut self, enabled: bool) {
+		self.dma_blocks_oam = enabled;
+	}
+
+	pub fn palette(&self) -> Palette {
+		self.palette
+	}
+
+	// takes effect from the next pixel drawn
+	pub fn set_palette(&mut self, palette: Palette) {
+		self.palette = palette;
+	}
+
+	pub fn layers(&self) -> Layers {
+		self.layers
+	}
+
+	// takes effect from the next pixel drawn
+	pub fn set_layers(&mut self, layers: Layers) {
+		self.layers = layers;
+	}
+
+	fn read_oam(&self, mmu: &MMU, address: u16) -> u8 {
+		match self.dma_blocks_oam && mmu.is_dma_active() {
+			true => 0xFF,
+			false => mmu.ppu_read(address),
+		}
+	}
+
+	// LY as seen through the register & compared to LYC
+	fn visible_ly(&self) -> u8 {
+		match self.early_ly_reset && self.ly == 153 && self.cycles_spent >= Self::LINE_153_LY_RESET {
+			true => 0,
+			false => self.ly,
+		}
+	}
+
+	// PPU matching the mode & LY currently in STAT & LY registers
+	pub fn from_mmu(mmu: &MMU, double_buffered: bool) -> Self {
+		Self::new(
+			Modes::from(mmu.read_byte(Self::STAT) & 0x03),
+			mmu.read_byte(Self::LY),
+			double_buffered,
+		)
+	}
+
 	pub fn is_frame_ready(&self) -> bool {
 		self.frame_ready
 	}
 
+	// first cycle of a frame, i.e. OAMSCAN of LY 0
+	pub fn is_frame_start(&self) -> bool {
+		self.frame_start
+	}
+
+	// whether any pixel of the last completed frame differs from the frame before it
+	pub fn is_frame_changed(&self) -> bool {
+		self.frame_changed
+	}
+
 	pub fn get_frame_buffer(&self) -> &[u32] {
-		&self.frame_buffer
+		&self.frame_buffer[..]
+	}
+
+	// (LY, OAM index) of the objects skipped due to 10 objects per scanline limit in current frame
+	pub fn get_dropped_sprites(&self) -> &[(u8, u8)] {
+		&self.dropped_sprites
+	}
+
+	// bytes held outside of the struct itself
+	pub fn heap_size(&self) -> usize {
+		size_of_val(&*self.frame_buffer)
+			+ self.back_buffer.as_ref().map_or(0, |b| size_of_val(&**b))
+			+ self.background_fifo.capacity()
+			+ self.sprite_fifo.capacity() * size_of::<SpriteFifoData>()
+			+ self.sprite_buffer.capacity() * size_of::<u16>()
+			+ self.dropped_sprites.capacity() * size_of::<(u8, u8)>()
+	}
+
+	// meant to be called right after a frame got completed
+	pub fn check_invariants(&self, mmu: &MMU) -> Vec<String> {
+		let mut violations = Vec::new();
+		let (ly, c) = (self.ly, self.cycles_spent);
+
+		if ly >= 0x9A {
+			violations.push(format!("LY out of range: {}", ly));
+		}
+		if mmu.read_byte(Self::LY) != self.visible_ly() {
+			violations.push(format!(
+				"LY register {} != PPU LY {}",
+				mmu.read_byte(Self::LY),
+				self.visible_ly()
+			));
+		}
+		let mode_consistent = match self.mode {
+			Modes::OAMSCAN => ly < 0x90 && (1..=80).contains(&c),
+			Modes::RENDER => ly < 0x90 && c > 80,
+			Modes::HBLANK => (ly < 0x90 && c > 80) || (ly <= 0x90 && c == 0),
+			Modes::VBLANK => ly >= 0x90 || (ly == 0 && c == 0),
+		};
+		if !mode_consistent {
+			violations.push(format!(
+				"mode {:?} inconsistent with LY {} & cycles {}",
+				self.mode, ly, c
+			));
+		}
+		if is_bit_set(mmu.read_byte(Self::LCDC), 7) && self.pixels_written != (WIDTH * HEIGHT) as u32 {
+			violations.push(format!(
+				"frame not fully written: {} of {} pixels",
+				self.pixels_written,
+				WIDTH * HEIGHT
+			));
+		}
+
+		violations
+	}
+
+	pub fn state(&self) -> String {
+		format!(
+			"mode: {:?}, LY: {}, LX: {}, cycles: {}, waste: {}",
+			self.mode, self.ly, self.lx, self.cycles_spent, self.cycles_waste
+		)
+	}
+
+	// Pixel pipeline after a RENDER cycle: cycles into the line, LX, cycles still to be wasted
+	// (fetch, SCX discard & window/object penalties), window state & the FIFOs front first. BG
+	// entries are color ids, OBJ entries color id, palette (0/1) & `b` when behind the background.
+	fn fifo_trace(&self) -> String {
+		let bg = self
+			.background_fifo
+			.iter()
+			.map(|color| color.to_string())
+			.collect::<String>();
+		let obj = self
+			.sprite_fifo
+			.iter()
+			.map(|data| {
+				format!(
+					"{}{}{}",
+					data.color,
+					(data.palette_address == Self::OBP1) as u8,
+					match data.bg_obj_priority_flag {
+						true => "b",
+						false => "",
+					}
+				)
+			})
+			.collect::<Vec<_>>()
+			.join(" ");
+		format!(
+			"FIFO LY:{:03} C:{:03} LX:{:03} waste:{} window:{} (LX {:03}, line {:03}) objects left:{} BG:[{}] OBJ:[{}]",
+			self.ly,
+			self.cycles_spent,
+			self.lx,
+			self.cycles_waste,
+			self.w_present as u8,
+			self.w_lx,
+			self.w_ly,
+			self.sprite_buffer.len(),
+			bg,
+			obj
+		)
+	}
+
+	// Unexpected (mode, LY, cycles) combination, picks the mode LY & cycles say we should be in.
+	// A line that was being rendered is abandoned, i.e. finishes in HBLANK.
+	fn resync(&mut self) -> Modes {
+		warn!("PPU out of sync, resyncing: {}", self.state());
+		self.background_fifo.clear();
+		self.sprite_fifo.clear();
+		self.cycles_waste = 0;
+		match (self.ly, self.cycles_spent) {
+			(0x90.., _) => Modes::VBLANK,
+			(_, 0..0x50) => Modes::OAMSCAN,
+			_ => Modes::HBLANK,
+		}
 	}
 
 	// PPU Modes - State Machine
@@ -157,8 +476,8 @@ impl PPU {
 			(Modes::HBLANK, ly, 0) if ly < 0x90 => Modes::OAMSCAN,
 			(Modes::HBLANK, _, 0) => Modes::VBLANK,
 			(Modes::HBLANK, ly, _) if ly < 0x90 => Modes::HBLANK,
-			(Modes::VBLANK, ly, _) if ly >= 0x90 && ly < 0x9A => Modes::VBLANK,
-			_ => unreachable!("{:?}", &self),
+			(Modes::VBLANK, ly, _) if (0x90..0x9A).contains(&ly) => Modes::VBLANK,
+			_ => self.resync(),
 		};
 
 		if self.mode == prev_mode {
@@ -167,26 +486,33 @@ impl PPU {
 
 		let stat = mmu.read_byte(Self::STAT);
 		let x = (stat & 0xFC) | (self.mode as u8);
-		mmu.write_byte(Self::STAT, x);
+		mmu.write_register(Self::STAT, x);
 
 		match self.mode {
-			Modes::OAMSCAN => self.cycles_waste += 79,
-			Modes::RENDER => self.cycles_waste += 12,
+			Modes::OAMSCAN => {
+				if self.ly == 0 {
+					self.frame_start = true;
+					self.frame_changed = false;
+					self.pixels_written = 0;
+				}
+				self.wy_triggered |= self.ly == mmu.read_byte(Self::WY);
+				self.cycles_waste += 79;
+			}
+			Modes::RENDER => {
+				self.scx_fine = mmu.read_byte(Self::SCX) & 0x07;
+				self.cycles_waste += 12;
+			}
 			Modes::VBLANK => {
+				if let Some(back_buffer) = &mut self.back_buffer {
+					std::mem::swap(&mut self.frame_buffer, back_buffer);
+				}
 				self.w_ly = 0;
+				self.wy_triggered = false;
 				self.frame_ready = true;
 				mmu.request_interrupt(0);
 			}
 			_ => {}
 		};
-
-		if self.mode != Modes::RENDER
-			&& !self.interrupt_triggered
-			&& (stat >> (3 + self.mode as u8)) & 0x01 == 0x01
-		{
-			self.interrupt_triggered = true;
-			mmu.request_interrupt(1);
-		}
 	}
 
 	fn find_object_address(&self, mmu: &MMU) -> Option<u16> {
@@ -194,37 +520,35 @@ impl PPU {
 			.sprite_buffer
 			.iter()
 			.filter(|address| {
-				let obj_x = mmu.read_byte(*address + 1);
+				let obj_x = self.read_oam(mmu, *address + 1);
 				if obj_x <= self.lx + 8 && self.lx < obj_x {
 					return true;
 				}
 				false
 			})
-			.map(|a| *a)
+			.copied()
 			.take(1)
 			.next()
 	}
 
 	fn fill_sprite_fifo(&mut self, mmu: &MMU) {
-		let obj_addr = self.find_object_address(mmu);
-		if obj_addr.is_none() {
-			self.sprite_fifo.push_back(SpriteFifoData {
-				color: 0,
-				palette_address: Self::OBP0,
-				bg_obj_priority_flag: true,
-			});
+		// no object at this pixel leaves the fifo empty, render treats that as a transparent pixel.
+		// LCDC.1 is sampled here, with objects disabled nothing is fetched & no penalty is paid,
+		// while pixels of an object fetched earlier keep being output.
+		let lcdc = mmu.read_byte(Self::LCDC);
+		if !is_bit_set(lcdc, 1) {
 			return;
 		}
+		let Some(obj_addr) = self.find_object_address(mmu) else {
+			return;
+		};
 
 		self.cycles_waste += 6;
-		let obj_addr = obj_addr.unwrap();
-		let lcdc = mmu.read_byte(Self::LCDC);
-		let obj_enable_flag = is_bit_set(lcdc, 1);
 		let obj_size = is_bit_set(lcdc, 2);
-		let obj_y = mmu.read_byte(obj_addr);
-		let obj_x = mmu.read_byte(obj_addr + 1);
-		let obj_tile_index = mmu.read_byte(obj_addr + 2) as u16;
-		let obj_attr = mmu.read_byte(obj_addr + 3);
+		let obj_y = self.read_oam(mmu, obj_addr);
+		let obj_x = self.read_oam(mmu, obj_addr + 1);
+		let obj_tile_index = self.read_oam(mmu, obj_addr + 2) as u16;
+		let obj_attr = self.read_oam(mmu, obj_addr + 3);
 
 		let bg_obj_priority_flag = is_bit_set(obj_attr, 7);
 		let y_flip = is_bit_set(obj_attr, 6);
@@ -250,8 +574,8 @@ impl PPU {
 		let obj_data_address = obj_tile_data_address + (obj_data_index * 2);
 
 		let mut pixels = Self::get_tile_row(
-			mmu.read_byte(obj_data_address),
-			mmu.read_byte(obj_data_address + 1),
+			mmu.ppu_read(obj_data_address),
+			mmu.ppu_read(obj_data_address + 1),
 		);
 
 		if x_flip {
@@ -260,25 +584,28 @@ impl PPU {
 
 		((self.lx + 8 - obj_x)..8).for_each(|idx| {
 			self.sprite_fifo.push_back(SpriteFifoData {
-				color: if obj_enable_flag {
-					pixels[idx as usize]
-				} else {
-					0
-				},
+				color: pixels[idx as usize],
 				palette_address: obj_palette_address,
-				bg_obj_priority_flag: bg_obj_priority_flag,
+				bg_obj_priority_flag,
 			});
 		});
 	}
 
+	// Register sampling (mid-frame / mid-line writes take effect at these points):
+	// - SCY, SCX (coarse), LCDC, WX: on every tile fetch, i.e. every 8 pixels
+	// - SCX (fine, low 3 bits): once per line, at the start of RENDER (see scx_fine)
+	// - WY: compared with LY at the start of every OAMSCAN, a match latches for the frame (see
+	//   wy_triggered)
+	// - BGP, OBP0, OBP1: on every pixel pushed to the LCD (see render)
+	// so a write during HBLANK applies to the whole next line, a write during RENDER to the
+	// following tile (scroll) or the following pixel (palettes).
 	fn fill_background_fifo(&mut self, mmu: &MMU) {
 		let scy = mmu.read_byte(Self::SCY);
 		let scx = mmu.read_byte(Self::SCX);
-		let wy = mmu.read_byte(Self::WY);
 		let wx = mmu.read_byte(Self::WX);
 		let lcdc = mmu.read_byte(Self::LCDC);
 		let bg_enable = is_bit_set(lcdc, 0);
-		let is_window = is_bit_set(lcdc, 5) && self.ly >= wy && self.lx + 7 >= wx;
+		let is_window = is_bit_set(lcdc, 5) && self.wy_triggered && self.lx + 7 >= wx;
 
 		let (tile_map_area, tile_index_offset, tile_line_offset) = match is_window {
 			true => (
@@ -286,27 +613,36 @@ impl PPU {
 				(self.w_ly.shr(3) as u16).shl(5) + self.lx.wrapping_add(7).wrapping_sub(wx).shr(3) as u16,
 				self.w_ly.bitand(0x07).shl(1) as u16,
 			),
-			false => (
-				is_bit_set(lcdc, 3),
-				(scy.wrapping_add(self.ly).shr(3) as u16).shl(5) + scx.wrapping_add(self.lx).shr(3) as u16,
-				scy.wrapping_add(self.ly).bitand(0x07).shl(1) as u16,
-			),
+			false => {
+				// Pixel coordinates within the 256x256 background, wrapping around as u8 so tile
+				// column & row are always 0..32. lx only counts pixels pushed, the ones trimmed
+				// off the first tile (SCX % 8) aren't part of it, so for every fetch after the
+				// first one scx + lx lands on the start of the next tile, across the wrap as well.
+				let (x, y) = (scx.wrapping_add(self.lx), scy.wrapping_add(self.ly));
+				(
+					is_bit_set(lcdc, 3),
+					(y.shr(3) as u16).shl(5) + x.shr(3) as u16,
+					y.bitand(0x07).shl(1) as u16,
+				)
+			}
 		};
 
-		let tile_index_address = tile_index_offset as u16
+		let tile_index_address = tile_index_offset
 			+ match tile_map_area {
-				true => 0x9C00,
-				false => 0x9800,
+				true => 0x9C00_u16,
+				false => 0x9800_u16,
 			};
-		let tile_index = mmu.read_byte(tile_index_address);
+		let tile_index = mmu.ppu_read(tile_index_address);
 
+		// addressing mode (LCDC.4) is taken from the LCDC sampled for this fetch, so flipping it
+		// between (or within) scanlines switches tile data area from the next fetched tile onwards
 		let tile_line_address = tile_line_offset
 			+ match is_bit_set(lcdc, 4) {
 				true => 0x8000 + (16 * (tile_index as u16)),
 				false => 0x9000u16.wrapping_add_signed(16 * (tile_index as i8) as i16),
 			};
-		let lb = mmu.read_byte(tile_line_address);
-		let hb = mmu.read_byte(tile_line_address + 1);
+		let lb = mmu.ppu_read(tile_line_address);
+		let hb = mmu.ppu_read(tile_line_address + 1);
 		let pixels = Self::get_tile_row(lb, hb);
 		pixels.iter().for_each(|p| {
 			self
@@ -314,10 +650,11 @@ impl PPU {
 				.push_back(if bg_enable { *p } else { 0 });
 		});
 
+		// fine scroll, discard the pixels of first tile that are scrolled off the left edge
 		if self.lx == 0 {
 			let remaining = match is_window {
 				true => 1 + wx,
-				false => 8 - (scx % 8),
+				false => 8 - self.scx_fine,
 			} as usize;
 			while self.background_fifo.len() > remaining {
 				self.cycles_waste += 1;
@@ -329,7 +666,7 @@ impl PPU {
 	fn render(&mut self, mmu: &MMU) {
 		if !self.w_present
 			&& is_bit_set(mmu.read_byte(Self::LCDC), 5)
-			&& self.ly >= mmu.read_byte(Self::WY)
+			&& self.wy_triggered
 			&& self.lx + 7 >= mmu.read_byte(Self::WX)
 		{
 			self.background_fifo.clear();
@@ -352,13 +689,46 @@ impl PPU {
 			}
 		}
 
-		let bg_pixel = self.background_fifo.pop_front().unwrap();
-		let obj_data = self.sprite_fifo.pop_front().unwrap();
-		let color = match obj_data.color == 0 || (obj_data.bg_obj_priority_flag && bg_pixel > 0) {
-			true => Self::palette_to_color(mmu.read_byte(Self::BGP), bg_pixel),
-			false => Self::palette_to_color(mmu.read_byte(obj_data.palette_address), obj_data.color),
+		let mut bg_pixel = self.background_fifo.pop_front().unwrap();
+		let mut obj_data = self.sprite_fifo.pop_front().unwrap_or(SpriteFifoData {
+			color: 0,
+			palette_address: Self::OBP0,
+			bg_obj_priority_flag: true,
+		});
+		// once the window started on this line, the background fifo holds window pixels only
+		let bg_shown = match self.w_present {
+			true => self.layers.window,
+			false => self.layers.background,
 		};
-		self.frame_buffer[self.ly as usize * WIDTH + self.lx as usize] = color as u32;
+		if !bg_shown {
+			bg_pixel = 0;
+		}
+		if !self.layers.objects {
+			obj_data.color = 0;
+		}
+		let shade = match obj_data.color == 0 || (obj_data.bg_obj_priority_flag && bg_pixel > 0) {
+			true => Self::palette_to_shade(mmu.ppu_read(Self::BGP), bg_pixel),
+			false => Self::palette_to_shade(mmu.ppu_read(obj_data.palette_address), obj_data.color),
+		};
+		let color = self.palette.shades[shade];
+		// only reachable if the mode state machine glitched, draw on the edge rather than crash
+		let in_frame = (self.ly as usize) < HEIGHT && (self.lx as usize) < WIDTH;
+		debug_assert!(in_frame, "pixel out of frame, {}", self.state());
+		if !in_frame {
+			warn!("pixel out of frame, clamped: {}", self.state());
+		}
+		let (x, y) = (
+			(self.lx as usize).min(WIDTH - 1),
+			(self.ly as usize).min(HEIGHT - 1),
+		);
+		// frame_buffer holds the previous frame either way, the back buffer the one before it
+		let index = y * WIDTH + x;
+		self.frame_changed |= self.frame_buffer[index] != color;
+		match self.back_buffer.as_mut() {
+			Some(back_buffer) => back_buffer[index] = color,
+			None => self.frame_buffer[index] = color,
+		};
+		self.pixels_written += 1;
 		self.lx += 1;
 	}
 
@@ -369,15 +739,48 @@ impl PPU {
 			false => 8,
 		};
 
-		while self.sprite_buffer.len() < 10 && address < 0xFEA0 {
-			let obj_y = mmu.read_byte(address);
+		// all 40 entries are evaluated in OAM order, objects beyond first 10 on the line are dropped
+		if self.ly == 0 {
+			self.dropped_sprites.clear();
+		}
+		while address < 0xFEA0 {
+			let obj_y = self.read_oam(mmu, address);
 			if obj_y <= self.ly + 16 && self.ly + 16 < obj_y + obj_size {
-				self.sprite_buffer.push_back(address);
+				match self.sprite_buffer.len() < 10 {
+					true => self.sprite_buffer.push_back(address),
+					false => {
+						mmu.record(Event::SpriteOverflow, address);
+						self
+							.dropped_sprites
+							.push((self.ly, ((address - 0xFE00) / 4) as u8))
+					}
+				};
 			}
 			address += 4;
 		}
 	}
 
+	// OAM corruption bug: OAM scan reads one 8 byte row per M-cycle, a CPU access on the same bus
+	// meanwhile mixes the first word of that row with the row before it & overwrites the other
+	// three words with the ones of the row before. The first row is never corrupted.
+	fn corrupt_oam(&self, mmu: &mut MMU, access: OamAccess) {
+		let row = 0xFE00 + 8 * (self.cycles_spent / 4);
+		if !(0xFE08..0xFEA0).contains(&row) {
+			return;
+		}
+		let word =
+			|address: u16| u16::from_le_bytes([mmu.ppu_read(address), mmu.ppu_read(address + 1)]);
+		let (a, b, c) = (word(row), word(row - 8), word(row - 4));
+		let first = match access {
+			OamAccess::Read => b | (a & c),
+			OamAccess::Write => ((a ^ c) & (b ^ c)) ^ c,
+		};
+		let [l, h] = first.to_le_bytes();
+		mmu.write_register(row, l);
+		mmu.write_register(row + 1, h);
+		(2..8).for_each(|i| mmu.write_register(row + i, mmu.ppu_read(row - 8 + i)));
+	}
+
 	fn process(&mut self, mmu: &MMU) {
 		if self.cycles_waste > 0 {
 			self.cycles_waste -= 1;
@@ -391,35 +794,645 @@ impl PPU {
 		};
 	}
 
+	// Runs as the last cycle of a line completes, so LY holds the new line from its cycle 0 on,
+	// exactly every 456 cycles. The mode (& STAT interrupts, VBLANK included) follows on that
+	// cycle's tick. The CPU sees LY as of the start of the reading instruction, as instructions
+	// run before the PPU catches up with their cycles: a LDH A,(0x44) polling loop of 24 cycles
+	// reads each LY 19 times.
 	fn setup_for_new_scanline(&mut self, mmu: &mut MMU) {
-		self.interrupt_triggered = false;
 		self.background_fifo.clear();
 		self.sprite_fifo.clear();
 		self.sprite_buffer.clear();
 		self.w_ly += if self.w_present { 1 } else { 0 };
 		self.w_lx = 0;
 		self.w_present = false;
-		let lyc = mmu.read_byte(Self::LYC);
 		self.ly = (self.ly + 1) % 0x9A;
 		self.lx = 0;
 
-		mmu.write_byte(Self::LY, self.ly);
-		if lyc == self.ly {
-			self.interrupt_triggered = true;
+		mmu.write_register(Self::LY, self.ly);
+	}
+
+	// Compared on every cycle, so a write to LYC (or STAT) takes effect immediately and not only
+	// when LY or the mode changes. Sets STAT bit 2 & requests the STAT interrupt on a rising edge of
+	// the STAT line.
+	fn update_coincidence(&mut self, mmu: &mut MMU) {
+		let coincidence = self.visible_ly() == mmu.read_byte(Self::LYC);
+		let stat = match coincidence {
+			true => mmu.read_byte(Self::STAT) | 0x04,
+			false => mmu.read_byte(Self::STAT) & !0x04,
+		};
+		mmu.write_register(Self::STAT, stat);
+		let stat_line = (coincidence && is_bit_set(stat, 6))
+			|| match self.mode {
+				Modes::HBLANK => is_bit_set(stat, 3),
+				Modes::VBLANK => is_bit_set(stat, 4),
+				Modes::OAMSCAN => is_bit_set(stat, 5),
+				Modes::RENDER => false,
+			};
+		if stat_line && !self.stat_line {
 			mmu.request_interrupt(1);
 		}
+		self.stat_line = stat_line;
 	}
 
 	pub fn tick(&mut self, mmu: &mut MMU) {
 		if self.frame_ready {
 			self.frame_ready = false;
 		}
+		if self.frame_start {
+			self.frame_start = false;
+		}
 		self.update_mode(mmu);
+		if let Some(access) = mmu.take_oam_bug_access()
+			&& self.mode == Modes::OAMSCAN
+		{
+			self.corrupt_oam(mmu, access);
+		}
 		self.process(mmu);
+		if self.mode == Modes::RENDER && self.fifo_trace_line == Some(self.ly) {
+			info!("{}", self.fifo_trace());
+		}
 
 		self.cycles_spent = (self.cycles_spent + 1) % Self::MAX_CYCLES_PER_SCANLINE;
 		if self.cycles_spent == 0 {
 			self.setup_for_new_scanline(mmu);
+		} else if self.early_ly_reset && self.cycles_spent == Self::LINE_153_LY_RESET && self.ly == 153
+		{
+			mmu.write_register(Self::LY, self.visible_ly());
+		}
+		self.update_coincidence(mmu);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::options::{EmulatorOptions, Model};
+
+	// post boot MMU & PPU, STAT interrupt sources as given & IF cleared
+	fn setup(stat: u8) -> (PPU, MMU) {
+		let mut mmu = MMU::new(vec![0; 0x8000].into(), None, &EmulatorOptions::default());
+		let ppu = PPU::from_mmu(&mmu, false);
+		mmu.write_byte(PPU::STAT, stat);
+		mmu.write_byte(0xFF0F, 0x00);
+		(ppu, mmu)
+	}
+
+	fn tick(ppu: &mut PPU, mmu: &mut MMU, cycles: u32) {
+		(0..cycles).for_each(|_| ppu.tick(mmu));
+	}
+
+	// takes the STAT interrupt request, if any
+	fn stat_interrupt(mmu: &mut MMU) -> bool {
+		let requested = mmu.read_byte(0xFF0F) & 0x02 == 0x02;
+		mmu.write_byte(0xFF0F, 0x00);
+		requested
+	}
+
+	#[test]
+	fn lyc_write_mid_line_sets_coincidence_and_fires() {
+		let (mut ppu, mut mmu) = setup(0x40);
+		mmu.write_byte(PPU::LYC, 0x80);
+		tick(&mut ppu, &mut mmu, 10 * 456 + 300);
+		assert!(!stat_interrupt(&mut mmu));
+		assert_eq!(mmu.read_byte(PPU::STAT) & 0x04, 0x00);
+		mmu.write_byte(PPU::LYC, 10);
+		tick(&mut ppu, &mut mmu, 1);
+		assert_eq!(mmu.read_byte(PPU::STAT) & 0x04, 0x04);
+		assert!(stat_interrupt(&mut mmu));
+		// the rest of the line only keeps the line high
+		tick(&mut ppu, &mut mmu, 100);
+		assert!(!stat_interrupt(&mut mmu));
+	}
+
+	// PPU at the first cycle of a frame, background of tile 0 (all color 0 after power on)
+	fn frame_start() -> (PPU, MMU) {
+		let (mut ppu, mut mmu) = setup(0x00);
+		while !ppu.is_frame_start() {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		(ppu, mmu)
+	}
+
+	#[test]
+	fn bgp_write_mid_line_applies_to_the_following_pixels() {
+		let (mut ppu, mut mmu) = frame_start();
+		// color 0 white, then black
+		mmu.write_byte(PPU::BGP, 0xFC);
+		while ppu.lx < 80 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		mmu.write_byte(PPU::BGP, 0xFF);
+		while ppu.ly == 0 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		let line = &ppu.get_frame_buffer()[..WIDTH];
+		assert_ne!(line[0], line[WIDTH - 1]);
+		assert!(line[..80].iter().all(|&pixel| pixel == line[0]));
+		assert!(line[80..].iter().all(|&pixel| pixel == line[WIDTH - 1]));
+	}
+
+	#[test]
+	fn tile_data_area_switched_between_lines() {
+		let (mut ppu, mut mmu) = frame_start();
+		// tile 0 black at 0x8000, white at 0x9000
+		(0x8000..0x8010).for_each(|address| mmu.write_byte(address, 0xFF));
+		mmu.write_byte(PPU::BGP, 0xE4);
+		mmu.write_byte(PPU::LCDC, 0x91);
+		while ppu.ly < 72 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		mmu.write_byte(PPU::LCDC, 0x81);
+		while ppu.ly < 144 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		let (upper, lower) = ppu.get_frame_buffer().split_at(72 * WIDTH);
+		assert_ne!(upper[0], lower[0]);
+		assert!(upper.iter().all(|&pixel| pixel == upper[0]));
+		assert!(lower.iter().all(|&pixel| pixel == lower[0]));
+	}
+
+	#[test]
+	fn oam_bug_corrupts_the_row_being_scanned() {
+		// row 2 (0xFE10-0xFE17) is scanned on the 3rd M-cycle, its first word is a = 0x3C3C & the
+		// row before it starts with b = 0xF0F0, its third word being c = 0x0FF0
+		let corrupted_write = [0xF0, 0x3C, 0x0A, 0x0B, 0xF0, 0x0F, 0x0E, 0x0F];
+		let corrupted_read = [0xF0, 0xFC, 0x0A, 0x0B, 0xF0, 0x0F, 0x0E, 0x0F];
+		let untouched = [0x3C, 0x3C, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17];
+		for (model, oam_bug, access, row) in [
+			(Model::DMG, true, OamAccess::Write, corrupted_write),
+			(Model::DMG, true, OamAccess::Read, corrupted_read),
+			(Model::DMG, false, OamAccess::Write, untouched),
+			(Model::CGB, true, OamAccess::Write, untouched),
+		] {
+			let options = EmulatorOptions {
+				model,
+				oam_bug,
+				..EmulatorOptions::default()
+			};
+			let mut mmu = MMU::new(vec![0; 0x8000].into(), None, &options);
+			let mut ppu = PPU::from_mmu(&mmu, false);
+			(0..0xA0).for_each(|i| mmu.write_register(0xFE00 + i, i as u8));
+			[
+				(0xFE08, 0xF0),
+				(0xFE09, 0xF0),
+				(0xFE0C, 0xF0),
+				(0xFE0D, 0x0F),
+			]
+			.into_iter()
+			.chain([(0xFE10, 0x3C), (0xFE11, 0x3C)])
+			.for_each(|(address, value)| mmu.write_register(address, value));
+			while !(ppu.mode == Modes::OAMSCAN && ppu.cycles_spent == 8) {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			match access {
+				OamAccess::Read => {
+					mmu.read_byte(0xFEA0);
+				}
+				OamAccess::Write => mmu.write_byte(0xFEA0, 0x00),
+			}
+			tick(&mut ppu, &mut mmu, 1);
+			let oam = (0xFE10..0xFE18)
+				.map(|a| mmu.ppu_read(a))
+				.collect::<Vec<_>>();
+			assert_eq!(oam, row, "{:?} {} {:?}", model, oam_bug, access);
+		}
+	}
+
+	#[test]
+	fn stat_blocking() {
+		// LYC & HBLANK sources, LYC matching line 10
+		let (mut ppu, mut mmu) = setup(0x48);
+		mmu.write_byte(PPU::LYC, 10);
+		tick(&mut ppu, &mut mmu, 9 * 456 + 100);
+		stat_interrupt(&mut mmu);
+		// HBLANK of line 9 raises the line...
+		tick(&mut ppu, &mut mmu, 355);
+		assert!(stat_interrupt(&mut mmu));
+		// ...which is still high as LY becomes 10, so the match is blocked
+		tick(&mut ppu, &mut mmu, 2);
+		assert_eq!(mmu.read_byte(PPU::LY), 10);
+		assert_eq!(mmu.read_byte(PPU::STAT) & 0x04, 0x04);
+		assert!(!stat_interrupt(&mut mmu));
+		// as is HBLANK of line 10, the match keeps it high
+		tick(&mut ppu, &mut mmu, 453);
+		assert_eq!(mmu.read_byte(PPU::STAT) & 0x03, 0x00);
+		assert!(!stat_interrupt(&mut mmu));
+		// on line 11 the line drops in OAM scan & HBLANK raises it again
+		tick(&mut ppu, &mut mmu, 456);
+		assert!(stat_interrupt(&mut mmu));
+	}
+
+	#[test]
+	fn stat_interrupts_per_line() {
+		let count = |stat: u8| {
+			let (mut ppu, mut mmu) = setup(stat);
+			tick(&mut ppu, &mut mmu, 10 * 456 + 100);
+			stat_interrupt(&mut mmu);
+			(0..3 * 456).fold(0, |count, _| {
+				ppu.tick(&mut mmu);
+				count + stat_interrupt(&mut mmu) as u32
+			})
+		};
+		assert_eq!(count(0x08), 3);
+		assert_eq!(count(0x20), 3);
+		// HBLANK keeps the line high into the next line's OAM scan
+		assert_eq!(count(0x28), 3);
+	}
+
+	#[test]
+	fn eleventh_object_on_a_line_is_dropped() {
+		let (mut ppu, mut mmu) = frame_start();
+		// tile 1 all color 3, objects shown black over the white background
+		(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+		mmu.write_byte(PPU::BGP, 0xE4);
+		mmu.write_byte(PPU::OBP0, 0xE4);
+		mmu.write_byte(PPU::LCDC, 0x93);
+		// OAM index 0 on line 50, 1 to 11 on lines 8-15 with the last one leftmost, so OAM order
+		// & X order disagree on which one is the 11th
+		let object = |mmu: &mut MMU, index: u16, y: u8, x: u8| {
+			[y, x, 1, 0]
+				.iter()
+				.enumerate()
+				.for_each(|(i, &byte)| mmu.write_register(0xFE00 + 4 * index + i as u16, byte));
+		};
+		object(&mut mmu, 0, 66, 8);
+		(1..=11).for_each(|i| object(&mut mmu, i, 24, 8 + 12 * (11 - i as u8)));
+		while ppu.ly < 144 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+
+		let black = ppu.palette().shades[3];
+		let line = &ppu.get_frame_buffer()[8 * WIDTH..9 * WIDTH];
+		(1..=11).for_each(|i| {
+			let x = 12 * (11 - i) + 2;
+			assert_eq!(line[x] == black, i != 11, "OAM index {}", i);
+		});
+		assert_eq!(
+			ppu.get_dropped_sprites(),
+			(8..16).map(|ly| (ly, 11)).collect::<Vec<_>>()
+		);
+		// a line of its own, nothing dropped
+		assert_eq!(ppu.get_frame_buffer()[50 * WIDTH + 2], black);
+	}
+
+	#[test]
+	fn frame_changed_only_when_a_pixel_differs() {
+		for double_buffered in [false, true] {
+			let (_, mut mmu) = frame_start();
+			let mut ppu = PPU::from_mmu(&mmu, double_buffered);
+			let mut next_frame = |bgp: u8| {
+				mmu.write_byte(PPU::BGP, bgp);
+				tick(&mut ppu, &mut mmu, 1);
+				while !ppu.is_frame_ready() {
+					tick(&mut ppu, &mut mmu, 1);
+				}
+				ppu.is_frame_changed()
+			};
+			// first frame over the blank buffer
+			assert!(next_frame(0xFC), "{}", double_buffered);
+			// a static screen
+			assert!(!next_frame(0xFC), "{}", double_buffered);
+			assert!(!next_frame(0xFC), "{}", double_buffered);
+			// background turned black, then static again
+			assert!(next_frame(0xFF), "{}", double_buffered);
+			assert!(!next_frame(0xFF), "{}", double_buffered);
+		}
+	}
+
+	#[test]
+	fn lines_with_objects_partially_off_screen_or_none() {
+		let (mut ppu, mut mmu) = frame_start();
+		// background tile 0 rows: 4 pixels of color 1, then 4 of color 0. Tile 1 all color 3.
+		(0x8000..0x8010)
+			.step_by(2)
+			.for_each(|address| mmu.write_byte(address, 0xF0));
+		(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+		mmu.write_byte(PPU::BGP, 0xE4);
+		mmu.write_byte(PPU::OBP0, 0xE4);
+		mmu.write_byte(PPU::LCDC, 0x93);
+		// cut by the left edge on lines 20-27, by the top edge (lines 0-1) & by the right edge (lines
+		// 40-47), lines 2-19 & 28-39 have none
+		[(36, 4), (10, 80), (56, 164)]
+			.iter()
+			.enumerate()
+			.for_each(|(index, &(y, x))| {
+				[y, x, 1, 0]
+					.iter()
+					.enumerate()
+					.for_each(|(i, &byte)| mmu.write_register(0xFE00 + 4 * index as u16 + i as u16, byte));
+			});
+		while ppu.ly < 144 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+
+		let shades = ppu.palette().shades;
+		let background = (0..WIDTH)
+			.map(|x| shades[(x % 8 < 4) as usize])
+			.collect::<Vec<_>>();
+		let with_object = |from: usize, to: usize| {
+			let mut line = background.clone();
+			line[from..to].fill(shades[3]);
+			line
+		};
+		let frame = ppu.get_frame_buffer();
+		let line = |ly: usize| &frame[ly * WIDTH..(ly + 1) * WIDTH];
+		(0..2).for_each(|ly| assert_eq!(line(ly), with_object(72, 80), "LY {}", ly));
+		(20..28).for_each(|ly| assert_eq!(line(ly), with_object(0, 4), "LY {}", ly));
+		(40..48).for_each(|ly| assert_eq!(line(ly), with_object(156, 160), "LY {}", ly));
+		(2..20)
+			.chain(28..40)
+			.chain(48..144)
+			.for_each(|ly| assert_eq!(line(ly), background, "LY {}", ly));
+		assert_eq!(ppu.pixels_written, (WIDTH * HEIGHT) as u32);
+	}
+
+	#[test]
+	fn scx_write_mid_line_keeps_the_fine_scroll_of_the_line() {
+		let (mut ppu, mut mmu) = frame_start();
+		// background columns alternate white & black (tile 1 all color 3)
+		(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+		(0x9801..0x9820)
+			.step_by(2)
+			.for_each(|address| mmu.write_byte(address, 1));
+		mmu.write_byte(PPU::BGP, 0xE4);
+		mmu.write_byte(PPU::SCX, 3);
+		while ppu.lx < 2 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		// after the first fetch: coarse 1, fine 5
+		mmu.write_byte(PPU::SCX, 13);
+		while ppu.ly < 2 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		// in RENDER, before the first fetch: coarse 0, fine 6
+		while ppu.mode != Modes::RENDER {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+		tick(&mut ppu, &mut mmu, 2);
+		assert_eq!(ppu.lx, 0);
+		mmu.write_byte(PPU::SCX, 6);
+		while ppu.ly < 3 {
+			tick(&mut ppu, &mut mmu, 1);
+		}
+
+		let shades = ppu.palette().shades;
+		let column = |column: usize| shades[3 * (column % 2)];
+		let expected = |scx: usize, x: usize| column((scx + x) / 8);
+		let frame = ppu.get_frame_buffer();
+		// the first tile is trimmed by SCX 3 as at the start of the line, the following ones are
+		// fetched whole from where SCX 13 puts them: column (13 + 5) / 8 = 2 at LX 5 & so on
+		(0..5).for_each(|x| assert_eq!(frame[x], expected(3, x), "LX {}", x));
+		(5..WIDTH).for_each(|x| assert_eq!(frame[x], column(2 + (x - 5) / 8), "LX {}", x));
+		// SCX 13 from the start of the next line
+		(0..WIDTH).for_each(|x| assert_eq!(frame[WIDTH + x], expected(13, x), "LX {}", x));
+		assert_ne!(frame[..5], frame[WIDTH..WIDTH + 5]);
+		// column 0 trimmed by the fine scroll of SCX 13 all the same, leaving 3 pixels
+		let line = &frame[2 * WIDTH..3 * WIDTH];
+		(0..3).for_each(|x| assert_eq!(line[x], column(0), "LX {}", x));
+		(3..WIDTH).for_each(|x| assert_eq!(line[x], column(1 + (x - 3) / 8), "LX {}", x));
+	}
+
+	#[test]
+	fn disabled_objects_cost_no_render_cycles() {
+		// cycles LY 10 spends in RENDER with `lcdc`, with or without 10 objects on it
+		let render_cycles = |lcdc: u8, objects: bool| {
+			let (mut ppu, mut mmu) = frame_start();
+			mmu.write_byte(PPU::LCDC, lcdc);
+			if objects {
+				(0..10).for_each(|i| {
+					mmu.write_register(0xFE00 + 4 * i, 26);
+					mmu.write_register(0xFE01 + 4 * i, 8 + 15 * i as u8);
+				});
+			}
+			while ppu.ly < 10 {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			(0..456).fold(0, |cycles, _| {
+				tick(&mut ppu, &mut mmu, 1);
+				cycles + (ppu.mode == Modes::RENDER) as u16
+			})
+		};
+		let plain = render_cycles(0x91, false);
+		assert_eq!(render_cycles(0x93, false), plain);
+		assert!(render_cycles(0x93, true) >= plain + 10 * 6);
+		// objects there, but disabled
+		assert_eq!(render_cycles(0x91, true), plain);
+	}
+
+	#[test]
+	fn out_of_sync_state_is_resynced() {
+		for (mode, ly, cycles, resynced) in [
+			(Modes::RENDER, 150, 100, Modes::VBLANK),
+			(Modes::OAMSCAN, 20, 200, Modes::HBLANK),
+			(Modes::VBLANK, 5, 30, Modes::OAMSCAN),
+			(Modes::HBLANK, 145, 10, Modes::VBLANK),
+		] {
+			let (mut ppu, mut mmu) = frame_start();
+			ppu.mode = mode;
+			ppu.ly = ly;
+			ppu.cycles_spent = cycles;
+			mmu.write_register(PPU::LY, ly);
+			tick(&mut ppu, &mut mmu, 1);
+			assert_eq!(ppu.mode, resynced, "{:?} LY {} C {}", mode, ly, cycles);
+			assert_eq!(mmu.read_byte(PPU::STAT) & 0x03, resynced as u8);
+
+			// back on track by the end of the next full frame
+			(0..2).for_each(|_| {
+				tick(&mut ppu, &mut mmu, 1);
+				while !ppu.is_frame_ready() {
+					tick(&mut ppu, &mut mmu, 1);
+				}
+			});
+			assert_eq!(ppu.check_invariants(&mmu), Vec::<String>::new());
+		}
+	}
+
+	// debug builds stop at the assert, release builds (cargo test --release) clamp to the edge
+	#[test]
+	#[cfg_attr(debug_assertions, should_panic(expected = "pixel out of frame"))]
+	fn pixel_out_of_frame_is_clamped() {
+		let (mut ppu, mut mmu) = frame_start();
+		mmu.write_byte(PPU::BGP, 0xFF);
+		ppu.mode = Modes::RENDER;
+		ppu.ly = 200;
+		ppu.lx = 170;
+		ppu.cycles_waste = 0;
+		ppu.render(&mmu);
+		let black = ppu.palette().shades[3];
+		assert_eq!(ppu.get_frame_buffer()[WIDTH * HEIGHT - 1], black);
+		assert!(
+			ppu.get_frame_buffer()[..WIDTH * HEIGHT - 1]
+				.iter()
+				.all(|&pixel| pixel != black)
+		);
+		assert_eq!((ppu.lx, ppu.pixels_written), (171, 1));
+	}
+
+	#[test]
+	fn new_and_from_mmu_run_the_same() {
+		for double_buffered in [false, true] {
+			let mmu = MMU::new(vec![0; 0x8000].into(), None, &EmulatorOptions::default());
+			// post boot: VBLANK, LY 0
+			let mut from_mmu = (PPU::from_mmu(&mmu, double_buffered), mmu.clone());
+			let mut new = (PPU::new(Modes::VBLANK, 0, double_buffered), mmu);
+			assert_eq!(format!("{:?}", from_mmu.0), format!("{:?}", new.0));
+			[&mut from_mmu, &mut new].iter_mut().for_each(|(ppu, mmu)| {
+				mmu.write_byte(PPU::BGP, 0xE4);
+				(0x8000..0x8010).for_each(|address| mmu.write_byte(address, address as u8));
+				while !ppu.is_frame_ready() {
+					tick(ppu, mmu, 1);
+				}
+			});
+			assert_eq!(format!("{:?}", from_mmu.0), format!("{:?}", new.0));
+			assert!(from_mmu.0.get_frame_buffer() == new.0.get_frame_buffer());
 		}
 	}
+
+	#[test]
+	fn line_153_reads_ly_0_early() {
+		// LY read & whether the LYC = 0 STAT interrupt got requested, after each cycle of line 153
+		let line_153 = |early_ly_reset: bool| {
+			let (mut ppu, mut mmu) = setup(0x40);
+			ppu.set_early_ly_reset(early_ly_reset);
+			mmu.write_byte(PPU::LYC, 0);
+			while ppu.ly != 153 {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			stat_interrupt(&mut mmu);
+			let mut cycles = vec![(mmu.read_byte(PPU::LY), false)];
+			while ppu.ly == 153 {
+				tick(&mut ppu, &mut mmu, 1);
+				cycles.push((mmu.read_byte(PPU::LY), stat_interrupt(&mut mmu)));
+			}
+			cycles
+		};
+
+		let cycles = line_153(true);
+		assert_eq!(cycles.len(), 457);
+		assert!(cycles[..4].iter().all(|&c| c == (153, false)));
+		assert_eq!(cycles[4], (0, true));
+		assert!(cycles[5..].iter().all(|&c| c == (0, false)));
+
+		// LY only changes on the line boundary, as does the LYC match
+		let cycles = line_153(false);
+		assert!(cycles[..456].iter().all(|&c| c == (153, false)));
+		assert_eq!(cycles[456], (0, true));
+	}
+
+	#[test]
+	fn oam_dma_during_oam_scan_hides_the_objects() {
+		// LYs an object shows on (left half black), with OAM DMA started in the OAM scan of LY 24
+		let object_lines = |dma_blocks_oam: bool| {
+			let (mut ppu, mut mmu) = frame_start();
+			ppu.set_dma_blocks_oam(dma_blocks_oam);
+			(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+			mmu.write_byte(PPU::BGP, 0xE4);
+			mmu.write_byte(PPU::OBP0, 0xE4);
+			mmu.write_byte(PPU::LCDC, 0x93);
+			// on LYs 8-15, 24-31 & 40-47
+			[24, 40, 56].iter().enumerate().for_each(|(i, &y)| {
+				[y, 8, 1, 0]
+					.iter()
+					.enumerate()
+					.for_each(|(j, &byte)| mmu.write_register(0xFE00 + 4 * i as u16 + j as u16, byte));
+			});
+			let tick = |ppu: &mut PPU, mmu: &mut MMU| {
+				ppu.tick(mmu);
+				mmu.update_timers(1);
+			};
+			while !(ppu.ly == 24 && ppu.cycles_spent == 10) {
+				tick(&mut ppu, &mut mmu);
+			}
+			// OAM copied onto itself, 640 cycles: into the RENDER of LY 25
+			mmu.write_byte(0xFF46, 0xFE);
+			while ppu.ly < 144 {
+				tick(&mut ppu, &mut mmu);
+			}
+			assert!(!mmu.is_dma_active());
+			let black = ppu.palette().shades[3];
+			(0..HEIGHT)
+				.filter(|ly| ppu.get_frame_buffer()[ly * WIDTH] == black)
+				.collect::<Vec<_>>()
+		};
+
+		let lines =
+			|ranges: [std::ops::Range<usize>; 3]| ranges.into_iter().flatten().collect::<Vec<_>>();
+		// the scans of LY 24 & 25 see no objects
+		assert_eq!(object_lines(true), lines([8..16, 26..32, 40..48]));
+		assert_eq!(object_lines(false), lines([8..16, 24..32, 40..48]));
+	}
+
+	#[test]
+	fn scroll_wraps_around_the_background() {
+		for (scx, scy) in [(250, 0), (0, 250), (250, 250), (255, 255)] {
+			let (mut ppu, mut mmu) = frame_start();
+			// checkerboard of tiles 0 (white) & 1 (black) over the whole 32x32 map
+			(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+			(0..0x400).for_each(|i| mmu.write_byte(0x9800 + i, ((i % 32 + i / 32) % 2) as u8));
+			mmu.write_byte(PPU::BGP, 0xE4);
+			mmu.write_byte(PPU::SCX, scx);
+			mmu.write_byte(PPU::SCY, scy);
+			while ppu.ly < 144 {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+
+			let shades = ppu.palette().shades;
+			let frame = ppu.get_frame_buffer();
+			(0..HEIGHT).for_each(|y| {
+				(0..WIDTH).for_each(|x| {
+					let (column, row) = (scx.wrapping_add(x as u8) / 8, scy.wrapping_add(y as u8) / 8);
+					let expected = shades[3 * ((column + row) % 2) as usize];
+					assert_eq!(
+						frame[y * WIDTH + x],
+						expected,
+						"SCX {} SCY {}: {}, {}",
+						scx,
+						scy,
+						x,
+						y
+					);
+				});
+			});
+		}
+	}
+
+	#[test]
+	fn wy_change_mid_frame_is_not_retroactive() {
+		// LYs the window (all black, from the left edge) shows on, WY set to `before` for the start
+		// of the frame & to `after` from LY 50
+		let window_lines = |before: u8, after: u8| {
+			let (mut ppu, mut mmu) = frame_start();
+			(0x8010..0x8020).for_each(|address| mmu.write_byte(address, 0xFF));
+			(0x9C00..0xA000).for_each(|address| mmu.write_byte(address, 1));
+			mmu.write_byte(PPU::BGP, 0xE4);
+			mmu.write_byte(PPU::WX, 7);
+			mmu.write_byte(PPU::WY, before);
+			mmu.write_byte(PPU::LCDC, 0xF1);
+			// WY 0 (post boot) already got compared on this frame, the next one is drawn
+			tick(&mut ppu, &mut mmu, 1);
+			while !ppu.is_frame_start() {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			while ppu.ly < 50 {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			mmu.write_byte(PPU::WY, after);
+			while ppu.ly < 144 {
+				tick(&mut ppu, &mut mmu, 1);
+			}
+			let black = ppu.palette().shades[3];
+			(0..HEIGHT)
+				.filter(|ly| ppu.get_frame_buffer()[ly * WIDTH] == black)
+				.collect::<Vec<_>>()
+		};
+
+		// lowered below LY, past the line it'd have matched: no window for the rest of the frame
+		assert_eq!(window_lines(100, 0), Vec::<usize>::new());
+		// lowered to a line still to come, shows from there
+		assert_eq!(window_lines(100, 60), (60..144).collect::<Vec<_>>());
+		// raised once triggered, keeps showing
+		assert_eq!(window_lines(20, 100), (20..144).collect::<Vec<_>>());
+		assert_eq!(window_lines(20, 0), (20..144).collect::<Vec<_>>());
+	}
 }
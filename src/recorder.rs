@@ -0,0 +1,58 @@
+use std::{cell::RefCell, panic};
+
+// Flight recorder: PC & opcode of the last executed instructions, dumped when emulation panics so
+// a crash report shows what led up to it. Unlike the trace it's always on, but only in debug
+// builds, the cost being one array write per instruction.
+
+const SIZE: usize = 256;
+
+struct Ring {
+	entries: [(u16, u8); SIZE],
+	next: usize,
+	len: usize,
+}
+
+thread_local! {
+	// per thread, so it holds the instructions executed by the thread that panicked
+	static RING: RefCell<Ring> = const {
+		RefCell::new(Ring {
+			entries: [(0, 0); SIZE],
+			next: 0,
+			len: 0,
+		})
+	};
+}
+
+#[cfg(debug_assertions)]
+pub(crate) fn record(pc: u16, opcode: u8) {
+	RING.with_borrow_mut(|ring| {
+		ring.entries[ring.next] = (pc, opcode);
+		ring.next = (ring.next + 1) % SIZE;
+		ring.len = (ring.len + 1).min(SIZE);
+	});
+}
+
+// oldest first, empty in release builds
+pub fn recent_instructions() -> Vec<(u16, u8)> {
+	RING.with_borrow(|ring| {
+		(0..ring.len)
+			.map(|i| ring.entries[(ring.next + SIZE - ring.len + i) % SIZE])
+			.collect()
+	})
+}
+
+// Prints the recorded instructions after the usual panic message
+pub fn install_panic_hook() {
+	let default_hook = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		default_hook(info);
+		let instructions = recent_instructions();
+		if instructions.is_empty() {
+			return;
+		}
+		eprintln!("last {} instructions (oldest first):", instructions.len());
+		instructions
+			.iter()
+			.for_each(|(pc, opcode)| eprintln!("  {:04X}: {:02X}", pc, opcode));
+	}));
+}
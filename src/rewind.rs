@@ -0,0 +1,58 @@
+use crate::{frametiming::FRAMES_PER_SECOND, gameboy::Snapshot};
+use std::collections::VecDeque;
+
+// Snapshots of the most recent frames, the oldest are dropped once they take more than
+// `max_bytes` altogether (the latest one is always kept)
+pub struct Rewind {
+	snapshots: VecDeque<(Snapshot, usize)>,
+	bytes: usize,
+	max_bytes: usize,
+}
+
+impl Rewind {
+	pub fn new(max_bytes: usize) -> Self {
+		Rewind {
+			snapshots: VecDeque::new(),
+			bytes: 0,
+			max_bytes,
+		}
+	}
+
+	// meant to be called with the state right after every frame
+	pub fn push(&mut self, snapshot: Snapshot) {
+		let size = snapshot.size();
+		self.bytes += size;
+		self.snapshots.push_back((snapshot, size));
+		while self.bytes > self.max_bytes && self.snapshots.len() > 1 {
+			if let Some((_, size)) = self.snapshots.pop_front() {
+				self.bytes -= size;
+			}
+		}
+	}
+
+	// Drops the snapshot of the current frame & returns the one of the frame before it, which
+	// becomes the current one. None once history runs out.
+	pub fn step_back(&mut self) -> Option<&Snapshot> {
+		if self.snapshots.len() < 2 {
+			return None;
+		}
+		if let Some((_, size)) = self.snapshots.pop_back() {
+			self.bytes -= size;
+		}
+		self.snapshots.back().map(|(snapshot, _)| snapshot)
+	}
+
+	// bytes taken by the stored snapshots
+	pub fn memory_usage(&self) -> usize {
+		self.bytes
+	}
+
+	// Seconds of emulation that fit within the cap at the current average snapshot size, what
+	// can be rewound once history fills up. 0 until there is a snapshot to measure.
+	pub fn window_seconds(&self) -> f64 {
+		match self.snapshots.len() {
+			0 => 0.0,
+			n => self.max_bytes as f64 / (self.bytes as f64 / n as f64) / FRAMES_PER_SECOND,
+		}
+	}
+}
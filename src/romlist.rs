@@ -0,0 +1,237 @@
+use crate::menu::Overlay;
+use rustboy::{
+	HEIGHT, WIDTH,
+	joypad::Button,
+	text::{draw_text, text_width},
+};
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+// roms listed per page, leaves room for the title & page number
+const PAGE_SIZE: usize = 14;
+// characters of a file name that fit next to the cursor
+const MAX_NAME_LENGTH: usize = 36;
+
+// .gb & .gbc files directly in `dir`, sorted by name ignoring case
+pub fn list_roms(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut roms = fs::read_dir(dir)?
+		.filter_map(|entry| entry.ok().map(|e| e.path()))
+		.filter(|path| {
+			path.is_file()
+				&& path
+					.extension()
+					.and_then(|e| e.to_str())
+					.is_some_and(|e| e.eq_ignore_ascii_case("gb") || e.eq_ignore_ascii_case("gbc"))
+		})
+		.collect::<Vec<_>>();
+	roms.sort_by_key(|path| file_name(path).to_ascii_lowercase());
+	Ok(roms)
+}
+
+fn file_name(path: &Path) -> String {
+	path
+		.file_name()
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_default()
+}
+
+pub enum Pick {
+	Rom(PathBuf),
+	Cancel,
+}
+
+// Rom list drawn over a blank screen, UP/DOWN move the cursor (wrapping around), LEFT/RIGHT flip
+// pages, A (or START) boots the selected rom & B cancels
+pub struct RomMenu {
+	roms: Vec<PathBuf>,
+	selected: usize,
+	held: Vec<Button>,
+}
+
+impl RomMenu {
+	pub fn new(roms: Vec<PathBuf>, held: Vec<Button>) -> Self {
+		RomMenu {
+			roms,
+			selected: 0,
+			held,
+		}
+	}
+
+	fn pages(&self) -> usize {
+		self.roms.len().div_ceil(PAGE_SIZE).max(1)
+	}
+
+	// takes the buttons currently held & returns the pick, if any
+	pub fn update(&mut self, pressed: Vec<Button>) -> Option<Pick> {
+		let just_pressed = |button: Button| pressed.contains(&button) && !self.held.contains(&button);
+		let count = self.roms.len().max(1);
+		let pick = if just_pressed(Button::UP) {
+			self.selected = (self.selected + count - 1) % count;
+			None
+		} else if just_pressed(Button::DOWN) {
+			self.selected = (self.selected + 1) % count;
+			None
+		} else if just_pressed(Button::LEFT) {
+			self.selected = self.selected.saturating_sub(PAGE_SIZE);
+			None
+		} else if just_pressed(Button::RIGHT) {
+			self.selected = (self.selected + PAGE_SIZE).min(count - 1);
+			None
+		} else if just_pressed(Button::A) || just_pressed(Button::START) {
+			self.roms.get(self.selected).cloned().map(Pick::Rom)
+		} else if just_pressed(Button::B) {
+			Some(Pick::Cancel)
+		} else {
+			None
+		};
+		self.held = pressed;
+		pick
+	}
+}
+
+impl Overlay for RomMenu {
+	fn draw(&self, buffer: &mut [u32]) {
+		draw_text(
+			buffer,
+			(WIDTH - text_width("ROMS")) / 2,
+			4,
+			"ROMS",
+			0x00FFFFFF,
+		);
+		if self.roms.is_empty() {
+			draw_text(buffer, 8, 20, "NO ROMS FOUND", 0x00A0A0A0);
+			return;
+		}
+
+		let page = self.selected / PAGE_SIZE;
+		self
+			.roms
+			.iter()
+			.enumerate()
+			.skip(page * PAGE_SIZE)
+			.take(PAGE_SIZE)
+			.for_each(|(i, path)| {
+				let y = 20 + 8 * (i % PAGE_SIZE);
+				let name = file_name(path)
+					.chars()
+					.take(MAX_NAME_LENGTH)
+					.collect::<String>();
+				match i == self.selected {
+					true => {
+						draw_text(buffer, 4, y, ">", 0x00FFFFFF);
+						draw_text(buffer, 12, y, &name, 0x00FFFFFF);
+					}
+					false => draw_text(buffer, 12, y, &name, 0x00A0A0A0),
+				}
+			});
+		let footer = format!("{}/{}", page + 1, self.pages());
+		draw_text(
+			buffer,
+			(WIDTH - text_width(&footer)) / 2,
+			HEIGHT - 10,
+			&footer,
+			0x00A0A0A0,
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{env, process};
+
+	// `count` roms named rom00.gb, rom01.gb...
+	fn roms(count: usize) -> Vec<PathBuf> {
+		(0..count)
+			.map(|i| PathBuf::from(format!("rom{:02}.gb", i)))
+			.collect()
+	}
+
+	// presses `button` & lets go of it, the selection after it
+	fn press(menu: &mut RomMenu, button: Button) -> usize {
+		assert!(menu.update(vec![button]).is_none());
+		menu.update(vec![]);
+		menu.selected
+	}
+
+	#[test]
+	fn roms_listed_by_name_ignoring_case() {
+		let dir = env::temp_dir().join(format!("rustboy-romlist-{}", process::id()));
+		fs::create_dir_all(dir.join("folder.gb")).unwrap();
+		["b.gb", "A.GBC", "notes.txt", "Zelda.gb", "c.gba"]
+			.iter()
+			.for_each(|name| fs::write(dir.join(name), []).unwrap());
+		let names = list_roms(&dir)
+			.unwrap()
+			.iter()
+			.map(|path| file_name(path))
+			.collect::<Vec<_>>();
+		assert_eq!(names, ["A.GBC", "b.gb", "Zelda.gb"]);
+		fs::remove_dir_all(&dir).unwrap();
+		assert!(list_roms(&dir).is_err());
+	}
+
+	#[test]
+	fn cursor_wraps_around_and_flips_pages() {
+		let mut menu = RomMenu::new(roms(30), vec![]);
+		assert_eq!(menu.pages(), 3);
+		assert_eq!(press(&mut menu, Button::UP), 29);
+		assert_eq!(press(&mut menu, Button::DOWN), 0);
+		assert_eq!(press(&mut menu, Button::DOWN), 1);
+		assert_eq!(press(&mut menu, Button::RIGHT), 1 + PAGE_SIZE);
+		assert_eq!(press(&mut menu, Button::RIGHT), 29);
+		assert_eq!(press(&mut menu, Button::LEFT), 29 - PAGE_SIZE);
+		assert_eq!(press(&mut menu, Button::LEFT), 1);
+		assert_eq!(press(&mut menu, Button::LEFT), 0);
+
+		// held buttons only count once, as are the ones held when the menu opened
+		menu.update(vec![Button::DOWN]);
+		menu.update(vec![Button::DOWN]);
+		assert_eq!(menu.selected, 1);
+		let mut menu = RomMenu::new(roms(30), vec![Button::A, Button::DOWN]);
+		assert!(menu.update(vec![Button::A, Button::DOWN]).is_none());
+		assert_eq!(menu.selected, 0);
+	}
+
+	#[test]
+	fn picks_the_selected_rom_or_cancels() {
+		let mut menu = RomMenu::new(roms(3), vec![]);
+		press(&mut menu, Button::DOWN);
+		assert!(matches!(menu.update(vec![Button::A]), Some(Pick::Rom(path)) if path == roms(3)[1]));
+		menu.update(vec![]);
+		assert!(matches!(
+			menu.update(vec![Button::START]),
+			Some(Pick::Rom(_))
+		));
+		menu.update(vec![]);
+		assert!(matches!(menu.update(vec![Button::B]), Some(Pick::Cancel)));
+
+		// nothing to pick
+		let mut menu = RomMenu::new(vec![], vec![]);
+		assert_eq!(press(&mut menu, Button::DOWN), 0);
+		assert!(menu.update(vec![Button::A]).is_none());
+		assert_eq!(menu.pages(), 1);
+	}
+
+	#[test]
+	fn draws_the_page_of_the_selection() {
+		let draw = |menu: &RomMenu| {
+			let mut buffer = vec![0; WIDTH * HEIGHT];
+			menu.draw(&mut buffer);
+			buffer
+		};
+		let mut long_names = roms(20);
+		long_names.push(PathBuf::from("x".repeat(100) + ".gb"));
+		let mut menu = RomMenu::new(long_names, vec![]);
+		let first_page = draw(&menu);
+		assert!(first_page.iter().any(|&pixel| pixel != 0));
+		press(&mut menu, Button::DOWN);
+		let moved = draw(&menu);
+		assert_ne!(moved, first_page);
+		press(&mut menu, Button::RIGHT);
+		assert_ne!(draw(&menu), moved);
+		assert_ne!(draw(&RomMenu::new(vec![], vec![])), vec![0; WIDTH * HEIGHT]);
+	}
+}
@@ -0,0 +1,223 @@
+use crate::utils::crc32;
+use log::warn;
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+// Battery save (.sav) layout: raw cartridge RAM, optionally followed by the MBC3 RTC state.
+// The RTC footer read & written is the 48 byte one shared by BGB & VBA-M (other emulators
+// mostly read it too), all values little endian:
+// - 5 x u32 : current seconds, minutes, hours, day low, day high/flags
+// - 5 x u32 : latched seconds, minutes, hours, day low, day high/flags
+// - u64     : unix timestamp of when the save got written
+// A 44 byte variant with a u32 timestamp exists as well & is accepted when reading. The footer is
+// detected by the file size exceeding the cartridge RAM size.
+//
+// Saves written here additionally end with an integrity footer (see seal), stripped before the
+// above applies. Files without it (from other emulators or tools) are taken as they are.
+
+const FOOTER_MAGIC: &[u8; 4] = b"RBSV";
+const FOOTER_SIZE: usize = 12;
+// previous saves kept next to the save as .bak1 (most recent) to .bakN
+pub const BACKUPS: usize = 3;
+
+// MBC3 RTC registers, in register order: S, M, H, DL, DH
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RtcState {
+	pub current: [u8; 5],
+	pub latched: [u8; 5],
+	pub timestamp: u64,
+}
+
+// Splits a save file into cartridge RAM & the RTC state, if there is a footer it recognises
+pub fn split(data: &[u8], ram_size: usize) -> (&[u8], Option<RtcState>) {
+	if data.len() <= ram_size {
+		return (data, None);
+	}
+	let (ram, footer) = data.split_at(ram_size);
+	let word = |i: usize| footer[4 * i];
+	let rtc = match footer.len() {
+		44 | 48 => Some(RtcState {
+			current: [word(0), word(1), word(2), word(3), word(4)],
+			latched: [word(5), word(6), word(7), word(8), word(9)],
+			timestamp: match footer.len() {
+				48 => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+				_ => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+			},
+		}),
+		_ => None,
+	};
+	(ram, rtc)
+}
+
+// Save file with given cartridge RAM, followed by the 48 byte RTC footer when `rtc` is given
+pub fn join(ram: &[u8], rtc: Option<&RtcState>) -> Vec<u8> {
+	let mut data = ram.to_vec();
+	if let Some(rtc) = rtc {
+		rtc
+			.current
+			.iter()
+			.chain(rtc.latched.iter())
+			.for_each(|&r| data.extend((r as u32).to_le_bytes()));
+		data.extend(rtc.timestamp.to_le_bytes());
+	}
+	data
+}
+
+// Appends the integrity footer: magic, length & CRC32 of the data (u32 little endian each)
+pub fn seal(data: &[u8]) -> Vec<u8> {
+	let mut sealed = data.to_vec();
+	sealed.extend(FOOTER_MAGIC);
+	sealed.extend((data.len() as u32).to_le_bytes());
+	sealed.extend(crc32(data).to_le_bytes());
+	sealed
+}
+
+// Data without the integrity footer, None if there is a footer it doesn't match
+pub fn unseal(sealed: &[u8]) -> Option<&[u8]> {
+	let Some(split) = sealed.len().checked_sub(FOOTER_SIZE) else {
+		return Some(sealed);
+	};
+	let (data, footer) = sealed.split_at(split);
+	if &footer[0..4] != FOOTER_MAGIC {
+		return Some(sealed);
+	}
+	let word = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap());
+	match word(4) as usize == data.len() && word(8) == crc32(data) {
+		true => Some(data),
+		false => None,
+	}
+}
+
+// Data without the integrity footer whether it matches or not, e.g. for a hex edited save
+pub fn strip(sealed: &[u8]) -> &[u8] {
+	match unseal(sealed) {
+		Some(data) => data,
+		None => &sealed[..sealed.len() - FOOTER_SIZE],
+	}
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(format!(".bak{}", n));
+	name.into()
+}
+
+// Writes a sealed save atomically (temporary file renamed over the save), the save it replaces
+// becomes .bak1 after shifting the older backups down by one
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+	let mut temporary = path.as_os_str().to_owned();
+	temporary.push(".tmp");
+	fs::write(&temporary, seal(data))?;
+	(1..BACKUPS)
+		.rev()
+		.try_for_each(|n| match backup_path(path, n).exists() {
+			true => fs::rename(backup_path(path, n), backup_path(path, n + 1)),
+			false => Ok(()),
+		})?;
+	if path.exists() {
+		fs::rename(path, backup_path(path, 1))?;
+	}
+	fs::rename(&temporary, path)
+}
+
+// Reads a save, falling back to the most recent intact backup when it is corrupted. Only fails
+// when the save itself can't be read.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+	let data = fs::read(path)?;
+	if let Some(data) = unseal(&data) {
+		return Ok(data.to_vec());
+	}
+	warn!("{} is corrupted (checksum mismatch)", path.display());
+	let backup = (1..=BACKUPS).find_map(|n| {
+		let backup = backup_path(path, n);
+		let data = fs::read(&backup).ok()?;
+		unseal(&data).map(|data| (backup, data.to_vec()))
+	});
+	match backup {
+		Some((backup, data)) => {
+			warn!("using backup {} instead", backup.display());
+			Ok(data)
+		}
+		None => {
+			warn!("no intact backup, using it anyway");
+			Ok(strip(&data).to_vec())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{env, process};
+
+	// a save of its own in the temp directory, without any backups left over
+	fn save_path(name: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("rustboy-save-{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join(format!("{}.sav", name));
+		(1..=BACKUPS + 1).for_each(|n| {
+			let _ = fs::remove_file(backup_path(&path, n));
+		});
+		let _ = fs::remove_file(&path);
+		path
+	}
+
+	// flips a bit of the data of a sealed file
+	fn corrupt(path: &Path) {
+		let mut data = fs::read(path).unwrap();
+		data[0] ^= 0x01;
+		fs::write(path, data).unwrap();
+	}
+
+	#[test]
+	fn backups_rotate_most_recent_first() {
+		let path = save_path("rotation");
+		(1..=5).for_each(|n| write(&path, &[n; 16]).unwrap());
+		let contents = |path: &Path| {
+			fs::read(path)
+				.ok()
+				.map(|data| unseal(&data).unwrap().to_vec())
+		};
+		assert_eq!(contents(&path), Some(vec![5; 16]));
+		assert_eq!(
+			(1..=BACKUPS + 1)
+				.map(|n| contents(&backup_path(&path, n)))
+				.collect::<Vec<_>>(),
+			[
+				Some(vec![4; 16]),
+				Some(vec![3; 16]),
+				Some(vec![2; 16]),
+				None
+			]
+		);
+		assert!(!path.with_extension("sav.tmp").exists());
+	}
+
+	#[test]
+	fn crc_mismatch_falls_back_to_the_latest_intact_backup() {
+		let path = save_path("fallback");
+		(1..=4).for_each(|n| write(&path, &[n; 16]).unwrap());
+		assert_eq!(read(&path).unwrap(), [4; 16]);
+
+		corrupt(&path);
+		assert_eq!(read(&path).unwrap(), [3; 16]);
+		corrupt(&backup_path(&path, 1));
+		assert_eq!(read(&path).unwrap(), [2; 16]);
+		// nothing intact left, the save as it is
+		corrupt(&backup_path(&path, 2));
+		corrupt(&backup_path(&path, 3));
+		let mut corrupted = vec![4; 16];
+		corrupted[0] ^= 0x01;
+		assert_eq!(read(&path).unwrap(), corrupted);
+
+		// a foreign save, without the footer
+		fs::write(&path, [7; 16]).unwrap();
+		assert_eq!(read(&path).unwrap(), [7; 16]);
+		assert_eq!(
+			read(&save_path("missing")).unwrap_err().kind(),
+			io::ErrorKind::NotFound
+		);
+	}
+}
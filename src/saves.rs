@@ -0,0 +1,276 @@
+// Game specific decoding of cartridge RAM (a .sav without RTC & integrity footers, see save), for
+// looking into a save & fixing its checksums after hex editing. A codec knows one game's layout,
+// CODECS maps header titles to them.
+pub trait SaveCodec {
+	// shown in the output as "codec"
+	fn name(&self) -> &'static str;
+
+	// One line JSON object, Err if `ram` is too small to hold this game's save
+	fn decode(&self, ram: &[u8]) -> Result<String, String>;
+
+	// Recomputes every checksum in `ram`, returns the names of the ones that didn't match
+	fn fix_checksums(&self, ram: &mut [u8]) -> Vec<&'static str>;
+}
+
+pub const CODECS: &[(&str, &dyn SaveCodec)] = &[
+	("POKEMON RED", &PokemonGen1),
+	("POKEMON BLUE", &PokemonGen1),
+	("POKEMON YELLOW", &PokemonGen1),
+];
+
+// codec for a header title (see header::Header)
+pub fn codec_for(title: &str) -> Option<&'static dyn SaveCodec> {
+	CODECS
+		.iter()
+		.find(|(codec_title, _)| *codec_title == title)
+		.map(|&(_, codec)| codec)
+}
+
+fn json_string(s: &str) -> String {
+	format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Pokémon Red, Blue & Yellow (international releases). Everything decoded lives in bank 1 of the
+// 32 KiB RAM, protected by a single checksum over 0x2598-0x3522 stored at 0x3523: the complement
+// of the 8 bit sum. The box banks (2 & 3) have checksums of their own which aren't covered.
+// Offsets as documented by the community (e.g. Bulbapedia "Save data structure (Generation I)").
+pub struct PokemonGen1;
+
+impl PokemonGen1 {
+	const PLAYER_NAME: usize = 0x2598;
+	const POKEDEX_OWNED: usize = 0x25A3;
+	const POKEDEX_SEEN: usize = 0x25B6;
+	const MONEY: usize = 0x25F3;
+	const RIVAL_NAME: usize = 0x25F6;
+	const BADGES: usize = 0x2602;
+	const PLAYER_ID: usize = 0x2605;
+	const PLAY_TIME: usize = 0x2CED;
+	// count, 6 species + terminator, 6 x 44 byte entries, 6 OT names & 6 nicknames
+	const PARTY: usize = 0x2F2C;
+	const CHECKSUM_START: usize = 0x2598;
+	const CHECKSUM: usize = 0x3523;
+
+	fn checksum(ram: &[u8]) -> u8 {
+		!ram[Self::CHECKSUM_START..Self::CHECKSUM]
+			.iter()
+			.fold(0_u8, |sum, &b| sum.wrapping_add(b))
+	}
+
+	// Names are 11 bytes ended by 0x50, only letters, digits & a few signs are mapped
+	fn text(bytes: &[u8]) -> String {
+		bytes
+			.iter()
+			.take_while(|&&b| b != 0x50)
+			.map(|&b| match b {
+				0x80..=0x99 => (b'A' + b - 0x80) as char,
+				0xA0..=0xB9 => (b'a' + b - 0xA0) as char,
+				0xF6..=0xFF => (b'0' + b - 0xF6) as char,
+				0x7F => ' ',
+				0xE3 => '-',
+				0xE7 => '!',
+				0xE8 => '.',
+				0xF4 => ',',
+				_ => '?',
+			})
+			.collect()
+	}
+
+	fn word(ram: &[u8], address: usize) -> u16 {
+		u16::from_be_bytes([ram[address], ram[address + 1]])
+	}
+
+	// Entry `i` of the party: species index (internal, not the Pokédex number), nickname, level,
+	// HP, moves, OT & experience
+	fn party_member(ram: &[u8], i: usize) -> String {
+		let entry = Self::PARTY + 8 + 44 * i;
+		let ot_name = Self::PARTY + 8 + 44 * 6 + 11 * i;
+		let nickname = Self::PARTY + 8 + 44 * 6 + 11 * 6 + 11 * i;
+		let moves = ram[entry + 0x08..entry + 0x0C]
+			.iter()
+			.filter(|&&m| m != 0)
+			.map(|m| m.to_string())
+			.collect::<Vec<_>>();
+		let exp = ram[entry + 0x0E..entry + 0x11]
+			.iter()
+			.fold(0_u32, |exp, &b| exp << 8 | b as u32);
+		format!(
+			"{{\"species_index\":{},\"nickname\":{},\"level\":{},\"hp\":{},\"max_hp\":{},\"moves\":[{}],\"ot_name\":{},\"ot_id\":{},\"exp\":{}}}",
+			ram[entry],
+			json_string(&Self::text(&ram[nickname..nickname + 11])),
+			ram[entry + 0x21],
+			Self::word(ram, entry + 0x01),
+			Self::word(ram, entry + 0x22),
+			moves.join(","),
+			json_string(&Self::text(&ram[ot_name..ot_name + 11])),
+			Self::word(ram, entry + 0x0C),
+			exp
+		)
+	}
+}
+
+impl SaveCodec for PokemonGen1 {
+	fn name(&self) -> &'static str {
+		"pokemon-gen1"
+	}
+
+	fn decode(&self, ram: &[u8]) -> Result<String, String> {
+		if ram.len() <= Self::CHECKSUM {
+			return Err(format!(
+				"{} bytes of RAM, a Gen 1 save needs 32 KiB",
+				ram.len()
+			));
+		}
+		// money is 6 BCD digits
+		let money = ram[Self::MONEY..Self::MONEY + 3]
+			.iter()
+			.fold(0_u32, |money, &b| {
+				money * 100 + (b >> 4) as u32 * 10 + (b & 0x0F) as u32
+			});
+		let pokedex = |address: usize| -> u32 {
+			ram[address..address + 19]
+				.iter()
+				.map(|b| b.count_ones())
+				.sum()
+		};
+		let party = (0..(ram[Self::PARTY] as usize).min(6))
+			.map(|i| Self::party_member(ram, i))
+			.collect::<Vec<_>>();
+		let (stored, computed) = (ram[Self::CHECKSUM], Self::checksum(ram));
+		let fields = [
+			("codec", json_string(self.name())),
+			(
+				"player_name",
+				json_string(&Self::text(&ram[Self::PLAYER_NAME..Self::PLAYER_NAME + 11])),
+			),
+			(
+				"rival_name",
+				json_string(&Self::text(&ram[Self::RIVAL_NAME..Self::RIVAL_NAME + 11])),
+			),
+			("player_id", Self::word(ram, Self::PLAYER_ID).to_string()),
+			("money", money.to_string()),
+			("badges", ram[Self::BADGES].count_ones().to_string()),
+			("pokedex_owned", pokedex(Self::POKEDEX_OWNED).to_string()),
+			("pokedex_seen", pokedex(Self::POKEDEX_SEEN).to_string()),
+			(
+				"play_time",
+				format!(
+					"\"{}:{:02}:{:02}\"",
+					ram[Self::PLAY_TIME],
+					ram[Self::PLAY_TIME + 2],
+					ram[Self::PLAY_TIME + 3]
+				),
+			),
+			("party", format!("[{}]", party.join(","))),
+			(
+				"checksum",
+				format!(
+					"{{\"stored\":{},\"computed\":{},\"valid\":{}}}",
+					stored,
+					computed,
+					stored == computed
+				),
+			),
+		];
+		let fields = fields
+			.iter()
+			.map(|(key, value)| format!("\"{}\":{}", key, value))
+			.collect::<Vec<_>>();
+		Ok(format!("{{{}}}", fields.join(",")))
+	}
+
+	fn fix_checksums(&self, ram: &mut [u8]) -> Vec<&'static str> {
+		if ram.len() <= Self::CHECKSUM {
+			return Vec::new();
+		}
+		let computed = Self::checksum(ram);
+		match ram[Self::CHECKSUM] == computed {
+			true => Vec::new(),
+			false => {
+				ram[Self::CHECKSUM] = computed;
+				vec!["main"]
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// 0x80-based letters, ended by 0x50
+	fn name(text: &str) -> Vec<u8> {
+		text
+			.bytes()
+			.map(|b| b - b'A' + 0x80)
+			.chain([0x50])
+			.collect()
+	}
+
+	// RAM of a Gen 1 save: RED with 12345 money, 3 badges & a level 5 BULBASAUR, checksum 0
+	fn ram() -> Vec<u8> {
+		let mut ram = vec![0; 0x8000];
+		let mut put =
+			|address: usize, bytes: &[u8]| ram[address..address + bytes.len()].copy_from_slice(bytes);
+		put(PokemonGen1::PLAYER_NAME, &name("RED"));
+		put(PokemonGen1::RIVAL_NAME, &name("BLUE"));
+		put(PokemonGen1::PLAYER_ID, &[0x12, 0x34]);
+		put(PokemonGen1::MONEY, &[0x01, 0x23, 0x45]);
+		put(PokemonGen1::BADGES, &[0b0000_0111]);
+		put(PokemonGen1::POKEDEX_OWNED, &[0x0F]);
+		put(PokemonGen1::POKEDEX_SEEN, &[0xFF, 0x01]);
+		put(PokemonGen1::PLAY_TIME, &[12, 0, 34, 56]);
+		let party = PokemonGen1::PARTY;
+		put(party, &[1, 0x99, 0xFF]);
+		// species, HP, then moves, OT ID & experience, then level & max HP
+		put(party + 8, &[0x99, 0x00, 0x15]);
+		put(party + 8 + 0x08, &[0x21, 0x2D]);
+		put(party + 8 + 0x0C, &[0x12, 0x34, 0x00, 0x01, 0x00]);
+		put(party + 8 + 0x21, &[5, 0x00, 0x15]);
+		put(party + 8 + 44 * 6, &name("RED"));
+		put(party + 8 + 44 * 6 + 11 * 6, &name("BULBASAUR"));
+		ram
+	}
+
+	fn json(stored: u8) -> String {
+		format!(
+			concat!(
+				r#"{{"codec":"pokemon-gen1","player_name":"RED","rival_name":"BLUE","player_id":4660,"#,
+				r#""money":12345,"badges":3,"pokedex_owned":4,"pokedex_seen":9,"play_time":"12:34:56","#,
+				r#""party":[{{"species_index":153,"nickname":"BULBASAUR","level":5,"hp":21,"max_hp":21,"#,
+				r#""moves":[33,45],"ot_name":"RED","ot_id":4660,"exp":256}}],"#,
+				r#""checksum":{{"stored":{},"computed":114,"valid":{}}}}}"#
+			),
+			stored,
+			stored == 114
+		)
+	}
+
+	#[test]
+	fn gen1_save_is_decoded() {
+		let codec = codec_for("POKEMON RED").unwrap();
+		assert_eq!(codec.name(), "pokemon-gen1");
+		assert!(codec_for("TETRIS").is_none());
+		assert_eq!(codec.decode(&ram()), Ok(json(0)));
+		assert_eq!(
+			codec.decode(&ram()[..0x2000]),
+			Err("8192 bytes of RAM, a Gen 1 save needs 32 KiB".to_string())
+		);
+	}
+
+	#[test]
+	fn gen1_checksum_is_repaired() {
+		let mut ram = ram();
+		assert_eq!(PokemonGen1.fix_checksums(&mut ram), ["main"]);
+		assert_eq!(ram[PokemonGen1::CHECKSUM], 114);
+		assert_eq!(PokemonGen1.decode(&ram), Ok(json(114)));
+		assert!(PokemonGen1.fix_checksums(&mut ram).is_empty());
+
+		// hex edited money, outside the checksum nothing matters
+		ram[PokemonGen1::MONEY] = 0x99;
+		ram[0x4000] = 0xFF;
+		assert_eq!(PokemonGen1.fix_checksums(&mut ram), ["main"]);
+		assert_eq!(ram[PokemonGen1::CHECKSUM], 114u8.wrapping_sub(0x98));
+		assert!(PokemonGen1.fix_checksums(&mut ram).is_empty());
+		assert!(PokemonGen1.fix_checksums(&mut ram[..0x2000]).is_empty());
+	}
+}
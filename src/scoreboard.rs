@@ -0,0 +1,129 @@
+// Result of a single test rom, as reported by every test harness
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestOutcome {
+	pub suite: String,
+	pub name: String,
+	pub passed: bool,
+	// what went wrong, or anything worth showing for a pass (e.g. a hash), a line per entry
+	pub details: Vec<String>,
+}
+
+fn passed_count(outcomes: &[TestOutcome]) -> usize {
+	outcomes.iter().filter(|o| o.passed).count()
+}
+
+// Table with a row per rom, suites in the order they first appear, followed by the totals
+pub fn markdown(outcomes: &[TestOutcome]) -> String {
+	let mut table = String::from("| suite | rom | result |\n|---|---|---|\n");
+	outcomes.iter().for_each(|o| {
+		table += &format!(
+			"| {} | {} | {} |\n",
+			o.suite,
+			o.name,
+			match o.passed {
+				true => "pass",
+				false => "FAIL",
+			}
+		);
+	});
+	table += &format!("\n{}/{} passed\n", passed_count(outcomes), outcomes.len());
+	table
+}
+
+// quoted & escaped, details come from test roms & may hold anything
+fn json_string(s: &str) -> String {
+	let mut quoted = String::from("\"");
+	s.chars().for_each(|c| match c {
+		'"' => quoted += "\\\"",
+		'\\' => quoted += "\\\\",
+		'\n' => quoted += "\\n",
+		'\r' => quoted += "\\r",
+		'\t' => quoted += "\\t",
+		c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+		c => quoted.push(c),
+	});
+	quoted + "\""
+}
+
+pub fn json(outcomes: &[TestOutcome]) -> String {
+	let entries = outcomes
+		.iter()
+		.map(|o| {
+			format!(
+				"{{\"suite\":{},\"rom\":{},\"passed\":{},\"details\":[{}]}}",
+				json_string(&o.suite),
+				json_string(&o.name),
+				o.passed,
+				o.details
+					.iter()
+					.map(|d| json_string(d))
+					.collect::<Vec<_>>()
+					.join(",")
+			)
+		})
+		.collect::<Vec<_>>();
+	format!(
+		"{{\"passed\":{},\"total\":{},\"results\":[{}]}}",
+		passed_count(outcomes),
+		outcomes.len(),
+		entries.join(",")
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn outcomes() -> Vec<TestOutcome> {
+		let outcome = |suite: &str, name: &str, passed, details: &[&str]| TestOutcome {
+			suite: suite.to_string(),
+			name: name.to_string(),
+			passed,
+			details: details.iter().map(|d| d.to_string()).collect(),
+		};
+		vec![
+			outcome("blargg", "cpu_instrs", true, &[]),
+			outcome(
+				"blargg",
+				"halt_bug",
+				false,
+				&["Failed #2\nat $C3F0", "\"quoted\"\t\\"],
+			),
+			outcome("mooneye", "div_timing", false, &["bell\u{7}"]),
+		]
+	}
+
+	#[test]
+	fn markdown_has_a_row_per_rom_and_the_totals() {
+		assert_eq!(
+			markdown(&outcomes()),
+			"| suite | rom | result |\n\
+			|---|---|---|\n\
+			| blargg | cpu_instrs | pass |\n\
+			| blargg | halt_bug | FAIL |\n\
+			| mooneye | div_timing | FAIL |\n\
+			\n\
+			1/3 passed\n"
+		);
+		assert_eq!(
+			markdown(&[]),
+			"| suite | rom | result |\n|---|---|---|\n\n0/0 passed\n"
+		);
+	}
+
+	#[test]
+	fn json_escapes_the_details() {
+		assert_eq!(
+			json(&outcomes()),
+			concat!(
+				r#"{"passed":1,"total":3,"results":["#,
+				r#"{"suite":"blargg","rom":"cpu_instrs","passed":true,"details":[]},"#,
+				r#"{"suite":"blargg","rom":"halt_bug","passed":false,"#,
+				r#""details":["Failed #2\nat $C3F0","\"quoted\"\t\\"]},"#,
+				r#"{"suite":"mooneye","rom":"div_timing","passed":false,"details":["bell\u0007"]}"#,
+				"]}"
+			)
+		);
+		assert_eq!(json(&[]), r#"{"passed":0,"total":0,"results":[]}"#);
+	}
+}
@@ -0,0 +1,510 @@
+use crate::{
+	gameboy::{Bus, CPU, GameBoy, HookAction, Snapshot},
+	joypad::Button,
+};
+use log::error;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, FnPtr, Position, Scope};
+use std::{
+	collections::HashMap,
+	sync::{
+		Arc, Mutex, Weak,
+		atomic::{AtomicU64, Ordering},
+	},
+};
+
+// frames an osd() message stays on screen
+const OSD_FRAMES: u32 = 120;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// What a hook callback works on: the GameBoy is busy executing the hooked instruction, so
+// copies of the CPU & memory are handed out & changes are carried over once the callback returns
+struct HookContext {
+	cpu: CPU,
+	bus: Box<dyn Bus>,
+	writes: Vec<(u16, u8)>,
+}
+
+// memory breakpoint, checked at every frame boundary
+struct Watch {
+	address: u16,
+	value: u8,
+	callback: FnPtr,
+}
+
+// state shared between the script's functions & the Script driving them
+#[derive(Default)]
+struct Host {
+	// only there while the script runs (top level, on_frame or a callback)
+	gameboy: Option<GameBoy>,
+	hook: Option<HookContext>,
+	engine: Weak<Engine>,
+	ast: Weak<AST>,
+	held: Vec<Button>,
+	states: HashMap<i64, Snapshot>,
+	watches: Vec<Watch>,
+	osd: Option<(String, u32)>,
+	exit_code: Option<i64>,
+	error: Option<String>,
+}
+
+// Rhai script driving the emulator. Functions available to scripts:
+// - read(address), write(address, value): memory as seen by the CPU
+// - reg(name), set_reg(name, value): registers a, bc, de, hl, sp & pc
+// - press(button), release(button): a, b, select, start, right, left, up & down, held until
+//   released (on top of whatever the frontend holds)
+// - frame(n): runs n frames, not from within a hook
+// - hook(address, callback), hook_label(label, callback): callback runs before the instruction at
+//   the address is executed, returning nothing carries on, "skip" skips the instruction & a
+//   number returns from the (CALLed) routine with A set to it. hook_label is false without such
+//   a label in the symbols.
+// - watch(address, callback): callback(old, new) runs at the frame boundary after the byte
+//   changed
+// - save_state(slot), load_state(slot): in memory snapshots, load_state is false for an empty slot
+// - osd(text): message shown on screen for a couple of seconds, print() goes to stdout
+// - exit(code): stops the script, headless runs exit with the code
+// An on_frame() function, if defined, is called at every frame boundary the frontend runs.
+// Hooks & watches can't be added from within a hook callback.
+pub struct Script {
+	engine: Arc<Engine>,
+	ast: Arc<AST>,
+	scope: Scope<'static>,
+	host: Arc<Mutex<Host>>,
+	// frames completed since the last on_frame, counted by a frame observer (see observe) so the
+	// ones run by frame(n) age the osd() message too
+	frames: Arc<AtomicU64>,
+}
+
+fn button(name: &str) -> ScriptResult<Button> {
+	Button::find(name).ok_or_else(|| format!("unknown button: {}", name).into())
+}
+
+fn register(cpu: &CPU, name: &str) -> ScriptResult<i64> {
+	Ok(match name {
+		"a" => cpu.a() as i64,
+		"bc" => cpu.bc() as i64,
+		"de" => cpu.de() as i64,
+		"hl" => cpu.hl() as i64,
+		"sp" => cpu.sp() as i64,
+		"pc" => cpu.pc() as i64,
+		_ => return Err(format!("unknown register: {}", name).into()),
+	})
+}
+
+fn set_register(cpu: &mut CPU, name: &str, value: i64) -> ScriptResult<()> {
+	match name {
+		"a" => cpu.set_a(value as u8),
+		"bc" => cpu.set_bc(value as u16),
+		"de" => cpu.set_de(value as u16),
+		"hl" => cpu.set_hl(value as u16),
+		"sp" => cpu.set_sp(value as u16),
+		"pc" => cpu.set_pc(value as u16),
+		_ => return Err(format!("unknown register: {}", name).into()),
+	};
+	Ok(())
+}
+
+fn no_gameboy() -> Box<EvalAltResult> {
+	"the emulator isn't available from within a hook".into()
+}
+
+impl Host {
+	fn gameboy(&mut self) -> ScriptResult<&mut GameBoy> {
+		self.gameboy.as_mut().ok_or_else(no_gameboy)
+	}
+
+	// exit() ends the script, anything else is reported & remembered
+	fn record(&mut self, result: ScriptResult<Dynamic>, what: &str) -> Option<Dynamic> {
+		match result {
+			Ok(value) => Some(value),
+			Err(e) => {
+				match *e {
+					EvalAltResult::Exit(value, _) => {
+						self.exit_code.get_or_insert(value.as_int().unwrap_or(0));
+					}
+					e => {
+						error!("script {} failed: {}", what, e);
+						self.error.get_or_insert(e.to_string());
+					}
+				};
+				None
+			}
+		}
+	}
+}
+
+// Calls a script callback from outside of the script (a hook or a watch), the host mustn't be
+// locked meanwhile
+fn call_back(
+	host: &Arc<Mutex<Host>>,
+	callback: &FnPtr,
+	args: Vec<Dynamic>,
+	what: &str,
+) -> Option<Dynamic> {
+	let (engine, ast) = {
+		let host = host.lock().unwrap();
+		(host.engine.upgrade()?, host.ast.upgrade()?)
+	};
+	let result = callback.call::<Dynamic>(&engine, &ast, args);
+	host.lock().unwrap().record(result, what)
+}
+
+fn add_hook(host: &Arc<Mutex<Host>>, address: u16, callback: FnPtr) -> ScriptResult<()> {
+	let shared = Arc::downgrade(host);
+	let hook = Box::new(move |cpu: &mut CPU, bus: &mut dyn Bus| {
+		let Some(host) = shared.upgrade() else {
+			return HookAction::Continue;
+		};
+		host.lock().unwrap().hook = Some(HookContext {
+			cpu: cpu.clone(),
+			bus: bus.box_clone(),
+			writes: Vec::new(),
+		});
+		let what = format!("hook at {:04X}", address);
+		let value = call_back(&host, &callback, Vec::new(), &what);
+		let Some(context) = host.lock().unwrap().hook.take() else {
+			return HookAction::Continue;
+		};
+		*cpu = context.cpu;
+		context
+			.writes
+			.iter()
+			.for_each(|&(address, value)| bus.write_byte(address, value));
+		match value {
+			Some(value) if value.is_int() => {
+				HookAction::ReturnFromCall(value.as_int().unwrap_or(0) as u8)
+			}
+			Some(value) if value.clone().into_string().is_ok_and(|s| s == "skip") => {
+				HookAction::SkipInstruction
+			}
+			_ => HookAction::Continue,
+		}
+	});
+	host.lock().unwrap().gameboy()?.add_pc_hook(address, hook);
+	Ok(())
+}
+
+// calls back the watches whose byte changed since the last check
+fn check_watches(host: &Arc<Mutex<Host>>) -> ScriptResult<()> {
+	let changed = {
+		let mut host = host.lock().unwrap();
+		let Host {
+			gameboy, watches, ..
+		} = &mut *host;
+		let gameboy = gameboy.as_ref().ok_or_else(no_gameboy)?;
+		watches
+			.iter_mut()
+			.filter_map(|watch| {
+				let value = gameboy.read_byte(watch.address);
+				let old = std::mem::replace(&mut watch.value, value);
+				(old != value).then(|| (watch.callback.clone(), watch.address, old, value))
+			})
+			.collect::<Vec<_>>()
+	};
+	changed
+		.into_iter()
+		.for_each(|(callback, address, old, new)| {
+			let args = vec![Dynamic::from_int(old as i64), Dynamic::from_int(new as i64)];
+			call_back(host, &callback, args, &format!("watch at {:04X}", address));
+		});
+	Ok(())
+}
+
+impl Script {
+	pub fn compile(source: &str) -> Result<Self, String> {
+		let host = Arc::new(Mutex::new(Host::default()));
+		let mut engine = Engine::new();
+
+		let h = host.clone();
+		engine.register_fn("read", move |address: i64| -> ScriptResult<i64> {
+			let mut host = h.lock().unwrap();
+			Ok(match &host.hook {
+				Some(context) => context.bus.read_byte(address as u16),
+				None => host.gameboy()?.read_byte(address as u16),
+			} as i64)
+		});
+		let h = host.clone();
+		engine.register_fn(
+			"write",
+			move |address: i64, value: i64| -> ScriptResult<()> {
+				let (address, value) = (address as u16, value as u8);
+				let mut host = h.lock().unwrap();
+				match &mut host.hook {
+					Some(context) => {
+						context.bus.write_byte(address, value);
+						context.writes.push((address, value));
+					}
+					None => host.gameboy()?.write_byte(address, value),
+				};
+				Ok(())
+			},
+		);
+		let h = host.clone();
+		engine.register_fn("reg", move |name: &str| -> ScriptResult<i64> {
+			let mut host = h.lock().unwrap();
+			match &host.hook {
+				Some(context) => register(&context.cpu, name),
+				None => register(host.gameboy()?.cpu(), name),
+			}
+		});
+		let h = host.clone();
+		engine.register_fn(
+			"set_reg",
+			move |name: &str, value: i64| -> ScriptResult<()> {
+				let mut host = h.lock().unwrap();
+				match &mut host.hook {
+					Some(context) => set_register(&mut context.cpu, name, value),
+					None => set_register(host.gameboy()?.cpu_mut(), name, value),
+				}
+			},
+		);
+		let h = host.clone();
+		engine.register_fn("press", move |name: &str| -> ScriptResult<()> {
+			let button = button(name)?;
+			let mut host = h.lock().unwrap();
+			if !host.held.contains(&button) {
+				host.held.push(button);
+			}
+			Ok(())
+		});
+		let h = host.clone();
+		engine.register_fn("release", move |name: &str| -> ScriptResult<()> {
+			let button = button(name)?;
+			h.lock().unwrap().held.retain(|&b| b != button);
+			Ok(())
+		});
+		let h = host.clone();
+		engine.register_fn("frame", move |frames: i64| -> ScriptResult<()> {
+			for _ in 0..frames {
+				// hooks run meanwhile, so the host isn't to be locked
+				let mut gameboy = h.lock().unwrap().gameboy.take().ok_or_else(no_gameboy)?;
+				gameboy.set_input(h.lock().unwrap().held.clone());
+				gameboy.run_frame();
+				h.lock().unwrap().gameboy = Some(gameboy);
+				check_watches(&h)?;
+				// exit() or a failure in a callback ends the script as well
+				let done = {
+					let host = h.lock().unwrap();
+					host.exit_code.is_some() || host.error.is_some()
+				};
+				if done {
+					return Err(EvalAltResult::Exit(Dynamic::UNIT, Position::NONE).into());
+				}
+			}
+			Ok(())
+		});
+		let h = host.clone();
+		engine.register_fn("hook", move |address: i64, callback: FnPtr| {
+			add_hook(&h, address as u16, callback)
+		});
+		let h = host.clone();
+		engine.register_fn("hook_label", move |label: &str, callback: FnPtr| {
+			let address = h.lock().unwrap().gameboy()?.cpu().symbol_address(label);
+			match address {
+				Some(address) => add_hook(&h, address, callback).map(|_| true),
+				None => Ok(false),
+			}
+		});
+		let h = host.clone();
+		engine.register_fn(
+			"watch",
+			move |address: i64, callback: FnPtr| -> ScriptResult<()> {
+				let mut host = h.lock().unwrap();
+				let value = host.gameboy()?.read_byte(address as u16);
+				host.watches.push(Watch {
+					address: address as u16,
+					value,
+					callback,
+				});
+				Ok(())
+			},
+		);
+		let h = host.clone();
+		engine.register_fn("save_state", move |slot: i64| -> ScriptResult<()> {
+			let mut host = h.lock().unwrap();
+			let snapshot = host.gameboy()?.snapshot();
+			host.states.insert(slot, snapshot);
+			Ok(())
+		});
+		let h = host.clone();
+		engine.register_fn("load_state", move |slot: i64| -> ScriptResult<bool> {
+			let mut host = h.lock().unwrap();
+			let Some(snapshot) = host.states.get(&slot).cloned() else {
+				return Ok(false);
+			};
+			host.gameboy()?.restore(&snapshot);
+			Ok(true)
+		});
+		// the built-in exit() only ends the top level, so it's replaced to end the whole script
+		let h = host.clone();
+		engine.register_fn("exit", move |code: i64| -> ScriptResult<()> {
+			h.lock().unwrap().exit_code.get_or_insert(code);
+			Err(EvalAltResult::Exit(code.into(), Position::NONE).into())
+		});
+		let h = host.clone();
+		engine.register_fn("exit", move || -> ScriptResult<()> {
+			h.lock().unwrap().exit_code.get_or_insert(0);
+			Err(EvalAltResult::Exit(0.into(), Position::NONE).into())
+		});
+		let h = host.clone();
+		engine.register_fn("osd", move |text: &str| {
+			h.lock().unwrap().osd = Some((text.to_string(), OSD_FRAMES));
+		});
+
+		let ast = Arc::new(engine.compile(source).map_err(|e| e.to_string())?);
+		let engine = Arc::new(engine);
+		{
+			let mut shared = host.lock().unwrap();
+			shared.engine = Arc::downgrade(&engine);
+			shared.ast = Arc::downgrade(&ast);
+		}
+		Ok(Script {
+			engine,
+			ast,
+			scope: Scope::new(),
+			host,
+			frames: Arc::new(AtomicU64::new(0)),
+		})
+	}
+
+	// Has the frames completed by `gameboy` counted, needed for every GameBoy the script runs on
+	pub fn observe(&self, gameboy: &mut GameBoy) {
+		let frames = self.frames.clone();
+		gameboy.add_frame_observer(Box::new(move |_, _| {
+			frames.fetch_add(1, Ordering::Relaxed);
+		}));
+	}
+
+	// runs `f` with the GameBoy handed over to the script's functions
+	fn with_gameboy(
+		&mut self,
+		gameboy: GameBoy,
+		what: &str,
+		f: impl FnOnce(&mut Self) -> ScriptResult<Dynamic>,
+	) -> GameBoy {
+		self.host.lock().unwrap().gameboy = Some(gameboy);
+		let result = f(self);
+		let mut host = self.host.lock().unwrap();
+		host.record(result, what);
+		host.gameboy.take().expect("GameBoy taken by the script")
+	}
+
+	// Runs the top level statements, which may drive emulation themselves (e.g. frame(60)) &
+	// set up hooks & watches
+	pub fn start(&mut self, gameboy: GameBoy) -> GameBoy {
+		self.with_gameboy(gameboy, "top level", |script| {
+			script
+				.engine
+				.run_ast_with_scope(&mut script.scope, &script.ast)
+				.map(|_| Dynamic::UNIT)
+		})
+	}
+
+	// Meant to be called after every frame the frontend runs: checks the watches & calls the
+	// script's on_frame(), if any
+	pub fn on_frame(&mut self, gameboy: GameBoy) -> GameBoy {
+		if self.is_done() {
+			return gameboy;
+		}
+		let has_on_frame = self
+			.ast
+			.iter_functions()
+			.any(|f| f.name == "on_frame" && f.params.is_empty());
+		let frames = self.frames.swap(0, Ordering::Relaxed);
+		self.with_gameboy(gameboy, "on_frame", |script| {
+			{
+				let mut host = script.host.lock().unwrap();
+				if let Some((_, left)) = &mut host.osd {
+					*left = left.saturating_sub(frames as u32);
+					if *left == 0 {
+						host.osd = None;
+					}
+				}
+			}
+			check_watches(&script.host)?;
+			match has_on_frame {
+				true => script
+					.engine
+					.call_fn::<Dynamic>(&mut script.scope, &script.ast, "on_frame", ()),
+				false => Ok(Dynamic::UNIT),
+			}
+		})
+	}
+
+	// Runs without a frontend: top level first, then frames until the script calls exit() (or
+	// fails), returns the GameBoy & exit code
+	pub fn run_headless(&mut self, mut gameboy: GameBoy) -> (GameBoy, i64) {
+		self.observe(&mut gameboy);
+		gameboy = self.start(gameboy);
+		while !self.is_done() {
+			gameboy.set_input(self.held());
+			gameboy.run_frame();
+			gameboy = self.on_frame(gameboy);
+		}
+		(gameboy, self.exit_code().unwrap_or(0))
+	}
+
+	// buttons the script holds
+	pub fn held(&self) -> Vec<Button> {
+		self.host.lock().unwrap().held.clone()
+	}
+
+	pub fn osd(&self) -> Option<String> {
+		self
+			.host
+			.lock()
+			.unwrap()
+			.osd
+			.as_ref()
+			.map(|(text, _)| text.clone())
+	}
+
+	// the script called exit() or failed
+	pub fn is_done(&self) -> bool {
+		let host = self.host.lock().unwrap();
+		host.exit_code.is_some() || host.error.is_some()
+	}
+
+	// code passed to exit(), 1 if the script failed
+	pub fn exit_code(&self) -> Option<i64> {
+		let host = self.host.lock().unwrap();
+		host.error.as_ref().map(|_| 1).or(host.exit_code)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::selftest;
+
+	// `source` run headless on the built-in test rom, the script & its exit code
+	fn run(source: &str) -> (Script, i64) {
+		let mut script = Script::compile(source).unwrap();
+		let (_, exit_code) = script.run_headless(GameBoy::from_bytes(selftest::rom()));
+		(script, exit_code)
+	}
+
+	#[test]
+	fn input_bot_passes() {
+		let (script, exit_code) = run(include_str!("../scripts/input_bot.rhai"));
+		assert_eq!(exit_code, 0);
+		assert!(script.held().is_empty());
+	}
+
+	#[test]
+	fn ram_watch_shows_the_latest_change() {
+		let source = include_str!("../scripts/ram_watch.rhai").to_string()
+			+ "write(0xC001, 0x42); frame(1); write(0xC000, 0x17); frame(1); exit(3);";
+		let (script, exit_code) = run(&source);
+		assert_eq!(exit_code, 3);
+		assert_eq!(script.osd(), Some("c000: 0 -> 17".to_string()));
+	}
+
+	#[test]
+	fn failing_script_exits_with_1() {
+		let (script, exit_code) = run("frame(2); press(\"turbo\");");
+		assert_eq!(exit_code, 1);
+		assert!(script.is_done());
+		assert_eq!(run("frame(1); exit();").1, 0);
+	}
+}
@@ -0,0 +1,121 @@
+use crate::menu::Overlay;
+#[cfg(feature = "scripting")]
+use rustboy::script::Script;
+use rustboy::{
+	HEIGHT, gameboy::GameBoy, joypad::Button, options::EmulatorOptions, text::draw_text,
+};
+#[cfg(feature = "scripting")]
+use std::fs;
+use std::process;
+
+// --script for the frontend, built without the scripting feature it only refuses to run one
+pub struct Runner {
+	#[cfg(feature = "scripting")]
+	script: Option<Script>,
+}
+
+// osd() message of the script, drawn along the bottom of the screen
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct Osd(String);
+
+impl Overlay for Osd {
+	fn draw(&self, buffer: &mut [u32]) {
+		draw_text(
+			buffer,
+			4,
+			HEIGHT - 10,
+			&self.0.to_ascii_uppercase(),
+			0x00FFFFFF,
+		);
+	}
+}
+
+#[cfg(feature = "scripting")]
+impl Runner {
+	// exits if the script can't be read or compiled
+	pub fn load(options: &EmulatorOptions) -> Self {
+		let script = options.script.as_ref().map(|path| {
+			fs::read_to_string(path)
+				.map_err(|e| e.to_string())
+				.and_then(|source| Script::compile(&source))
+				.unwrap_or_else(|e| {
+					eprintln!("unable to load {}: {}", path.display(), e);
+					process::exit(1);
+				})
+		});
+		Runner { script }
+	}
+
+	pub fn start(&mut self, gameboy: GameBoy) -> GameBoy {
+		match &mut self.script {
+			Some(script) => script.start(gameboy),
+			None => gameboy,
+		}
+	}
+
+	pub fn observe(&self, gameboy: &mut GameBoy) {
+		if let Some(script) = &self.script {
+			script.observe(gameboy);
+		}
+	}
+
+	pub fn on_frame(&mut self, gameboy: GameBoy) -> GameBoy {
+		match &mut self.script {
+			Some(script) => script.on_frame(gameboy),
+			None => gameboy,
+		}
+	}
+
+	pub fn held(&self) -> Vec<Button> {
+		self.script.as_ref().map(Script::held).unwrap_or_default()
+	}
+
+	pub fn osd(&self) -> Option<Osd> {
+		self.script.as_ref()?.osd().map(Osd)
+	}
+
+	// Runs the script without a window (see Script::run_headless), the process exits with the
+	// script's exit code
+	pub fn run_headless(&mut self, gameboy: GameBoy) -> ! {
+		let Some(script) = &mut self.script else {
+			eprintln!("--headless needs a --script");
+			process::exit(1);
+		};
+		let (_, exit_code) = script.run_headless(gameboy);
+		process::exit(exit_code as i32);
+	}
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Runner {
+	pub fn load(options: &EmulatorOptions) -> Self {
+		if options.script.is_some() {
+			eprintln!("--script needs rustboy built with the scripting feature");
+			process::exit(1);
+		}
+		Runner {}
+	}
+
+	pub fn start(&mut self, gameboy: GameBoy) -> GameBoy {
+		gameboy
+	}
+
+	pub fn observe(&self, _: &mut GameBoy) {}
+
+	pub fn on_frame(&mut self, gameboy: GameBoy) -> GameBoy {
+		gameboy
+	}
+
+	pub fn held(&self) -> Vec<Button> {
+		Vec::new()
+	}
+
+	pub fn osd(&self) -> Option<Osd> {
+		None
+	}
+
+	pub fn run_headless(&mut self, _: GameBoy) -> ! {
+		eprintln!("--headless needs a --script");
+		process::exit(1);
+	}
+}
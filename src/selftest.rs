@@ -0,0 +1,143 @@
+use crate::{
+	frametiming::CYCLES_PER_FRAME,
+	gameboy::{GameBoy, RunOutcome},
+	scoreboard::TestOutcome,
+	utils::hash_frame,
+};
+
+// Hand assembled program placed at 0x0150, it draws a sprite & the window over a blank
+// background, checks a few ALU results and that TIMA is counting, then prints PASS/FAIL
+// over serial and spins forever.
+#[rustfmt::skip]
+const PROGRAM: [u8; 0x7F] = [
+	0x31, 0xFE, 0xFF,       // 0150: LD SP, $FFFE
+	0x21, 0x10, 0x80,       // 0153: LD HL, $8010       ; tile 1 = all color 3
+	0x06, 0x10,             // 0156: LD B, $10
+	0x3E, 0xFF,             // 0158: LD A, $FF          ; tile_loop
+	0x22,                   // 015A: LD (HL+), A
+	0x05,                   // 015B: DEC B
+	0x20, 0xFA,             // 015C: JR NZ, tile_loop
+	0x21, 0x00, 0x9C,       // 015E: LD HL, $9C00       ; window map = all tile 1
+	0x01, 0x00, 0x04,       // 0161: LD BC, $0400
+	0x3E, 0x01,             // 0164: LD A, $01          ; map_loop
+	0x22,                   // 0166: LD (HL+), A
+	0x0B,                   // 0167: DEC BC
+	0x78,                   // 0168: LD A, B
+	0xB1,                   // 0169: OR C
+	0x20, 0xF8,             // 016A: JR NZ, map_loop
+	0x21, 0x00, 0xFE,       // 016C: LD HL, $FE00       ; sprite 0 at (40, 40) using tile 1
+	0x3E, 0x28,             // 016F: LD A, $28
+	0x22,                   // 0171: LD (HL+), A
+	0x22,                   // 0172: LD (HL+), A
+	0x3E, 0x01,             // 0173: LD A, $01
+	0x22,                   // 0175: LD (HL+), A
+	0xAF,                   // 0176: XOR A
+	0x22,                   // 0177: LD (HL+), A
+	0x3E, 0xE4,             // 0178: LD A, $E4          ; OBP0 = identity
+	0xE0, 0x48,             // 017A: LDH ($48), A
+	0x3E, 0x48,             // 017C: LD A, $48          ; WY = 72
+	0xE0, 0x4A,             // 017E: LDH ($4A), A
+	0x3E, 0x57,             // 0180: LD A, $57          ; WX = 87
+	0xE0, 0x4B,             // 0182: LDH ($4B), A
+	0x3E, 0xF3,             // 0184: LD A, $F3          ; LCD, window & sprites on
+	0xE0, 0x40,             // 0186: LDH ($40), A
+	0x3E, 0x12,             // 0188: LD A, $12
+	0xC6, 0x34,             // 018A: ADD A, $34
+	0xFE, 0x46,             // 018C: CP $46
+	0x20, 0x1C,             // 018E: JR NZ, fail
+	0xCB, 0x37,             // 0190: SWAP A
+	0xFE, 0x64,             // 0192: CP $64
+	0x20, 0x16,             // 0194: JR NZ, fail
+	0xAF,                   // 0196: XOR A              ; TIMA = 0, TAC = 16 cycles
+	0xE0, 0x05,             // 0197: LDH ($05), A
+	0x3E, 0x05,             // 0199: LD A, $05
+	0xE0, 0x07,             // 019B: LDH ($07), A
+	0x06, 0x40,             // 019D: LD B, $40
+	0x05,                   // 019F: DEC B              ; wait
+	0x20, 0xFD,             // 01A0: JR NZ, wait
+	0xF0, 0x05,             // 01A2: LDH A, ($05)
+	0xB7,                   // 01A4: OR A
+	0x28, 0x05,             // 01A5: JR Z, fail
+	0x21, 0xC3, 0x01,       // 01A7: LD HL, pass_msg
+	0x18, 0x03,             // 01AA: JR print
+	0x21, 0xC9, 0x01,       // 01AC: LD HL, fail_msg    ; fail
+	0x2A,                   // 01AF: LD A, (HL+)        ; print
+	0xB7,                   // 01B0: OR A
+	0x28, 0x0E,             // 01B1: JR Z, done
+	0xE0, 0x01,             // 01B3: LDH ($01), A
+	0x3E, 0x81,             // 01B5: LD A, $81
+	0xE0, 0x02,             // 01B7: LDH ($02), A
+	0xF0, 0x02,             // 01B9: LDH A, ($02)       ; serial_wait
+	0xCB, 0x7F,             // 01BB: BIT 7, A
+	0x20, 0xFA,             // 01BD: JR NZ, serial_wait
+	0x18, 0xEE,             // 01BF: JR print
+	0x18, 0xFE,             // 01C1: JR done            ; done
+	b'P', b'A', b'S', b'S', b'\n', 0x00, // 01C3: pass_msg
+	b'F', b'A', b'I', b'L', b'\n', 0x00, // 01C9: fail_msg
+];
+
+const FRAMES: usize = 30;
+
+// Hash of the frame buffer after FRAMES frames. To regenerate after an intended change in
+// rendering (e.g. palette colors), run `rustboy --selftest` and copy the printed hash here.
+const EXPECTED_FRAME_HASH: u64 = 0x3C8AC5F00E22BF25;
+
+pub fn rom() -> Vec<u8> {
+	let mut rom = vec![0_u8; 0x8000];
+	rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+	rom[0x0134..0x013C].copy_from_slice(b"SELFTEST");
+	rom[0x014D] = 0x7D; // header checksum
+	rom[0x0150..0x0150 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+	rom
+}
+
+pub fn outcome() -> TestOutcome {
+	let mut gameboy = GameBoy::from_bytes(rom());
+	let mut violations = Vec::new();
+	let mut frame_cycles = Vec::new();
+	(0..FRAMES).for_each(|_| {
+		if let RunOutcome::Stalled(stall) = gameboy.run_frame() {
+			violations.push(format!("stalled: {}", stall));
+		}
+		violations.extend(gameboy.check_invariants());
+		frame_cycles.extend(gameboy.frame_cycles());
+	});
+	violations.extend(
+		frame_cycles
+			.iter()
+			.filter(|&&cycles| cycles != CYCLES_PER_FRAME)
+			.map(|cycles| {
+				format!(
+					"frame took {} cycles instead of {}",
+					cycles, CYCLES_PER_FRAME
+				)
+			}),
+	);
+
+	let serial = String::from_utf8_lossy(&gameboy.take_serial_output()).into_owned();
+	let frame_hash = hash_frame(gameboy.get_frame_buffer());
+	let passed = serial == "PASS\n" && frame_hash == EXPECTED_FRAME_HASH && violations.is_empty();
+
+	let mut details = vec![
+		format!("serial: {:?}", serial),
+		format!("frame hash: {:#018X}", frame_hash),
+	];
+	details.extend(
+		violations
+			.iter()
+			.map(|v| format!("invariant violated: {}", v)),
+	);
+	TestOutcome {
+		suite: "selftest".to_string(),
+		name: "built-in".to_string(),
+		passed,
+		details,
+	}
+}
+
+pub fn run() -> bool {
+	let outcome = outcome();
+	outcome.details.iter().for_each(|d| println!("{}", d));
+	println!("{}", if outcome.passed { "PASS" } else { "FAIL" });
+	outcome.passed
+}
@@ -0,0 +1,320 @@
+use std::{
+	io::{self, Read, Write},
+	net::{TcpStream, ToSocketAddrs},
+};
+
+// BGB link cable protocol (1.4), spoken over TCP in 8 byte packets: command, 3 data bytes & a
+// little endian u32 timestamp in 2 MiHz ticks (31 bits, wraps around)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Packet {
+	pub command: u8,
+	pub data: [u8; 3],
+	pub timestamp: u32,
+}
+
+impl Packet {
+	pub const VERSION: u8 = 1;
+	pub const JOYPAD: u8 = 101;
+	// master transfer: data, control (SC) & 0
+	pub const SYNC1: u8 = 104;
+	// slave reply to SYNC1: data, 0x80 & 1
+	pub const SYNC2: u8 = 105;
+	// data byte 0: timestamp sync (to be echoed back), 1: acknowledges a SYNC1 without transfer
+	pub const SYNC3: u8 = 106;
+	// flags: 0 running, 1 paused, 2 supports reconnect
+	pub const STATUS: u8 = 108;
+	pub const WANT_DISCONNECT: u8 = 109;
+
+	pub fn new(command: u8, data: [u8; 3], timestamp: u32) -> Self {
+		Packet {
+			command,
+			data,
+			timestamp: timestamp & 0x7FFFFFFF,
+		}
+	}
+
+	pub fn to_bytes(self) -> [u8; 8] {
+		let [t0, t1, t2, t3] = self.timestamp.to_le_bytes();
+		[
+			self.command,
+			self.data[0],
+			self.data[1],
+			self.data[2],
+			t0,
+			t1,
+			t2,
+			t3,
+		]
+	}
+
+	pub fn from_bytes(bytes: [u8; 8]) -> Self {
+		Packet {
+			command: bytes[0],
+			data: [bytes[1], bytes[2], bytes[3]],
+			timestamp: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+		}
+	}
+}
+
+// Client side of a BGB link, i.e. BGB listens & we connect to it. Only transfers where we drive
+// the clock (SC = 0x81) are supported, a transfer started by the other side is answered as if
+// our serial port wasn't ready. accept() & answer() are the server side, BGB's role, enough to
+// link against ourselves.
+pub struct BgbLink<S: Read + Write = TcpStream> {
+	stream: S,
+}
+
+impl BgbLink {
+	pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+		let stream = TcpStream::connect(address)?;
+		stream.set_nodelay(true)?;
+		BgbLink::handshake(stream)
+	}
+}
+
+impl<S: Read + Write> BgbLink<S> {
+	// exchanges VERSION & STATUS packets, fails on a protocol version other than 1.4
+	pub fn handshake(stream: S) -> io::Result<Self> {
+		let mut link = BgbLink { stream };
+		link.send(Packet::new(Packet::VERSION, [1, 4, 0], 0))?;
+		let version = link.receive()?;
+		if version.command != Packet::VERSION || version.data != [1, 4, 0] {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("unsupported link protocol: {:?}", version),
+			));
+		}
+		link.send(Packet::new(Packet::STATUS, [0x01, 0, 0], 0))?;
+		Ok(link)
+	}
+
+	// reads the client's VERSION & STATUS & replies with our VERSION
+	pub fn accept(stream: S) -> io::Result<Self> {
+		let mut link = BgbLink { stream };
+		let version = link.receive()?;
+		if version.command != Packet::VERSION || version.data != [1, 4, 0] {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("unsupported link protocol: {:?}", version),
+			));
+		}
+		link.send(Packet::new(Packet::VERSION, [1, 4, 0], 0))?;
+		let status = link.receive()?;
+		if status.command != Packet::STATUS {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("expected status, got {:?}", status),
+			));
+		}
+		Ok(link)
+	}
+
+	// Waits for the client's next transfer & shifts `byte` out in reply, as the slave. Returns the
+	// byte the client sent.
+	pub fn answer(&mut self, byte: u8) -> io::Result<u8> {
+		loop {
+			let packet = self.receive()?;
+			match packet.command {
+				Packet::SYNC1 => {
+					self.send(Packet::new(
+						Packet::SYNC2,
+						[byte, 0x80, 1],
+						packet.timestamp,
+					))?;
+					return Ok(packet.data[0]);
+				}
+				Packet::WANT_DISCONNECT => {
+					return Err(io::Error::new(
+						io::ErrorKind::ConnectionAborted,
+						"link closed by the other side",
+					));
+				}
+				_ => {}
+			}
+		}
+	}
+
+	// sends a timestamp sync the other side is to echo back
+	pub fn sync_time(&mut self, timestamp: u32) -> io::Result<()> {
+		self.send(Packet::new(Packet::SYNC3, [0, 0, 0], timestamp))
+	}
+
+	fn send(&mut self, packet: Packet) -> io::Result<()> {
+		self.stream.write_all(&packet.to_bytes())
+	}
+
+	fn receive(&mut self) -> io::Result<Packet> {
+		let mut bytes = [0; 8];
+		self.stream.read_exact(&mut bytes)?;
+		Ok(Packet::from_bytes(bytes))
+	}
+
+	// Sends `byte` with us as master & returns the byte shifted in by the other side, `timestamp`
+	// being our clock in 2 MiHz ticks when the transfer started
+	pub fn exchange(&mut self, byte: u8, timestamp: u32) -> io::Result<u8> {
+		self.send(Packet::new(Packet::SYNC1, [byte, 0x81, 0], timestamp))?;
+		loop {
+			let packet = self.receive()?;
+			match packet.command {
+				Packet::SYNC2 => return Ok(packet.data[0]),
+				Packet::SYNC1 => self.send(Packet::new(Packet::SYNC2, [0xFF, 0x80, 1], timestamp))?,
+				Packet::SYNC3 if packet.data[0] == 0 => self.send(packet)?,
+				Packet::WANT_DISCONNECT => {
+					return Err(io::Error::new(
+						io::ErrorKind::ConnectionAborted,
+						"link closed by the other side",
+					));
+				}
+				// joypad, status & acknowledgements don't affect a transfer
+				_ => {}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	// the other side's packets canned, ours collected
+	struct Canned {
+		input: Cursor<Vec<u8>>,
+		output: Vec<u8>,
+	}
+
+	impl Canned {
+		fn new(packets: &[Packet]) -> Self {
+			Canned {
+				input: Cursor::new(packets.iter().flat_map(|p| p.to_bytes()).collect()),
+				output: Vec::new(),
+			}
+		}
+
+		fn sent(&self) -> Vec<Packet> {
+			self
+				.output
+				.chunks_exact(8)
+				.map(|bytes| Packet::from_bytes(bytes.try_into().unwrap()))
+				.collect()
+		}
+	}
+
+	impl Read for Canned {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			self.input.read(buf)
+		}
+	}
+
+	impl Write for Canned {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.output.write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	const VERSION: Packet = Packet {
+		command: Packet::VERSION,
+		data: [1, 4, 0],
+		timestamp: 0,
+	};
+
+	fn link(packets: &[Packet]) -> BgbLink<Canned> {
+		let mut canned = vec![VERSION];
+		canned.extend_from_slice(packets);
+		BgbLink::handshake(Canned::new(&canned)).unwrap()
+	}
+
+	#[test]
+	fn packet_layout() {
+		let packet = Packet::new(Packet::SYNC1, [0x42, 0x81, 0], 0xF1234567);
+		// 31 bit timestamp
+		assert_eq!(packet.timestamp, 0x71234567);
+		assert_eq!(
+			packet.to_bytes(),
+			[104, 0x42, 0x81, 0, 0x67, 0x45, 0x23, 0x71]
+		);
+		assert_eq!(Packet::from_bytes(packet.to_bytes()), packet);
+	}
+
+	#[test]
+	fn handshake() {
+		let link = link(&[]);
+		assert_eq!(
+			link.stream.sent(),
+			[VERSION, Packet::new(Packet::STATUS, [0x01, 0, 0], 0)]
+		);
+		let other = Packet::new(Packet::VERSION, [1, 3, 0], 0);
+		let error = BgbLink::handshake(Canned::new(&[other])).err().unwrap();
+		assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+		// nothing sent back
+		let error = BgbLink::handshake(Canned::new(&[])).err().unwrap();
+		assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+	}
+
+	#[test]
+	fn exchange() {
+		let mut link = link(&[
+			Packet::new(Packet::STATUS, [0x00, 0, 0], 0),
+			Packet::new(Packet::JOYPAD, [0x03, 0, 0], 0),
+			Packet::new(Packet::SYNC3, [0, 0, 0], 1234),
+			Packet::new(Packet::SYNC2, [0x99, 0x80, 1], 5678),
+		]);
+		assert_eq!(link.exchange(0x42, 1000).unwrap(), 0x99);
+		assert_eq!(
+			link.stream.sent()[2..],
+			[
+				Packet::new(Packet::SYNC1, [0x42, 0x81, 0], 1000),
+				// timestamp syncs are echoed
+				Packet::new(Packet::SYNC3, [0, 0, 0], 1234),
+			]
+		);
+	}
+
+	#[test]
+	fn transfer_of_the_other_side_is_refused() {
+		let mut link = link(&[
+			Packet::new(Packet::SYNC1, [0x11, 0x81, 0], 500),
+			// acknowledgement, not echoed
+			Packet::new(Packet::SYNC3, [1, 0, 0], 0),
+			Packet::new(Packet::SYNC2, [0x22, 0x80, 1], 600),
+		]);
+		assert_eq!(link.exchange(0x33, 700).unwrap(), 0x22);
+		assert_eq!(
+			link.stream.sent()[2..],
+			[
+				Packet::new(Packet::SYNC1, [0x33, 0x81, 0], 700),
+				Packet::new(Packet::SYNC2, [0xFF, 0x80, 1], 700),
+			]
+		);
+	}
+
+	#[test]
+	fn disconnect() {
+		let mut link = link(&[Packet::new(Packet::WANT_DISCONNECT, [0, 0, 0], 0)]);
+		let error = link.exchange(0x42, 0).err().unwrap();
+		assert_eq!(error.kind(), io::ErrorKind::ConnectionAborted);
+		// the stream ending mid transfer
+		let error = link.exchange(0x42, 0).err().unwrap();
+		assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+	}
+
+	#[test]
+	fn accept() {
+		let status = Packet::new(Packet::STATUS, [0x01, 0, 0], 0);
+		let sync1 = Packet::new(Packet::SYNC1, [0x42, 0x81, 0], 900);
+		let mut link = BgbLink::accept(Canned::new(&[VERSION, status, sync1])).unwrap();
+		assert_eq!(link.answer(0x24).unwrap(), 0x42);
+		assert_eq!(
+			link.stream.sent(),
+			[VERSION, Packet::new(Packet::SYNC2, [0x24, 0x80, 1], 900)]
+		);
+		let error = BgbLink::accept(Canned::new(&[VERSION, sync1]))
+			.err()
+			.unwrap();
+		assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+	}
+}
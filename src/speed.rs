@@ -0,0 +1,208 @@
+use std::time::{Duration, Instant};
+
+// Emulated cycles per second of a single speed DMG
+const CLOCK_HZ: f64 = 4_194_304.0;
+
+// Source of the current time, the system's monotonic clock unless tests need another one
+pub trait Clock {
+	fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+}
+
+// Frames & emulated cycles against (monotonic) real time, both over the last complete second
+// and over the whole run
+pub struct SpeedMeter<C: Clock = SystemClock> {
+	clock: C,
+	start: Instant,
+	frames: u64,
+	cycles: u64,
+	window_start: Instant,
+	window_frames: u64,
+	window_cycles: u64,
+	fps: f64,
+	speed: f64,
+}
+
+impl Default for SpeedMeter {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SpeedMeter {
+	pub fn new() -> Self {
+		Self::with_clock(SystemClock)
+	}
+}
+
+impl<C: Clock> SpeedMeter<C> {
+	pub fn with_clock(clock: C) -> Self {
+		let now = clock.now();
+		SpeedMeter {
+			clock,
+			start: now,
+			frames: 0,
+			cycles: 0,
+			window_start: now,
+			window_frames: 0,
+			window_cycles: 0,
+			fps: 0.0,
+			speed: 0.0,
+		}
+	}
+
+	// a clock going back (not the case of the system's) counts as no time passed
+	pub fn record(&mut self, frames: u64, cycles: u64) {
+		let now = self.clock.now();
+		self.frames += frames;
+		self.cycles += cycles;
+		self.window_frames += frames;
+		self.window_cycles += cycles;
+
+		let elapsed = now.saturating_duration_since(self.window_start);
+		if elapsed >= Duration::from_secs(1) {
+			let seconds = elapsed.as_secs_f64();
+			self.fps = self.window_frames as f64 / seconds;
+			self.speed = 100.0 * self.window_cycles as f64 / CLOCK_HZ / seconds;
+			self.window_start = now;
+			self.window_frames = 0;
+			self.window_cycles = 0;
+		}
+	}
+
+	// frames per second over the last complete second
+	pub fn fps(&self) -> f64 {
+		self.fps
+	}
+
+	// emulated time vs real time over the last complete second, 100% is full speed
+	pub fn speed_percent(&self) -> f64 {
+		self.speed
+	}
+
+	pub fn lifetime_fps(&self) -> f64 {
+		self.frames as f64 / self.elapsed().as_secs_f64().max(f64::EPSILON)
+	}
+
+	pub fn frames(&self) -> u64 {
+		self.frames
+	}
+
+	pub fn cycles(&self) -> u64 {
+		self.cycles
+	}
+
+	pub fn elapsed(&self) -> Duration {
+		self.clock.now().saturating_duration_since(self.start)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{cell::Cell, rc::Rc};
+
+	// moved by hand, shared with the meter
+	#[derive(Clone)]
+	struct TestClock(Rc<Cell<Instant>>);
+
+	impl TestClock {
+		fn advance(&self, duration: Duration) {
+			self.0.set(self.0.get() + duration);
+		}
+
+		fn rewind(&self, duration: Duration) {
+			self.0.set(self.0.get() - duration);
+		}
+	}
+
+	impl Clock for TestClock {
+		fn now(&self) -> Instant {
+			self.0.get()
+		}
+	}
+
+	fn meter() -> (SpeedMeter<TestClock>, TestClock) {
+		// ahead of the system's, so it can be rewound whatever the uptime
+		let clock = TestClock(Rc::new(Cell::new(Instant::now() + Duration::from_secs(60))));
+		(SpeedMeter::with_clock(clock.clone()), clock)
+	}
+
+	// a frame's worth of cycles at full speed, 59.73 frames per second
+	const CYCLES_PER_FRAME: u64 = 70224;
+
+	#[test]
+	fn last_second_and_lifetime() {
+		let (mut meter, clock) = meter();
+		// full speed over the first second
+		(0..60).for_each(|_| {
+			clock.advance(Duration::from_millis(16));
+			meter.record(1, CYCLES_PER_FRAME);
+		});
+		assert_eq!((meter.fps(), meter.speed_percent()), (0.0, 0.0));
+		clock.advance(Duration::from_millis(40));
+		meter.record(0, 0);
+		assert!((meter.fps() - 60.0).abs() < 0.1, "{}", meter.fps());
+		assert!(
+			(meter.speed_percent() - 100.5).abs() < 0.1,
+			"{}",
+			meter.speed_percent()
+		);
+
+		// half speed over the next 1.5 seconds, the half second after it not being a complete window
+		clock.advance(Duration::from_millis(1500));
+		meter.record(45, 45 * CYCLES_PER_FRAME);
+		clock.advance(Duration::from_millis(500));
+		meter.record(30, 30 * CYCLES_PER_FRAME);
+		assert!((meter.fps() - 30.0).abs() < 0.1, "{}", meter.fps());
+		assert!(
+			(meter.speed_percent() - 50.2).abs() < 0.1,
+			"{}",
+			meter.speed_percent()
+		);
+
+		assert_eq!(
+			(meter.frames(), meter.cycles()),
+			(135, 135 * CYCLES_PER_FRAME)
+		);
+		assert_eq!(meter.elapsed(), Duration::from_secs(3));
+		assert!(
+			(meter.lifetime_fps() - 45.0).abs() < 0.1,
+			"{}",
+			meter.lifetime_fps()
+		);
+	}
+
+	#[test]
+	fn clock_jumps() {
+		let (mut meter, clock) = meter();
+		// back in time: no time passed, nothing computed
+		clock.rewind(Duration::from_secs(5));
+		meter.record(60, 60 * CYCLES_PER_FRAME);
+		assert_eq!((meter.fps(), meter.speed_percent()), (0.0, 0.0));
+		assert_eq!(meter.elapsed(), Duration::ZERO);
+		assert_eq!(meter.frames(), 60);
+
+		// forward, e.g. out of a suspend: the frames are spread over the whole jump
+		clock.advance(Duration::from_secs(5 + 10));
+		meter.record(60, 60 * CYCLES_PER_FRAME);
+		assert!((meter.fps() - 12.0).abs() < 0.1, "{}", meter.fps());
+		assert!(
+			(meter.lifetime_fps() - 12.0).abs() < 0.1,
+			"{}",
+			meter.lifetime_fps()
+		);
+
+		// & the next second is measured on its own
+		clock.advance(Duration::from_secs(1));
+		meter.record(60, 60 * CYCLES_PER_FRAME);
+		assert!((meter.fps() - 60.0).abs() < 0.1, "{}", meter.fps());
+	}
+}
@@ -0,0 +1,200 @@
+use rustboy::{
+	gameboy::{Layers, Palette},
+	options::EmulatorOptions,
+};
+use std::{
+	env, fs, io,
+	path::{Path, PathBuf},
+};
+
+// Frontend state remembered between runs, written on exit. Kept apart from anything a user
+// edits by hand, it's simply overwritten every time. Remembered values rank below the command
+// line & palettes.toml, they only replace the built-in defaults.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct State {
+	// window size on exit, only restored along with the scale it was for
+	pub window: Option<(usize, usize)>,
+	pub scale: usize,
+	// the last one picked, for games without one in palettes.toml
+	pub palette: Palette,
+	pub layers: Layers,
+}
+
+// $XDG_CONFIG_HOME/rustboy, falling back to ~/.config/rustboy
+pub fn config_dir() -> Option<PathBuf> {
+	let config_dir = env::var_os("XDG_CONFIG_HOME")
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+	Some(config_dir.join("rustboy"))
+}
+
+// state.toml in config_dir()
+pub fn default_path() -> Option<PathBuf> {
+	Some(config_dir()?.join("state.toml"))
+}
+
+impl State {
+	// what's to be remembered of `options`, without a window size yet
+	pub fn from_options(options: &EmulatorOptions) -> Self {
+		State {
+			window: None,
+			scale: options.scale,
+			palette: options.palette,
+			layers: options.layers,
+		}
+	}
+
+	// None if there is no state yet, unknown keys are ignored & values that can't be made sense of
+	// are left at their defaults
+	pub fn load(path: &Path) -> Option<Self> {
+		let text = fs::read_to_string(path).ok()?;
+		let value = |key: &str| {
+			text.lines().find_map(|line| {
+				let (k, v) = line.split_once('=')?;
+				(k.trim() == key).then(|| v.trim().to_string())
+			})
+		};
+		let size = |key: &str| value(key)?.parse::<usize>().ok().filter(|&s| s > 0);
+		let defaults = Self::from_options(&EmulatorOptions::default());
+		Some(State {
+			window: size("window_width").zip(size("window_height")),
+			scale: size("scale").filter(|&s| s <= 8).unwrap_or(defaults.scale),
+			palette: value("palette")
+				.and_then(|name| Palette::find(&name))
+				.unwrap_or(defaults.palette),
+			layers: value("hidden_layers")
+				.and_then(|names| Layers::hiding(&names))
+				.unwrap_or(defaults.layers),
+		})
+	}
+
+	// `options` (the defaults) with the remembered values, for the command line to override
+	pub fn restore(&self, options: EmulatorOptions) -> EmulatorOptions {
+		EmulatorOptions {
+			scale: self.scale,
+			palette: self.palette,
+			layers: self.layers,
+			..options
+		}
+	}
+
+	// the remembered window size, unless the scale changed since
+	pub fn window_size(&self, scale: usize) -> Option<(usize, usize)> {
+		self.window.filter(|_| self.scale == scale)
+	}
+
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		let window = self
+			.window
+			.map(|(width, height)| format!("window_width = {}\nwindow_height = {}\n", width, height))
+			.unwrap_or_default();
+		fs::write(
+			path,
+			format!(
+				"# written by rustboy on exit, changes get overwritten\n{}scale = {}\npalette = {}\nhidden_layers = {}\n",
+				window,
+				self.scale,
+				self.palette.name,
+				self.layers.hidden()
+			),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rustboy::gameboy::PALETTES;
+
+	// a state file of its own in the temp directory, never the real one
+	fn path(name: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("rustboy-state-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join(format!("{}.toml", name));
+		let _ = fs::remove_file(&path);
+		path
+	}
+
+	fn parse(state: &State, args: &[&str]) -> EmulatorOptions {
+		let args = args.iter().map(|arg| arg.to_string());
+		EmulatorOptions::parse_onto(state.restore(EmulatorOptions::default()), args).unwrap()
+	}
+
+	#[test]
+	fn round_trip() {
+		let path = path("round_trip");
+		assert_eq!(State::load(&path), None);
+		let state = State {
+			window: Some((500, 460)),
+			scale: 3,
+			palette: PALETTES[1],
+			layers: Layers::hiding("window,objects").unwrap(),
+		};
+		state.save(&path).unwrap();
+		assert_eq!(State::load(&path), Some(state));
+		// nothing hidden
+		let state = State {
+			window: None,
+			layers: Layers::default(),
+			..state
+		};
+		state.save(&path).unwrap();
+		assert_eq!(State::load(&path), Some(state));
+	}
+
+	#[test]
+	fn unknown_or_missing_values_are_left_at_their_defaults() {
+		let path = path("defaults");
+		// as written before anything but the window size was remembered
+		fs::write(&path, "window_width = 640\nwindow_height = 576\n").unwrap();
+		let defaults = State::from_options(&EmulatorOptions::default());
+		let state = State::load(&path).unwrap();
+		assert_eq!(
+			state,
+			State {
+				window: Some((640, 576)),
+				..defaults
+			}
+		);
+		assert_eq!(state.window_size(4), Some((640, 576)));
+		fs::write(
+			&path,
+			"window_width = 0\nscale = 100\npalette = neon\nhidden_layers = sky\nvolume = 3\n",
+		)
+		.unwrap();
+		assert_eq!(State::load(&path), Some(defaults));
+	}
+
+	#[test]
+	fn command_line_ranks_above_the_state() {
+		let path = path("precedence");
+		State {
+			window: Some((500, 460)),
+			scale: 3,
+			palette: PALETTES[0],
+			layers: Layers::hiding("background").unwrap(),
+		}
+		.save(&path)
+		.unwrap();
+		let state = State::load(&path).unwrap();
+
+		let options = parse(&state, &[]);
+		assert_eq!(options.scale, 3);
+		assert_eq!(options.palette, PALETTES[0]);
+		assert!(!options.layers.background);
+		assert_eq!(state.window_size(options.scale), Some((500, 460)));
+
+		let options = parse(
+			&state,
+			&["--scale", "2", "--palette", "hollow", "--hide", "objects"],
+		);
+		assert_eq!(options.scale, 2);
+		assert_eq!(options.palette, Palette::find("hollow").unwrap());
+		assert_eq!(options.layers, Layers::hiding("objects").unwrap());
+		// the window gets the size of the new scale
+		assert_eq!(state.window_size(options.scale), None);
+	}
+}
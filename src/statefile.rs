@@ -0,0 +1,146 @@
+use crate::gameboy::BankingState;
+use std::{fs, io, path::Path};
+
+const MAGIC: &[u8; 4] = b"RBSS";
+const VERSION: u8 = 1;
+// 0x8000-0xFFFF, VRAM to IE
+const MEMORY_SIZE: usize = 0x8000;
+const HEADER_SIZE: usize = 4 + 1 + 8 + 4 + 1 + 2 + 5;
+
+// Save state file (<rom>.ss1), written from the pause menu. Unlike a Snapshot it stands on its
+// own, tools read it without the rom (e.g. --export-gfx with --from-state). It's taken between
+// frames, so the PPU picks up again at the start of VBLANK from its registers alone, the frame
+// itself isn't kept.
+//
+// Little endian: "RBSS", version, AF BC DE HL SP PC, IME, DIV counter, rom bank (2 bytes), ram
+// bank, ram enable, banking mode, 0x8000-0xFFFF as in memory, then all of cartridge RAM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateFile {
+	// AF, BC, DE & HL
+	pub registers: [u16; 4],
+	pub sp: u16,
+	pub pc: u16,
+	pub ime: bool,
+	pub div_counter: u16,
+	pub banking: BankingState,
+	// 0x8000-0xFFFF, as written by the CPU (reads of some IO registers differ)
+	pub memory: Vec<u8>,
+	pub cartridge_ram: Vec<u8>,
+}
+
+impl StateFile {
+	pub fn encode(&self) -> Vec<u8> {
+		let mut data = Vec::with_capacity(HEADER_SIZE + MEMORY_SIZE + self.cartridge_ram.len());
+		data.extend(MAGIC);
+		data.push(VERSION);
+		self
+			.registers
+			.iter()
+			.chain(&[self.sp, self.pc])
+			.for_each(|r| data.extend(r.to_le_bytes()));
+		data.push(self.ime as u8);
+		data.extend(self.div_counter.to_le_bytes());
+		data.extend(self.banking.rom_bank.to_le_bytes());
+		data.push(self.banking.ram_bank);
+		data.push(self.banking.ram_enable as u8);
+		data.push(self.banking.banking_mode as u8);
+		data.extend(&self.memory);
+		data.extend(&self.cartridge_ram);
+		data
+	}
+
+	pub fn decode(data: &[u8]) -> Result<Self, String> {
+		if data.len() < HEADER_SIZE + MEMORY_SIZE || &data[..4] != MAGIC {
+			return Err("not a save state".to_string());
+		}
+		if data[4] != VERSION {
+			return Err(format!("unsupported save state version {}", data[4]));
+		}
+		let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+		Ok(StateFile {
+			registers: [word(5), word(7), word(9), word(11)],
+			sp: word(13),
+			pc: word(15),
+			ime: data[17] != 0,
+			div_counter: word(18),
+			banking: BankingState {
+				rom_bank: word(20),
+				ram_bank: data[22],
+				ram_enable: data[23] != 0,
+				banking_mode: data[24] != 0,
+			},
+			memory: data[HEADER_SIZE..HEADER_SIZE + MEMORY_SIZE].to_vec(),
+			cartridge_ram: data[HEADER_SIZE + MEMORY_SIZE..].to_vec(),
+		})
+	}
+
+	pub fn read(path: &Path) -> Result<Self, String> {
+		let data = fs::read(path).map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+		Self::decode(&data).map_err(|e| format!("{}: {}", path.display(), e))
+	}
+
+	pub fn write(&self, path: &Path) -> io::Result<()> {
+		fs::write(path, self.encode())
+	}
+
+	// 0x8000-0x9FFF
+	pub fn vram(&self) -> &[u8] {
+		&self.memory[..0x2000]
+	}
+
+	// byte at `address` (0x8000 or above) as stored
+	pub fn byte(&self, address: u16) -> u8 {
+		self.memory[address as usize - 0x8000]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let state = StateFile {
+			registers: [0x01B0, 0x0013, 0x00D8, 0x014D],
+			sp: 0xFFFE,
+			pc: 0x0150,
+			ime: true,
+			div_counter: 0xABCC,
+			banking: BankingState {
+				rom_bank: 0x101,
+				ram_bank: 2,
+				ram_enable: true,
+				banking_mode: false,
+			},
+			memory: (0..MEMORY_SIZE).map(|i| i as u8).collect(),
+			cartridge_ram: vec![0x42; 0x2000],
+		};
+		let data = state.encode();
+		assert_eq!(data.len(), HEADER_SIZE + MEMORY_SIZE + 0x2000);
+		assert_eq!(StateFile::decode(&data), Ok(state.clone()));
+		assert_eq!(state.byte(0xFF40), 0x40);
+		assert_eq!(state.vram().len(), 0x2000);
+
+		// no cartridge RAM
+		let state = StateFile {
+			cartridge_ram: Vec::new(),
+			..state
+		};
+		assert_eq!(StateFile::decode(&state.encode()), Ok(state));
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		assert!(StateFile::decode(b"RBSS").is_err());
+		let mut data = vec![0; HEADER_SIZE + MEMORY_SIZE];
+		assert_eq!(
+			StateFile::decode(&data),
+			Err("not a save state".to_string())
+		);
+		data[..5].copy_from_slice(b"RBSS\x07");
+		assert_eq!(
+			StateFile::decode(&data),
+			Err("unsupported save state version 7".to_string())
+		);
+	}
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+// Labels from a RGBDS/BGB style symbol file, one `BB:AAAA label` per line, `;` starts a comment
+#[derive(Clone, Default)]
+pub struct Symbols(HashMap<(u16, u16), String>);
+
+impl Symbols {
+	pub fn parse(text: &str) -> Self {
+		let symbols = text
+			.lines()
+			.map(|line| line.split(';').next().unwrap_or("").trim())
+			.filter_map(|line| {
+				let (location, label) = line.split_once(char::is_whitespace)?;
+				let (bank, address) = location.split_once(':')?;
+				let bank = u16::from_str_radix(bank, 16).ok()?;
+				let address = u16::from_str_radix(address, 16).ok()?;
+				Some(((bank, address), label.trim().to_string()))
+			})
+			.collect();
+		Symbols(symbols)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	// address of the label, whichever bank it's in
+	pub fn find(&self, label: &str) -> Option<u16> {
+		self
+			.0
+			.iter()
+			.find_map(|(&(_, address), l)| (l == label).then_some(address))
+	}
+
+	// `rom_bank` is the bank mapped at 0x4000-0x7FFF, only consulted for addresses in that range
+	pub fn lookup(&self, address: u16, rom_bank: u16) -> Option<&str> {
+		let bank = match address {
+			0x4000..0x8000 => rom_bank.max(1),
+			_ => 0,
+		};
+		self
+			.0
+			.get(&(bank, address))
+			.or_else(|| self.0.get(&(0, address)))
+			.map(|s| s.as_str())
+	}
+}
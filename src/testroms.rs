@@ -0,0 +1,188 @@
+use crate::{gameboy::GameBoy, scoreboard::TestOutcome};
+use std::{fs, path::Path};
+
+// Runs a test rom & tells how it went
+type Harness = fn(&str, Vec<u8>) -> TestOutcome;
+
+// Give up on a test rom that hasn't reported a result after a minute of emulated time
+const MAX_FRAMES: u64 = 60 * 60;
+
+// Blargg's roms print their name, the results & finally "Passed" or "Failed" over serial
+pub fn blargg(name: &str, rom: Vec<u8>) -> TestOutcome {
+	let mut gameboy = GameBoy::from_bytes(rom);
+	let mut serial = String::new();
+	let mut frames = 0;
+	while !serial.contains("Passed") && !serial.contains("Failed") && frames < MAX_FRAMES {
+		gameboy.run_frame();
+		serial += &String::from_utf8_lossy(&gameboy.take_serial_output());
+		frames += 1;
+	}
+	let mut details = serial
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty())
+		.map(String::from)
+		.collect::<Vec<_>>();
+	if frames == MAX_FRAMES {
+		details.push(format!("no result after {} frames", MAX_FRAMES));
+	}
+	TestOutcome {
+		suite: "blargg".to_string(),
+		name: name.to_string(),
+		passed: serial.contains("Passed"),
+		details,
+	}
+}
+
+// Mooneye's roms execute LD B, B once done, with the Fibonacci numbers 3, 5, 8, 13, 21 & 34 in
+// B, C, D, E, H & L on success
+pub fn mooneye(name: &str, rom: Vec<u8>) -> TestOutcome {
+	const FIBONACCI: [u16; 3] = [0x0305, 0x080D, 0x1522];
+	let mut gameboy = GameBoy::from_bytes(rom);
+	let mut frames = 0;
+	while gameboy.read_byte(gameboy.cpu().pc()) != 0x40 && frames < MAX_FRAMES {
+		if gameboy.step() {
+			frames += 1;
+		}
+	}
+	let cpu = gameboy.cpu();
+	let registers = [cpu.bc(), cpu.de(), cpu.hl()];
+	let details = match frames == MAX_FRAMES {
+		true => vec![format!("no result after {} frames", MAX_FRAMES)],
+		false => vec![format!(
+			"BC={:04X} DE={:04X} HL={:04X}",
+			registers[0], registers[1], registers[2]
+		)],
+	};
+	TestOutcome {
+		suite: "mooneye".to_string(),
+		name: name.to_string(),
+		passed: frames < MAX_FRAMES && registers == FIBONACCI,
+		details,
+	}
+}
+
+// Every .gb rom in `dir`/blargg & `dir`/mooneye (either may be missing), ordered by suite & name
+pub fn run_dir(dir: &Path) -> Vec<TestOutcome> {
+	let suites: [(&str, Harness); 2] = [("blargg", blargg), ("mooneye", mooneye)];
+	suites
+		.iter()
+		.flat_map(|(suite, harness)| {
+			let mut roms = fs::read_dir(dir.join(suite))
+				.into_iter()
+				.flatten()
+				.filter_map(|entry| Some(entry.ok()?.path()))
+				.filter(|path| path.extension().is_some_and(|e| e == "gb"))
+				.collect::<Vec<_>>();
+			roms.sort();
+			roms.into_iter().map(move |path| {
+				let name = path.file_stem().unwrap_or_default().to_string_lossy();
+				match fs::read(&path) {
+					Ok(rom) => harness(&name, rom),
+					Err(e) => TestOutcome {
+						suite: suite.to_string(),
+						name: name.to_string(),
+						passed: false,
+						details: vec![format!("unreadable: {}", e)],
+					},
+				}
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{env, process};
+
+	// ROM-only cart with `program` at the entry point
+	fn rom(program: &[u8]) -> Vec<u8> {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+		rom
+	}
+
+	// prints `message` over serial (internal clock) & spins
+	fn printing(message: &str) -> Vec<u8> {
+		#[rustfmt::skip]
+		let mut program = vec![
+			0x21, 0x50, 0x01, // 0100: LD HL, $0150
+			0x2A,             // 0103: LD A, (HL+)  ; print
+			0xB7,             // 0104: OR A
+			0x28, 0xFE,       // 0105: JR Z, $0105
+			0xE0, 0x01,       // 0107: LDH ($01), A
+			0x3E, 0x81,       // 0109: LD A, $81
+			0xE0, 0x02,       // 010B: LDH ($02), A
+			0xF0, 0x02,       // 010D: LDH A, ($02) ; serial_wait
+			0xCB, 0x7F,       // 010F: BIT 7, A
+			0x20, 0xFA,       // 0111: JR NZ, serial_wait
+			0x18, 0xEE,       // 0113: JR print
+		];
+		program.resize(0x50, 0);
+		program.extend(message.bytes());
+		program.push(0);
+		rom(&program)
+	}
+
+	// loads `b` & the rest of the Fibonacci numbers, then LD B, B & spins
+	fn fibonacci(b: u8) -> Vec<u8> {
+		#[rustfmt::skip]
+		let program = [
+			0x06, b, 0x0E, 5, 0x16, 8, 0x1E, 13, 0x26, 21, 0x2E, 34,
+			0x40,       // LD B, B
+			0x18, 0xFE, // JR -2
+		];
+		rom(&program)
+	}
+
+	#[test]
+	fn blargg_result_comes_from_serial() {
+		let outcome = blargg("cpu_instrs", printing("cpu_instrs\n\nPassed\n"));
+		assert!(outcome.passed);
+		assert_eq!(outcome.suite, "blargg");
+		assert_eq!(outcome.name, "cpu_instrs");
+		assert_eq!(outcome.details, ["cpu_instrs", "Passed"]);
+
+		let outcome = blargg("halt_bug", printing("halt_bug\n\nFailed #2\n"));
+		assert!(!outcome.passed);
+		assert_eq!(outcome.details, ["halt_bug", "Failed #2"]);
+	}
+
+	#[test]
+	fn mooneye_result_comes_from_the_registers() {
+		let outcome = mooneye("div_timing", fibonacci(3));
+		assert!(outcome.passed, "{:?}", outcome.details);
+		assert_eq!(outcome.details, ["BC=0305 DE=080D HL=1522"]);
+
+		let outcome = mooneye("ei_timing", fibonacci(0x42));
+		assert!(!outcome.passed);
+		assert_eq!(outcome.details, ["BC=4205 DE=080D HL=1522"]);
+	}
+
+	#[test]
+	fn run_dir_goes_through_both_suites_in_order() {
+		let dir = env::temp_dir().join(format!("rustboy-testroms-{}", process::id()));
+		fs::create_dir_all(dir.join("blargg")).unwrap();
+		fs::create_dir_all(dir.join("mooneye")).unwrap();
+		fs::write(dir.join("blargg/b.gb"), printing("Failed\n")).unwrap();
+		fs::write(dir.join("blargg/a.gb"), printing("Passed\n")).unwrap();
+		fs::write(dir.join("blargg/notes.txt"), "not a rom").unwrap();
+		fs::write(dir.join("mooneye/c.gb"), fibonacci(3)).unwrap();
+
+		let outcomes = run_dir(&dir)
+			.into_iter()
+			.map(|o| (o.suite, o.name, o.passed))
+			.collect::<Vec<_>>();
+		let expected = [
+			("blargg", "a", true),
+			("blargg", "b", false),
+			("mooneye", "c", true),
+		]
+		.map(|(suite, name, passed)| (suite.to_string(), name.to_string(), passed));
+		assert_eq!(outcomes, expected);
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert!(run_dir(&dir).is_empty());
+	}
+}
@@ -0,0 +1,70 @@
+use crate::{HEIGHT, WIDTH};
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+#[rustfmt::skip]
+fn glyph(c: char) -> [u8; 5] {
+	match c.to_ascii_uppercase() {
+		'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+		'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+		'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+		'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+		'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+		'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+		'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+		'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+		'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+		'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+		'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+		'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+		'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+		'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+		'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+		'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+		'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+		'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+		'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+		'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+		'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+		'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+		'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+		'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+		'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+		'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+		'0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+		'1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+		'2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+		'3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+		'4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+		'5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+		'6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+		'7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+		'8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+		'9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+		':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+		'.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+		'-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+		'>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+		'/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+		'%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+		_ => [0b000; 5],
+	}
+}
+
+// Width of given text in pixels when drawn by draw_text
+pub fn text_width(text: &str) -> usize {
+	(text.chars().count() * 4).saturating_sub(1)
+}
+
+// Draws text with its top-left corner at (x, y) into a WIDTH x HEIGHT buffer, pixels falling
+// outside the buffer are clipped. Unsupported characters are drawn as spaces.
+pub fn draw_text(buffer: &mut [u32], x: usize, y: usize, text: &str, color: u32) {
+	text.chars().enumerate().for_each(|(i, c)| {
+		glyph(c).iter().enumerate().for_each(|(row, bits)| {
+			(0..3)
+				.filter(|col| (bits >> (2 - col)) & 0x01 == 0x01)
+				.map(|col| (x + 4 * i + col, y + row))
+				.filter(|&(px, py)| px < WIDTH && py < HEIGHT)
+				.for_each(|(px, py)| buffer[py * WIDTH + px] = color);
+		});
+	});
+}
@@ -37,7 +37,7 @@ impl Checks for u8 {
 
 impl Checks for u16 {
 	fn check_half_carry_add(a: u16, b: u16, c: u16) -> bool {
-		(a & 0x0FFF) + (b & 0x0FFF) + (c & 0xFFFF) > 0x0FFF
+		(a & 0x0FFF) + (b & 0x0FFF) + c > 0x0FFF
 	}
 
 	fn check_half_carry_sub(_: u16, _: u16, _: u16) -> bool {
@@ -54,3 +54,32 @@ impl Checks for u16 {
 		unimplemented!();
 	}
 }
+
+// SplitMix64: advances `state` & returns the next value, any seed (incl. 0) is fine
+pub fn splitmix64(state: &mut u64) -> u64 {
+	*state = state.wrapping_add(0x9E3779B97F4A7C15);
+	let mut z = *state;
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+	z ^ (z >> 31)
+}
+
+// FNV-1a, good enough to tell frames apart
+pub fn hash_frame(frame: &[u32]) -> u64 {
+	frame
+		.iter()
+		.flat_map(|p| p.to_le_bytes())
+		.fold(0xCBF29CE484222325, |hash, byte| {
+			(hash ^ byte as u64).wrapping_mul(0x00000100000001B3)
+		})
+}
+
+// CRC-32 (IEEE), as used by PNG & zip
+pub fn crc32(bytes: &[u8]) -> u32 {
+	!bytes.iter().fold(0xFFFFFFFF_u32, |crc, &byte| {
+		(0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+			1 => (crc >> 1) ^ 0xEDB88320,
+			_ => crc >> 1,
+		})
+	})
+}
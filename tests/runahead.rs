@@ -0,0 +1,112 @@
+// Run-ahead against plain emulation of the same scripted input: the frame run ahead is the one
+// the next run_frame produces, & running ahead leaves no trace in the emulation that counts.
+
+use rustboy::{
+	gameboy::{GameBoy, HookAction},
+	joypad::Button,
+};
+use std::sync::{
+	Arc,
+	atomic::{AtomicUsize, Ordering},
+};
+
+// Draws the left half of tile 0 in color 3, then keeps scrolling the background by the
+// direction keys held (SCX = ~P1 & 0x0F), so every input shows up in the frame.
+#[rustfmt::skip]
+const PROGRAM: [u8; 0x18] = [
+	0x21, 0x00, 0x80, // 0100: LD HL, $8000
+	0x06, 0x10,       // 0103: LD B, $10
+	0x3E, 0xF0,       // 0105: LD A, $F0      ; tile_loop
+	0x22,             // 0107: LD (HL+), A
+	0x05,             // 0108: DEC B
+	0x20, 0xFA,       // 0109: JR NZ, tile_loop
+	0x3E, 0x20,       // 010B: LD A, $20      ; direction keys
+	0xE0, 0x00,       // 010D: LDH ($00), A
+	0xF0, 0x00,       // 010F: LDH A, ($00)   ; main
+	0x2F,             // 0111: CPL
+	0xE6, 0x0F,       // 0112: AND $0F
+	0xE0, 0x43,       // 0114: LDH ($43), A
+	0x18, 0xF7,       // 0116: JR main
+];
+
+const FRAMES: usize = 40;
+
+fn gameboy() -> GameBoy {
+	let mut rom = vec![0; 0x8000];
+	rom[0x0100..0x0100 + PROGRAM.len()].copy_from_slice(&PROGRAM);
+	GameBoy::from_bytes(rom)
+}
+
+// held for frame `i`
+fn input(i: usize) -> Vec<Button> {
+	match i % 8 {
+		0..=2 => vec![Button::RIGHT],
+		3 => vec![],
+		4..=6 => vec![Button::LEFT, Button::UP],
+		_ => vec![Button::DOWN],
+	}
+}
+
+// counts how often the SCX write executes
+fn count_scx_writes(gameboy: &mut GameBoy) -> Arc<AtomicUsize> {
+	let count = Arc::new(AtomicUsize::new(0));
+	let counter = count.clone();
+	gameboy.add_pc_hook(
+		0x0114,
+		Box::new(move |_, _| {
+			counter.fetch_add(1, Ordering::Relaxed);
+			HookAction::Continue
+		}),
+	);
+	count
+}
+
+// what the emulation that counts is made of
+fn state(gameboy: &GameBoy) -> (String, Vec<u8>, Vec<u32>, u64) {
+	(
+		gameboy.state(),
+		gameboy.state_file().encode(),
+		gameboy.get_frame_buffer().to_vec(),
+		gameboy.frame_count(),
+	)
+}
+
+#[test]
+fn frame_run_ahead_is_the_next_frame() {
+	let mut vanilla = gameboy();
+	let vanilla_writes = count_scx_writes(&mut vanilla);
+	let frames = (0..=FRAMES)
+		.map(|i| {
+			vanilla.set_input(input(i));
+			vanilla.run_frame();
+			vanilla.get_frame_buffer().to_vec()
+		})
+		.collect::<Vec<_>>();
+	// the input does show
+	assert_ne!(frames[1], frames[5]);
+
+	let mut ahead = gameboy();
+	let ahead_writes = count_scx_writes(&mut ahead);
+	(0..FRAMES).for_each(|i| {
+		ahead.set_input(input(i));
+		ahead.run_frame();
+		assert!(ahead.get_frame_buffer() == frames[i], "frame {}", i);
+
+		// with the buttons for the next frame, as the frontend does before presenting
+		ahead.set_input(input(i + 1));
+		let before = state(&ahead);
+		let writes = ahead_writes.load(Ordering::Relaxed);
+		let presented = ahead.run_ahead();
+		assert!(presented == frames[i + 1], "frame {} run ahead", i + 1);
+		assert!(state(&ahead) == before, "frame {} left a trace", i);
+		assert_eq!(ahead_writes.load(Ordering::Relaxed), writes, "hook ran ahead");
+	});
+	ahead.set_input(input(FRAMES));
+	ahead.run_frame();
+	assert!(ahead.get_frame_buffer() == frames[FRAMES]);
+	assert_eq!(
+		ahead_writes.load(Ordering::Relaxed),
+		vanilla_writes.load(Ordering::Relaxed)
+	);
+	assert!(ahead_writes.load(Ordering::Relaxed) > FRAMES);
+}
@@ -0,0 +1,32 @@
+// A BGB link over TCP on the loopback interface, with both ends ours: the client as run against
+// BGB & the server side standing in for it.
+
+use rustboy::serial::BgbLink;
+use std::{net::TcpListener, thread};
+
+#[test]
+fn loopback() {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	// answers every byte with its complement, syncing time before every other one
+	let server = thread::spawn(move || {
+		let (stream, _) = listener.accept().unwrap();
+		let mut link = BgbLink::accept(stream).unwrap();
+		(0..=255u8)
+			.map(|i| {
+				if i % 2 == 0 {
+					link.sync_time(i as u32 * 1024).unwrap();
+				}
+				link.answer(!i).unwrap()
+			})
+			.collect::<Vec<_>>()
+	});
+
+	let mut link = BgbLink::connect(address).unwrap();
+	(0..=255u8).for_each(|i| {
+		// a transfer at 8192 Hz takes 8 * 256 ticks of the 2 MiHz clock
+		assert_eq!(link.exchange(i.wrapping_mul(3), i as u32 * 2048).unwrap(), !i);
+	});
+	let received = server.join().unwrap();
+	assert_eq!(received, (0..=255u8).map(|i| i.wrapping_mul(3)).collect::<Vec<_>>());
+}